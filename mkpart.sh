@@ -0,0 +1,27 @@
+#!/bin/sh
+# Writes an MBR partition table with a single partition to IMAGE: TYPE (hex) from sector FIRST to the end of the
+# image. CHS addressing isn't used, the CHS fields are set to the maximum as for disks larger than CHS can address.
+IMAGE=$1
+TYPE=$2
+FIRST=$3
+SECTORS=$(( $(stat -c %s "$IMAGE") / 512 - FIRST ))
+
+byte() {
+    printf "\\$(printf %03o "$1")"
+}
+
+le32() {
+    byte $(( $1 & 255 ))
+    byte $(( ($1 >> 8) & 255 ))
+    byte $(( ($1 >> 16) & 255 ))
+    byte $(( ($1 >> 24) & 255 ))
+}
+
+{
+    byte 0; byte 255; byte 255; byte 255
+    byte $(( 0x$TYPE )); byte 255; byte 255; byte 255
+    le32 "$FIRST"
+    le32 "$SECTORS"
+    # the three other entries are unused
+    head -c 48 /dev/zero
+} | dd of="$IMAGE" bs=1 seek=446 conv=notrunc status=none
@@ -0,0 +1,138 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#include "src/libc/libc.h"
+
+// Kernel microbenchmarks, a stable yardstick for performance work:
+//     bench [name...]
+// runs the named benchmarks, or all of them. Each one runs batches of doubling size until a batch takes at least
+// kSliceNs, and prints a line "<name> <iterations> <ns per iteration>" for that batch on stdout. Nothing else goes
+// to stdout, so runs can be compared by a script.
+
+constexpr uint64_t kSliceNs = 500000000;
+constexpr int kPageSize = 4096;
+constexpr int kBlockSize = 65536;
+
+static char block[kBlockSize];
+
+// One round trip of a byte between two processes through two pipes.
+static bool PipePingPong(int iterations) {
+    int ping[2], pong[2];
+    if (Pipe(ping) < 0) return false;
+    if (Pipe(pong) < 0) {
+        Close(ping[0]);
+        Close(ping[1]);
+        return false;
+    }
+    int child = Fork();
+    if (child == 0) {
+        char c;
+        for (int i = 0; i < iterations; i++) {
+            if (Read(ping[0], &c, 1) != 1 || Write(pong[1], &c, 1) != 1) Exit(1);
+        }
+        Exit(0);
+    }
+    bool ok = child > 0;
+    char c = 'x';
+    for (int i = 0; ok && i < iterations; i++) {
+        ok = Write(ping[1], &c, 1) == 1 && Read(pong[0], &c, 1) == 1;
+    }
+    for (int fd : {ping[0], ping[1], pong[0], pong[1]}) Close(fd);
+    int status;
+    if (child > 0) Wait(child, &status);
+    return ok;
+}
+
+// Fork a child that exits immediately and wait for it.
+static bool ForkStorm(int iterations) {
+    for (int i = 0; i < iterations; i++) {
+        int child = Fork();
+        if (child == 0) Exit(0);
+        int status;
+        if (child < 0 || Wait(child, &status) != child) return false;
+    }
+    return true;
+}
+
+// Read a block of a file, starting over at its end.
+static const char* read_path = "src/arch/x86/kernel.bin";
+
+static bool FileRead(int iterations) {
+    int fd = Open(read_path, kOpenReadOnly, 0);
+    if (fd < 0) return false;
+    bool ok = true;
+    for (int i = 0; ok && i < iterations; i++) {
+        auto n = static_cast<int>(Read(fd, block, kBlockSize));
+        if (n == 0) {
+            ok = Seek(fd, 0, 0) == 0 && static_cast<int>(Read(fd, block, kBlockSize)) > 0;
+        } else {
+            ok = n > 0;
+        }
+    }
+    Close(fd);
+    return ok;
+}
+
+// A yield from one process to another and back, the two processes yield in turns.
+static bool ContextSwitch(int iterations) {
+    int child = Fork();
+    if (child == 0) {
+        for (int i = 0; i < iterations; i++) Yield();
+        Exit(0);
+    }
+    if (child < 0) return false;
+    for (int i = 0; i < iterations; i++) Yield();
+    int status;
+    return Wait(child, &status) == child;
+}
+
+// Touch a fresh page of the heap, which faults in a zero page.
+static bool PageFault(int iterations) {
+    auto base = static_cast<char*>(Sbrk(iterations * kPageSize));
+    if (base == (void*) -1) return false;
+    for (int i = 0; i < iterations; i++) base[i * kPageSize] = 1;
+    Sbrk(-iterations * kPageSize);
+    return true;
+}
+
+struct Benchmark {
+    std::string_view name;
+    bool (*run)(int iterations);
+    int max_iterations;  // bounded by memory or descriptors rather than time
+};
+
+static const Benchmark benchmarks[] = {
+    {"pipe_pingpong", PipePingPong, 1 << 20},
+    {"fork_storm", ForkStorm, 1 << 16},
+    {"file_read_64k", FileRead, 1 << 20},
+    {"context_switch", ContextSwitch, 1 << 20},
+    {"page_fault", PageFault, 1 << 13},  // 32mb of heap
+};
+
+static bool Run(const Benchmark& benchmark) {
+    for (int iterations = 1; ; iterations *= 2) {
+        auto start = GetTimeNs();
+        if (!benchmark.run(iterations)) {
+            Writer err(2);
+            print(err, "bench: {} failed\n", benchmark.name);
+            return false;
+        }
+        auto elapsed = GetTimeNs() - start;
+        if (elapsed >= kSliceNs || iterations >= benchmark.max_iterations) {
+            uprint("{} {} {}\n", benchmark.name, iterations, elapsed / iterations);
+            return true;
+        }
+    }
+}
+
+extern "C"
+int main(int argc, char* argv[]) {
+    bool ok = true;
+    for (auto& benchmark : benchmarks) {
+        bool selected = argc < 2;
+        for (int i = 1; i < argc; i++) selected |= benchmark.name == argv[i];
+        if (selected) ok &= Run(benchmark);
+    }
+    return ok ? 0 : 1;
+}
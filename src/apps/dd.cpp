@@ -0,0 +1,105 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#include "src/libc/libc.h"
+
+// Copies blocks between files, like dd:
+//     dd if=<input> of=<output> bs=<block size> count=<blocks> skip=<blocks> seek=<blocks>
+// Input and output default to stdin and stdout, the block size to 512 bytes and count to everything. skip and seek
+// are in blocks of the input and output respectively. Raw devices are in /dev (see devfs.h).
+
+constexpr int kMaxBlockSize = 65536;
+
+static char buffer[kMaxBlockSize];
+
+static bool ParseInt(std::string_view s, int* value) {
+    if (s.empty() || s.size() > 9) return false;
+    int v = 0;
+    for (char c : s) {
+        if (c < '0' || c > '9') return false;
+        v = v * 10 + (c - '0');
+    }
+    *value = v;
+    return true;
+}
+
+static int Usage() {
+    uprint("usage: dd [if=file] [of=file] [bs=n] [count=n] [skip=n] [seek=n]\n");
+    return 2;
+}
+
+extern "C"
+int main(int argc, char* argv[]) {
+    const char* in_path = nullptr;
+    const char* out_path = nullptr;
+    int block_size = 512;
+    int count = -1;
+    int skip = 0;
+    int seek = 0;
+    for (int i = 1; i < argc; i++) {
+        std::string_view arg = argv[i];
+        std::size_t eq = 0;
+        while (eq < arg.size() && arg[eq] != '=') eq++;
+        if (eq == arg.size()) return Usage();
+        auto key = std::string_view(arg.data(), eq);
+        auto value = std::string_view(arg.data() + eq + 1, arg.size() - eq - 1);
+        bool ok = true;
+        if (key == "if") {
+            in_path = argv[i] + eq + 1;
+        } else if (key == "of") {
+            out_path = argv[i] + eq + 1;
+        } else if (key == "bs") {
+            ok = ParseInt(value, &block_size) && block_size > 0 && block_size <= kMaxBlockSize;
+        } else if (key == "count") {
+            ok = ParseInt(value, &count);
+        } else if (key == "skip") {
+            ok = ParseInt(value, &skip);
+        } else if (key == "seek") {
+            ok = ParseInt(value, &seek);
+        } else {
+            ok = false;
+        }
+        if (!ok) return Usage();
+    }
+
+    int in = in_path ? Open(in_path, 0, 0) : 0;
+    if (in < 0) {
+        uprint("dd: can't open {}\n", in_path);
+        return 1;
+    }
+    int out = out_path ? Open(out_path, 0, 0) : 1;
+    if (out < 0) {
+        uprint("dd: can't open {}\n", out_path);
+        return 1;
+    }
+    if ((skip && Seek(in, skip * block_size, 0) < 0) || (seek && Seek(out, seek * block_size, 0) < 0)) {
+        uprint("dd: can't seek\n");
+        return 1;
+    }
+
+    int full = 0, partial = 0;
+    int written = 0;
+    while (count < 0 || full + partial < count) {
+        int n = static_cast<int>(Read(in, buffer, block_size));
+        if (n < 0) {
+            uprint("dd: read error\n");
+            return 1;
+        }
+        if (n == 0) break;
+        if (n == block_size) full++; else partial++;
+        for (int done = 0; done < n; ) {
+            int m = Write(out, buffer + done, n - done);
+            if (m <= 0) {
+                uprint("dd: write error\n");
+                return 1;
+            }
+            done += m;
+            written += m;
+        }
+    }
+    // Like dd, the statistics go to stderr.
+    Writer err(2);
+    print(err, "{}+{} records, {} bytes\n", full, partial, written);
+    return 0;
+}
@@ -0,0 +1,51 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#include "src/libc/libc.h"
+
+// Prints the kernel log:
+//     dmesg [-f] [-r]
+// -f keeps following the log for new messages, -r prints the lines raw, with their level like "<6>".
+
+static char buf[4096];
+static char out[4096];
+
+extern "C"
+int main(int argc, char* argv[]) {
+    bool follow = false;
+    bool raw = false;
+    for (int i = 1; i < argc; i++) {
+        std::string_view arg = argv[i];
+        if (arg == "-f") {
+            follow = true;
+        } else if (arg == "-r") {
+            raw = true;
+        } else {
+            uprint("usage: dmesg [-f] [-r]\n");
+            return 2;
+        }
+    }
+    uint32_t pos = 0;
+    // The level is skipped from a '<' at the start of a line through the '>', reads can end anywhere in between.
+    bool line_start = true;
+    bool in_level = false;
+    while (true) {
+        int n = Klog(buf, sizeof(buf), &pos, follow);
+        if (n < 0) return 1;
+        if (n == 0) break;
+        int size = 0;
+        for (int i = 0; i < n; i++) {
+            char c = buf[i];
+            if (!raw && (in_level || (line_start && c == '<'))) {
+                in_level = c != '>';
+                line_start = false;
+                continue;
+            }
+            line_start = c == '\n';
+            out[size++] = c;
+        }
+        Write(1, out, size);
+    }
+    return 0;
+}
@@ -0,0 +1,71 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#include "src/libc/libc.h"
+
+// Lists directories:
+//     ls [-l] [path...]
+// The path defaults to the root. Directories are shown with a trailing '/', a path that isn't a directory is shown
+// as is. -l shows the type and mode, the size and the mtime in seconds since 1970 before the names.
+
+static bool long_format = false;
+
+static void Show(const char* path, std::string_view name, FileType type) {
+    FileStat stat;
+    if (long_format && Stat(path, &stat) == 0) {
+        char mode[11];
+        mode[0] = type == kDirectory ? 'd' : type == kCharDevice ? 'c' : '-';
+        for (int i = 0; i < 9; i++) mode[1 + i] = stat.mode & (0400 >> i) ? "rwx"[i % 3] : '-';
+        mode[10] = 0;
+        uprint("{} {} {} ", mode, stat.size, stat.mtime);
+    }
+    uprint("{}{}\n", name, type == kDirectory ? "/" : "");
+}
+
+static int List(const char* path, bool header) {
+    FileStat stat;
+    if (Stat(path, &stat) < 0) {
+        uprint("ls: {} doesn't exist\n", path);
+        return 1;
+    }
+    if (stat.type != kDirectory) {
+        Show(path, path, stat.type);
+        return 0;
+    }
+    int fd = Open(path, kOpenReadOnly, 0);
+    if (fd < 0) {
+        uprint("ls: can't open {}\n", path);
+        return 1;
+    }
+    if (header) uprint("{}:\n", path);
+    std::string_view dir = path;
+    while (!dir.empty() && dir.back() == '/') dir.remove_suffix(1);
+    DirEntry entry;
+    int res;
+    while ((res = ReadDir(fd, &entry)) > 0) {
+        char child[256];
+        std::string_view name = entry.name;
+        if (dir.size() + 1 + name.size() >= sizeof(child)) continue;
+        memcpy(child, dir.data(), dir.size());
+        child[dir.size()] = '/';
+        memcpy(child + dir.size() + 1, name.data(), name.size());
+        child[dir.size() + 1 + name.size()] = 0;
+        Show(child, name, entry.type);
+    }
+    Close(fd);
+    return res < 0;
+}
+
+extern "C"
+int main(int argc, char* argv[]) {
+    int first = 1;
+    if (argc > 1 && std::string_view(argv[1]) == "-l") {
+        long_format = true;
+        first = 2;
+    }
+    if (first == argc) return List("/", false);
+    int status = 0;
+    for (int i = first; i < argc; i++) status |= List(argv[i], argc - first > 1);
+    return status;
+}
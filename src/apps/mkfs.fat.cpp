@@ -0,0 +1,73 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#include "src/freestanding/fat.h"
+#include "src/libc/libc.h"
+
+// Formats an image file with an empty FAT filesystem:
+//     mkfs.fat [-n <label>] <image> <size in kb>
+// The image is created if it doesn't exist and zeroed up to the size. Images below about 2mb get FAT12, larger ones
+// FAT16 (see fat.h). The result is mounted with MountImage, /tmp is the place for small images.
+
+static int image;
+
+static bool ParseInt(std::string_view s, int* value) {
+    if (s.empty() || s.size() > 9) return false;
+    int v = 0;
+    for (char c : s) {
+        if (c < '0' || c > '9') return false;
+        v = v * 10 + (c - '0');
+    }
+    *value = v;
+    return true;
+}
+
+static bool WriteImage(uint64_t offset, const void* data, std::size_t len) {
+    return Pwrite(image, data, len, offset) == static_cast<int>(len);
+}
+
+static bool Zero(uint64_t size) {
+    static char zeros[4096];
+    for (uint64_t offset = 0; offset < size; offset += sizeof(zeros)) {
+        auto len = min<uint64_t>(size - offset, sizeof(zeros));
+        if (!WriteImage(offset, zeros, len)) return false;
+    }
+    return true;
+}
+
+extern "C"
+int main(int argc, char* argv[]) {
+    std::string_view label;
+    int arg = 1;
+    if (argc > 2 && std::string_view(argv[1]) == "-n") {
+        label = argv[2];
+        arg = 3;
+    }
+    int kb;
+    if (argc != arg + 2 || !ParseInt(argv[arg + 1], &kb)) {
+        uprint("usage: mkfs.fat [-n label] <image> <size in kb>\n");
+        return 2;
+    }
+    Writer err(2);
+    const char* path = argv[arg];
+    uint64_t size = uint64_t(kb) * 1024;
+    if (size < kFatMinImageSize || label.size() > 11) {
+        print(err, "mkfs.fat: the size must be at least {} kb and the label at most 11 characters\n",
+              int(kFatMinImageSize / 1024));
+        return 1;
+    }
+    image = Open(path, kOpenWriteOnly | kOpenCreate, 0644);
+    if (image < 0) {
+        print(err, "mkfs.fat: can't open {}\n", path);
+        return 1;
+    }
+    bool ok = Zero(size) && FormatFat(size, label, static_cast<uint32_t>(GetTimeNs()), WriteImage);
+    Close(image);
+    if (!ok) {
+        print(err, "mkfs.fat: can't format {}\n", path);
+        return 1;
+    }
+    uprint("mkfs.fat: {} kb FAT filesystem on {}\n", kb, path);
+    return 0;
+}
@@ -0,0 +1,107 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#include "src/libc/libc.h"
+
+// Whole system profile. Samples for a few seconds and prints the hottest addresses as a flat profile. The sampled
+// stacks are written to the serial port in folded format (root;...;leaf count), ready for flamegraph.pl on the host.
+// The port is detached from the console meanwhile (serial/raw), so no console output ends up in between. Addresses
+// are mapped to functions on the host with
+//     addr2line -f -e build/src/arch/x86/kernel.elf <address>...
+// for kernel addresses (>= 0xE0000000) or the elf of the user program otherwise.
+
+constexpr int kMaxSamples = 2048;
+constexpr int kMaxAddresses = 512;
+constexpr int kTop = 20;
+
+struct Bucket {
+    uint32_t address;
+    int count;
+};
+
+struct Stack {
+    const ProfileSample* sample;
+    int count;
+};
+
+static ProfileSample samples[kMaxSamples];
+static Bucket buckets[kMaxAddresses];
+static Stack stacks[kMaxSamples];
+
+static bool SameStack(const ProfileSample& a, const ProfileSample& b) {
+    if (a.depth != b.depth) return false;
+    for (uint32_t i = 0; i < a.depth; i++) {
+        if (a.pcs[i] != b.pcs[i]) return false;
+    }
+    return true;
+}
+
+static void PrintFlat(int n) {
+    int num_buckets = 0;
+    int other = 0;
+    for (int i = 0; i < n; i++) {
+        auto address = samples[i].pcs[0];
+        int j = 0;
+        while (j < num_buckets && buckets[j].address != address) j++;
+        if (j == num_buckets) {
+            if (num_buckets == kMaxAddresses) {
+                other++;
+                continue;
+            }
+            buckets[num_buckets++] = Bucket{address, 0};
+        }
+        buckets[j].count++;
+    }
+
+    uprint("{} samples\n", n);
+    for (int k = 0; k < kTop && k < num_buckets; k++) {
+        int best = k;
+        for (int j = k + 1; j < num_buckets; j++) {
+            if (buckets[j].count > buckets[best].count) best = j;
+        }
+        swap(buckets[k], buckets[best]);
+        uprint("{} {} {}%\n", Hex(buckets[k].address), buckets[k].count, buckets[k].count * 100 / n);
+    }
+    if (other) uprint("{} samples not aggregated\n", other);
+}
+
+static void PrintFolded(OutputStream& out, int n) {
+    int num_stacks = 0;
+    for (int i = 0; i < n; i++) {
+        int j = 0;
+        while (j < num_stacks && !SameStack(*stacks[j].sample, samples[i])) j++;
+        if (j == num_stacks) stacks[num_stacks++] = Stack{&samples[i], 0};
+        stacks[j].count++;
+    }
+    for (int j = 0; j < num_stacks; j++) {
+        auto& sample = *stacks[j].sample;
+        for (int d = sample.depth - 1; d >= 0; d--) {
+            print(out, d ? "{};" : "{}", Hex(sample.pcs[d]));
+        }
+        print(out, " {}\n", stacks[j].count);
+    }
+}
+
+extern "C"
+int main(int argc, char* argv[]) {
+    (void)argc; (void)argv;
+    constexpr uint64_t kDurationNs = 5'000'000'000;
+    ProfileStart();
+    NanoSleep(kDurationNs);
+    ProfileStop();
+    int n = ProfileFetch(samples, kMaxSamples);
+    PrintFlat(n);
+    int port = Open("/dev/ttyS0", kOpenWriteOnly, 0);
+    int raw = port < 0 ? -1 : SetSysctl("serial/raw", 1);
+    if (raw < 0) {
+        Writer err(2);
+        print(err, "profile: no serial port for the stacks, or not privileged to make it raw\n");
+        Exit(1);
+    }
+    Writer out(port);
+    PrintFolded(out, n);
+    SetSysctl("serial/raw", raw);
+    Close(port);
+    Exit(0);
+}
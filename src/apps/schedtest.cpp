@@ -0,0 +1,43 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#include "src/libc/libc.h"
+
+// Checks that the starvation detector of the scheduler fires:
+//     schedtest
+// With a time slice longer than the starvation limit, a busy child keeps the CPU while its low priority parent is
+// ready, so the parent starves. Exits with 0 if sched/starvations counted it, 1 otherwise. The tunables it changes
+// are restored before exiting.
+
+constexpr int kTickFrequency = 1000;
+constexpr int kStarvationTicks = 500;  // the limit in the scheduler
+constexpr int kTimeSlice = 1000;
+constexpr int kSpinTicks = kStarvationTicks + 100;
+constexpr int kMinPriority = -20;
+
+static bool Starve() {
+    if (SetSysctl("sched/starvations", 0) < 0 || SetPriority(GetTid(), kMinPriority) == -1) return false;
+    int child = Fork();
+    if (child == 0) {
+        auto end = GetTimeNs() + uint64_t(kSpinTicks) * 1000000000 / kTickFrequency;
+        while (GetTimeNs() < end) {}
+        Exit(0);
+    }
+    if (child < 0) return false;
+    // Let the child take the CPU, we're ready again but only get it back when its slice ends or it exits.
+    Yield();
+    int status;
+    return Wait(child, &status) == child && GetSysctl("sched/starvations") > 0;
+}
+
+extern "C"
+int main() {
+    int frequency = SetSysctl("sched/tick_frequency", kTickFrequency);
+    int slice = SetSysctl("sched/time_slice", kTimeSlice);
+    bool ok = frequency > 0 && slice > 0 && Starve();
+    if (slice > 0) SetSysctl("sched/time_slice", slice);
+    if (frequency > 0) SetSysctl("sched/tick_frequency", frequency);
+    uprint("schedtest: {}\n", ok ? "starvation detected" : "FAILED, no starvation detected");
+    return ok ? 0 : 1;
+}
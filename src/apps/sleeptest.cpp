@@ -0,0 +1,44 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#include "src/libc/libc.h"
+
+// Checks that sleeps keep their deadline when the tick frequency changes while sleeping:
+//     sleeptest
+// A child changes sched/tick_frequency while the parent sleeps, once to a lower and once to a higher frequency. The
+// parent must wake after its deadline and within a tick of the slowest frequency of it. Exits with 0 if both sleeps
+// were on time, 1 otherwise. The frequency is restored before exiting.
+
+constexpr int kLowFrequency = 19;
+constexpr int kHighFrequency = 1000;
+constexpr uint64_t kSleepNs = 1'000'000'000;
+constexpr uint64_t kChangeAfterNs = 100'000'000;
+constexpr uint64_t kSlackNs = 2'000'000'000 / kLowFrequency;
+
+static bool SleepAcrossChange(int from, int to) {
+    if (SetSysctl("sched/tick_frequency", from) < 0) return false;
+    int child = Fork();
+    if (child == 0) {
+        NanoSleep(kChangeAfterNs);
+        Exit(SetSysctl("sched/tick_frequency", to) < 0 ? 1 : 0);
+    }
+    if (child < 0) return false;
+    auto start = GetTimeNs();
+    NanoSleep(kSleepNs);
+    auto slept = GetTimeNs() - start;
+    int status;
+    bool ok = Wait(child, &status) == child && status == 0 && slept >= kSleepNs && slept < kSleepNs + kSlackNs;
+    uprint("sleeptest: {} Hz to {} Hz, slept {} us\n", from, to, uint32_t(slept / 1000));
+    return ok;
+}
+
+extern "C"
+int main() {
+    int frequency = GetSysctl("sched/tick_frequency");
+    bool ok = frequency > 0 && SleepAcrossChange(kHighFrequency, kLowFrequency) &&
+              SleepAcrossChange(kLowFrequency, kHighFrequency);
+    if (frequency > 0) SetSysctl("sched/tick_frequency", frequency);
+    uprint("sleeptest: {}\n", ok ? "passed" : "FAILED");
+    return ok ? 0 : 1;
+}
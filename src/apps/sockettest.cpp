@@ -0,0 +1,66 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#include "src/libc/libc.h"
+
+// Checks Unix domain stream sockets:
+//     sockettest
+// A child connects to a socket the parent listens on, they exchange a message each way and the parent sees the end
+// of the stream once the child closes its end. Along the way it checks that names can't be bound twice, that
+// connecting to a missing name fails and that the name goes away with the socket. Exits with 0 on success, 1 otherwise.
+
+constexpr char kPath[] = "/tmp/sockettest";
+
+static bool ReadMessage(int fd, std::string_view expected) {
+    char buf[16];
+    std::size_t n = 0;
+    while (n < expected.size()) {
+        int res = Read(fd, buf + n, expected.size() - n);
+        if (res <= 0) return false;
+        n += res;
+    }
+    return std::string_view(buf, n) == expected;
+}
+
+static int Client() {
+    int fd = Socket();
+    if (fd < 0 || Connect(fd, kPath) < 0) return 1;
+    if (Write(fd, "ping", 4) != 4 || !ReadMessage(fd, "pong")) return 1;
+    Close(fd);
+    return 0;
+}
+
+static bool Server(int listener) {
+    int child = Fork();
+    if (child == 0) Exit(Client());
+    if (child < 0) return false;
+    int fd = Accept(listener);
+    bool ok = fd >= 0 && ReadMessage(fd, "ping") && Write(fd, "pong", 4) == 4;
+    char c;
+    ok = ok && Read(fd, &c, 1) == 0;  // the client closed its end
+    int status;
+    ok = Wait(child, &status) == child && status == 0 && ok;
+    Close(fd);
+    return ok;
+}
+
+static bool Check() {
+    int unbound = Socket();
+    if (unbound < 0 || Connect(unbound, kPath) != -1) return false;  // nothing listens yet
+    int listener = Socket();
+    if (listener < 0 || Bind(listener, kPath) < 0 || Listen(listener) < 0) return false;
+    if (Bind(unbound, kPath) != -1) return false;  // the name is taken
+    bool ok = Server(listener);
+    Close(listener);
+    ok = ok && Connect(unbound, kPath) == -1;  // the name went away
+    Close(unbound);
+    return ok;
+}
+
+extern "C"
+int main() {
+    bool ok = Check();
+    uprint("sockettest: {}\n", ok ? "passed" : "FAILED");
+    return ok ? 0 : 1;
+}
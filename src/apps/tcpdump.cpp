@@ -0,0 +1,52 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#include "src/libc/libc.h"
+
+// Prints the packets sent and received on all interfaces. Interfaces carry IPv4 packets without link layer header
+// (the only interface so far is loopback), so decoding starts at the IP header.
+
+static uint16_t Load16(const uint8_t* p) {
+    return (p[0] << 8) | p[1];
+}
+
+static void PrintAddress(const uint8_t* p) {
+    uprint("{}.{}.{}.{}", p[0], p[1], p[2], p[3]);
+}
+
+static void PrintPacket(const CaptureHeader& header, const uint8_t* data, int captured) {
+    uint32_t ms = header.time_ns / 1000000;
+    uprint("{}ms if{} {} {} bytes", ms, header.iface,
+           header.direction ? "out" : "in ", header.size);
+    if (captured < 20 || (data[0] >> 4) != 4) {
+        uprint(" (not IPv4)\n");
+        return;
+    }
+    int ihl = (data[0] & 0xF) * 4;
+    int protocol = data[9];
+    uprint(" ");
+    PrintAddress(data + 12);
+    uprint(" > ");
+    PrintAddress(data + 16);
+    constexpr int kTcp = 6;
+    constexpr int kUdp = 17;
+    if ((protocol == kTcp || protocol == kUdp) && captured >= ihl + 4) {
+        uprint(" {} {} > {}\n", protocol == kTcp ? "tcp" : "udp", Load16(data + ihl), Load16(data + ihl + 2));
+    } else {
+        uprint(" proto {}\n", protocol);
+    }
+}
+
+extern "C"
+int main(int argc, char* argv[]) {
+    (void)argc; (void)argv;
+    alignas(8) uint8_t buf[sizeof(CaptureHeader) + 256];
+    while (true) {
+        int n = NetCapture(buf, sizeof(buf), -1);
+        if (n < int(sizeof(CaptureHeader))) continue;
+        CaptureHeader header;
+        memcpy(&header, buf, sizeof(header));
+        PrintPacket(header, buf + sizeof(header), n - sizeof(header));
+    }
+}
@@ -0,0 +1,201 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#include "src/libc/libc.h"
+
+// File exchange over a null-modem cable on the first serial port:
+//     xmodem send <file>
+//     xmodem recv <file>
+// XMODEM with 128 byte blocks. The sender follows the receiver in using a CRC-16 or the original checksum, the
+// receiver asks for CRC-16 and falls back to the checksum if the sender doesn't respond to that. The last block is
+// padded with ^Z, which the receiver strips. The port is detached from the console for the transfer (serial/raw) and
+// given back when done. recv doesn't overwrite existing files.
+
+constexpr char kSoh = 0x01;
+constexpr char kEot = 0x04;
+constexpr char kAck = 0x06;
+constexpr char kNak = 0x15;
+constexpr char kCan = 0x18;
+constexpr char kCrcRequest = 'C';
+constexpr char kPad = 0x1A;
+
+constexpr int kBlockSize = 128;
+constexpr int kMaxRetries = 10;
+constexpr int kTimeoutMs = 10000;
+constexpr int kStartTimeoutMs = 3000;  // per request to start, the receiver keeps asking
+constexpr int kCrcRequests = 3;  // before falling back to the checksum
+constexpr uint64_t kPollNs = 10000000;
+
+static int port;
+
+// The next byte from the port, or -1 if none arrived within timeout_ms. The port doesn't wait for data, so it's
+// polled. The kernel buffers what arrives in between.
+static int ReadByte(int timeout_ms) {
+    auto deadline = GetTimeNs() + uint64_t(timeout_ms) * 1000000;
+    while (true) {
+        uint8_t c;
+        if (Read(port, &c, 1) == 1) return c;
+        if (GetTimeNs() >= deadline) return -1;
+        NanoSleep(kPollNs);
+    }
+}
+
+static void WriteByte(char c) {
+    Write(port, &c, 1);
+}
+
+// Drops whatever the other side is still sending, for resynchronizing after an error.
+static void Purge() {
+    while (ReadByte(1000) >= 0) {}
+}
+
+static uint16_t Crc16(const uint8_t* data, int size) {
+    uint16_t crc = 0;
+    for (int i = 0; i < size; i++) {
+        crc ^= data[i] << 8;
+        for (int bit = 0; bit < 8; bit++) crc = crc & 0x8000 ? (crc << 1) ^ 0x1021 : crc << 1;
+    }
+    return crc;
+}
+
+static uint8_t Checksum(const uint8_t* data, int size) {
+    uint8_t sum = 0;
+    for (int i = 0; i < size; i++) sum += data[i];
+    return sum;
+}
+
+static bool Send(int fd) {
+    // The receiver starts with a NAK for the checksum or a 'C' for CRC-16.
+    int c;
+    int tries = 0;
+    while ((c = ReadByte(kTimeoutMs)) != kNak && c != kCrcRequest) {
+        if (c == kCan || c < 0 || ++tries == kMaxRetries) return false;
+    }
+    bool crc = c == kCrcRequest;
+    uint8_t block[3 + kBlockSize + 2];
+    for (uint8_t number = 1; ; number++) {
+        auto n = static_cast<int>(Read(fd, block + 3, kBlockSize));
+        if (n < 0) return false;
+        if (n == 0) break;
+        for (int i = n; i < kBlockSize; i++) block[3 + i] = kPad;
+        block[0] = kSoh;
+        block[1] = number;
+        block[2] = ~number;
+        int size = 3 + kBlockSize;
+        if (crc) {
+            auto sum = Crc16(block + 3, kBlockSize);
+            block[size++] = sum >> 8;
+            block[size++] = sum;
+        } else {
+            block[size++] = Checksum(block + 3, kBlockSize);
+        }
+        for (tries = 0; ; tries++) {
+            if (tries == kMaxRetries) return false;
+            Write(port, block, size);
+            c = ReadByte(kTimeoutMs);
+            if (c == kAck) break;
+            if (c == kCan) return false;
+        }
+    }
+    for (tries = 0; tries < kMaxRetries; tries++) {
+        WriteByte(kEot);
+        if (ReadByte(kTimeoutMs) == kAck) return true;
+    }
+    return false;
+}
+
+// Blocks are written one behind, so the padding of the last one can be stripped once the EOT says it was the last.
+static bool Receive(int fd) {
+    uint8_t block[2 + kBlockSize + 2];
+    uint8_t last[kBlockSize];
+    bool have_last = false;
+    bool crc = true;
+    uint8_t expected = 1;
+    int c = -1;
+    for (int tries = 0; c != kSoh && c != kEot; tries++) {
+        if (tries == kMaxRetries) return false;
+        if (tries == kCrcRequests) crc = false;
+        WriteByte(crc ? kCrcRequest : kNak);
+        c = ReadByte(kStartTimeoutMs);
+    }
+    for (int errors = 0; errors < kMaxRetries; ) {
+        if (c == kEot) {
+            WriteByte(kAck);
+            int size = kBlockSize;
+            while (have_last && size > 0 && last[size - 1] == kPad) size--;
+            return !have_last || Write(fd, last, size) == size;
+        }
+        if (c == kCan) return false;
+        bool ok = c == kSoh;
+        int size = 2 + kBlockSize + (crc ? 2 : 1);
+        for (int i = 0; ok && i < size; i++) {
+            int b = ReadByte(1000);
+            ok = b >= 0;
+            block[i] = b;
+        }
+        uint8_t* data = block + 2;
+        if (ok && crc) {
+            ok = Crc16(data, kBlockSize) == (block[2 + kBlockSize] << 8 | block[3 + kBlockSize]);
+        } else if (ok) {
+            ok = Checksum(data, kBlockSize) == block[2 + kBlockSize];
+        }
+        ok = ok && uint8_t(block[0] + block[1]) == 0xFF;
+        if (ok && block[0] == uint8_t(expected - 1)) {
+            // Our ACK got lost, the sender repeats the previous block.
+            WriteByte(kAck);
+        } else if (ok && block[0] == expected) {
+            if (have_last && Write(fd, last, kBlockSize) != kBlockSize) {
+                WriteByte(kCan);
+                return false;
+            }
+            memcpy(last, data, kBlockSize);
+            have_last = true;
+            expected++;
+            errors = 0;
+            WriteByte(kAck);
+        } else {
+            errors++;
+            Purge();
+            WriteByte(kNak);
+        }
+        c = ReadByte(kTimeoutMs);
+    }
+    WriteByte(kCan);
+    return false;
+}
+
+extern "C"
+int main(int argc, char* argv[]) {
+    std::string_view mode = argc == 3 ? argv[1] : "";
+    if (mode != "send" && mode != "recv") {
+        uprint("usage: xmodem send|recv <file>\n");
+        return 2;
+    }
+    Writer err(2);
+    int fd;
+    if (mode == "send") {
+        fd = Open(argv[2], kOpenReadOnly, 0);
+    } else if ((fd = Open(argv[2], kOpenReadOnly, 0)) >= 0) {
+        Close(fd);
+        print(err, "xmodem: {} exists\n", argv[2]);
+        return 1;
+    } else {
+        fd = Open(argv[2], kOpenWriteOnly | kOpenCreate, 0644);
+    }
+    if (fd < 0) {
+        print(err, "xmodem: can't open {}\n", argv[2]);
+        return 1;
+    }
+    port = Open("/dev/ttyS0", kOpenReadWrite, 0);
+    if (port < 0 || SetSysctl("serial/raw", 1) < 0) {
+        print(err, "xmodem: no serial port, or not privileged to make it raw\n");
+        return 1;
+    }
+    bool ok = mode == "send" ? Send(fd) : Receive(fd);
+    SetSysctl("serial/raw", 0);
+    Close(fd);
+    Close(port);
+    if (!ok) print(err, "xmodem: transfer failed\n");
+    return ok ? 0 : 1;
+}
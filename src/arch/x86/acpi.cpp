@@ -0,0 +1,169 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#include "acpi.h"
+
+#include <string_view>
+
+#include "kassert.h"
+#include "paging.h"
+#include "src/freestanding/utils.h"
+
+struct [[gnu::packed]] AcpiHeader {
+    char signature[4];
+    uint32_t length;
+    uint8_t revision;
+    uint8_t checksum;
+    char oem[6];
+    char oem_table[8];
+    uint32_t oem_revision;
+    uint32_t creator;
+    uint32_t creator_revision;
+};
+
+struct [[gnu::packed]] Rsdp {
+    char signature[8];
+    uint8_t checksum;
+    char oem[6];
+    uint8_t revision;
+    uint32_t rsdt;
+    // From revision 2 on.
+    uint32_t length;
+    uint64_t xsdt;
+    uint8_t extended_checksum;
+    uint8_t reserved[3];
+};
+
+// MADT entry types.
+constexpr uint8_t kMadtCpu = 0;
+constexpr uint8_t kMadtIoapic = 1;
+constexpr uint8_t kMadtOverride = 2;
+
+constexpr uint32_t kMadtCpuEnabled = 1;
+
+static constinit AcpiInfo acpi{};
+
+// Tables are copied here to parse them, they're only read at boot.
+static char table[kPageSize];
+
+const AcpiInfo& Acpi() {
+    return acpi;
+}
+
+static bool ValidChecksum(const void* data, std::size_t size) {
+    uint8_t sum = 0;
+    for (std::size_t i = 0; i < size; i++) sum += static_cast<const uint8_t*>(data)[i];
+    return sum == 0;
+}
+
+// The RSDP lies on a 16 byte boundary in the first kb of the EBDA or in the BIOS area, both in the low 1mb.
+static const Rsdp* FindRsdp() {
+    auto low_mem = reinterpret_cast<const char*>(kLowMemBase);
+    uintptr_t ebda = *reinterpret_cast<const uint16_t*>(low_mem + 0x40E) << 4;
+    uintptr_t ranges[2][2] = {{ebda, ebda + 1024}, {0xE0000, 0x100000}};
+    // The revision 1 part is checksummed on its own.
+    constexpr std::size_t kRsdpV1Size = 20;
+    for (auto& range : ranges) {
+        if (range[0] == 0) continue;
+        for (auto p = range[0]; p + sizeof(Rsdp) <= range[1]; p += 16) {
+            auto rsdp = reinterpret_cast<const Rsdp*>(low_mem + p);
+            if (std::string_view(rsdp->signature, 8) == "RSD PTR " && ValidChecksum(rsdp, kRsdpV1Size)) return rsdp;
+        }
+    }
+    return nullptr;
+}
+
+// Copies the table at phys to the table buffer if it's intact. Returns its size or 0.
+static uint32_t ReadTable(uint32_t phys) {
+    AcpiHeader header;
+    CopyFromPhys(&header, phys, sizeof(header));
+    if (header.length < sizeof(header) || header.length > sizeof(table)) return 0;
+    CopyFromPhys(table, phys, header.length);
+    return ValidChecksum(table, header.length) ? header.length : 0;
+}
+
+template <typename T>
+static T Field(uint32_t offset) {
+    T value;
+    memcpy(&value, table + offset, sizeof(T));
+    return value;
+}
+
+static void ParseMadt(uint32_t length) {
+    acpi.has_madt = true;
+    acpi.lapic_address = Field<uint32_t>(sizeof(AcpiHeader));
+    acpi.madt_flags = Field<uint32_t>(sizeof(AcpiHeader) + 4);
+    for (auto p = sizeof(AcpiHeader) + 8; p + 2 <= length; ) {
+        auto type = uint8_t(table[p]);
+        auto size = uint8_t(table[p + 1]);
+        if (size < 2 || p + size > length) break;
+        if (type == kMadtCpu && size >= 8 && acpi.num_cpus < kMaxAcpiCpus) {
+            if (Field<uint32_t>(p + 4) & kMadtCpuEnabled) {
+                acpi.cpus[acpi.num_cpus++] = AcpiCpu{uint8_t(table[p + 2]), uint8_t(table[p + 3])};
+            }
+        } else if (type == kMadtIoapic && size >= 12 && acpi.num_ioapics < kMaxAcpiIoapics) {
+            acpi.ioapics[acpi.num_ioapics++] = AcpiIoapic{uint8_t(table[p + 2]), Field<uint32_t>(p + 4),
+                                                          Field<uint32_t>(p + 8)};
+        } else if (type == kMadtOverride && size >= 10 && acpi.num_overrides < kMaxAcpiOverrides) {
+            acpi.overrides[acpi.num_overrides++] = AcpiIrqOverride{uint8_t(table[p + 3]), Field<uint32_t>(p + 4),
+                                                                   Field<uint16_t>(p + 8)};
+        }
+        p += size;
+    }
+}
+
+static void ParseFadt(uint32_t length) {
+    // The fields up to the PM timer block are in every revision.
+    if (length < 80) return;
+    acpi.has_fadt = true;
+    acpi.dsdt = Field<uint32_t>(40);
+    acpi.sci_irq = Field<uint16_t>(46);
+    acpi.smi_command = Field<uint32_t>(48);
+    acpi.acpi_enable = table[52];
+    acpi.acpi_disable = table[53];
+    acpi.pm1a_event = Field<uint32_t>(56);
+    acpi.pm1b_event = Field<uint32_t>(60);
+    acpi.pm1a_control = Field<uint32_t>(64);
+    acpi.pm1b_control = Field<uint32_t>(68);
+    acpi.pm_timer = Field<uint32_t>(76);
+}
+
+static void ParseHpet(uint32_t length) {
+    // The base address is a generic address structure, whose 64 bit address follows 4 bytes of address space id,
+    // width and offset.
+    if (length < 56) return;
+    auto address = Field<uint64_t>(44);
+    if (address >> 32) return;
+    acpi.has_hpet = true;
+    acpi.hpet_address = address;
+}
+
+void InitAcpi() {
+    auto rsdp = FindRsdp();
+    if (!rsdp) return;
+    acpi.present = true;
+    // The XSDT has 64 bit pointers, it replaces the RSDT from revision 2 on.
+    bool xsdt = rsdp->revision >= 2 && ValidChecksum(rsdp, sizeof(Rsdp)) && rsdp->xsdt != 0 && (rsdp->xsdt >> 32) == 0;
+    uint32_t root = xsdt ? uint32_t(rsdp->xsdt) : rsdp->rsdt;
+    std::size_t entry_size = xsdt ? 8 : 4;
+    AcpiHeader header;
+    CopyFromPhys(&header, root, sizeof(header));
+    if (std::string_view(header.signature, 4) != (xsdt ? "XSDT" : "RSDT")) return;
+    for (auto p = root + sizeof(header); p + entry_size <= root + header.length; p += entry_size) {
+        uint64_t phys = 0;
+        CopyFromPhys(&phys, p, entry_size);
+        if (phys >> 32) continue;
+        auto length = ReadTable(phys);
+        if (length == 0) continue;
+        auto signature = std::string_view(table, 4);
+        if (signature == "APIC") {
+            ParseMadt(length);
+        } else if (signature == "FACP") {
+            ParseFadt(length);
+        } else if (signature == "HPET") {
+            ParseHpet(length);
+        }
+    }
+    kprint("ACPI: {} cpus, {} I/O APICs{}\n", acpi.num_cpus, acpi.num_ioapics, acpi.has_hpet ? ", HPET" : "");
+}
@@ -0,0 +1,70 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#ifndef OS_ACPI_H
+#define OS_ACPI_H
+
+#include <cstdint>
+
+constexpr int kMaxAcpiCpus = 16;
+constexpr int kMaxAcpiIoapics = 4;
+constexpr int kMaxAcpiOverrides = 16;
+
+struct AcpiCpu {
+    uint8_t processor_id;
+    uint8_t apic_id;
+};
+
+struct AcpiIoapic {
+    uint8_t id;
+    uint32_t address;
+    uint32_t gsi_base;  // the global system interrupt of its first pin
+};
+
+// An ISA IRQ that isn't connected to the pin of the same number, or not active high and edge triggered.
+struct AcpiIrqOverride {
+    uint8_t irq;
+    uint32_t gsi;
+    uint16_t flags;  // two bit fields for the polarity and the trigger mode, 0 means the bus default
+};
+
+// The parts of the firmware tables the kernel uses, copied out at boot. Tables that aren't there are all zero.
+// Physical addresses above 4gb are dropped, they can't be reached without PAE.
+struct AcpiInfo {
+    bool present;  // there is an RSDP
+
+    // MADT, the interrupt controllers. Only enabled cpus are listed.
+    bool has_madt;
+    uint32_t lapic_address;
+    uint32_t madt_flags;
+    int num_cpus;
+    AcpiCpu cpus[kMaxAcpiCpus];
+    int num_ioapics;
+    AcpiIoapic ioapics[kMaxAcpiIoapics];
+    int num_overrides;
+    AcpiIrqOverride overrides[kMaxAcpiOverrides];
+
+    // FADT, the power management ports. A port of 0 means the block doesn't exist.
+    bool has_fadt;
+    uint16_t sci_irq;
+    uint32_t smi_command;
+    uint8_t acpi_enable;  // written to the SMI command port to switch from legacy to ACPI mode
+    uint8_t acpi_disable;
+    uint32_t pm1a_event, pm1b_event;
+    uint32_t pm1a_control, pm1b_control;
+    uint32_t pm_timer;
+    uint32_t dsdt;  // physical address, in memory that's reclaimed after InitAcpi
+
+    // HPET, the high precision event timer.
+    bool has_hpet;
+    uint32_t hpet_address;
+};
+
+constexpr uint32_t kMadtPcAtCompat = 1;  // flag, the board has the 8259 PICs too
+
+// Finds the RSDP in the low 1mb and parses the tables of the RSDT, or the XSDT when the firmware has one.
+void InitAcpi();
+const AcpiInfo& Acpi();
+
+#endif //OS_ACPI_H
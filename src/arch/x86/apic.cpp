@@ -0,0 +1,233 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#include "apic.h"
+
+#include <cstdint>
+
+#include "acpi.h"
+#include "kassert.h"
+#include "paging.h"
+#include "src/freestanding/utils.h"
+#include "x86_inst.h"
+
+// Local APIC registers, as offsets in its MMIO page.
+constexpr uint32_t kLapicId = 0x20;
+constexpr uint32_t kLapicTpr = 0x80;
+constexpr uint32_t kLapicEoi = 0xB0;
+constexpr uint32_t kLapicSvr = 0xF0;
+constexpr uint32_t kLapicIsr = 0x100;
+constexpr uint32_t kLapicLvtTimer = 0x320;
+constexpr uint32_t kLapicLvtLint0 = 0x350;
+constexpr uint32_t kLapicLvtError = 0x370;
+constexpr uint32_t kLapicTimerInitial = 0x380;
+constexpr uint32_t kLapicTimerCurrent = 0x390;
+constexpr uint32_t kLapicTimerDivide = 0x3E0;
+
+constexpr uint32_t kLvtMasked = 1 << 16;
+constexpr uint32_t kLvtPeriodic = 1 << 17;
+constexpr uint32_t kSvrEnable = 1 << 8;
+// Before the P4 the low 4 bits of the spurious vector are hardwired to 1. Vector 47 is also IRQ 15, which is told
+// apart by its bit in the in service register, just like a spurious IRQ 15 of the PIC.
+constexpr uint32_t kSpuriousVector = 47;
+
+// I/O APIC registers, accessed indirectly through a select and a window register.
+constexpr uint32_t kIoapicVersion = 1;
+constexpr uint32_t kIoapicRedirection = 0x10;  // two registers per pin
+
+constexpr uint64_t kRedirectionLowActive = 1 << 13;
+constexpr uint64_t kRedirectionLevel = 1 << 15;
+constexpr uint64_t kRedirectionMasked = 1 << 16;
+
+constexpr uint32_t kApicBaseMsr = 0x1B;
+constexpr uint64_t kApicBaseEnable = 1 << 11;
+
+class Apic : public InterruptController {
+public:
+    // When the local APIC timer is the tick it's IRQ 0, the PIT pin stays masked.
+    void Mask(int irq) override {
+        if (irq == 0 && timer_tick) {
+            WriteLapic(kLapicLvtTimer, ReadLapic(kLapicLvtTimer) | kLvtMasked);
+        } else {
+            WriteRedirection(irq, ReadRedirection(irq) | kRedirectionMasked);
+        }
+    }
+
+    void Unmask(int irq) override {
+        if (irq == 0 && timer_tick) {
+            WriteLapic(kLapicLvtTimer, ReadLapic(kLapicLvtTimer) & ~kLvtMasked);
+        } else {
+            WriteRedirection(irq, ReadRedirection(irq) & ~kRedirectionMasked);
+        }
+    }
+
+    bool IsMasked(int irq) override {
+        if (irq == 0 && timer_tick) return ReadLapic(kLapicLvtTimer) & kLvtMasked;
+        return ReadRedirection(irq) & kRedirectionMasked;
+    }
+
+    bool IsSpurious(int irq) override {
+        int vector = 32 + irq;
+        return vector == kSpuriousVector && (ReadLapic(kLapicIsr + vector / 32 * 0x10) & (1 << (vector % 32))) == 0;
+    }
+
+    void EndOfInterrupt(int) override {
+        WriteLapic(kLapicEoi, 0);
+    }
+
+    volatile uint32_t* lapic = nullptr;
+    volatile uint32_t* ioapic = nullptr;
+    bool timer_tick = false;
+    int num_pins = 0;
+    int pins[16] = {};  // the I/O APIC pin of each ISA IRQ, -1 if it isn't connected
+    uint64_t flags[16] = {};  // polarity and trigger mode of each ISA IRQ
+
+    uint32_t ReadLapic(uint32_t reg) {
+        return lapic[reg / 4];
+    }
+
+    void WriteLapic(uint32_t reg, uint32_t value) {
+        lapic[reg / 4] = value;
+    }
+
+    uint32_t ReadIoapic(uint32_t reg) {
+        ioapic[0] = reg;
+        return ioapic[4];
+    }
+
+    void WriteIoapic(uint32_t reg, uint32_t value) {
+        ioapic[0] = reg;
+        ioapic[4] = value;
+    }
+
+    // An unconnected IRQ reads as masked and ignores writes.
+    uint64_t ReadRedirection(int irq) {
+        if (pins[irq] < 0) return kRedirectionMasked;
+        uint32_t reg = kIoapicRedirection + 2 * pins[irq];
+        return ReadIoapic(reg) | uint64_t(ReadIoapic(reg + 1)) << 32;
+    }
+
+    void WriteRedirection(int irq, uint64_t entry) {
+        if (pins[irq] < 0) return;
+        uint32_t reg = kIoapicRedirection + 2 * pins[irq];
+        WriteIoapic(reg, entry);
+        WriteIoapic(reg + 1, entry >> 32);
+    }
+};
+
+static constinit Apic apic;
+
+class ApicTimer : public TickTimer {
+public:
+    uint32_t Start(int hz) override {
+        initial_count = max<uint32_t>(counts_per_second / hz, 1);
+        // Keep the mask, the timer is unmasked when the tick handler is registered.
+        auto masked = apic.ReadLapic(kLapicLvtTimer) & kLvtMasked;
+        apic.WriteLapic(kLapicLvtTimer, masked | kLvtPeriodic | 32);
+        apic.WriteLapic(kLapicTimerInitial, initial_count);
+        return (uint64_t(initial_count) * count_ns) >> 16;
+    }
+
+    uint32_t ElapsedNs() override {
+        uint32_t elapsed = initial_count - apic.ReadLapic(kLapicTimerCurrent);
+        if (elapsed >= initial_count) elapsed = 0;
+        return (uint64_t(elapsed) * count_ns) >> 16;
+    }
+
+    uint32_t counts_per_second = 0;
+    uint32_t count_ns = 0;  // duration of a count in ns as a 16.16 fixed point number
+    uint32_t initial_count = 0;
+};
+
+static constinit ApicTimer apic_timer;
+
+InterruptController* InitApic() {
+    if (!HasCpuid()) return nullptr;
+    uint32_t regs[4];
+    X86_cpuid(1, regs);
+    constexpr uint32_t kCpuidApic = 1 << 9;
+    // The APIC base MSR and with it the global enable exist from the P6 on.
+    auto& acpi = Acpi();
+    if (!(regs[3] & kCpuidApic) || !(X86_rdmsr(kApicBaseMsr) & kApicBaseEnable) || !acpi.has_madt) return nullptr;
+
+    // Only the I/O APIC with the ISA IRQs is used, boards with more route PCI interrupts through the others.
+    uint32_t ioapic_address = 0;
+    for (int i = 0; i < acpi.num_ioapics; i++) {
+        if (acpi.ioapics[i].gsi_base == 0) ioapic_address = acpi.ioapics[i].address;
+    }
+    for (int irq = 0; irq < 16; irq++) {
+        apic.pins[irq] = irq;
+        apic.flags[irq] = 0;  // ISA IRQs are edge triggered and active high
+    }
+    for (int i = 0; i < acpi.num_overrides; i++) {
+        auto& o = acpi.overrides[i];
+        if (o.irq >= 16) continue;
+        apic.pins[o.irq] = o.gsi;
+        apic.flags[o.irq] = ((o.flags & 3) == 3 ? kRedirectionLowActive : 0) |
+                            (((o.flags >> 2) & 3) == 3 ? kRedirectionLevel : 0);
+    }
+    if (ioapic_address == 0) return nullptr;
+
+    apic.lapic = static_cast<volatile uint32_t*>(MapKernelPhys(acpi.lapic_address));
+    apic.ioapic = static_cast<volatile uint32_t*>(MapKernelPhys(ioapic_address));
+    if (!apic.lapic || !apic.ioapic) return nullptr;
+    apic.num_pins = ((apic.ReadIoapic(kIoapicVersion) >> 16) & 0xFF) + 1;
+
+    // Accept all priorities, mask the local interrupts and enable the APIC with its spurious vector. The timer is
+    // left masked until it's made the tick.
+    apic.WriteLapic(kLapicTpr, 0);
+    apic.WriteLapic(kLapicTimerDivide, 0xB);  // divide by 1
+    apic.WriteLapic(kLapicLvtTimer, kLvtMasked | 32);
+    apic.WriteLapic(kLapicLvtLint0, kLvtMasked);
+    apic.WriteLapic(kLapicLvtError, kLvtMasked);
+    apic.WriteLapic(kLapicSvr, kSvrEnable | kSpuriousVector);
+
+    // An IRQ whose pin is taken by an override isn't connected, typically IRQ 2 which is the cascade of the PICs
+    // and whose pin the timer gets.
+    for (int irq = 0; irq < 16; irq++) {
+        for (int other = 0; other < 16; other++) {
+            if (other != irq && apic.pins[other] == irq && apic.pins[irq] == irq) apic.pins[irq] = -1;
+        }
+        if (apic.pins[irq] >= apic.num_pins) apic.pins[irq] = -1;
+    }
+    // All IRQs go to this cpu in physical destination mode, masked until a handler is registered.
+    uint64_t destination = uint64_t(apic.ReadLapic(kLapicId) >> 24) << 56;
+    for (int irq = 0; irq < 16; irq++) {
+        apic.WriteRedirection(irq, destination | apic.flags[irq] | kRedirectionMasked | (32 + irq));
+    }
+
+    kprint("Using the APIC for IRQs\n");
+    return &apic;
+}
+
+TickTimer* InitApicTimer() {
+    // Count down from the maximum while channel 2 of the PIT, which is gated by the speaker port and not connected to
+    // an IRQ, counts down 50 ms in interrupt on terminal count mode. Its output goes high at the end.
+    constexpr uint16_t kPitChannel2 = 0x42;
+    constexpr uint16_t kPitCommand = 0x43;
+    constexpr uint16_t kSpeakerPort = 0x61;
+    constexpr uint32_t kCalibrationCounts = 1193182 / 20;
+    auto flags = X86_save_flags_cli();
+    X86_outb(kSpeakerPort, (X86_inb(kSpeakerPort) & ~2) | 1);  // gate on, speaker off
+    X86_outb(kPitCommand, 0xB0);  // channel 2, LSB then MSB, mode 0
+    X86_outb(kPitChannel2, kCalibrationCounts & 0xFF);
+    apic.WriteLapic(kLapicLvtTimer, kLvtMasked | 32);
+    X86_outb(kPitChannel2, kCalibrationCounts >> 8);  // starts the count
+    apic.WriteLapic(kLapicTimerInitial, 0xFFFFFFFF);
+    uint32_t current;
+    do {
+        current = apic.ReadLapic(kLapicTimerCurrent);
+    } while (!(X86_inb(kSpeakerPort) & 0x20) && current != 0);
+    apic.WriteLapic(kLapicTimerInitial, 0);
+    X86_restore_flags(flags);
+
+    uint64_t counts = 0xFFFFFFFFu - current;
+    // A timer slower than 1 MHz would make for coarse ticks, a stopped PIT is a broken calibration.
+    if (current == 0 || counts * 20 < 1000000 || counts * 20 > 0xFFFFFFFFu) return nullptr;
+    apic_timer.counts_per_second = counts * 20;
+    apic_timer.count_ns = (uint64_t(1000000000) << 16) / apic_timer.counts_per_second;
+    apic.timer_tick = true;
+    kprint("The APIC timer runs at {} kHz\n", apic_timer.counts_per_second / 1000);
+    return &apic_timer;
+}
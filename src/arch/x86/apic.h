@@ -0,0 +1,17 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#ifndef OS_APIC_H
+#define OS_APIC_H
+
+#include "irq.h"
+
+// Sets up the local APIC and the I/O APIC described by the ACPI MADT and routes the ISA IRQs through them, masked.
+// Returns nullptr when the cpu or firmware doesn't have them, then the PICs remain in charge.
+InterruptController* InitApic();
+// Calibrates the local APIC timer against the PIT, it then raises IRQ 0 instead of the PIT. Only after InitApic
+// succeeded, returns nullptr if the calibration fails.
+TickTimer* InitApicTimer();
+
+#endif //OS_APIC_H
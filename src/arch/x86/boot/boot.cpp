@@ -3,6 +3,7 @@
 //
 #include "boot.h"
 
+#include "src/freestanding/mbr.h"
 #include "src/freestanding/utils.h"
 #include "src/arch/x86/x86_inst.h"
 
@@ -146,8 +147,16 @@ extern char _start[], _edata[], _end[];
     print(out, "Extended BIOS at {}\n", Hex(uintptr_t(*reinterpret_cast<uint16_t*>(0x40E)) << 4));
     EnableA20();
     print(out, "A20 enabled\n");
-    unsigned fs_lba = (reinterpret_cast<uintptr_t >(_edata) - reinterpret_cast<uintptr_t >(_start) + 511) / 512;
-    TarFSReader tar(drive, fs_lba);
+    // The archive is a partition, the MBR with the partition table is still where the BIOS loaded it.
+    Partition partitions[kMbrPartitions];
+    const Partition* archive = nullptr;
+    if (ParseMbr({_start, 512}, partitions)) archive = FindPartition(partitions, kPartitionTar);
+    if (!archive) {
+        print(out, "No boot archive partition\n");
+        terminate(-1);
+    }
+    print(out, "Boot archive at sector {}\n", archive->first_lba);
+    TarFSReader tar(drive, archive->first_lba);
     char* ramdisk = reinterpret_cast<char*>(0x80000);
     char* load_address = ramdisk;
     std::size_t size = 0;
@@ -193,26 +202,10 @@ extern char _start[], _edata[], _end[];
     __builtin_unreachable();
 }
 
-// Master boot record code (must fit the 512 byte limit)
-__attribute__((always_inline))
-inline void Print(std::string_view str) {
-    regs.ax = 0x300;
-    regs.bx = 0;
-    generate_real_interrupt(0x10);
-    regs.ax = 0x1301;
-    regs.bx = 7;
-    regs.cx = str.size();
-    regs.es = 0;
-    regs.bp = reinterpret_cast<uintptr_t>(str.data());
-    generate_real_interrupt(0x10);
-}
-
-extern "C"
-char start_msg[15];
-
+// Master boot record code, it must fit below the partition table at 0x1BE (see boot.ld). The full bootloader prints
+// the first message.
 extern "C" __attribute__((noinline, fastcall, section(".boot")))
 [[noreturn]] void BootLoader(int /* dummy */, int drive) {
-    Print({start_msg, 15});
     auto nsectors = (reinterpret_cast<uintptr_t>(_edata) - reinterpret_cast<uintptr_t>(_start) - 1) / 512;
     if (read_disk(drive, 1, nsectors, reinterpret_cast<void*>(0x7C00 + 512))) {
         FullBootLoader(drive);
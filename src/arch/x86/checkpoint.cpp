@@ -0,0 +1,164 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#include "checkpoint.h"
+
+#include "exec.h"
+#include "file.h"
+#include "paging.h"
+#include "thread.h"
+
+constexpr uint32_t kCheckpointMagic = 0x54504B43;  // "CKPT"
+
+// The image is the header, followed by the numbers of the saved pages and then their contents in the same order.
+struct CheckpointVma {
+    uint32_t start, end;
+    uint32_t writable;
+};
+
+struct CheckpointHeader {
+    uint32_t magic;
+    Regs regs;  // at the return of the checkpoint system call
+    uint32_t brk_base, brk, mmap_base;
+    uint32_t linux_abi;
+    int64_t offsets[kMaxFileDescriptors];  // of the descriptors that are files in the VFS, -1 for the others
+    uint32_t num_vmas;
+    CheckpointVma vmas[kMaxVmas];
+    uint32_t num_pages;
+};
+
+static CheckpointHeader header;
+
+// Only pages that are present are saved, the others were never touched and are zero.
+static bool IsPresentPage(uintptr_t page) {
+    return GetCurrentDir()[page / kNumPageEntries].IsPresent() && GetPageEntry(page)->IsPresent();
+}
+
+// Calls f with every present page of the program, its mappings, the heap and the stack.
+template <typename F>
+static bool ForEachPage(const CheckpointHeader& h, F f) {
+    auto range = [&f](uintptr_t start, uintptr_t end) {
+        for (auto page = start / kPageSize; page < (end + kPageSize - 1) / kPageSize; page++) {
+            if (IsPresentPage(page) && !f(page)) return false;
+        }
+        return true;
+    };
+    for (uint32_t i = 0; i < h.num_vmas; i++) {
+        if (!range(h.vmas[i].start, h.vmas[i].end)) return false;
+    }
+    return range(h.brk_base, h.brk) && range(kStackLimit, kKernelBase);
+}
+
+static bool InLayout(const CheckpointHeader& h, uintptr_t page) {
+    auto address = page * kPageSize;
+    if (address >= kStackLimit && address < kKernelBase) return true;
+    if (address >= (h.brk_base & -kPageSize) && address < h.brk) return true;
+    for (uint32_t i = 0; i < h.num_vmas; i++) {
+        if (address >= h.vmas[i].start && address < h.vmas[i].end) return true;
+    }
+    return false;
+}
+
+// The layout must lie in user space below the stack, like the segments of a binary (see exec.cpp).
+static bool IsValidHeader(const CheckpointHeader& h) {
+    if (h.magic != kCheckpointMagic || h.num_vmas > kMaxVmas || h.num_pages > kKernelBase / kPageSize) return false;
+    if (h.brk_base < kProgramBase || h.brk_base > h.brk || h.brk > kStackLimit) return false;
+    if (h.mmap_base < kProgramBase || h.mmap_base > kStackLimit) return false;
+    for (uint32_t i = 0; i < h.num_vmas; i++) {
+        auto& vma = h.vmas[i];
+        if (vma.start < kProgramBase || vma.start >= vma.end || vma.end > kStackLimit) return false;
+    }
+    return true;
+}
+
+// edx is a descriptor of a file in the VFS, the image is written at its offset. Returns 0, or 1 when the process is
+// restored from the image, -1 on failure.
+void SysCheckpoint(Regs* regs) {
+    unsigned fd = regs->edx;
+    regs->eax = -1;
+    if (!GetVNode(fd).fs) return;
+    auto thread = current_thread;
+    header.magic = kCheckpointMagic;
+    header.regs = *regs;
+    header.regs.eax = 1;
+    header.brk_base = thread->brk_base;
+    header.brk = thread->brk;
+    header.mmap_base = thread->mmap_base;
+    header.linux_abi = thread->linux_abi;
+    for (int i = 0; i < kMaxFileDescriptors; i++) header.offsets[i] = GetFileOffset(i);
+    header.num_vmas = thread->num_vmas;
+    for (int i = 0; i < thread->num_vmas; i++) {
+        auto& vma = thread->vmas[i];
+        if (vma.file.fs) return;
+        header.vmas[i] = CheckpointVma{vma.start, vma.end, vma.writable};
+    }
+    header.num_pages = 0;
+    ForEachPage(header, [](uintptr_t) { header.num_pages++; return true; });
+
+    // Writing to a file in the VFS doesn't block, so none of these restart the system call.
+    auto write = [regs, fd](const void* buf, std::size_t len) {
+        return WriteFile(regs, fd, static_cast<const char*>(buf), len) == static_cast<int>(len);
+    };
+    if (!write(&header, sizeof(header))) return;
+    if (!ForEachPage(header, [&write](uintptr_t page) { uint32_t p = page; return write(&p, sizeof(p)); })) return;
+    auto write_page = [&write](uintptr_t page) { return write(reinterpret_cast<void*>(page * kPageSize), kPageSize); };
+    if (!ForEachPage(header, write_page)) return;
+    regs->eax = 0;
+}
+
+// edx is a descriptor of a file in the VFS, the image is read from its offset. Replaces the calling process by the
+// one in the image, so it only returns on failure, with -1.
+void SysRestore(Regs* regs) {
+    unsigned fd = regs->edx;
+    regs->eax = -1;
+    auto file = GetVNode(fd);
+    if (!file.fs) return;
+    uint64_t offset = GetFileOffset(fd);
+    if (file.fs->Read(file.node, offset, &header, sizeof(header)) != sizeof(header) || !IsValidHeader(header)) return;
+    // Everything is checked before the address space is cleared, after that there is no process to return to.
+    auto list = offset + sizeof(header);
+    auto contents = list + uint64_t{header.num_pages} * sizeof(uint32_t);
+    auto end = contents + uint64_t{header.num_pages} * kPageSize;
+    FileStat stat;
+    if (!file.fs->Stat(file.node, &stat) || stat.size < end) return;
+    for (uint32_t i = 0; i < header.num_pages; i++) {
+        uint32_t page;
+        if (file.fs->Read(file.node, list + i * sizeof(page), &page, sizeof(page)) != sizeof(page)) return;
+        if (!InLayout(header, page)) return;
+    }
+
+    auto thread = current_thread;
+    ReleaseBinary(thread->binary);
+    thread->binary = -1;
+    ClearUserSpace();
+    thread->num_vmas = 0;
+    for (uint32_t i = 0; i < header.num_vmas; i++) {
+        auto& vma = header.vmas[i];
+        AddVma(thread, vma.start, vma.end, VNode{nullptr, -1}, 0, vma.writable);
+    }
+    thread->brk_base = header.brk_base;
+    thread->brk = header.brk;
+    thread->mmap_base = header.mmap_base;
+    thread->linux_abi = header.linux_abi;
+    // The file is long enough, a page that can't be read anyway is left zero.
+    for (uint32_t i = 0; i < header.num_pages; i++) {
+        uint32_t page;
+        file.fs->Read(file.node, list + i * sizeof(page), &page, sizeof(page));
+        auto dst = reinterpret_cast<void*>(page * kPageSize);
+        file.fs->Read(file.node, contents + uint64_t{i} * kPageSize, dst, kPageSize);
+    }
+    SetFileOffset(fd, end);
+    for (int i = 0; i < kMaxFileDescriptors; i++) {
+        if (header.offsets[i] >= 0) SetFileOffset(i, header.offsets[i]);
+    }
+
+    // Only the arithmetic and direction flags are taken from the image, the segments and privileged flags are those
+    // of any user thread.
+    constexpr uint32_t kUserFlags = 0xDD5;
+    constexpr uint32_t kIFMask = 1 << 9;
+    *regs = header.regs;
+    regs->gs = regs->fs = regs->es = regs->ds = regs->ss = 0x23;
+    regs->cs = 0x1B;
+    regs->eflags = (regs->eflags & kUserFlags) | kIFMask;
+}
@@ -0,0 +1,24 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#ifndef OS_CHECKPOINT_H
+#define OS_CHECKPOINT_H
+
+#include "entry.h"
+
+// Checkpoint and restore of a single process. A checkpoint is an image of the process written to a file: its
+// registers, the layout of its address space, the contents of the pages it has touched and the offsets of its
+// descriptors. Restoring an image replaces the program of the calling process like exec does, so a fork followed by
+// a restore brings the checkpointed process back as a new process. It continues by returning 1 from the checkpoint
+// system call, which returns 0 to the process that made the checkpoint.
+//
+// The descriptors themselves can't be saved, an open file doesn't know its path and pipes, consoles and watches are
+// tied to other processes. The restored process keeps the descriptors of the process restoring it, which is expected
+// to open the files again at the same descriptors. The offsets of descriptors that are files in the VFS both in the
+// image and in the restoring process are restored. Processes with file mappings are refused, the mapped file can't
+// be found again on restore.
+void SysCheckpoint(Regs* regs);
+void SysRestore(Regs* regs);
+
+#endif //OS_CHECKPOINT_H
@@ -0,0 +1,104 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#include "console.h"
+
+#include "paging.h"
+#include "serial.h"
+#include "tty.h"
+
+constinit Console consoles[kNumConsoles];
+int active_console = 0;
+
+inline uint16_t* VgaMemory() {
+    return reinterpret_cast<uint16_t*>(kLowMemBase + 0xB8000);
+}
+
+struct Selection {
+    bool active;
+    int anchor, end;  // cell indices into the screen
+};
+
+static Selection selection;
+static char clipboard[(kScreenWidth + 1) * kScreenHeight];
+static std::size_t clipboard_size;
+
+uint16_t* Console::Video() {
+    return this == &ActiveConsole() ? VgaMemory() : backing;
+}
+
+// Inverts the colors of the selected cells, doing it twice restores the screen.
+static void ToggleHighlight() {
+    auto video = VgaMemory();
+    int lo = min(selection.anchor, selection.end);
+    int hi = selection.anchor + selection.end - lo;
+    for (int i = lo; i <= hi; i++) video[i] ^= 0x7700;
+}
+
+static void CancelSelection() {
+    if (!selection.active) return;
+    ToggleHighlight();
+    selection.active = false;
+}
+
+void Console::Write(std::string_view str) {
+    // Output moves the screen contents under the selection.
+    if (this == &ActiveConsole()) {
+        CancelSelection();
+        SerialWrite(str);
+    }
+    auto video = Video();
+    Screen tmp = screen;
+    for (char c : str) {
+        tmp.Put(video, c);
+    }
+    screen = tmp;
+}
+
+void SwitchConsole(int n) {
+    if (n < 0 || n >= kNumConsoles || n == active_console) return;
+    CancelSelection();
+    memcpy(ActiveConsole().backing, VgaMemory(), sizeof(Console::backing));
+    active_console = n;
+    memcpy(VgaMemory(), ActiveConsole().backing, sizeof(Console::backing));
+}
+
+void MoveSelection(int dx, int dy) {
+    if (selection.active) {
+        ToggleHighlight();
+    } else {
+        auto& screen = ActiveConsole().screen;
+        selection.active = true;
+        selection.anchor = selection.end = screen.cursor_y * kScreenWidth + screen.cursor_x;
+    }
+    int x = selection.end % kScreenWidth + dx;
+    int y = selection.end / kScreenWidth + dy;
+    if (x >= 0 && x < kScreenWidth && y >= 0 && y < kScreenHeight) selection.end = y * kScreenWidth + x;
+    ToggleHighlight();
+}
+
+void EndSelection() {
+    if (!selection.active) return;
+    CancelSelection();
+    auto video = VgaMemory();
+    int lo = min(selection.anchor, selection.end);
+    int hi = selection.anchor + selection.end - lo;
+    std::size_t n = 0;
+    for (int row = lo / kScreenWidth; row <= hi / kScreenWidth; row++) {
+        int first = row == lo / kScreenWidth ? lo % kScreenWidth : 0;
+        int last = row == hi / kScreenWidth ? hi % kScreenWidth : kScreenWidth - 1;
+        // Trailing blanks are padding, not text.
+        while (last >= first && (video[row * kScreenWidth + last] & 0xFF) <= ' ') last--;
+        for (int x = first; x <= last; x++) {
+            char c = video[row * kScreenWidth + x] & 0xFF;
+            clipboard[n++] = c ? c : ' ';
+        }
+        if (row != hi / kScreenWidth) clipboard[n++] = '\n';
+    }
+    clipboard_size = n;
+}
+
+void Paste() {
+    for (std::size_t i = 0; i < clipboard_size; i++) TtyInput(active_console, clipboard[i]);
+}
@@ -0,0 +1,78 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#ifndef OS_CONSOLE_H
+#define OS_CONSOLE_H
+
+#include <cstdint>
+
+#include "src/freestanding/utils.h"
+
+constexpr int kScreenWidth = 80;
+constexpr int kScreenHeight = 25;
+constexpr int kNumConsoles = 4;
+
+struct Screen {
+    int cursor_x = 0, cursor_y = 0;
+
+    void Clear(uint16_t* video) {
+        memset(video, 0, kScreenWidth * kScreenHeight * 2);
+        cursor_x = cursor_y = 0;
+    }
+
+    void Put(uint16_t* video, char c) {
+        if (c == '\n') {
+            cursor_x = 0;
+            cursor_y++;
+        } else if (c == '\b') {
+            if (cursor_x > 0) {
+                cursor_x--;
+            } else if (cursor_y > 0) {
+                cursor_x = kScreenWidth - 1;
+                cursor_y--;
+            }
+        } else {
+            video[cursor_y * kScreenWidth + cursor_x] = 0x700 | static_cast<uint8_t>(c);
+            cursor_x++;
+        }
+        if (cursor_x == kScreenWidth) {
+            cursor_x = 0;
+            cursor_y++;
+        }
+        if (cursor_y == kScreenHeight) {
+            memmove(video, video + kScreenWidth, kScreenWidth * (kScreenHeight - 1) * 2);
+            memset(video + kScreenWidth * (kScreenHeight - 1), 0, kScreenWidth * 2);
+            cursor_y = kScreenHeight - 1;
+        }
+    }
+};
+
+// A virtual console. Only the active console is visible, it renders directly into VGA memory. The others render
+// into their backing buffer, which is swapped with VGA memory when the console becomes active. Every console is a
+// terminal (see tty.h) with its own input queue, the keyboard only feeds the active console.
+struct Console {
+    Screen screen;
+    uint16_t backing[kScreenWidth * kScreenHeight] = {};
+
+    uint16_t* Video();
+    void Write(std::string_view str);
+};
+
+extern Console consoles[kNumConsoles];
+extern int active_console;
+
+inline Console& ActiveConsole() {
+    return consoles[active_console];
+}
+
+void SwitchConsole(int n);
+
+// Selection and clipboard. The clipboard is shared by all consoles, a selection is made on the active console by
+// moving the selection end away from the cursor (shift+arrows) and is copied when the selection ends (shift
+// released). Pasting feeds the clipboard into the input queue of the active console as if it were typed.
+void MoveSelection(int dx, int dy);
+void EndSelection();
+void Paste();
+
+#endif //OS_CONSOLE_H
@@ -4,18 +4,23 @@
 
 #include "descriptors.h"
 
+#include "kassert.h"
+#include "paging.h"
 #include "x86_inst.h"
 #include "src/freestanding/utils.h"
 
-alignas(4096) uint8_t kernel_stack[4096 * 4];
+alignas(4096) uint8_t kernel_stack[kKernelStackSize];
+alignas(4096) static uint8_t double_fault_stack[kDoubleFaultStackSize];
 
-DescriptorEntry gdt[6] = {
+DescriptorEntry gdt[8] = {
         {},
         MakeSegDesc(true, true, 0),  // cs = 0x8
         MakeSegDesc(true, false, 0),  // ds = 0x10
         MakeSegDesc(true, true, 3),  // cs = 0x18
         MakeSegDesc(true, false, 3),  // ds = 0x20
         {}, // TSS
+        {}, // TSS of the double fault task
+        {}, // LDT
 //        {0xFFFF, kernel_access_cs, k16_flags},  // cs = 0x28
 //        {0xFFFF, kernel_access_ds, k16_flags},  // ds = 0x30
 };
@@ -23,10 +28,21 @@ DescriptorEntry gdt[6] = {
 constexpr int kIdtEntries = 0x81;
 IdtEntry idt[kIdtEntries];
 
-TSS task_state_segment(kernel_stack + sizeof(kernel_stack), kKernelDS);
+constinit TSS task_state_segment(kernel_stack + sizeof(kernel_stack), kKernelDS);
+static TaskState double_fault_task;
 
 extern "C" uint64_t int_vector[];
 
+// Runs as its own task, the registers at the fault are saved in the TSS of the interrupted task. There is no way back,
+// the fault happened while delivering another exception.
+static void DoubleFault() {
+    auto& s = task_state_segment.state;
+    auto guard = AsLinear(kernel_stack);
+    // The faulting push went just below the stack, into the guard page.
+    bool overflow = s.esp >= guard && s.esp < guard + kStackGuardSize + 64;
+    panic("Double fault @{}:{} stack {}{}\n", Hex(s.cs), Hex(s.eip), Hex(s.esp), overflow ? " (stack overflow)" : "");
+}
+
 void SetupDescriptorTables() {
     // CPU exceptions
     for (int i = 0; i < 48; i++) {
@@ -36,7 +52,18 @@ void SetupDescriptorTables() {
     // Set int 0x80 syscall
     idt[0x80] = MakeInterruptGate(int_vector + 48, 3);
 
-    gdt[5] = MakeTSSDescriptor(&task_state_segment);
+    gdt[5] = MakeTSSDescriptor(&task_state_segment, sizeof(task_state_segment));
+
+    // The kernel page tables are shared by all address spaces, so the double fault task can run in any of them.
+    double_fault_task.cr3 = CurrentCR3();
+    double_fault_task.eip = reinterpret_cast<uintptr_t>(DoubleFault);
+    double_fault_task.eflags = 2;  // interrupts disabled, bit 1 is always set
+    double_fault_task.esp = reinterpret_cast<uintptr_t>(double_fault_stack + sizeof(double_fault_stack));
+    double_fault_task.cs = kKernelCS;
+    double_fault_task.ss = double_fault_task.ds = double_fault_task.es = kKernelDS;
+    double_fault_task.fs = double_fault_task.gs = kKernelDS;
+    gdt[6] = MakeTSSDescriptor(&double_fault_task, sizeof(double_fault_task));
+    idt[8] = MakeTaskGate(kDoubleFaultTSS);
 
     X86_lgdt(gdt, sizeof(gdt));
     X86_lidt(idt, sizeof(idt));
@@ -53,3 +80,18 @@ void SetupDescriptorTables() {
             ::"r"(kKernelDS), "i"(kKernelCS));
 }
 
+
+void SetLdtDescriptor(const void* ldt, uint32_t size) {
+    gdt[7] = MakeLdtDescriptor(ldt, size);
+}
+
+void SetIoPermission(int base, int count, bool allowed) {
+    for (int port = base; port < base + count; port++) {
+        uint8_t bit = 1 << (port & 7);
+        if (allowed) {
+            task_state_segment.io_bitmap[port / 8] &= ~bit;
+        } else {
+            task_state_segment.io_bitmap[port / 8] |= bit;
+        }
+    }
+}
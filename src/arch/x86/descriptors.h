@@ -5,6 +5,7 @@
 #ifndef OS_DESCRIPTORS_H
 #define OS_DESCRIPTORS_H
 
+#include <cstddef>
 #include <cstdint>
 
 constexpr int kKernelCS = 0x8;
@@ -12,6 +13,22 @@ constexpr int kKernelDS = 0x10;
 constexpr int kUserCS = 0x18;
 constexpr int kUserDS = 0x20;
 constexpr int kTSS = 0x28;
+constexpr int kDoubleFaultTSS = 0x30;
+constexpr int kLdtSelector = 0x38;  // the LDT of the running thread, see ldt.h
+
+// Stacks. The kernel is entered from user mode on kernel_stack (see TSS), all threads share it as a thread that is
+// blocked in the kernel restarts its system call instead of keeping a kernel context. Its lowest page is unmapped, so
+// overflowing it faults, which becomes a double fault as the cpu can't push the page fault either. The double fault
+// is a task switch to a task with a stack of its own, which reports the overflow. Boot runs on a separate stack
+// until it enters the first thread (see start32.cpp).
+//
+// TODO: per thread kernel stacks, with esp0 of the TSS switched with the thread. Only needed once threads sleep in
+// the middle of the kernel (nested blocking, kernel threads) instead of restarting system calls.
+constexpr std::size_t kStackGuardSize = 4096;
+constexpr std::size_t kKernelStackSize = kStackGuardSize + 4096 * 4;
+constexpr std::size_t kDoubleFaultStackSize = 4096;
+
+extern uint8_t kernel_stack[kKernelStackSize];
 
 struct DescriptorEntry {
     uint32_t limit : 16;
@@ -32,17 +49,37 @@ struct DescriptorEntry {
 
 static_assert(sizeof(DescriptorEntry) == 8);
 
-struct TSS {
-    constexpr TSS(void* stack, int stack_selector) : esp0(stack), ss0(stack_selector) {}
-    uint32_t _link = 0;
+constexpr int kNumIoPorts = 65536;
+
+// The state of a hardware task, the cpu saves the registers here when it switches away from the task.
+struct TaskState {
+    uint32_t link = 0;
     void* esp0 = 0;
     uint32_t ss0 = 0;
-    uint32_t _unused[22] = {};
-    uint16_t _trap = 0;
-    uint16_t _io_map_base = sizeof(TSS);  // it starts at the end of the segment limit, so empty array => no access
+    uint32_t esp1 = 0, ss1 = 0, esp2 = 0, ss2 = 0;
+    uint32_t cr3 = 0, eip = 0, eflags = 0;
+    uint32_t eax = 0, ecx = 0, edx = 0, ebx = 0, esp = 0, ebp = 0, esi = 0, edi = 0;
+    uint32_t es = 0, cs = 0, ss = 0, ds = 0, fs = 0, gs = 0, ldt = 0;
+    uint16_t trap = 0;
+    uint16_t io_map_base = 104;  // beyond the limit there is no io bitmap
+} __attribute__((packed));
+
+static_assert(sizeof(TaskState) == 104);
+
+struct TSS {
+    constexpr TSS(void* stack, int stack_selector) {
+        state.esp0 = stack;
+        state.ss0 = stack_selector;
+        for (auto& b : io_bitmap) b = 0xFF;
+    }
+    TaskState state;
+    // A set bit denies user mode access to the port. Ports are granted to the thread that is running, see
+    // SetIoPermission.
+    uint8_t io_bitmap[kNumIoPorts / 8] = {};
+    uint8_t _io_map_end = 0xFF;  // the cpu reads 2 bytes of the bitmap at a time, so it must end with a 0xFF byte
 } __attribute__((packed));
 
-static_assert(sizeof(TSS) == 104);
+static_assert(sizeof(TSS) == 104 + kNumIoPorts / 8 + 1);
 
 constexpr DescriptorEntry MakeSegDesc(bool is_32bit, bool is_code, int dpl) {
     return DescriptorEntry {
@@ -63,10 +100,10 @@ constexpr DescriptorEntry MakeSegDesc(bool is_32bit, bool is_code, int dpl) {
     };
 }
 
-inline DescriptorEntry MakeTSSDescriptor(TSS* ptr) {
+inline DescriptorEntry MakeTSSDescriptor(const void* ptr, uint32_t size) {
     uintptr_t base = reinterpret_cast<uintptr_t>(ptr);
     return DescriptorEntry {
-            sizeof(TSS) - 1,  // limit
+            size - 1,  // limit
             base & 0xFFFFFF,
             1,  // access
             0,  // busy
@@ -83,6 +120,26 @@ inline DescriptorEntry MakeTSSDescriptor(TSS* ptr) {
     };
 }
 
+inline DescriptorEntry MakeLdtDescriptor(const void* ptr, uint32_t size) {
+    uintptr_t base = reinterpret_cast<uintptr_t>(ptr);
+    return DescriptorEntry {
+            size - 1,  // limit
+            base & 0xFFFFFF,
+            0,  // access
+            1,  // type 2 is an LDT
+            0,  // dc
+            0,  // executable
+            0,  // special is zero
+            0,  // dpl
+            1,  // present
+            0,  // limit high
+            0,  // reserved
+            0,  // 32 bit (operand size for cs)
+            0,  // granularity of limit
+            base >> 24,  // base high
+    };
+}
+
 struct IdtEntry {
     uint16_t offset_low;
     uint16_t selector;
@@ -102,6 +159,17 @@ inline IdtEntry MakeInterruptGate(void* ptr, uint16_t dpl) {
     return IdtEntry{offset_low, 0x8, 0, 0xE, dpl, 1, offset_high};
 }
 
+// Interrupting through a task gate switches to the task, with all of its registers and its stack.
+inline IdtEntry MakeTaskGate(uint16_t tss_selector) {
+    return IdtEntry{0, tss_selector, 0, 0x5, 0, 1, 0};
+}
+
 void SetupDescriptorTables();
 
+// Allow or deny user mode access to the io ports [base, base + count).
+void SetIoPermission(int base, int count, bool allowed);
+
+// Points the LDT descriptor at a table of size bytes, it's used by the next lldt.
+void SetLdtDescriptor(const void* ldt, uint32_t size);
+
 #endif //OS_DESCRIPTORS_H
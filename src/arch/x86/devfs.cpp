@@ -0,0 +1,124 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#include "devfs.h"
+
+#include "random.h"
+#include "serial.h"
+#include "thread.h"
+#include "src/freestanding/utils.h"
+
+enum DevNode {
+    kRootNode,
+    kRam0Node,
+    kConsoleNode,
+    kNullNode,
+    kZeroNode,
+    kRandomNode,
+    kSerialNode,
+};
+
+struct Device {
+    std::string_view name;
+    DevNode node;
+    FileType type;
+    uint32_t mode;
+};
+
+constexpr Device kDevices[] = {
+    {"console", kConsoleNode, kCharDevice, 0666},
+    {"null", kNullNode, kCharDevice, 0666},
+    {"ram0", kRam0Node, kRegularFile, 0444},
+    {"random", kRandomNode, kCharDevice, 0666},
+    {"ttyS0", kSerialNode, kCharDevice, 0666},
+    {"zero", kZeroNode, kCharDevice, 0666},
+};
+
+constinit DevFileSystem devfs;
+
+static const char* ramdisk;
+static std::size_t ramdisk_size;
+
+void InitDevFs(const void* ramdisk, std::size_t ramdisk_size) {
+    ::ramdisk = static_cast<const char*>(ramdisk);
+    ::ramdisk_size = ramdisk_size;
+    Mount("/dev", &devfs);
+}
+
+bool IsConsoleDevice(VNode vnode) {
+    return vnode.fs == &devfs && vnode.node == kConsoleNode;
+}
+
+int DevFileSystem::Lookup(std::string_view path) {
+    if (path.empty()) return kRootNode;
+    for (auto& device : kDevices) {
+        if (path != device.name) continue;
+        if (device.node == kRam0Node && !(current_thread && current_thread->privileged)) return -1;
+        return device.node;
+    }
+    return -1;
+}
+
+// The console is read through the terminal of the thread, which the open file does itself (see file.cpp).
+int DevFileSystem::Read(int node, uint64_t offset, void* buf, std::size_t len) {
+    switch (node) {
+        case kRam0Node:
+            if (offset >= ramdisk_size) return 0;
+            len = min<uint64_t>(len, ramdisk_size - offset);
+            memcpy(buf, ramdisk + offset, len);
+            return len;
+        case kNullNode:
+            return 0;
+        case kZeroNode:
+            memset(buf, 0, len);
+            return len;
+        case kRandomNode:
+            GetRandomBytes(buf, len);
+            return len;
+        case kSerialNode:
+            return SerialReadRaw(buf, len);
+        default:
+            return -1;
+    }
+}
+
+// Everything written to null and zero is discarded, what is written to random is mixed into the entropy pool and
+// what is written to ttyS0 is sent as is.
+int DevFileSystem::Write(int node, uint64_t, const void* buf, std::size_t len) {
+    switch (node) {
+        case kNullNode:
+        case kZeroNode:
+            return len;
+        case kRandomNode:
+            for (std::size_t i = 0; i < len; i++) AddEntropy(static_cast<const uint8_t*>(buf)[i]);
+            return len;
+        case kSerialNode:
+            return SerialWriteRaw(buf, len);
+        default:
+            return -1;
+    }
+}
+
+int DevFileSystem::ReadDir(int node, std::size_t index, DirEntry* entry) {
+    if (node != kRootNode) return -1;
+    if (index >= array_size(kDevices)) return 0;
+    auto& device = kDevices[index];
+    memcpy(entry->name, device.name.data(), device.name.size());
+    entry->name[device.name.size()] = 0;
+    entry->type = device.type;
+    return 1;
+}
+
+bool DevFileSystem::Stat(int node, FileStat* stat) {
+    if (node == kRootNode) {
+        *stat = FileStat{0, kDirectory, 0755, 0};
+        return true;
+    }
+    for (auto& device : kDevices) {
+        if (device.node != node) continue;
+        *stat = FileStat{node == kRam0Node ? ramdisk_size : 0, device.type, device.mode, 0};
+        return true;
+    }
+    return false;
+}
@@ -0,0 +1,36 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#ifndef OS_DEVFS_H
+#define OS_DEVFS_H
+
+#include "vfs.h"
+
+// The /dev filesystem, a file per device. Character devices:
+//  console  the terminal of the process opening it, like its inherited standard descriptors
+//  null     reads as empty, discards writes
+//  zero     reads as zeros, discards writes
+//  random   reads random bytes (see random.h), writes are mixed into the entropy pool
+//  ttyS0    the first serial port as raw bytes, while the tunable serial/raw is 1 (see serial.h)
+// Block devices give access to all of their bytes and only privileged processes can open them. ram0 is the ramdisk,
+// the boot disk image as loaded by the bootloader. It's read only, the memory scrubber relies on the ramdisk never
+// changing.
+//
+// TODO: writable whole disks and partitions (hda, hda1, ...) once there is a disk driver. Until then mkfs.fat formats
+// image files, which are mounted with MountImage.
+class DevFileSystem : public FileSystem {
+public:
+    constexpr DevFileSystem() = default;
+
+    int Lookup(std::string_view path) override;
+    int Read(int node, uint64_t offset, void* buf, std::size_t len) override;
+    int Write(int node, uint64_t offset, const void* buf, std::size_t len) override;
+    int ReadDir(int node, std::size_t index, DirEntry* entry) override;
+    bool Stat(int node, FileStat* stat) override;
+};
+
+void InitDevFs(const void* ramdisk, std::size_t ramdisk_size);
+bool IsConsoleDevice(VNode vnode);  // opening it gives a console descriptor instead of a file
+
+#endif //OS_DEVFS_H
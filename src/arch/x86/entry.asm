@@ -54,6 +54,26 @@ exit_kernel:
     add esp, 8  ; skip error code and interrupt number
     iret
 
+; size_t copy_user(void* dst, const void* src, size_t size), one of dst and src is in user memory. Returns the number
+; of bytes not copied. A page fault on the user memory that can't be resolved continues at copy_user_fault, rep movsb
+; leaves the bytes still to copy in ecx.
+global copy_user
+global copy_user_copy
+global copy_user_fault
+copy_user:
+    push esi
+    push edi
+    mov edi, [esp + 12]
+    mov esi, [esp + 16]
+    mov ecx, [esp + 20]
+copy_user_copy:
+    rep movsb
+copy_user_fault:
+    mov eax, ecx
+    pop edi
+    pop esi
+    ret
+
 align 64
 global int_vector
 int_vector:
@@ -5,6 +5,7 @@
 #ifndef OS_ENTRY_H
 #define OS_ENTRY_H
 
+#include <cstddef>
 #include <cstdint>
 
 // Matches the stack frame of the entry.asm
@@ -19,4 +20,15 @@ extern "C" uint64_t int_vector[];
 
 extern "C" [[noreturn]] void exit_kernel(Regs* regs);
 
+// The copy between user and kernel memory of entry.asm, use CopyFromUser and CopyToUser. A page fault at
+// copy_user_copy continues at copy_user_fault.
+extern "C" std::size_t copy_user(void* dst, const void* src, std::size_t size);
+extern "C" char copy_user_copy[];
+extern "C" char copy_user_fault[];
+
+// Nesting of kernel entries on the kernel stack, 0 while user mode runs. Entering a thread always starts from an
+// empty kernel stack, so it's reset there.
+constexpr int kMaxInterruptDepth = 8;
+extern int interrupt_depth;
+
 #endif //OS_ENTRY_H
@@ -0,0 +1,239 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#include "exec.h"
+
+#include "kassert.h"
+#include "ldt.h"
+#include "paging.h"
+#include "thread.h"
+#include "vfs.h"
+#include "src/freestanding/elf.h"
+#include "src/freestanding/utils.h"
+
+struct Binary {
+    char path[kMaxPathLength];
+    std::size_t path_length;
+    std::string_view contents;  // in the ramdisk
+    ElfImage image;  // a flat binary is a single segment at kProgramBase
+    char md5[16];
+    int refcount;  // threads running the binary
+};
+
+static Binary binaries[kMaxBinaries];
+static int num_binaries;
+static int next_victim;
+
+void AcquireBinary(int binary) {
+    if (binary >= 0) binaries[binary].refcount++;
+}
+
+void ReleaseBinary(int binary) {
+    if (binary < 0) return;
+    kassert(binaries[binary].refcount > 0);
+    binaries[binary].refcount--;
+}
+
+void InvalidateBinary(const char* contents) {
+    if (contents == nullptr) return;
+    for (int i = 0; i < num_binaries; i++) {
+        // An empty path never matches a lookup, the entry is replaced once unreferenced.
+        if (binaries[i].contents.data() == contents) binaries[i].path_length = 0;
+    }
+}
+
+// Segments must lie in user space below the stack, otherwise loading them would overwrite the kernel or the stack.
+static bool InUserRange(const ElfImage& image) {
+    for (int i = 0; i < image.num_segments; i++) {
+        auto& segment = image.segments[i];
+        if (segment.vaddr < kProgramBase || segment.vaddr > kStackLimit) return false;
+        if (segment.memsz > kStackLimit - segment.vaddr) return false;
+    }
+    return true;
+}
+
+// Execute permission can't be enforced without NX, so only pages without writable segments are protected, the others
+// stay writable.
+static void ProtectSegments(const ElfImage& image) {
+    ForEachReadOnlyPage(image, kPageSize, WriteProtectPage);
+}
+
+// Returns the index of the cache entry of the binary, or -1 if it doesn't exist or the cache is full.
+static int LookupBinary(std::string_view path) {
+    for (int i = 0; i < num_binaries; i++) {
+        if (std::string_view(binaries[i].path, binaries[i].path_length) == path) return i;
+    }
+    auto file = VfsLookup(path);
+    if (!file.fs) return -1;
+    // TODO: binaries on filesystems that aren't memory backed need to be read into memory.
+    auto contents = file.fs->Map(file.node);
+    if (contents.data() == nullptr) return -1;
+    ElfImage image;
+    if (IsElf(contents)) {
+        if (!ParseElf(contents, &image)) {
+            kprint("Exec of {} refused, malformed ELF\n", path);
+            return -1;
+        }
+    } else {
+        if (contents.size() == 0) return -1;
+        constexpr uint32_t kAll = ElfSegment::kRead | ElfSegment::kWrite | ElfSegment::kExecute;
+        image.entry = kProgramBase;
+        image.osabi = 0;
+        image.num_segments = 1;
+        image.segments[0] = ElfSegment{kProgramBase, uint32_t(contents.size() + kFlatBssSize), 0, uint32_t(contents.size()), kAll};
+    }
+    if (!InUserRange(image)) {
+        kprint("Exec of {} refused, segment outside user space\n", path);
+        return -1;
+    }
+    // When full, replace unreferenced entries round robin.
+    int index = -1;
+    if (num_binaries < kMaxBinaries) {
+        index = num_binaries++;
+    } else {
+        for (int i = 0; i < kMaxBinaries && index < 0; i++) {
+            if (binaries[next_victim].refcount == 0) index = next_victim;
+            next_victim = (next_victim + 1) % kMaxBinaries;
+        }
+        if (index < 0) return -1;
+    }
+    auto& binary = binaries[index];
+    memcpy(binary.path, path.data(), path.size());
+    binary.path_length = path.size();
+    binary.contents = contents;
+    binary.image = image;
+    md5(contents, binary.md5);
+    binary.refcount = 0;
+    return index;
+}
+
+static bool Verify(const Binary& binary) {
+    char md5_out[16];
+    md5(binary.contents, md5_out);
+    return std::string_view(md5_out, sizeof(md5_out)) == std::string_view(binary.md5, sizeof(binary.md5));
+}
+
+// The argument and environment strings, collected from the old program to be put on the stack of the new one.
+struct ExecArgs {
+    char strings[kMaxArgBytes];
+    std::size_t size;
+    uint32_t offsets[kMaxArgs];  // of the strings
+    int argc;
+    int envc;
+};
+
+static ExecArgs exec_args;
+
+// Appends the strings of the null terminated user array of string pointers, which may be null itself.
+static bool CopyStrings(uintptr_t user_array, ExecArgs* args, int* count) {
+    *count = 0;
+    if (user_array == 0) return true;
+    for (auto p = user_array; ; p += sizeof(uintptr_t)) {
+        uintptr_t str;
+        if (!CopyFromUser(&str, p, sizeof(str))) return false;
+        if (str == 0) return true;
+        if (args->argc + args->envc + *count == kMaxArgs) return false;
+        args->offsets[args->argc + args->envc + (*count)++] = args->size;
+        while (true) {
+            char c;
+            if (args->size == kMaxArgBytes || !CopyFromUser(&c, str++, 1)) return false;
+            args->strings[args->size++] = c;
+            if (c == 0) break;
+        }
+    }
+}
+
+// Puts the arguments on the stack in the System V layout and returns the initial esp.
+static uintptr_t PushArgs(const ExecArgs& args) {
+    auto strings = (kKernelBase - args.size) & -4;
+    memcpy(reinterpret_cast<void*>(strings), args.strings, args.size);
+    // argc, argv, null, envp, null and the two words of the terminating aux vector entry.
+    int words = 1 + args.argc + 1 + args.envc + 1 + 2;
+    auto esp = (strings - words * 4) & -16;
+    auto stack = reinterpret_cast<uint32_t*>(esp);
+    *stack++ = args.argc;
+    for (int i = 0; i < args.argc + args.envc; i++) {
+        if (i == args.argc) *stack++ = 0;
+        *stack++ = strings + args.offsets[i];
+    }
+    // The rest is zero already.
+    return esp;
+}
+
+// edx points to the zero terminated path of the binary, ecx and ebx to the null terminated argv and envp arrays
+// (null for none). Replaces the program of the calling process, so it only returns on failure, with -1.
+void SysExec(Regs* regs) {
+    // Copy the path and arguments, they're about to be unmapped.
+    char path[kMaxPathLength];
+    int length = CopyPathFromUser(regs->edx, path);
+    regs->eax = -1;
+    if (length < 0) return;
+    auto& args = exec_args;
+    args.size = 0;
+    args.argc = args.envc = 0;
+    if (!CopyStrings(regs->ecx, &args, &args.argc) || !CopyStrings(regs->ebx, &args, &args.envc)) return;
+
+    auto index = LookupBinary(std::string_view(path, length));
+    if (index < 0) return;
+    auto& binary = binaries[index];
+    if (!Verify(binary)) {
+        kprint("Exec of {} refused, the binary doesn't match its checksum\n", std::string_view(path, length));
+        return;
+    }
+    AcquireBinary(index);
+    ReleaseBinary(current_thread->binary);
+    current_thread->binary = index;
+
+    ClearUserSpace();
+    ReleaseLdt(current_thread->tid);
+    current_thread->num_vmas = 0;
+    for (int i = 0; i < binary.image.num_segments; i++) {
+        auto& segment = binary.image.segments[i];
+        AddVma(current_thread, segment.vaddr, segment.vaddr + segment.memsz);
+    }
+    // The fresh address space is all zero, so only the file part of the segments needs copying. This includes the
+    // bss in a partial page after the file part: segments don't overlap and only the exact file part is copied, so
+    // nothing but zeroes ends up there, even when the file continues with the next segment in the same page.
+    for (int i = 0; i < binary.image.num_segments; i++) {
+        auto& segment = binary.image.segments[i];
+        memcpy(reinterpret_cast<void*>(segment.vaddr), binary.contents.data() + segment.offset, segment.filesz);
+    }
+    ProtectSegments(binary.image);
+    uintptr_t end = kProgramBase;
+    for (int i = 0; i < binary.image.num_segments; i++) {
+        auto& segment = binary.image.segments[i];
+        end = max<uintptr_t>(end, segment.vaddr + segment.memsz);
+    }
+    current_thread->brk_base = current_thread->brk = (end + kPageSize - 1) & -kPageSize;
+    current_thread->mmap_base = kStackLimit;
+    if (binary.image.osabi == kElfOsAbiLinux) current_thread->linux_abi = true;
+    uintptr_t esp = PushArgs(args);
+
+    constexpr uint32_t kIFMask = 1 << 9;
+    *regs = Regs {
+        0x23, 0x23, 0x23, 0x23,  // gs, fs, es, ds;
+        0, 0, 0, 0, 0, 0, 0, 0,  // edi, esi, ebp, temp_esp, ebx, edx, ecx, eax;
+        0, 0,                    // int_no, err_code;
+        binary.image.entry, 0x1B, kIFMask, esp, 0x23    // eip, cs, eflags, esp, ss
+    };
+}
+
+// edx is the new program break, 0 to only query it. Memory is demand paged so growing the heap only moves the break,
+// shrinking frees the pages above it. Returns the program break, or -1 if the new one is below the end of the
+// program or runs into the stack.
+void SysBrk(Regs* regs) {
+    uintptr_t brk = regs->edx;
+    if (brk == 0) {
+        regs->eax = current_thread->brk;
+        return;
+    }
+    if (brk < current_thread->brk_base || brk > kStackLimit) {
+        regs->eax = -1;
+        return;
+    }
+    auto page_end = [](uintptr_t address) { return (address + kPageSize - 1) / kPageSize; };
+    if (page_end(brk) < page_end(current_thread->brk)) FreeUserPages(page_end(brk), page_end(current_thread->brk));
+    current_thread->brk = brk;
+    regs->eax = brk;
+}
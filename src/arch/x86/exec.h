@@ -0,0 +1,42 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#ifndef OS_EXEC_H
+#define OS_EXEC_H
+
+#include <cstddef>
+
+#include "entry.h"
+#include "paging.h"
+
+// Programs are ELF executables, or flat binaries linked at kProgramBase with their entry point at the start.
+// Executed binaries are kept parsed in a cache keyed by path, shared by all processes executing them, so an exec
+// doesn't search the ramdisk and parse the binary again. The cache stores where the binary lives in the ramdisk with its md5, which is checked on every exec so a
+// corrupted binary is refused instead of run. Every thread holds a reference to the cache entry of the program it
+// runs, only unreferenced entries are replaced.
+//
+// TODO: DOS .COM files (loaded at 0x100 behind a PSP, with int 21h trapped for a minimal DOS API) need user threads
+// running in virtual 8086 mode, which the kernel doesn't support yet: entry.asm would have to save the extra segment
+// registers pushed on entry from v86 mode and the general protection handler emulate int and the other sensitive
+// instructions.
+constexpr uintptr_t kProgramBase = 0x10000;
+constexpr uintptr_t kFlatBssSize = 0x100000;  // a flat binary doesn't tell the size of its bss, it gets 1mb
+constexpr uintptr_t kStackLimit = kKernelBase - 0x100000;  // the top 1mb of user space is reserved for the stack
+constexpr int kMaxBinaries = 16;
+
+// The arguments and environment are passed on the stack in the System V i386 layout: esp points to argc, followed
+// by the argv pointers, a null, the envp pointers, a null and an empty aux vector. The strings are above them.
+constexpr int kMaxArgs = 64;  // arguments and environment strings together
+constexpr std::size_t kMaxArgBytes = 4096;
+
+void SysExec(Regs* regs);
+void SysBrk(Regs* regs);
+
+void AcquireBinary(int binary);  // -1 is no binary
+void ReleaseBinary(int binary);
+// Drops the cache entries of binaries mapped at contents, before their file is modified. Threads running them keep
+// their reference.
+void InvalidateBinary(const char* contents);
+
+#endif //OS_EXEC_H
@@ -0,0 +1,402 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#include "fatfs.h"
+
+#include <cstddef>
+
+#include "src/freestanding/fat.h"
+#include "src/freestanding/utils.h"
+
+constexpr int kMaxLfnEntries = 20;
+constexpr int kLfnChars = 13;
+
+struct FatFileSystem::Entry {
+    char name[kMaxLfnEntries * kLfnChars];  // the long name if there is one, otherwise the 8.3 name
+    std::size_t length;
+    char short_name[12];
+    std::size_t short_length;
+    FatDirEntry raw;
+    uint64_t offset;  // in the image
+};
+
+static char zeros[512];
+
+bool FatFileSystem::ReadImage(uint64_t offset, void* buf, std::size_t len) {
+    return image_.fs->Read(image_.node, offset, buf, len) == static_cast<int>(len);
+}
+
+bool FatFileSystem::WriteImage(uint64_t offset, const void* buf, std::size_t len) {
+    return image_.fs->Write(image_.node, offset, buf, len) == static_cast<int>(len);
+}
+
+bool FatFileSystem::Init(VNode image) {
+    image_ = image;
+    num_nodes_ = 0;
+    FatBootSector boot;
+    if (!ReadImage(0, &boot, sizeof(boot)) || boot.signature != 0xAA55) return false;
+    uint32_t sector = boot.bytes_per_sector;
+    if (sector < 512 || sector > 4096 || (sector & (sector - 1)) != 0) return false;
+    if (boot.sectors_per_cluster == 0 || (boot.sectors_per_cluster & (boot.sectors_per_cluster - 1)) != 0) return false;
+    if (boot.reserved_sectors == 0 || boot.num_fats == 0) return false;
+    uint32_t fat_sectors = boot.fat_size16 ? boot.fat_size16 : boot.fat_size32;
+    uint32_t total_sectors = boot.total_sectors16 ? boot.total_sectors16 : boot.total_sectors32;
+    uint32_t root_sectors = (boot.root_entries * sizeof(FatDirEntry) + sector - 1) / sector;
+    uint64_t data_sector = boot.reserved_sectors + uint64_t{boot.num_fats} * fat_sectors + root_sectors;
+    if (fat_sectors == 0 || data_sector >= total_sectors) return false;
+    num_clusters_ = (total_sectors - data_sector) / boot.sectors_per_cluster;
+    if (num_clusters_ == 0) return false;
+    fat12_ = num_clusters_ <= kFat12MaxClusters;
+    fat32_ = num_clusters_ > kFat16MaxClusters;
+    if (fat32_ && (boot.root_entries != 0 || boot.fat_size16 != 0)) return false;
+    // The FAT must have an entry for every cluster, the first two are reserved. FAT12 entries are 1.5 bytes.
+    uint64_t entries = num_clusters_ + 2;
+    if (uint64_t{fat_sectors} * sector < (fat12_ ? (entries * 3 + 1) / 2 : entries * (fat32_ ? 4 : 2))) return false;
+
+    cluster_size_ = sector * boot.sectors_per_cluster;
+    fat_offset_ = uint64_t{boot.reserved_sectors} * sector;
+    fat_size_ = fat_sectors * sector;
+    num_fats_ = boot.num_fats;
+    root_offset_ = fat_offset_ + uint64_t(num_fats_) * fat_size_;
+    root_entries_ = boot.root_entries;
+    data_offset_ = data_sector * sector;
+    next_free_ = 2;
+    nodes_[0] = Node{fat32_ ? boot.root_cluster : 0, 0, true, 0};
+    num_nodes_ = 1;
+    return true;
+}
+
+uint64_t FatFileSystem::ClusterOffset(uint32_t cluster) const {
+    return data_offset_ + uint64_t{cluster - 2} * cluster_size_;
+}
+
+// FAT12 packs two entries in three bytes, the one of an odd cluster is in the high 12 bits of the two bytes it's in.
+bool FatFileSystem::ReadFatEntry(uint32_t cluster, uint32_t* entry) {
+    if (fat12_) {
+        uint16_t pair;
+        if (!ReadImage(fat_offset_ + cluster + cluster / 2, &pair, sizeof(pair))) return false;
+        *entry = cluster & 1 ? pair >> 4 : pair & 0xFFF;
+        return true;
+    }
+    *entry = 0;
+    if (!ReadImage(fat_offset_ + cluster * (fat32_ ? 4 : 2), entry, fat32_ ? 4 : 2)) return false;
+    if (fat32_) *entry &= 0x0FFFFFFF;
+    return true;
+}
+
+uint32_t FatFileSystem::NextCluster(uint32_t cluster) {
+    uint32_t next;
+    if (!ReadFatEntry(cluster, &next)) return 0;
+    // End of chain markers, bad clusters and corrupt links all end the chain.
+    if (next < 2 || next >= num_clusters_ + 2) return 0;
+    return next;
+}
+
+// Updates all copies of the FAT.
+bool FatFileSystem::SetNextCluster(uint32_t cluster, uint32_t next) {
+    if (fat12_) {
+        // The other half of the bytes belongs to the neighbouring cluster.
+        auto offset = fat_offset_ + cluster + cluster / 2;
+        uint16_t pair;
+        if (!ReadImage(offset, &pair, sizeof(pair))) return false;
+        next &= 0xFFF;
+        pair = cluster & 1 ? (pair & 0x000F) | next << 4 : (pair & 0xF000) | next;
+        for (int i = 0; i < num_fats_; i++) {
+            if (!WriteImage(offset + uint64_t(i) * fat_size_, &pair, sizeof(pair))) return false;
+        }
+        return true;
+    }
+    auto offset = fat_offset_ + cluster * (fat32_ ? 4 : 2);
+    if (fat32_) {
+        // The top 4 bits are reserved and must be kept.
+        uint32_t old;
+        if (!ReadImage(offset, &old, 4)) return false;
+        next = (next & 0x0FFFFFFF) | (old & 0xF0000000);
+    }
+    for (int i = 0; i < num_fats_; i++) {
+        if (!WriteImage(offset + uint64_t(i) * fat_size_, &next, fat32_ ? 4 : 2)) return false;
+    }
+    return true;
+}
+
+// New clusters are zeroed, so the part of a file skipped by a write past its end reads as zeroes. The free cluster
+// count in the FSInfo sector of FAT32 isn't updated, it's only a hint.
+uint32_t FatFileSystem::AllocCluster() {
+    for (uint32_t i = 0; i < num_clusters_; i++) {
+        uint32_t cluster = 2 + (next_free_ - 2 + i) % num_clusters_;
+        uint32_t entry;
+        if (!ReadFatEntry(cluster, &entry)) return 0;
+        if (entry != 0) continue;
+        for (uint32_t done = 0; done < cluster_size_; done += sizeof(zeros)) {
+            if (!WriteImage(ClusterOffset(cluster) + done, zeros, sizeof(zeros))) return 0;
+        }
+        if (!SetNextCluster(cluster, fat32_ ? 0x0FFFFFFF : fat12_ ? 0xFFF : 0xFFFF)) return 0;
+        next_free_ = cluster + 1;
+        return cluster;
+    }
+    return 0;
+}
+
+// Returns the cluster after cluster, appending a new one if it's the last. 0 on failure.
+uint32_t FatFileSystem::GrowChain(uint32_t cluster) {
+    auto next = NextCluster(cluster);
+    if (next != 0) return next;
+    next = AllocCluster();
+    if (next == 0 || !SetNextCluster(cluster, next)) return 0;
+    return next;
+}
+
+// Follows the chain n clusters, returns 0 if it's shorter. A chain can't be longer than the number of clusters, so a
+// corrupt chain with a cycle ends too.
+uint32_t FatFileSystem::SeekCluster(uint32_t cluster, uint64_t n) {
+    if (n >= num_clusters_) return 0;
+    for (; n > 0 && cluster != 0; n--) cluster = NextCluster(cluster);
+    return cluster;
+}
+
+// Returns the position in the image of offset in the contents of node, 0 past the clusters of the node.
+uint64_t FatFileSystem::Locate(const Node& node, uint64_t offset) {
+    if (node.entry == 0 && !fat32_) return offset < root_entries_ * sizeof(FatDirEntry) ? root_offset_ + offset : 0;
+    if (node.cluster == 0) return 0;
+    auto cluster = SeekCluster(node.cluster, offset / cluster_size_);
+    if (cluster == 0) return 0;
+    return ClusterOffset(cluster) + offset % cluster_size_;
+}
+
+static uint8_t ShortNameChecksum(const char* name) {
+    uint8_t sum = 0;
+    for (int i = 0; i < 11; i++) sum = ((sum & 1) << 7) + (sum >> 1) + static_cast<uint8_t>(name[i]);
+    return sum;
+}
+
+static std::size_t FormatShortName(const FatDirEntry& raw, char* out) {
+    std::size_t length = 0;
+    auto append = [&](const char* part, int n, bool lower) {
+        while (n > 0 && part[n - 1] == ' ') n--;
+        for (int i = 0; i < n; i++) {
+            char c = part[i];
+            out[length++] = lower && c >= 'A' && c <= 'Z' ? c - 'A' + 'a' : c;
+        }
+    };
+    append(raw.name, 8, raw.case_flags & kCaseLowerBase);
+    // 0x05 stands for a first character of 0xE5, which marks deleted entries.
+    if (length > 0 && static_cast<uint8_t>(out[0]) == 0x05) out[0] = static_cast<char>(kDeleted);
+    if (raw.name[8] != ' ') {
+        out[length++] = '.';
+        append(raw.name + 8, 3, raw.case_flags & kCaseLowerExtension);
+    }
+    return length;
+}
+
+// Reads the directory entries of dir from *index on, for the next file. Returns 1 and fills entry, 0 at the end of
+// the directory or -1 on error. The "." and ".." entries are skipped.
+int FatFileSystem::NextEntry(const Node& dir, uint32_t* index, Entry* entry) {
+    std::size_t lfn_length = 0;
+    uint8_t lfn_checksum = 0;
+    while (true) {
+        auto offset = Locate(dir, uint64_t{*index} * sizeof(FatDirEntry));
+        if (offset == 0) return 0;
+        FatDirEntry raw;
+        if (!ReadImage(offset, &raw, sizeof(raw))) return -1;
+        (*index)++;
+        auto first = static_cast<uint8_t>(raw.name[0]);
+        if (first == 0) return 0;
+        if (first == kDeleted || first == '.') {
+            lfn_length = 0;
+            continue;
+        }
+        if (raw.attributes == kAttrLfn) {
+            FatLfnEntry lfn;
+            memcpy(&lfn, &raw, sizeof(lfn));
+            int part = lfn.order & 0x1F;
+            if (part == 0 || part > kMaxLfnEntries) {
+                lfn_length = 0;
+                continue;
+            }
+            if (lfn.order & kLfnLast) {
+                lfn_length = part * kLfnChars;
+                lfn_checksum = lfn.checksum;
+            }
+            uint16_t chars[kLfnChars];
+            memcpy(chars, lfn.name1, sizeof(lfn.name1));
+            memcpy(chars + 5, lfn.name2, sizeof(lfn.name2));
+            memcpy(chars + 11, lfn.name3, sizeof(lfn.name3));
+            for (int i = 0; i < kLfnChars; i++) {
+                std::size_t pos = (part - 1) * kLfnChars + i;
+                if (pos >= lfn_length) break;
+                // The name is terminated by a 0 if it doesn't fill the last part.
+                if (chars[i] == 0) {
+                    lfn_length = pos;
+                    break;
+                }
+                entry->name[pos] = chars[i] < 0x80 ? chars[i] : '?';
+            }
+            continue;
+        }
+        if (raw.attributes & kAttrVolume) {
+            lfn_length = 0;
+            continue;
+        }
+        entry->raw = raw;
+        entry->offset = offset;
+        entry->short_length = FormatShortName(raw, entry->short_name);
+        if (lfn_length > 0 && lfn_checksum == ShortNameChecksum(raw.name)) {
+            entry->length = lfn_length;
+        } else {
+            memcpy(entry->name, entry->short_name, entry->short_length);
+            entry->length = entry->short_length;
+        }
+        return 1;
+    }
+}
+
+int FatFileSystem::AddNode(const Entry& entry) {
+    for (int i = 1; i < num_nodes_; i++) {
+        if (nodes_[i].entry == entry.offset) return i;
+    }
+    if (num_nodes_ == kMaxNodes) return -1;
+    uint32_t cluster = entry.raw.cluster_low | (fat32_ ? uint32_t{entry.raw.cluster_high} << 16 : 0);
+    bool directory = entry.raw.attributes & kAttrDirectory;
+    nodes_[num_nodes_] = Node{cluster, directory ? 0 : entry.raw.size, directory, entry.offset};
+    return num_nodes_++;
+}
+
+bool FatFileSystem::UpdateEntry(const Node& node) {
+    uint16_t high = node.cluster >> 16;
+    uint16_t low = node.cluster;
+    return WriteImage(node.entry + offsetof(FatDirEntry, cluster_high), &high, sizeof(high)) &&
+           WriteImage(node.entry + offsetof(FatDirEntry, cluster_low), &low, sizeof(low)) &&
+           WriteImage(node.entry + offsetof(FatDirEntry, size), &node.size, sizeof(node.size));
+}
+
+static bool EqualsIgnoreCase(std::string_view a, std::string_view b) {
+    if (a.size() != b.size()) return false;
+    auto lower = [](char c) { return c >= 'A' && c <= 'Z' ? c - 'A' + 'a' : c; };
+    for (std::size_t i = 0; i < a.size(); i++) {
+        if (lower(a[i]) != lower(b[i])) return false;
+    }
+    return true;
+}
+
+int FatFileSystem::Lookup(std::string_view path) {
+    if (!image_.fs) return -1;
+    int node = 0;
+    while (!path.empty()) {
+        auto slash = path.find('/');
+        auto name = path.substr(0, slash);
+        path.remove_prefix(slash == std::string_view::npos ? path.size() : slash + 1);
+        if (name.empty()) continue;
+        if (!nodes_[node].directory) return -1;
+        Entry entry;
+        uint32_t index = 0;
+        int found;
+        while ((found = NextEntry(nodes_[node], &index, &entry)) == 1) {
+            if (EqualsIgnoreCase(name, std::string_view(entry.name, entry.length)) ||
+                EqualsIgnoreCase(name, std::string_view(entry.short_name, entry.short_length))) {
+                break;
+            }
+        }
+        if (found != 1) return -1;
+        node = AddNode(entry);
+        if (node < 0) return -1;
+    }
+    return node;
+}
+
+int FatFileSystem::Read(int node, uint64_t offset, void* buf, std::size_t len) {
+    if (node < 0 || node >= num_nodes_ || nodes_[node].directory) return -1;
+    auto& n = nodes_[node];
+    if (offset >= n.size) return 0;
+    len = min<uint64_t>(len, n.size - offset);
+    auto cluster = SeekCluster(n.cluster, offset / cluster_size_);
+    auto out = static_cast<char*>(buf);
+    std::size_t done = 0;
+    // The chain can be shorter than the size.
+    while (cluster != 0 && done < len) {
+        auto in_cluster = (offset + done) % cluster_size_;
+        auto chunk = min<std::size_t>(len - done, cluster_size_ - in_cluster);
+        if (!ReadImage(ClusterOffset(cluster) + in_cluster, out + done, chunk)) break;
+        done += chunk;
+        if (done < len) cluster = NextCluster(cluster);
+    }
+    return done > 0 ? static_cast<int>(done) : -1;
+}
+
+int FatFileSystem::Write(int node, uint64_t offset, const void* buf, std::size_t len) {
+    if (node < 0 || node >= num_nodes_ || nodes_[node].directory) return -1;
+    auto& n = nodes_[node];
+    if (len == 0) return 0;
+    if (offset + len > UINT32_MAX) return -1;
+    if (n.cluster == 0) {
+        n.cluster = AllocCluster();
+        if (n.cluster == 0) return -1;
+    }
+    // The clusters are allocated up to the end of the write, they are zero so a gap past the old end reads as zeroes.
+    // Only the rest of the old last cluster needs clearing.
+    if (offset > n.size && n.size % cluster_size_ != 0) {
+        auto end = min<uint64_t>(offset, (n.size / cluster_size_ + 1) * cluster_size_);
+        auto tail = Locate(n, n.size);
+        for (uint64_t pos = n.size; tail != 0 && pos < end; ) {
+            auto chunk = min<uint64_t>(end - pos, sizeof(zeros));
+            if (!WriteImage(tail + (pos - n.size), zeros, chunk)) return -1;
+            pos += chunk;
+        }
+    }
+    auto cluster = n.cluster;
+    for (uint64_t i = 0; i < offset / cluster_size_ && cluster != 0; i++) cluster = GrowChain(cluster);
+    auto in = static_cast<const char*>(buf);
+    std::size_t done = 0;
+    while (cluster != 0 && done < len) {
+        auto in_cluster = (offset + done) % cluster_size_;
+        auto chunk = min<std::size_t>(len - done, cluster_size_ - in_cluster);
+        if (!WriteImage(ClusterOffset(cluster) + in_cluster, in + done, chunk)) break;
+        done += chunk;
+        if (done < len) cluster = GrowChain(cluster);
+    }
+    if (offset + done > n.size) n.size = offset + done;
+    if (!UpdateEntry(n)) return -1;
+    return done > 0 ? static_cast<int>(done) : -1;
+}
+
+int FatFileSystem::ReadDir(int node, std::size_t index, DirEntry* entry) {
+    if (node < 0 || node >= num_nodes_ || !nodes_[node].directory) return -1;
+    Entry e;
+    uint32_t pos = 0;
+    for (std::size_t i = 0; i <= index; i++) {
+        int found = NextEntry(nodes_[node], &pos, &e);
+        if (found != 1) return found;
+    }
+    auto size = min(e.length, kMaxNameLength - 1);
+    memcpy(entry->name, e.name, size);
+    entry->name[size] = 0;
+    entry->type = e.raw.attributes & kAttrDirectory ? kDirectory : kRegularFile;
+    return 1;
+}
+
+// Seconds since 1970 of a FAT date and time, which are in an unknown time zone and taken as UTC. 0 if not set.
+static uint64_t FatTimestamp(uint16_t date, uint16_t time) {
+    if (date == 0) return 0;
+    int year = 1980 + (date >> 9);
+    int month = (date >> 5) & 15;
+    int day = date & 31;
+    // Days since 1970 with years starting in March, so the leap day comes last.
+    if (month <= 2) year--;
+    int years = year - 1600;  // since the start of a 400 year cycle
+    int day_of_year = (153 * (month > 2 ? month - 3 : month + 9) + 2) / 5 + day - 1;
+    int days = years * 365 + years / 4 - years / 100 + years / 400 + day_of_year - 135080;
+    return uint64_t(days) * 86400 + (time >> 11) * 3600 + ((time >> 5) & 63) * 60 + (time & 31) * 2;
+}
+
+// The mode is 0444 for read only entries and the mtime their write time, the root has neither.
+bool FatFileSystem::Stat(int node, FileStat* stat) {
+    if (node < 0 || node >= num_nodes_) return false;
+    auto& n = nodes_[node];
+    *stat = FileStat{n.size, n.directory ? kDirectory : kRegularFile, n.directory ? 0755u : 0644u, 0};
+    if (n.entry == 0) return true;
+    FatDirEntry raw;
+    if (!ReadImage(n.entry, &raw, sizeof(raw))) return false;
+    if (raw.attributes & kAttrReadOnly) stat->mode &= 0555;
+    stat->mtime = FatTimestamp(raw.write_date, raw.write_time);
+    return true;
+}
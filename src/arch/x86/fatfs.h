@@ -0,0 +1,72 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#ifndef OS_FATFS_H
+#define OS_FATFS_H
+
+#include "vfs.h"
+
+// FAT12, FAT16 and FAT32 filesystem in an image file, like the disk images made by mkfs.fat and mounted by other
+// systems (see fat.h for the on-disk structures). All access goes through the image file, so writes end up in it
+// when its filesystem is writable. Long file names (VFAT) are shown and looked up next to the 8.3 names, case
+// insensitively. Nodes are the files that were looked up. Writing a file allocates clusters as it grows.
+//
+// TODO: files and directories can't be created, removed or renamed, that needs allocating and freeing directory
+// entries along with their long names. Names with characters outside ASCII show them as '?'. The FAT can only replace
+// the boot archive as the root once there is a disk driver to read the partition from.
+class FatFileSystem : public FileSystem {
+public:
+    constexpr FatFileSystem() = default;
+
+    bool Init(VNode image);  // false if the image doesn't hold a FAT filesystem
+
+    int Lookup(std::string_view path) override;
+    int Read(int node, uint64_t offset, void* buf, std::size_t len) override;
+    int Write(int node, uint64_t offset, const void* buf, std::size_t len) override;
+    int ReadDir(int node, std::size_t index, DirEntry* entry) override;
+    bool Stat(int node, FileStat* stat) override;
+
+private:
+    struct Node {
+        uint32_t cluster;  // the first, 0 for an empty file
+        uint32_t size;
+        bool directory;
+        uint64_t entry;  // offset of the directory entry in the image, 0 for the root
+    };
+
+    struct Entry;
+
+    static constexpr int kMaxNodes = 64;
+
+    bool ReadImage(uint64_t offset, void* buf, std::size_t len);
+    bool WriteImage(uint64_t offset, const void* buf, std::size_t len);
+    bool ReadFatEntry(uint32_t cluster, uint32_t* entry);
+    uint32_t NextCluster(uint32_t cluster);  // 0 at the end of the chain
+    bool SetNextCluster(uint32_t cluster, uint32_t next);
+    uint32_t AllocCluster();  // 0 when the filesystem is full
+    uint32_t GrowChain(uint32_t cluster);
+    uint32_t SeekCluster(uint32_t cluster, uint64_t n);
+    uint64_t ClusterOffset(uint32_t cluster) const;
+    uint64_t Locate(const Node& node, uint64_t offset);
+    int NextEntry(const Node& dir, uint32_t* index, Entry* entry);
+    int AddNode(const Entry& entry);
+    bool UpdateEntry(const Node& node);
+
+    VNode image_ = {nullptr, -1};
+    bool fat12_ = false;
+    bool fat32_ = false;
+    uint32_t cluster_size_ = 0;
+    uint64_t fat_offset_ = 0;
+    uint32_t fat_size_ = 0;  // bytes per copy of the FAT
+    int num_fats_ = 0;
+    uint64_t root_offset_ = 0;  // of the fixed root directory of FAT16
+    uint32_t root_entries_ = 0;
+    uint64_t data_offset_ = 0;
+    uint32_t num_clusters_ = 0;
+    uint32_t next_free_ = 2;  // where the search for a free cluster starts
+    Node nodes_[kMaxNodes] = {};
+    int num_nodes_ = 0;
+};
+
+#endif //OS_FATFS_H
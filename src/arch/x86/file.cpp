@@ -0,0 +1,941 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#include "file.h"
+
+#include "devfs.h"
+#include "kassert.h"
+#include "paging.h"
+#include "pipe.h"
+#include "thread.h"
+#include "tty.h"
+#include "vfs.h"
+
+enum FileKind {
+    kUnused = 0,
+    kConsoleFile,
+    kVfsFile,
+    kPipeReadEnd,
+    kPipeWriteEnd,
+    kPtyMaster,
+    kPtySlave,
+    kWatchFile,
+    kSocket,
+};
+
+// TODO: read ahead. All files are in the ramdisk so there is nothing to prefetch. With a disk driver and block cache,
+// reads continuing at the offset where the previous one ended should prefetch the next blocks asynchronously.
+struct OpenFile {
+    FileKind kind;
+    int refcount;  // descriptors referring to it
+    VNode vnode;
+    uint64_t offset;
+    int pipe;  // a connected socket receives from pipe and sends to send_pipe
+    int send_pipe;
+    int pty;
+    int watch;
+    int socket;  // of a bound socket
+};
+
+// A pipe lives while either end is open. Readers block while it's empty and writers while it's full.
+struct KernelPipe {
+    bool used;
+    int readers;  // open files of the read end
+    int writers;  // open files of the write end
+    WaitQueue read_queue;
+    WaitQueue write_queue;
+    PipeN<kPipeSize> buffer;
+};
+
+// A named pipe. mkfifo puts a path in the namespace through which a pipe is opened, the pipe exists while either end
+// is open. Opening one end blocks until the other end is opened as well.
+struct Fifo {
+    bool used;
+    char path[kMaxPathLength];  // without leading '/'
+    std::size_t path_length;
+    int pipe;  // -1 while neither end is open
+    WaitQueue readers;  // opening the read end, waiting for a writer
+    WaitQueue writers;  // opening the write end, waiting for a reader
+};
+
+struct Watch {
+    bool used;
+    char path[kMaxPathLength];  // without leading '/'
+    std::size_t path_length;
+    VNode vnode;  // of the watched file, fs is nullptr for a FIFO
+    uint32_t mask;  // of WatchEventType
+    int head, count;
+    WatchEvent events[kWatchQueueSize];
+    WaitQueue readers;
+};
+
+// A socket bound to a path. Once listening it queues the server ends of the connections made to it until they're
+// accepted, they're open files without descriptors.
+struct Socket {
+    bool used;
+    bool listening;
+    char path[kMaxPathLength];  // without leading '/'
+    std::size_t path_length;
+    int pending[kSocketBacklog];
+    int head, count;
+    WaitQueue acceptors;
+};
+
+static OpenFile open_files[kMaxOpenFiles];
+static KernelPipe pipes[kMaxPipes];
+static Fifo fifos[kMaxFifos];
+static Watch watches[kMaxWatches];
+static Socket sockets[kMaxSockets];
+
+// Frees the pipe once both ends are closed.
+static void ReleasePipe(int pipe) {
+    auto& p = pipes[pipe];
+    if (p.readers != 0 || p.writers != 0) return;
+    p.used = false;
+    for (auto& fifo : fifos) {
+        if (fifo.used && fifo.pipe == pipe) fifo.pipe = -1;
+    }
+}
+
+static int AllocPipe() {
+    int pipe = 0;
+    while (pipe < kMaxPipes && pipes[pipe].used) pipe++;
+    if (pipe == kMaxPipes) return -1;
+    auto& p = pipes[pipe];
+    p.used = true;
+    p.readers = 0;
+    p.writers = 0;
+    p.buffer.Clear();
+    return pipe;
+}
+
+static int AllocOpenFile(FileKind kind, VNode vnode) {
+    for (int i = 0; i < kMaxOpenFiles; i++) {
+        if (open_files[i].kind == kUnused) {
+            open_files[i] = OpenFile{kind, 0, vnode, 0, -1, -1, -1, -1, -1};
+            return i;
+        }
+    }
+    return -1;
+}
+
+// Blocked writers fail without readers and blocked readers see the end of file without writers.
+static void DropReader(int pipe) {
+    if (--pipes[pipe].readers == 0) WakeAll(&pipes[pipe].write_queue);
+    ReleasePipe(pipe);
+}
+
+static void DropWriter(int pipe) {
+    if (--pipes[pipe].writers == 0) WakeAll(&pipes[pipe].read_queue);
+    ReleasePipe(pipe);
+}
+
+static void NotifyDelete(std::string_view path);
+
+static void ReleaseFile(int file) {
+    auto& f = open_files[file];
+    if (f.kind == kPipeReadEnd) DropReader(f.pipe);
+    if (f.kind == kPipeWriteEnd) DropWriter(f.pipe);
+    if (f.kind == kPtyMaster || f.kind == kPtySlave) ClosePtyEnd(f.pty, f.kind == kPtyMaster);
+    if (f.kind == kWatchFile) watches[f.watch].used = false;
+    if (f.kind == kSocket && f.pipe >= 0) {
+        DropReader(f.pipe);
+        DropWriter(f.send_pipe);
+    }
+    if (f.kind == kSocket && f.socket >= 0) {
+        // The connections that weren't accepted are closed and the name goes away with the socket.
+        auto& s = sockets[f.socket];
+        for (; s.count > 0; s.count--, s.head = (s.head + 1) % kSocketBacklog) ReleaseFile(s.pending[s.head]);
+        s.used = false;
+        NotifyDelete(std::string_view(s.path, s.path_length));
+    }
+    f.kind = kUnused;
+}
+
+static void Unref(int file) {
+    if (--open_files[file].refcount == 0) ReleaseFile(file);
+}
+
+int OpenFileCount() {
+    int count = 0;
+    for (auto& f : open_files) count += f.kind != kUnused;
+    return count;
+}
+
+// Every descriptor holds a reference to its open file.
+int FileDescriptorCount() {
+    int count = 0;
+    for (auto& f : open_files) count += f.kind != kUnused ? f.refcount : 0;
+    return count;
+}
+
+// Returns the open file of descriptor fd of the current thread, nullptr if fd isn't open.
+static OpenFile* GetFile(unsigned fd) {
+    if (fd >= kMaxFileDescriptors || current_thread->file_descriptors[fd] < 0) return nullptr;
+    return &open_files[current_thread->file_descriptors[fd]];
+}
+
+static int AllocDescriptor(int file) {
+    for (int fd = 0; fd < kMaxFileDescriptors; fd++) {
+        if (current_thread->file_descriptors[fd] < 0) {
+            current_thread->file_descriptors[fd] = file;
+            open_files[file].refcount++;
+            return fd;
+        }
+    }
+    return -1;
+}
+
+void InheritFiles(Thread* thread, const Thread* parent) {
+    for (int fd = 0; fd < kMaxFileDescriptors; fd++) thread->file_descriptors[fd] = -1;
+    if (parent) {
+        for (int fd = 0; fd < kMaxFileDescriptors; fd++) {
+            int file = parent->file_descriptors[fd];
+            thread->file_descriptors[fd] = file;
+            if (file >= 0) open_files[file].refcount++;
+        }
+        return;
+    }
+    int console = AllocOpenFile(kConsoleFile, VNode{nullptr, -1});
+    kassert(console >= 0);
+    for (int fd = 0; fd < 3; fd++) thread->file_descriptors[fd] = console;
+    open_files[console].refcount = 3;
+}
+
+void CloseFiles(Thread* thread) {
+    for (int fd = 0; fd < kMaxFileDescriptors; fd++) {
+        if (thread->file_descriptors[fd] >= 0) Unref(thread->file_descriptors[fd]);
+        thread->file_descriptors[fd] = -1;
+    }
+}
+
+VNode GetVNode(unsigned fd) {
+    auto file = GetFile(fd);
+    if (!file || file->kind != kVfsFile) return VNode{nullptr, -1};
+    return file->vnode;
+}
+
+int64_t GetFileOffset(unsigned fd) {
+    auto file = GetFile(fd);
+    if (!file || file->kind != kVfsFile) return -1;
+    return file->offset;
+}
+
+bool SetFileOffset(unsigned fd, uint64_t offset) {
+    auto file = GetFile(fd);
+    if (!file || file->kind != kVfsFile) return false;
+    file->offset = offset;
+    return true;
+}
+
+static std::string_view StripLeadingSlashes(std::string_view path) {
+    while (!path.empty() && path.front() == '/') path.remove_prefix(1);
+    return path;
+}
+
+static Fifo* FindFifo(std::string_view path) {
+    path = StripLeadingSlashes(path);
+    for (auto& fifo : fifos) {
+        if (fifo.used && std::string_view(fifo.path, fifo.path_length) == path) return &fifo;
+    }
+    return nullptr;
+}
+
+static Socket* FindSocket(std::string_view path) {
+    path = StripLeadingSlashes(path);
+    for (auto& socket : sockets) {
+        if (socket.used && std::string_view(socket.path, socket.path_length) == path) return &socket;
+    }
+    return nullptr;
+}
+
+static void QueueEvent(Watch* watch, uint32_t type, std::string_view name) {
+    if (!(watch->mask & type)) return;
+    auto& event = watch->events[(watch->head + min(watch->count, kWatchQueueSize - 1)) % kWatchQueueSize];
+    if (watch->count == kWatchQueueSize) {
+        type = kWatchOverflow;
+        name = {};
+    } else {
+        watch->count++;
+    }
+    event.type = type;
+    // Paths are longer than names, a FIFO name isn't limited per component.
+    auto size = min(name.size(), kMaxNameLength - 1);
+    memcpy(event.name, name.data(), size);
+    event.name[size] = 0;
+    WakeAll(&watch->readers);
+}
+
+// Reports a file created or deleted at path to the watches of its directory.
+static void NotifyDirectory(std::string_view path, WatchEventType type) {
+    for (auto& watch : watches) {
+        if (!watch.used) continue;
+        auto dir = std::string_view(watch.path, watch.path_length);
+        auto name = path;
+        if (!dir.empty()) {
+            if (!name.starts_with(dir) || name.size() <= dir.size() || name[dir.size()] != '/') continue;
+            name.remove_prefix(dir.size() + 1);
+        }
+        bool in_dir = true;
+        for (char c : name) in_dir &= c != '/';
+        if (in_dir) QueueEvent(&watch, type, name);
+    }
+}
+
+static void NotifyCreate(std::string_view path) {
+    NotifyDirectory(path, kWatchCreate);
+}
+
+// A watch on the deleted file itself gets the event too, after which it no longer refers to the file so it doesn't
+// see a new file that reuses the node.
+static void NotifyDelete(std::string_view path) {
+    NotifyDirectory(path, kWatchDelete);
+    for (auto& watch : watches) {
+        if (!watch.used || std::string_view(watch.path, watch.path_length) != path) continue;
+        QueueEvent(&watch, kWatchDelete, {});
+        watch.vnode = VNode{nullptr, -1};
+    }
+}
+
+static void NotifyModify(VNode vnode) {
+    for (auto& watch : watches) {
+        if (watch.used && watch.vnode.fs && watch.vnode.fs == vnode.fs && watch.vnode.node == vnode.node) {
+            QueueEvent(&watch, kWatchModify, {});
+        }
+    }
+}
+
+static int ReadWatch(Regs* regs, Watch* watch, char* buf, std::size_t len) {
+    if (len < sizeof(WatchEvent)) return -1;
+    if (watch->count == 0) BlockOn(regs, &watch->readers, 0);
+    int n = 0;
+    while (watch->count > 0 && len - n >= sizeof(WatchEvent)) {
+        memcpy(buf + n, &watch->events[watch->head], sizeof(WatchEvent));
+        watch->head = (watch->head + 1) % kWatchQueueSize;
+        watch->count--;
+        n += sizeof(WatchEvent);
+    }
+    return n;
+}
+
+static void OpenFifo(Regs* regs, Fifo* fifo, uint32_t access) {
+    if (access != kOpenReadOnly && access != kOpenWriteOnly) return;
+    bool reader = access == kOpenReadOnly;
+    if (fifo->pipe < 0) {
+        fifo->pipe = AllocPipe();
+        if (fifo->pipe < 0) return;
+    }
+    int file = AllocOpenFile(reader ? kPipeReadEnd : kPipeWriteEnd, VNode{nullptr, -1});
+    int fd = file >= 0 ? AllocDescriptor(file) : -1;
+    if (fd < 0) {
+        if (file >= 0) open_files[file].kind = kUnused;
+        ReleasePipe(fifo->pipe);
+        return;
+    }
+    auto& p = pipes[fifo->pipe];
+    open_files[file].pipe = fifo->pipe;
+    (reader ? p.readers : p.writers)++;
+    regs->eax = fd;
+    CompleteAll(reader ? &fifo->writers : &fifo->readers);
+    // Woken without restarting by the open of the other end, which returns the descriptor.
+    if ((reader ? p.writers : p.readers) == 0) BlockOn(regs, reader ? &fifo->readers : &fifo->writers, 0);
+}
+
+void OpenPath(Regs* regs, uintptr_t user_path, uint32_t flags) {
+    char path[kMaxPathLength];
+    int length = CopyPathFromUser(user_path, path);
+    regs->eax = -1;
+    if (length < 0) return;
+    auto fifo = FindFifo(std::string_view(path, length));
+    if (fifo) return OpenFifo(regs, fifo, flags & kOpenAccessMask);
+    if (FindSocket(std::string_view(path, length))) return;  // sockets are connected to, not opened
+    // TODO: the mode is ignored, there are no permissions.
+    auto vnode = VfsLookup(std::string_view(path, length));
+    if (!vnode.fs && (flags & kOpenCreate)) {
+        vnode = VfsCreate(std::string_view(path, length), kRegularFile);
+        if (vnode.fs) NotifyCreate(StripLeadingSlashes(std::string_view(path, length)));
+    }
+    if (!vnode.fs) return;
+    bool console = IsConsoleDevice(vnode);
+    int file = console ? AllocOpenFile(kConsoleFile, VNode{nullptr, -1}) : AllocOpenFile(kVfsFile, vnode);
+    if (file < 0) return;
+    int fd = AllocDescriptor(file);
+    if (fd < 0) {
+        open_files[file].kind = kUnused;
+        return;
+    }
+    regs->eax = fd;
+}
+
+// edx points to the zero terminated path, ecx are the flags and ebx the mode. Returns the descriptor or -1.
+void SysOpen(Regs* regs) {
+    OpenPath(regs, regs->edx, regs->ecx);
+}
+
+static void CloseDescriptor(int fd) {
+    Unref(current_thread->file_descriptors[fd]);
+    current_thread->file_descriptors[fd] = -1;
+}
+
+// edx is the descriptor
+void SysClose(Regs* regs) {
+    unsigned fd = regs->edx;
+    if (!GetFile(fd)) {
+        regs->eax = -1;
+        return;
+    }
+    CloseDescriptor(fd);
+    regs->eax = 0;
+}
+
+static int ReadPipe(Regs* regs, int pipe, char* buf, std::size_t len) {
+    auto& p = pipes[pipe];
+    if (p.buffer.Empty()) {
+        if (p.writers == 0 || len == 0) return 0;
+        BlockOn(regs, &p.read_queue, 0);
+    }
+    int n = p.buffer.Read(buf, len);
+    WakeAll(&p.write_queue);
+    return n;
+}
+
+// Writes what fits, blocking only while nothing fits.
+static int WritePipe(Regs* regs, int pipe, const char* buf, std::size_t len, bool may_block) {
+    auto& p = pipes[pipe];
+    if (p.readers == 0) return -1;
+    int n = p.buffer.Write(std::string_view(buf, len));
+    if (n == 0 && len > 0 && may_block) BlockOn(regs, &p.write_queue, 0);
+    WakeAll(&p.read_queue);
+    return n;
+}
+
+int ReadFile(Regs* regs, unsigned fd, char* buf, std::size_t len) {
+    auto file = GetFile(fd);
+    if (!file || file->kind == kPipeWriteEnd) return -1;
+    if (file->kind == kConsoleFile) return TtyRead(regs, current_thread->console, buf, len);
+    if (file->kind == kPtySlave) return TtyRead(regs, PtyTty(file->pty), buf, len);
+    if (file->kind == kPtyMaster) return PtyMasterRead(regs, file->pty, buf, len);
+    if (file->kind == kWatchFile) return ReadWatch(regs, &watches[file->watch], buf, len);
+    if (file->kind == kPipeReadEnd) return ReadPipe(regs, file->pipe, buf, len);
+    if (file->kind == kSocket) return file->pipe >= 0 ? ReadPipe(regs, file->pipe, buf, len) : -1;
+    int n = file->vnode.fs->Read(file->vnode.node, file->offset, buf, len);
+    if (n > 0) file->offset += n;
+    return n;
+}
+
+int WriteFile(Regs* regs, unsigned fd, const char* buf, std::size_t len, bool may_block) {
+    auto file = GetFile(fd);
+    if (!file || file->kind == kPipeReadEnd || file->kind == kWatchFile) return -1;
+    if (file->kind == kConsoleFile) return TtyWrite(regs, current_thread->console, buf, len, may_block);
+    if (file->kind == kPtySlave) return TtyWrite(regs, PtyTty(file->pty), buf, len, may_block);
+    if (file->kind == kPtyMaster) return PtyMasterWrite(file->pty, buf, len);
+    if (file->kind == kPipeWriteEnd) return WritePipe(regs, file->pipe, buf, len, may_block);
+    if (file->kind == kSocket) return file->pipe >= 0 ? WritePipe(regs, file->send_pipe, buf, len, may_block) : -1;
+    int n = file->vnode.fs->Write(file->vnode.node, file->offset, buf, len);
+    if (n > 0) {
+        file->offset += n;
+        NotifyModify(file->vnode);
+    }
+    return n;
+}
+
+// The filesystems and devices never see user pointers, a bad one would fault in their code. File data moves through
+// a buffer on the kernel stack instead.
+constexpr std::size_t kUserChunk = 512;
+
+// Only files of the VFS read past the first chunk, another read of a pipe or terminal could block after consuming
+// the first. The bytes read into a bad buffer are lost.
+int ReadToUser(Regs* regs, unsigned fd, uintptr_t buf, std::size_t len) {
+    if (!IsUserRange(buf, len)) return -kEFAULT;
+    auto file = GetFile(fd);
+    std::size_t done = 0;
+    do {
+        char chunk[kUserChunk];
+        auto size = min(len - done, kUserChunk);
+        int n = ReadFile(regs, fd, chunk, size);
+        if (n < 0) return done > 0 ? done : n;
+        if (!CopyToUser(buf + done, chunk, n)) return done > 0 ? done : -kEFAULT;
+        done += n;
+        if (std::size_t(n) < size) break;
+    } while (done < len && file && file->kind == kVfsFile);
+    return done;
+}
+
+// Only the first chunk may block, like the buffers of the Linux writev. Otherwise restarting the call would write
+// the first chunks again.
+int WriteFromUser(Regs* regs, unsigned fd, uintptr_t buf, std::size_t len, bool may_block) {
+    if (!IsUserRange(buf, len)) return -kEFAULT;
+    std::size_t done = 0;
+    do {
+        char chunk[kUserChunk];
+        auto size = min(len - done, kUserChunk);
+        if (!CopyFromUser(chunk, buf + done, size)) return done > 0 ? done : -kEFAULT;
+        int n = WriteFile(regs, fd, chunk, size, may_block && done == 0);
+        if (n < 0) return done > 0 ? done : -1;
+        done += n;
+        if (std::size_t(n) < size) break;
+    } while (done < len);
+    return done;
+}
+
+// edx is the descriptor, ecx points to a buffer of ebx bytes. Returns the number of bytes read or -1.
+void SysRead(Regs* regs) {
+    regs->eax = ReadToUser(regs, regs->edx, regs->ecx, regs->ebx);
+}
+
+// edx is the descriptor, ecx points to ebx bytes to write. Returns the number of bytes written or -1.
+void SysWrite(Regs* regs) {
+    regs->eax = WriteFromUser(regs, regs->edx, regs->ecx, regs->ebx);
+}
+
+// edx is the descriptor, ecx the offset and ebx whence (0 from the start, 1 from the current offset, 2 from the end).
+// Returns the new offset or -1.
+void SysSeek(Regs* regs) {
+    auto file = GetFile(regs->edx);
+    int64_t offset = static_cast<int32_t>(regs->ecx);
+    regs->eax = -1;
+    if (!file || file->kind != kVfsFile) return;
+    switch (regs->ebx) {
+        case 0:
+            break;
+        case 1:
+            offset += file->offset;
+            break;
+        case 2: {
+            FileStat stat;
+            if (!file->vnode.fs->Stat(file->vnode.node, &stat)) return;
+            offset += stat.size;
+            break;
+        }
+        default:
+            return;
+    }
+    if (offset < 0) return;
+    file->offset = offset;
+    regs->eax = offset;
+}
+
+// Like ReadToUser and WriteFromUser at an offset of a file in the VFS.
+static int PreadToUser(VNode vnode, uint64_t offset, uintptr_t buf, std::size_t len) {
+    if (!IsUserRange(buf, len)) return -kEFAULT;
+    std::size_t done = 0;
+    while (done < len) {
+        char chunk[kUserChunk];
+        auto size = min(len - done, kUserChunk);
+        int n = vnode.fs->Read(vnode.node, offset + done, chunk, size);
+        if (n < 0) return done > 0 ? done : n;
+        if (!CopyToUser(buf + done, chunk, n)) return done > 0 ? done : -kEFAULT;
+        done += n;
+        if (std::size_t(n) < size) break;
+    }
+    return done;
+}
+
+static int PwriteFromUser(VNode vnode, uint64_t offset, uintptr_t buf, std::size_t len) {
+    if (!IsUserRange(buf, len)) return -kEFAULT;
+    std::size_t done = 0;
+    while (done < len) {
+        char chunk[kUserChunk];
+        auto size = min(len - done, kUserChunk);
+        int n = -kEFAULT;
+        if (CopyFromUser(chunk, buf + done, size)) n = vnode.fs->Write(vnode.node, offset + done, chunk, size);
+        if (n < 0) {
+            if (done == 0) return n;
+            break;
+        }
+        done += n;
+        if (std::size_t(n) < size) break;
+    }
+    if (done > 0) NotifyModify(vnode);
+    return done;
+}
+
+// edx is the descriptor of a file in the VFS, ecx points to a buffer of ebx bytes and esi and edi are the low and high
+// half of the offset to read at. The offset of the descriptor doesn't move. Returns the number of bytes read or -1.
+void SysPread(Regs* regs) {
+    auto file = GetFile(regs->edx);
+    regs->eax = -1;
+    if (!file || file->kind != kVfsFile) return;
+    uint64_t offset = regs->esi | uint64_t{regs->edi} << 32;
+    regs->eax = PreadToUser(file->vnode, offset, regs->ecx, regs->ebx);
+}
+
+// Like pread, writing the ebx bytes at ecx. Returns the number of bytes written or -1.
+void SysPwrite(Regs* regs) {
+    auto file = GetFile(regs->edx);
+    regs->eax = -1;
+    if (!file || file->kind != kVfsFile) return;
+    uint64_t offset = regs->esi | uint64_t{regs->edi} << 32;
+    regs->eax = PwriteFromUser(file->vnode, offset, regs->ecx, regs->ebx);
+}
+
+// edx is the descriptor of a directory, ecx points to a DirEntry that receives the entry at the offset of the
+// descriptor, which counts entries, and the offset moves on to the next one. Returns 1, 0 past the last entry or -1.
+void SysReadDir(Regs* regs) {
+    auto file = GetFile(regs->edx);
+    regs->eax = -1;
+    if (!file || file->kind != kVfsFile) return;
+    DirEntry entry;
+    int res = file->vnode.fs->ReadDir(file->vnode.node, file->offset, &entry);
+    if (res > 0) {
+        if (!CopyToUser(regs->ecx, &entry, sizeof(entry))) {
+            regs->eax = -kEFAULT;
+            return;
+        }
+        file->offset++;
+    }
+    regs->eax = res;
+}
+
+// Pipes, FIFOs, terminals, watches and sockets aren't files of the VFS, they show as character devices.
+constexpr FileStat kStreamStat = {0, kCharDevice, 0666, 0};
+
+// edx points to the zero terminated path, ecx to a FileStat that receives the size, type, mode and mtime of the file.
+// Returns 0 or -1.
+void SysStat(Regs* regs) {
+    char path[kMaxPathLength];
+    int length = CopyPathFromUser(regs->edx, path);
+    regs->eax = -1;
+    if (length < 0) return;
+    FileStat stat = kStreamStat;
+    if (!FindFifo(std::string_view(path, length)) && !FindSocket(std::string_view(path, length))) {
+        auto vnode = VfsLookup(std::string_view(path, length));
+        if (!vnode.fs || !vnode.fs->Stat(vnode.node, &stat)) return;
+    }
+    regs->eax = CopyToUser(regs->ecx, &stat, sizeof(stat)) ? 0 : -kEFAULT;
+}
+
+// edx is the descriptor, ecx points to a FileStat like for stat. Returns 0 or -1.
+void SysFstat(Regs* regs) {
+    auto file = GetFile(regs->edx);
+    regs->eax = -1;
+    if (!file) return;
+    FileStat stat = kStreamStat;
+    if (file->kind == kVfsFile && !file->vnode.fs->Stat(file->vnode.node, &stat)) return;
+    regs->eax = CopyToUser(regs->ecx, &stat, sizeof(stat)) ? 0 : -kEFAULT;
+}
+
+// edx is the descriptor to duplicate, returns the lowest free descriptor referring to the same file or -1.
+void SysDup(Regs* regs) {
+    regs->eax = -1;
+    if (!GetFile(regs->edx)) return;
+    regs->eax = AllocDescriptor(current_thread->file_descriptors[regs->edx]);
+}
+
+// edx is the descriptor to duplicate into descriptor ecx, which is closed first if open. Returns ecx or -1.
+void SysDup2(Regs* regs) {
+    unsigned fd = regs->edx;
+    unsigned new_fd = regs->ecx;
+    regs->eax = -1;
+    if (!GetFile(fd) || new_fd >= kMaxFileDescriptors) return;
+    int file = current_thread->file_descriptors[fd];
+    if (fd != new_fd) {
+        open_files[file].refcount++;
+        if (current_thread->file_descriptors[new_fd] >= 0) Unref(current_thread->file_descriptors[new_fd]);
+        current_thread->file_descriptors[new_fd] = file;
+    }
+    regs->eax = new_fd;
+}
+
+// Stores the two descriptors made by pipe or openpty at the user address fds, closing them if that fails.
+static void ReturnDescriptorPair(Regs* regs, int fd0, int fd1) {
+    int fds[2] = {fd0, fd1};
+    if (CopyToUser(regs->edx, fds, sizeof(fds))) {
+        regs->eax = 0;
+        return;
+    }
+    CloseDescriptor(fd0);
+    CloseDescriptor(fd1);
+    regs->eax = -kEFAULT;
+}
+
+// edx points to two ints which receive the descriptors of the read and write end of a new pipe. Returns 0 or -1.
+void SysPipe(Regs* regs) {
+    regs->eax = -1;
+    int pipe = AllocPipe();
+    if (pipe < 0) return;
+    int read_file = AllocOpenFile(kPipeReadEnd, VNode{nullptr, -1});
+    int write_file = read_file >= 0 ? AllocOpenFile(kPipeWriteEnd, VNode{nullptr, -1}) : -1;
+    if (write_file < 0) {
+        if (read_file >= 0) open_files[read_file].kind = kUnused;
+        ReleasePipe(pipe);
+        return;
+    }
+    int read_fd = AllocDescriptor(read_file);
+    int write_fd = read_fd >= 0 ? AllocDescriptor(write_file) : -1;
+    if (write_fd < 0) {
+        if (read_fd >= 0) current_thread->file_descriptors[read_fd] = -1;
+        open_files[read_file].kind = kUnused;
+        open_files[write_file].kind = kUnused;
+        ReleasePipe(pipe);
+        return;
+    }
+    auto& p = pipes[pipe];
+    p.readers = 1;
+    p.writers = 1;
+    open_files[read_file].pipe = pipe;
+    open_files[write_file].pipe = pipe;
+    ReturnDescriptorPair(regs, read_fd, write_fd);
+}
+
+// edx is the descriptor, ecx the request and ebx its argument. Only terminals have requests, see TtyRequest. Returns
+// the result of the request or -1.
+void SysIoctl(Regs* regs) {
+    auto file = GetFile(regs->edx);
+    if (file && file->kind == kConsoleFile) {
+        regs->eax = TtyIoctl(current_thread->console, regs->ecx, regs->ebx);
+    } else if (file && file->kind == kPtySlave) {
+        regs->eax = TtyIoctl(PtyTty(file->pty), regs->ecx, regs->ebx);
+    } else {
+        regs->eax = -1;
+    }
+}
+
+// edx points to two ints which receive the descriptors of the master and slave of a new pseudo-terminal. Returns 0
+// or -1.
+void SysOpenPty(Regs* regs) {
+    regs->eax = -1;
+    int master_file = AllocOpenFile(kPtyMaster, VNode{nullptr, -1});
+    int slave_file = master_file >= 0 ? AllocOpenFile(kPtySlave, VNode{nullptr, -1}) : -1;
+    int master_fd = slave_file >= 0 ? AllocDescriptor(master_file) : -1;
+    int slave_fd = master_fd >= 0 ? AllocDescriptor(slave_file) : -1;
+    int pty = slave_fd >= 0 ? OpenPty() : -1;
+    if (pty < 0) {
+        if (master_fd >= 0) current_thread->file_descriptors[master_fd] = -1;
+        if (slave_fd >= 0) current_thread->file_descriptors[slave_fd] = -1;
+        if (master_file >= 0) open_files[master_file].kind = kUnused;
+        if (slave_file >= 0) open_files[slave_file].kind = kUnused;
+        return;
+    }
+    open_files[master_file].pty = pty;
+    open_files[slave_file].pty = pty;
+    ReturnDescriptorPair(regs, master_fd, slave_fd);
+}
+
+// Writes back the buffered data of all filesystems. Returns 0.
+void SysSync(Regs* regs) {
+    SyncAll();
+    regs->eax = 0;
+}
+
+// edx is the descriptor. Writes back the buffered data of the filesystem of the file, consoles and pipes have
+// nothing to write back. Returns 0 or -1.
+void SysFsync(Regs* regs) {
+    auto file = GetFile(regs->edx);
+    if (!file) {
+        regs->eax = -1;
+        return;
+    }
+    if (file->kind == kVfsFile) file->vnode.fs->Sync();
+    regs->eax = 0;
+}
+
+// edx points to the zero terminated path of the FIFO to create, which must not exist yet. Returns 0 or -1.
+void SysMkfifo(Regs* regs) {
+    char path[kMaxPathLength];
+    int length = CopyPathFromUser(regs->edx, path);
+    regs->eax = -1;
+    if (length < 0) return;
+    auto name = StripLeadingSlashes(std::string_view(path, length));
+    if (name.empty() || FindFifo(name) || FindSocket(name) || VfsLookup(name).fs) return;
+    for (auto& fifo : fifos) {
+        if (fifo.used) continue;
+        fifo.used = true;
+        memcpy(fifo.path, name.data(), name.size());
+        fifo.path_length = name.size();
+        fifo.pipe = -1;
+        regs->eax = 0;
+        NotifyCreate(name);
+        return;
+    }
+}
+
+// edx points to the zero terminated path of the directory to create, in an existing directory. Returns 0 or -1.
+void SysMkdir(Regs* regs) {
+    char path[kMaxPathLength];
+    int length = CopyPathFromUser(regs->edx, path);
+    regs->eax = -1;
+    if (length < 0) return;
+    auto name = StripLeadingSlashes(std::string_view(path, length));
+    while (!name.empty() && name.back() == '/') name.remove_suffix(1);
+    if (FindFifo(name) || FindSocket(name) || !VfsCreate(name, kDirectory).fs) return;
+    regs->eax = 0;
+    NotifyCreate(name);
+}
+
+// Nodes aren't refcounted, so a file that is open or mapped can't be removed or replaced.
+static bool IsBusy(VNode vnode) {
+    for (auto& file : open_files) {
+        if (file.kind == kVfsFile && file.vnode.fs == vnode.fs && file.vnode.node == vnode.node) return true;
+    }
+    for (auto& thread : threads) {
+        if (thread.state == THREAD_UNUSED) continue;
+        for (int i = 0; i < thread.num_vmas; i++) {
+            auto& file = thread.vmas[i].file;
+            if (file.fs == vnode.fs && file.node == vnode.node) return true;
+        }
+    }
+    return false;
+}
+
+// edx points to the zero terminated path of the file, FIFO or empty directory to remove. Returns 0 or -1.
+void SysUnlink(Regs* regs) {
+    char path[kMaxPathLength];
+    int length = CopyPathFromUser(regs->edx, path);
+    regs->eax = -1;
+    if (length < 0) return;
+    auto name = StripLeadingSlashes(std::string_view(path, length));
+    while (!name.empty() && name.back() == '/') name.remove_suffix(1);
+    // An open FIFO stays until both ends are closed, like a pipe.
+    if (auto fifo = FindFifo(name)) {
+        if (fifo->pipe >= 0) return;
+        fifo->used = false;
+    } else {
+        auto vnode = VfsLookup(name);
+        if (!vnode.fs || IsBusy(vnode) || !VfsUnlink(name)) return;
+    }
+    regs->eax = 0;
+    NotifyDelete(name);
+}
+
+// edx and ecx point to the zero terminated old and new paths, which must be on the same filesystem. A file at the new
+// path is replaced. Returns 0 or -1.
+void SysRename(Regs* regs) {
+    char from_path[kMaxPathLength], to_path[kMaxPathLength];
+    int from_length = CopyPathFromUser(regs->edx, from_path);
+    int to_length = CopyPathFromUser(regs->ecx, to_path);
+    regs->eax = -1;
+    if (from_length < 0 || to_length < 0) return;
+    auto from = StripLeadingSlashes(std::string_view(from_path, from_length));
+    auto to = StripLeadingSlashes(std::string_view(to_path, to_length));
+    while (!from.empty() && from.back() == '/') from.remove_suffix(1);
+    while (!to.empty() && to.back() == '/') to.remove_suffix(1);
+    if (FindFifo(from) || FindFifo(to) || FindSocket(from) || FindSocket(to)) return;
+    auto old = VfsLookup(to);
+    if (old.fs && IsBusy(old)) return;
+    if (!VfsRename(from, to)) return;
+    regs->eax = 0;
+    NotifyDelete(from);
+    NotifyCreate(to);
+}
+
+// edx points to the zero terminated path to watch, which must exist, ecx is the mask of WatchEventTypes to report.
+// Returns the descriptor of the watch or -1.
+void SysWatch(Regs* regs) {
+    char path[kMaxPathLength];
+    int length = CopyPathFromUser(regs->edx, path);
+    regs->eax = -1;
+    if (length < 0) return;
+    auto name = StripLeadingSlashes(std::string_view(path, length));
+    while (!name.empty() && name.back() == '/') name.remove_suffix(1);
+    auto vnode = VfsLookup(name);
+    if (!vnode.fs && !FindFifo(name) && !FindSocket(name)) return;
+    int watch = 0;
+    while (watch < kMaxWatches && watches[watch].used) watch++;
+    if (watch == kMaxWatches) return;
+    int file = AllocOpenFile(kWatchFile, VNode{nullptr, -1});
+    int fd = file >= 0 ? AllocDescriptor(file) : -1;
+    if (fd < 0) {
+        if (file >= 0) open_files[file].kind = kUnused;
+        return;
+    }
+    auto& w = watches[watch];
+    w.used = true;
+    memcpy(w.path, name.data(), name.size());
+    w.path_length = name.size();
+    w.vnode = vnode;
+    w.mask = regs->ecx | kWatchOverflow;
+    w.head = w.count = 0;
+    open_files[file].watch = watch;
+    regs->eax = fd;
+}
+
+// Returns a new unconnected socket or -1.
+void SysSocket(Regs* regs) {
+    regs->eax = -1;
+    int file = AllocOpenFile(kSocket, VNode{nullptr, -1});
+    int fd = file >= 0 ? AllocDescriptor(file) : -1;
+    if (fd < 0) {
+        if (file >= 0) open_files[file].kind = kUnused;
+        return;
+    }
+    regs->eax = fd;
+}
+
+// edx is an unconnected socket, ecx points to the zero terminated path to bind it to, which must not exist yet.
+// Returns 0 or -1.
+void SysBind(Regs* regs) {
+    auto file = GetFile(regs->edx);
+    char path[kMaxPathLength];
+    int length = CopyPathFromUser(regs->ecx, path);
+    regs->eax = -1;
+    if (!file || file->kind != kSocket || file->pipe >= 0 || file->socket >= 0 || length < 0) return;
+    auto name = StripLeadingSlashes(std::string_view(path, length));
+    if (name.empty() || FindFifo(name) || FindSocket(name) || VfsLookup(name).fs) return;
+    for (int i = 0; i < kMaxSockets; i++) {
+        auto& socket = sockets[i];
+        if (socket.used) continue;
+        socket.used = true;
+        socket.listening = false;
+        memcpy(socket.path, name.data(), name.size());
+        socket.path_length = name.size();
+        socket.head = socket.count = 0;
+        file->socket = i;
+        regs->eax = 0;
+        NotifyCreate(name);
+        return;
+    }
+}
+
+// edx is a bound socket, which accepts connections from then on. Returns 0 or -1.
+void SysListen(Regs* regs) {
+    auto file = GetFile(regs->edx);
+    regs->eax = -1;
+    if (!file || file->kind != kSocket || file->socket < 0) return;
+    sockets[file->socket].listening = true;
+    regs->eax = 0;
+}
+
+// edx is a listening socket. Blocks until a connection is made to it, returns the connected socket or -1.
+void SysAccept(Regs* regs) {
+    auto file = GetFile(regs->edx);
+    regs->eax = -1;
+    if (!file || file->kind != kSocket || file->socket < 0 || !sockets[file->socket].listening) return;
+    auto& socket = sockets[file->socket];
+    if (socket.count == 0) BlockOn(regs, &socket.acceptors, 0);
+    int fd = AllocDescriptor(socket.pending[socket.head]);
+    if (fd < 0) return;  // the connection stays pending
+    socket.head = (socket.head + 1) % kSocketBacklog;
+    socket.count--;
+    regs->eax = fd;
+}
+
+// edx is an unconnected socket, ecx points to the zero terminated path of a listening socket. The connection is
+// made right away, it's pending at the listening socket until accepted. Returns 0, or -1 if nothing listens at the
+// path or its queue is full.
+void SysConnect(Regs* regs) {
+    auto file = GetFile(regs->edx);
+    char path[kMaxPathLength];
+    int length = CopyPathFromUser(regs->ecx, path);
+    regs->eax = -1;
+    if (!file || file->kind != kSocket || file->pipe >= 0 || file->socket >= 0 || length < 0) return;
+    auto socket = FindSocket(std::string_view(path, length));
+    if (!socket || !socket->listening || socket->count == kSocketBacklog) return;
+    int to_server = AllocPipe();
+    int to_client = to_server >= 0 ? AllocPipe() : -1;
+    int server = to_client >= 0 ? AllocOpenFile(kSocket, VNode{nullptr, -1}) : -1;
+    if (server < 0) {
+        if (to_client >= 0) ReleasePipe(to_client);
+        if (to_server >= 0) ReleasePipe(to_server);
+        return;
+    }
+    for (int pipe : {to_server, to_client}) pipes[pipe].readers = pipes[pipe].writers = 1;
+    open_files[server].pipe = to_server;
+    open_files[server].send_pipe = to_client;
+    file->pipe = to_client;
+    file->send_pipe = to_server;
+    socket->pending[(socket->head + socket->count++) % kSocketBacklog] = server;
+    WakeAll(&socket->acceptors);
+    regs->eax = 0;
+}
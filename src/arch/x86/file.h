@@ -0,0 +1,116 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#ifndef OS_FILE_H
+#define OS_FILE_H
+
+#include <cstddef>
+#include <cstdint>
+
+#include "entry.h"
+#include "vfs.h"
+
+struct Thread;
+
+// File descriptors. Every thread has a table of descriptors which refer to entries of the system wide open file table,
+// an open file is either the controlling console of the thread using it, a file in the VFS, an end of a pipe, an
+// end of a pseudo-terminal, a watch or a socket.
+// Descriptors duplicated by dup or inherited on fork share the open file and therefore its offset, like in POSIX.
+constexpr int kMaxFileDescriptors = 16;
+constexpr int kMaxOpenFiles = 128;
+constexpr int kMaxPipes = 16;
+constexpr int kPipeSize = 4096;
+constexpr int kMaxFifos = 16;
+constexpr int kMaxWatches = 16;
+constexpr int kWatchQueueSize = 8;
+
+// The open flags. Only FIFOs look at the access mode, files in the VFS can be written if their filesystem allows it
+// and consoles are readable and writable.
+constexpr uint32_t kOpenReadOnly = 0;
+constexpr uint32_t kOpenWriteOnly = 1;
+constexpr uint32_t kOpenReadWrite = 2;
+constexpr uint32_t kOpenAccessMask = 3;
+constexpr uint32_t kOpenCreate = 0x40;  // create a missing file, only /tmp supports it
+
+// File change notification. A watch on a path is a descriptor from which events are read, whole WatchEvents at a
+// time, blocking while there are none. Watching a directory reports the files created in it, watching a file the
+// writes to it. Deleting or renaming a file reports kWatchDelete to its directory and to a watch on the file itself,
+// renaming then reports kWatchCreate for the new name. When the queue of a watch is full its last event becomes
+// kWatchOverflow.
+//
+// TODO: without poll a process can't wait for a watch and other descriptors at the same time.
+enum WatchEventType : uint32_t {
+    kWatchCreate = 1,
+    kWatchModify = 2,
+    kWatchDelete = 4,
+    kWatchOverflow = 8,  // always reported
+};
+
+struct WatchEvent {
+    uint32_t type;
+    char name[kMaxNameLength];  // zero terminated, relative to the watched directory, empty for the watched file
+};
+
+// Unix domain stream sockets. A socket is named by binding it to a path, in a table of the open file layer like the
+// FIFOs, and the name goes away when the socket is closed. A listening socket queues up to kSocketBacklog
+// connections until they're accepted, connecting fails when nothing listens at the path or the queue is full. A
+// connected socket is a pair of pipes, one for each direction, reading and writing it works like the ends of a pipe.
+//
+// TODO: passing descriptors along with the data, a service could then hand out open files to its clients.
+constexpr int kMaxSockets = 16;
+constexpr int kSocketBacklog = 4;
+
+int OpenFileCount();  // entries of the open file table in use
+int FileDescriptorCount();  // descriptors referring to them, of all threads
+
+void InheritFiles(Thread* thread, const Thread* parent);  // parent == nullptr gives stdin/out/err on the console
+void CloseFiles(Thread* thread);
+
+// Reading and writing descriptors of the current thread on behalf of the system call in regs, they block by
+// restarting it (see BlockOn) so they must be called before regs is modified. Return the number of bytes
+// transferred or -1.
+int ReadFile(Regs* regs, unsigned fd, char* buf, std::size_t len);
+int WriteFile(Regs* regs, unsigned fd, const char* buf, std::size_t len, bool may_block = true);
+
+// Like ReadFile and WriteFile with buf in user memory, which goes through a kernel buffer a chunk at a time. A bad
+// pointer returns -kEFAULT when nothing was transferred yet.
+int ReadToUser(Regs* regs, unsigned fd, uintptr_t buf, std::size_t len);
+int WriteFromUser(Regs* regs, unsigned fd, uintptr_t buf, std::size_t len, bool may_block = true);
+
+VNode GetVNode(unsigned fd);  // of a file in the VFS, fs is nullptr for other descriptors
+int64_t GetFileOffset(unsigned fd);  // of a file in the VFS, -1 for other descriptors
+bool SetFileOffset(unsigned fd, uint64_t offset);
+
+// SysOpen with the path and flags passed separately, for the Linux open which has them in other registers. regs is
+// still the frame of the calling system call, opening a FIFO blocks on it.
+void OpenPath(Regs* regs, uintptr_t user_path, uint32_t flags);
+void SysOpen(Regs* regs);
+void SysClose(Regs* regs);
+void SysRead(Regs* regs);
+void SysWrite(Regs* regs);
+void SysSeek(Regs* regs);
+void SysPread(Regs* regs);
+void SysPwrite(Regs* regs);
+void SysReadDir(Regs* regs);
+void SysStat(Regs* regs);
+void SysFstat(Regs* regs);
+void SysDup(Regs* regs);
+void SysDup2(Regs* regs);
+void SysPipe(Regs* regs);
+void SysMkfifo(Regs* regs);
+void SysMkdir(Regs* regs);
+void SysUnlink(Regs* regs);
+void SysRename(Regs* regs);
+void SysOpenPty(Regs* regs);
+void SysWatch(Regs* regs);
+void SysIoctl(Regs* regs);
+void SysSync(Regs* regs);
+void SysFsync(Regs* regs);
+void SysSocket(Regs* regs);
+void SysBind(Regs* regs);
+void SysListen(Regs* regs);
+void SysAccept(Regs* regs);
+void SysConnect(Regs* regs);
+
+#endif //OS_FILE_H
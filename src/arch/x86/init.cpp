@@ -4,25 +4,113 @@
 
 #include "src/libc/libc.h"
 
-int global = 1;
-[[noreturn]] void Shell() {
-    global = 2;
-    for (int i = 0; i < 3; i++) {
-        uprint("I am the child! {} {}\n", i, global);
-        Yield();
+// Init starts the services listed in etc/inittab and supervises them, a service that exits is started again. Each
+// line is "console:program arguments...", empty lines and lines starting with # are skipped. A ! after the console
+// ("console!:program") starts a driver, which is privileged like init. No other process is.
+//
+// TODO: shutdown requests (ctrl+alt+del, a shutdown command) need signals to reach init, which would then call
+// Shutdown. Until then init waits for its children forever.
+constexpr int kMaxServices = 8;
+constexpr int kMaxServiceArgs = 8;
+constexpr uint64_t kRespawnDelayNs = 1000000000;  // so a service that fails at start doesn't hog the CPU
+
+struct Service {
+    int console;
+    bool driver;
+    char* argv[kMaxServiceArgs + 1];  // null terminated, argv[0] is the program
+    int pid;  // 0 if not running
+};
+
+static char inittab[4096];
+static Service services[kMaxServices];
+static int num_services;
+
+static bool IsSpace(char c) {
+    return c == ' ' || c == '\t' || c == '\r';
+}
+
+// Splits the line in place into the arguments of the service.
+static void ParseLine(char* line) {
+    while (IsSpace(*line)) line++;
+    if (*line == 0 || *line == '#') return;
+    if (num_services == kMaxServices) {
+        uprint("init: too many services\n");
+        return;
+    }
+    bool driver = line[1] == '!';
+    if (line[0] < '0' || line[0] > '9' || line[driver ? 2 : 1] != ':') {
+        uprint("init: malformed line {}\n", static_cast<const char*>(line));
+        return;
+    }
+    auto& service = services[num_services];
+    service.console = line[0] - '0';
+    service.driver = driver;
+    int argc = 0;
+    char* p = line + (driver ? 3 : 2);
+    while (true) {
+        while (IsSpace(*p)) *p++ = 0;
+        if (*p == 0 || argc == kMaxServiceArgs) break;
+        service.argv[argc++] = p;
+        while (*p != 0 && !IsSpace(*p)) p++;
     }
-    Exit(0);
+    if (argc == 0) return;
+    service.argv[argc] = nullptr;
+    service.pid = 0;
+    num_services++;
+}
+
+static void ReadInittab() {
+    int fd = Open("etc/inittab", 0, 0);
+    if (fd < 0) {
+        uprint("init: no etc/inittab\n");
+        return;
+    }
+    int size = Read(fd, inittab, sizeof(inittab) - 1);
+    Close(fd);
+    if (size < 0) return;
+    inittab[size] = 0;
+    char* line = inittab;
+    while (*line) {
+        char* end = line;
+        while (*end != 0 && *end != '\n') end++;
+        bool last = *end == 0;
+        *end = 0;
+        ParseLine(line);
+        if (last) break;
+        line = end + 1;
+    }
+}
+
+static void Start(Service& service) {
+    int pid = Fork(service.driver ? kForkPrivileged : 0);
+    if (pid == 0) {
+        SetConsole(service.console);
+        char* envp[] = {nullptr};
+        Exec(service.argv[0], service.argv, envp);
+        uprint("init: can't exec {}\n", static_cast<const char*>(service.argv[0]));
+        Exit(127);
+    }
+    service.pid = pid;
 }
 
 extern "C"
 int main(int argc, char* argv[]) {
     (void)argc; (void)argv;
+    ReadInittab();
+    for (int i = 0; i < num_services; i++) Start(services[i]);
     while (true) {
-        uprint("Logging in!\n");
-        int pid = Fork();
-        if (pid == 0) {
-            Shell();
+        int status;
+        int pid = Wait(-1, &status);
+        if (pid < 0) {
+            // Nothing to supervise, sleep rather than spin.
+            NanoSleep(kRespawnDelayNs);
+            continue;
+        }
+        for (int i = 0; i < num_services; i++) {
+            if (services[i].pid != pid) continue;
+            uprint("init: {} exited with status {}, restarting\n", static_cast<const char*>(services[i].argv[0]), status);
+            NanoSleep(kRespawnDelayNs);
+            Start(services[i]);
         }
-        Yield();
     }
 }
@@ -0,0 +1,201 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#include "ipc.h"
+
+#include "irq.h"
+#include "paging.h"
+#include "thread.h"
+#include "x86_inst.h"
+#include "src/freestanding/utils.h"
+
+struct Message {
+    uint32_t type;
+    uint32_t size;
+    char data[kMaxMessageSize];
+};
+
+struct Port {
+    int owner;  // pid of the receiving process, 0 if the port is unused
+    int head, count;
+    Message queue[kPortQueueSize];
+    WaitQueue senders;  // blocked on a full queue
+    WaitQueue receivers;  // blocked on an empty queue
+};
+
+static Port ports[kMaxPorts];
+
+static bool IsValidPort(unsigned port) {
+    return port < kMaxPorts && ports[port].owner != 0;
+}
+
+// Returns the new port id or -1.
+void SysCreatePort(Regs* regs) {
+    regs->eax = -1;
+    for (int i = 0; i < kMaxPorts; i++) {
+        if (ports[i].owner == 0) {
+            ports[i].owner = current_thread->pid;
+            ports[i].head = ports[i].count = 0;
+            regs->eax = i;
+            return;
+        }
+    }
+}
+
+static void DestroyPort(int port) {
+    ports[port].owner = 0;
+    // Their system calls are restarted and fail on the invalid port.
+    WakeAll(&ports[port].senders);
+    WakeAll(&ports[port].receivers);
+}
+
+// edx is the port, only its owner can destroy it.
+void SysDestroyPort(Regs* regs) {
+    unsigned port = regs->edx;
+    if (!IsValidPort(port) || ports[port].owner != current_thread->pid) {
+        regs->eax = -1;
+        return;
+    }
+    DestroyPort(port);
+    regs->eax = 0;
+}
+
+void ReleasePorts(int pid) {
+    for (int i = 0; i < kMaxPorts; i++) {
+        if (ports[i].owner == pid) DestroyPort(i);
+    }
+}
+
+// edx is the port, ecx the message type, ebx points to the message of esi bytes. Blocks while the port is full.
+void SysSend(Regs* regs) {
+    unsigned port = regs->edx;
+    uint32_t size = regs->esi;
+    if (!IsValidPort(port) || size > kMaxMessageSize) {
+        regs->eax = -1;
+        return;
+    }
+    auto& p = ports[port];
+    if (p.count == kPortQueueSize) BlockOn(regs, &p.senders, 0);
+    auto& message = p.queue[(p.head + p.count) % kPortQueueSize];
+    if (!CopyFromUser(message.data, regs->ebx, size)) {
+        regs->eax = -kEFAULT;
+        return;
+    }
+    message.type = regs->ecx;
+    message.size = size;
+    p.count++;
+    WakeAll(&p.receivers);
+    regs->eax = 0;
+}
+
+// edx is the port, ecx points to a buffer of ebx bytes, the message type is stored at edi. esi is the timeout in
+// ms, negative to wait forever. Returns the message size or -1 on timeout or error. A message larger than the
+// buffer is truncated. With a bad pointer the message stays queued and -kEFAULT is returned.
+void SysReceive(Regs* regs) {
+    unsigned port = regs->edx;
+    int timeout_ms = regs->esi;
+    if (!IsValidPort(port) || ports[port].owner != current_thread->pid) {
+        regs->eax = -1;
+        return;
+    }
+    auto& p = ports[port];
+    if (p.count == 0) {
+        if (timeout_ms == 0) {
+            regs->eax = -1;
+            return;
+        }
+        BlockOn(regs, &p.receivers, timeout_ms > 0 ? MsToTicks(timeout_ms) : 0);
+    }
+    auto& message = p.queue[p.head];
+    auto size = min<uint32_t>(message.size, regs->ebx);
+    if (!CopyToUser(regs->ecx, message.data, size) || !CopyToUser(regs->edi, &message.type, sizeof(message.type))) {
+        regs->eax = -kEFAULT;
+        return;
+    }
+    p.head = (p.head + 1) % kPortQueueSize;
+    p.count--;
+    WakeAll(&p.senders);
+    regs->eax = size;
+}
+
+struct Event {
+    int owner;  // pid of the creating process, 0 if the event is unused
+    uint64_t counter;
+    WaitQueue readers;  // blocked on a zero counter
+};
+
+static Event events[kMaxEvents];
+
+static bool IsValidEvent(unsigned event) {
+    return event < kMaxEvents && events[event].owner != 0;
+}
+
+// Returns the new event id or -1.
+void SysCreateEvent(Regs* regs) {
+    regs->eax = -1;
+    for (int i = 0; i < kMaxEvents; i++) {
+        if (events[i].owner == 0) {
+            events[i] = Event{current_thread->pid, 0, {}};
+            regs->eax = i;
+            return;
+        }
+    }
+}
+
+static void DestroyEvent(int event) {
+    events[event].owner = 0;
+    WakeAll(&events[event].readers);
+}
+
+// edx is the event, only its creator can destroy it.
+void SysDestroyEvent(Regs* regs) {
+    unsigned event = regs->edx;
+    if (!IsValidEvent(event) || events[event].owner != current_thread->pid) {
+        regs->eax = -1;
+        return;
+    }
+    DestroyEvent(event);
+    regs->eax = 0;
+}
+
+void ReleaseEvents(int pid) {
+    for (int i = 0; i < kMaxEvents; i++) {
+        if (events[i].owner == pid) DestroyEvent(i);
+    }
+}
+
+// edx is the event, the counter is stored at ecx and reset. Blocks while the counter is zero.
+void SysEventRead(Regs* regs) {
+    unsigned event = regs->edx;
+    if (!IsValidEvent(event)) {
+        regs->eax = -1;
+        return;
+    }
+    // Interrupt handlers signal events.
+    X86_cli();
+    if (events[event].counter == 0) BlockOn(regs, &events[event].readers, 0);
+    // Interrupts may signal the event during the copy, only what was read is taken off the counter.
+    uint64_t counter = events[event].counter;
+    X86_sti();
+    if (!CopyToUser(regs->ecx, &counter, sizeof(counter))) {
+        regs->eax = -kEFAULT;
+        return;
+    }
+    X86_cli();
+    events[event].counter -= counter;
+    X86_sti();
+    regs->eax = 0;
+}
+
+// edx is the event, ecx (low) and ebx (high) are added to the counter.
+void SysEventWrite(Regs* regs) {
+    regs->eax = SignalEvent(regs->edx, regs->ecx | (uint64_t(regs->ebx) << 32)) ? 0 : -1;
+}
+
+bool SignalEvent(int event, uint64_t n) {
+    if (!IsValidEvent(event)) return false;
+    events[event].counter += n;
+    if (events[event].counter != 0) WakeAll(&events[event].readers);
+    return true;
+}
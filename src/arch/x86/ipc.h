@@ -0,0 +1,40 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#ifndef OS_IPC_H
+#define OS_IPC_H
+
+#include <cstdint>
+
+#include "entry.h"
+
+// Message passing between processes. A port is a message queue created by a process, which is the only one that
+// can receive from it, any process can send to it. Messages are small typed byte strings, copied into a kernel
+// buffer by the sender and out again by the receiver. Send blocks while the queue is full, receive while it's empty.
+// Byte streams between processes are pipes, FIFOs and Unix domain sockets, which are open files (see file.h).
+constexpr int kMaxPorts = 32;
+constexpr int kMaxMessageSize = 256;
+constexpr int kPortQueueSize = 8;
+
+void SysCreatePort(Regs* regs);
+void SysDestroyPort(Regs* regs);
+void SysSend(Regs* regs);
+void SysReceive(Regs* regs);
+
+void ReleasePorts(int pid);
+
+// Event objects, a 64 bit counter to which writers add and which a reader takes, blocking until it's nonzero. This
+// is the simplest way to wake up a thread, from another thread or from an interrupt handler through SignalEvent.
+// Any process can use an event, it's destroyed by its creator or when the creator exits.
+constexpr int kMaxEvents = 32;
+
+void SysCreateEvent(Regs* regs);
+void SysDestroyEvent(Regs* regs);
+void SysEventRead(Regs* regs);
+void SysEventWrite(Regs* regs);
+
+bool SignalEvent(int event, uint64_t n);
+void ReleaseEvents(int pid);
+
+#endif //OS_IPC_H
@@ -4,7 +4,12 @@
 
 #include "irq.h"
 
+#include "apic.h"
 #include "kassert.h"
+#include "keyboard.h"
+#include "profile.h"
+#include "random.h"
+#include "thread.h"
 #include "x86_inst.h"
 
 constexpr uint16_t kMasterPort = 0x20;
@@ -15,196 +20,171 @@ inline uint16_t PicPort(int irq) {
     return irq >= 8 ? kSlavePort : kMasterPort;
 }
 
-PipeN<1024> key_pipe;
 static volatile int counter = 0;
 
+constexpr uint16_t kPitPort = 0x40;
+constexpr uint16_t kPitCommand = 3;
+
+// Duration of a PIT count in ns as a 16.16 fixed point number (1e9 / 1193182 Hz = 838.095 ns).
+constexpr uint32_t kPitCountNs = 54925;
+static uint32_t pit_divisor;
+static TickTimer* tick_timer;
+static uint32_t tick_ns;
+static int tick_frequency;
+// The tick length changes with the frequency, time is counted in ticks of the current length since the epoch.
+static uint64_t epoch_ns;
+static int epoch_ticks;
+
 int GetTime() {
     return counter;
 }
 
+uint32_t TickNs() {
+    return tick_ns;
+}
+
+int TickFrequency() {
+    return tick_frequency;
+}
+
+int MsToTicks(int ms) {
+    return (uint64_t(ms) * 1000000 + tick_ns - 1) / tick_ns;
+}
+
+int NsToTick(uint64_t ns) {
+    if (ns < epoch_ns) return epoch_ticks;  // deadlines before the change have passed
+    return epoch_ticks + int((ns - epoch_ns) / tick_ns);
+}
+
+uint64_t TickToNs(int tick) {
+    return epoch_ns + int64_t(tick - epoch_ticks) * tick_ns;
+}
+
+uint64_t GetTimeNs() {
+    if (!tick_timer) return 0;  // before the timer is set up
+    int ticks;
+    uint32_t elapsed;
+    do {
+        ticks = counter;
+        elapsed = tick_timer->ElapsedNs();
+    } while (ticks != counter);
+    return epoch_ns + uint64_t(ticks - epoch_ticks) * tick_ns + elapsed;
+}
+
+class Pic : public InterruptController {
+public:
+    void Mask(int irq) override {
+        uint16_t pic_port = PicPort(irq);
+        X86_outb(pic_port + 1, X86_inb(pic_port + 1) | (1 << (irq & 7)));
+    }
+
+    void Unmask(int irq) override {
+        uint16_t pic_port = PicPort(irq);
+        X86_outb(pic_port + 1, X86_inb(pic_port + 1) & ~(1 << (irq & 7)));
+    }
+
+    bool IsMasked(int irq) override {
+        return X86_inb(PicPort(irq) + 1) & (1 << (irq & 7));
+    }
+
+    bool IsSpurious(int irq) override {
+        if ((irq & 7) != 7) return false;
+        // Note, we have set the PIC to ISR mode, so we can read the ISR from the PIC.
+        if (X86_inb(PicPort(irq)) & 0x80) return false;
+        // The master did raise IRQ 2 for a spurious interrupt of the slave, so it still needs its EOI.
+        if (irq == 15) X86_outb(kMasterPort, kEOI);
+        return true;
+    }
+
+    void EndOfInterrupt(int irq) override {
+        if (irq >= 8) {
+            // A slave interrupt is always raised through IRQ 2 of the master,
+            // so we have to send an EOI to the master.
+            X86_outb(kMasterPort, kEOI);
+        }
+        X86_outb(PicPort(irq), kEOI);
+    }
+};
+
+static constinit Pic pic;
+static InterruptController* controller = &pic;
+
 void (*irq_handlers[16])() = {nullptr};
 
 bool RegisterIrqHandler(int irq, void (*handler)()) {
-    uint16_t pic_port = PicPort(irq);
-    auto mask = X86_inb(pic_port + 1);
-    auto irq_bit = 1 << (irq & 7);
-    if ((mask & irq_bit) == 0) {
+    if (!controller->IsMasked(irq)) {
         // IRQ is already enabled, so we can't register a handler.
         return false;
     }
-    X86_outb(pic_port + 1, mask & ~irq_bit);
+    controller->Unmask(irq);
     irq_handlers[irq] = handler;
     return true;
 }
 
-void TimerHandler() {
-    counter++;
-}
+// IRQs forwarded to user space drivers, the tid of the owning thread or 0. A forwarded IRQ stays masked until the
+// driver has serviced the device and acknowledges it, otherwise a level triggered device keeps interrupting.
+static int irq_owner[16];
 
-enum Special {
-    LSHIFT = 0x2A,
-    RSHIFT = 0x36,
-    CTRL = 0x1D,
-    ALT = 0x38,
-    CAPSLOCK = 0x3A,
-    F1 = 0x3B,
-    F2 = 0x3C,
-    F3 = 0x3D,
-    F4 = 0x3E,
-    F5 = 0x3F,
-    F6 = 0x40,
-    F7 = 0x41,
-    F8 = 0x42,
-    F9 = 0x43,
-    F10 = 0x44,
-    F11 = 0x57,
-    F12 = 0x58,
-    NUMLOCK = 0x45,
-    SCROLLLOCK = 0x46,
-    HOME = 0x47,
-    UP = 0x48,
-    PGUP = 0x49,
-    LEFT = 0x4B,
-    RIGHT = 0x4D,
-    END = 0x4F,
-    DOWN = 0x50,
-    PGDN = 0x51,
-    INS = 0x52,
-    DEL = 0x53,
-};
+bool ClaimIrq(int irq, int tid) {
+    constexpr int kCascadeIRQ = 2;
+    if (irq < 0 || irq >= 16 || irq == kCascadeIRQ || irq_handlers[irq] != nullptr || irq_owner[irq] != 0) {
+        return false;
+    }
+    irq_owner[irq] = tid;
+    return true;
+}
 
-char kbd_US[128] = {
-    0,
-    27,  // Escape
-    '1', '2', '3', '4', '5', '6', '7', '8', '9', '0', '-', '=', '\b', '\t',
-    'q', 'w', 'e', 'r', 't', 'y', 'u', 'i', 'o', 'p', '[', ']', '\n',
-    -29, /* control key */
-    'a', 's', 'd', 'f', 'g', 'h', 'j', 'k', 'l', ';', '\'', '`',
-    -42, /* left shift */
-    '\\',
-    'z', 'x', 'c', 'v', 'b', 'n', 'm', ',', '.', '/',
-    -54, /* right shift */
-    '*',
-    -56,  /* Alt */
-    ' ',  /* Space bar */
-    -58,  /* Caps lock */
-    -59, -60, -61, -62, -63, -64, -65, -66, -67, -68,  /* F1 - F10 keys */
-    -69,  /* 69 - Num lock*/
-    -70,  /* Scroll Lock */
-    -71,  /* Home key */
-    -72,  /* Up Arrow */
-    -73,  /* Page Up */
-    '-',
-    -75,  /* Left Arrow */
-    0,
-    -77,  /* Right Arrow */
-    '+',
-    -79,  /* 79 - End key*/
-    -80,  /* Down Arrow */
-    -81,  /* Page Down */
-    -82,  /* Insert Key */
-    -83,  /* Delete Key */
-    0,   0,   0,
-    -87,  /* F11 Key */
-    -88,  /* F12 Key */
-    0,  /* All other keys are undefined */
-};
-char kbd_US_shift[128] = {
-    0,
-    27,  // Escape
-    '!', '@', '#', '$', '%', '^', '&', '*', '(', ')', '_', '+', '\b', '\t',
-    'Q', 'W', 'E', 'R', 'T', 'Y', 'U', 'I', 'O', 'P', '{', '}', '\n',
-    -29, /* control key */
-    'A', 'S', 'D', 'F', 'G', 'H', 'J', 'K', 'L', ':', '\"', '~',
-    -42,  /* left shift */
-    '|',
-    'Z', 'X', 'C', 'V', 'B', 'N', 'M', '<', '>', '?',
-    -54,  /* right shift */
-    '*',  /* keypad '*' */
-    -56,  /* Alt */
-    ' ',  /* Space bar */
-    -58,  /* Caps lock */
-    -59, -60, -61, -62, -63, -64, -65, -66, -67, -68,
-    -69,  /* 69 - Num lock*/
-    -70,  /* Scroll Lock */
-    -71,  /* Home key */
-    -72,  /* Up Arrow */
-    -73,  /* Page Up */
-    '-',
-    -75,  /* Left Arrow */
-    0,
-    -77,  /* Right Arrow */
-    '+',
-    -79,  /* 79 - End key*/
-    -80,  /* Down Arrow */
-    -81,  /* Page Down */
-    -82,  /* Insert Key */
-    -83,  /* Delete Key */
-    0,   0,   0,
-    -87,  /* F11 Key */
-    -88,  /* F12 Key */
-    0,  /* All other keys are undefined */
-};
+void AcknowledgeIrq(int irq, int tid) {
+    if (irq_owner[irq] == tid) controller->Unmask(irq);
+}
 
-void KeyboardHandler() {
-    static uint8_t key_state[16];
-    int key = X86_inb(0x60);
-    if ((key & 0x80) == 0) {
-        key_state[(key & 0x7f) >> 3] |= 1 << (key & 7);
-        bool shift = (key_state[LSHIFT / 8] & (1 << (LSHIFT & 7))) || (key_state[RSHIFT / 8] & (1 << (RSHIFT & 7)));
-        bool capslock = key_state[CAPSLOCK / 8] & (1 << (CAPSLOCK & 7));
-        int8_t c = (shift != capslock) ? kbd_US_shift[key] : kbd_US[key];
-        if (c <= 0) {
-            return;
-        } else {
-            key_pipe.Push(c);
-        }
-    } else {
-        key_state[(key & 0x7f) >> 3] &= ~(1 << (key & 7));
+void ReleaseIrqs(int tid) {
+    for (int irq = 0; irq < 16; irq++) {
+        if (irq_owner[irq] != tid) continue;
+        controller->Mask(irq);
+        irq_owner[irq] = 0;
     }
 }
 
+void TimerHandler() {
+    counter++;
+    SchedulerTick(counter);
+}
+
 void IrqHandler(Regs* regs) {
     int irq = regs->int_no - 32;
-    if (irq >= 8) {
-        // A slave interrupt is always raised through IRQ 2 of the master,
-        // so we have to send an EOI to the master.
-        X86_outb(kMasterPort, kEOI);
-    }
-    uint16_t pic_port = PicPort(irq);
-    uint8_t irq_bit = 1 << (irq & 7);
-
-    bool spurious_irq = irq_bit == 0x80;  // irq == 7 || irq == 15
-    if (spurious_irq) {
-        // Note, we have set the PIC to ISR mode, so we can read the ISR from the PIC.
-        auto isr_reg = X86_inb(pic_port);
-        if ((isr_reg & irq_bit) == 0) return;
-    }
-    // Interrupts are allowed to nest except for the same IRQ. At this point the PIC
+    if (controller->IsSpurious(irq)) return;
+    // Interrupts are allowed to nest except for the same IRQ. At this point the controller
     // is blocking all IRQs it handles. So we first block the IRQ we are handling.
-    auto mask = X86_inb(pic_port + 1);
-    X86_outb(pic_port + 1, mask | irq_bit);
-    // Acknowledge interrupt by sending End Of Interrupt to PIC.
-    X86_outb(pic_port, kEOI);
+    controller->Mask(irq);
+    // Acknowledge interrupt by sending End Of Interrupt to the controller.
+    controller->EndOfInterrupt(irq);
     // At this point interrupts are resumed except for the IRQ we are handling.
+    AddEntropy(irq);
 
-    if (irq_handlers[irq] != nullptr) {
-        irq_handlers[irq]();
+    if (irq_owner[irq] != 0) {
+        // Stays blocked until the driver acknowledges it.
+        DeliverIrq(irq_owner[irq], irq);
     } else {
-        kprint("Unhandled IRQ {}\n", irq);
+        if (irq_handlers[irq] != nullptr) {
+            irq_handlers[irq]();
+        } else {
+            kprint("Unhandled IRQ {}\n", irq);
+        }
+
+        // Unblock IRQ.
+        controller->Unmask(irq);
     }
 
-    // Unblock IRQ.
-    X86_outb(pic_port + 1, mask);
+    if (irq == 0) ProfileTick(regs);
 }
 
 void InitializePit(int channel, int frequency) {
-    constexpr uint16_t kPitPort = 0x40;
-    constexpr uint16_t kCommand = 3;
-    // Set PIT to mode 3 (square wave generator)
-    // channel(2 bits) = 0, rw mode (2 bits) = 3 (LSB then MSB), mode (3 bits) = 3 (square wave), bcd (1 bit) = 0
-    constexpr uint8_t kMode3 = 0x36;
-    X86_outb(kPitPort + kCommand, (channel << 6) | kMode3);
+    // Set PIT to mode 2 (rate generator), unlike the square wave mode the count decreases by one per PIT clock
+    // which makes it usable to tell the time within a tick.
+    // channel(2 bits) = 0, rw mode (2 bits) = 3 (LSB then MSB), mode (3 bits) = 2 (rate generator), bcd (1 bit) = 0
+    constexpr uint8_t kMode2 = 0x34;
+    X86_outb(kPitPort + kPitCommand, (channel << 6) | kMode2);
     // 2^32 ticks per hour equals 1193046.47111 Hz which is surprisingly close to 1193182 Hz.
     // The lowest frequency is chosen when the divisor = 65536 leading to the classical 18.2 Hz timer.
     // Calculate divisor for frequency
@@ -218,6 +198,42 @@ void InitializePit(int channel, int frequency) {
     // Set frequency by sending the divisor LSB then MSB
     X86_outb(kPitPort + channel, divisor & 0xFF);
     X86_outb(kPitPort + channel, divisor >> 8);
+    if (channel == 0) pit_divisor = divisor ? divisor : 0x10000;
+}
+
+class Pit : public TickTimer {
+public:
+    uint32_t Start(int hz) override {
+        InitializePit(0, hz);
+        return (uint64_t(pit_divisor) * kPitCountNs) >> 16;
+    }
+
+    // The PIT runs in rate generator mode, its counter goes from the divisor down to 1 once per tick, so the
+    // count tells how far we are into the current tick.
+    uint32_t ElapsedNs() override {
+        X86_outb(kPitPort + kPitCommand, 0);  // latch channel 0
+        uint32_t count = X86_inb(kPitPort);
+        count |= X86_inb(kPitPort) << 8;
+        uint32_t elapsed = pit_divisor - (count ? count : 0x10000);
+        if (elapsed >= pit_divisor) elapsed = 0;
+        return (uint64_t(elapsed) * kPitCountNs) >> 16;
+    }
+};
+
+static constinit Pit pit;
+
+// Time continues without a jump, but deadlines already expressed in ticks (timeouts) pass at the new rate. Sleeps
+// have a deadline in ns, the scheduler moves them to the tick of the new rate (see RearmSleepers).
+bool SetTickFrequency(int hz) {
+    // Below 19 Hz the divisor of the PIT doesn't fit in 16 bits.
+    if (hz < 19 || hz > 10000) return false;
+    auto flags = X86_save_flags_cli();
+    epoch_ns = GetTimeNs();
+    epoch_ticks = counter;
+    tick_ns = tick_timer->Start(hz);
+    tick_frequency = hz;
+    X86_restore_flags(flags);
+    return true;
 }
 
 void InitializePic(uint16_t port, uint8_t irq_offset, uint8_t cascade) {
@@ -245,8 +261,22 @@ void RemapInterrupts() {
     InitializePic(kMasterPort, 0x20, 1 << kCascadeIRQ);
     // Set slave PIC IRQs starting at 40 (0x28)
     InitializePic(kSlavePort, 0x28, kCascadeIRQ);
+    // Even when the APIC takes over, the PICs stay remapped so their spurious interrupts don't look like exceptions.
+    if (auto apic = InitApic()) {
+        X86_outb(kMasterPort + 1, 0xFF);
+        X86_outb(kSlavePort + 1, 0xFF);
+        controller = apic;
+    }
 
-    InitializePit(0, 100);
+    // The local APIC timer is the tick when the APIC is used and the timer can be calibrated, it has a far better
+    // resolution than the PIT and reading it is cheaper than latching the PIT. The PIT stays masked then.
+    tick_timer = &pit;
+    if (controller != &pic) {
+        if (auto apic_timer = InitApicTimer()) tick_timer = apic_timer;
+    }
+    tick_ns = tick_timer->Start(100);
+    tick_frequency = 100;
     RegisterIrqHandler(0, TimerHandler);
+    InitKeyboard();
     RegisterIrqHandler(1, KeyboardHandler);
 }
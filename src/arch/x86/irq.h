@@ -5,13 +5,51 @@
 #ifndef OS_IRQ_H
 #define OS_IRQ_H
 
+#include <cstdint>
+
 #include "entry.h"
-#include "pipe.h"
 
-extern PipeN<1024> key_pipe;
+// Routes the 16 ISA IRQs to vectors 32 to 47. The 8259 PICs are always there, the APIC replaces them when the
+// firmware describes it.
+class InterruptController {
+public:
+    virtual void Mask(int irq) = 0;
+    virtual void Unmask(int irq) = 0;
+    virtual bool IsMasked(int irq) = 0;
+    // Spurious interrupts must be neither handled nor acknowledged.
+    virtual bool IsSpurious(int irq) = 0;
+    virtual void EndOfInterrupt(int irq) = 0;
+};
+
+// The timer raising IRQ 0 for the tick, the PIT unless the local APIC timer can take over. It tells how far along
+// the current tick is, which gives the time with sub-tick precision.
+class TickTimer {
+public:
+    virtual uint32_t Start(int hz) = 0;  // returns the duration of a tick in ns
+    virtual uint32_t ElapsedNs() = 0;  // since the start of the current tick
+};
 
-int GetTime();
+int GetTime();  // timer ticks since boot
+uint32_t TickNs();  // duration of a timer tick in ns
+uint64_t GetTimeNs();  // monotonic time since boot in ns, with sub-tick precision
+// TODO: there is no wall clock yet, it would start from the CMOS RTC. An NTP client could then keep it in sync by
+// slewing (running it slightly fast or slow rather than jumping) and report its state in procfs, but it needs UDP
+// and a NIC driver, net only has raw packets on loopback.
+int MsToTicks(int ms);  // rounded up
+// Conversion between time in ns and ticks since boot, ticks of different lengths passed before the last frequency
+// change. NsToTick gives the tick during which time ns falls, TickToNs the time at which tick starts.
+int NsToTick(uint64_t ns);
+uint64_t TickToNs(int tick);
+int TickFrequency();
+bool SetTickFrequency(int hz);
 void IrqHandler(Regs* regs);
+bool RegisterIrqHandler(int irq, void (*handler)());  // for kernel drivers, false if the IRQ is in use
+
+// Forwarding of IRQs to user space drivers. An IRQ that has no kernel handler can be claimed by a thread, it's
+// masked while the driver handles it and unmasked again by AcknowledgeIrq.
+bool ClaimIrq(int irq, int tid);
+void AcknowledgeIrq(int irq, int tid);
+void ReleaseIrqs(int tid);
 void RemapInterrupts();
 
 #endif //OS_IRQ_H
@@ -16,8 +16,26 @@ void kprint(std::string_view fmt, Args... args) {
     print(reinterpret_cast<OutputStream&>(kout), fmt, args...);
 }
 
+// Levels of kernel messages, lower is more important. kprint logs at the info level.
+enum LogLevel : int {
+    kLogError = 3,
+    kLogWarning = 4,
+    kLogInfo = 6,
+    kLogDebug = 7,
+};
+
+int SetMessageLevel(int level);  // returns the old level
+
+template <typename... Args>
+void klog(LogLevel level, std::string_view fmt, Args... args) {
+    auto old = SetMessageLevel(level);
+    kprint(fmt, args...);
+    SetMessageLevel(old);
+}
+
 template<typename... Args>
 void panic(std::string_view format, const Args&... args) {
+    SetMessageLevel(kLogError);
     kprint("Kernel panic: ");
     kprint(format, args...);
     terminate(-1);
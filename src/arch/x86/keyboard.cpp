@@ -0,0 +1,494 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#include "keyboard.h"
+
+#include "console.h"
+#include "kassert.h"
+#include "tty.h"
+#include "vfs.h"
+#include "x86_inst.h"
+
+enum Special {
+    ESC = 0x01,
+    LSHIFT = 0x2A,
+    RSHIFT = 0x36,
+    CTRL = 0x1D,
+    ALT = 0x38,
+    CAPSLOCK = 0x3A,
+    F1 = 0x3B,
+    F2 = 0x3C,
+    F3 = 0x3D,
+    F4 = 0x3E,
+    F5 = 0x3F,
+    F6 = 0x40,
+    F7 = 0x41,
+    F8 = 0x42,
+    F9 = 0x43,
+    F10 = 0x44,
+    F11 = 0x57,
+    F12 = 0x58,
+    NUMLOCK = 0x45,
+    SCROLLLOCK = 0x46,
+    HOME = 0x47,
+    UP = 0x48,
+    PGUP = 0x49,
+    KP_MINUS = 0x4A,
+    LEFT = 0x4B,
+    KP_5 = 0x4C,
+    RIGHT = 0x4D,
+    KP_PLUS = 0x4E,
+    END = 0x4F,
+    DOWN = 0x50,
+    PGDN = 0x51,
+    INS = 0x52,
+    DEL = 0x53,
+};
+
+constexpr Keymap kUSKeymap = {
+    {
+        0,
+        27,  // Escape
+        '1', '2', '3', '4', '5', '6', '7', '8', '9', '0', '-', '=', '\b', '\t',
+        'q', 'w', 'e', 'r', 't', 'y', 'u', 'i', 'o', 'p', '[', ']', '\n',
+        0,  /* control key */
+        'a', 's', 'd', 'f', 'g', 'h', 'j', 'k', 'l', ';', '\'', '`',
+        0,  /* left shift */
+        '\\',
+        'z', 'x', 'c', 'v', 'b', 'n', 'm', ',', '.', '/',
+        0,  /* right shift */
+        '*',  /* keypad '*' */
+        0,  /* Alt */
+        ' ',  /* Space bar */
+        0,  /* Caps lock */
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0,  /* F1 - F10 keys */
+        0,  /* 69 - Num lock*/
+        0,  /* Scroll Lock */
+        0,  /* Home key */
+        0,  /* Up Arrow */
+        0,  /* Page Up */
+        '-',  /* keypad '-' */
+        0,  /* Left Arrow */
+        0,
+        0,  /* Right Arrow */
+        '+',  /* keypad '+' */
+        0,  /* 79 - End key*/
+        0,  /* Down Arrow */
+        0,  /* Page Down */
+        0,  /* Insert Key */
+        0,  /* Delete Key */
+        0,   0,   0,
+        0,  /* F11 Key */
+        0,  /* F12 Key */
+        0,  /* All other keys are undefined */
+    },
+    {
+        0,
+        27,  // Escape
+        '!', '@', '#', '$', '%', '^', '&', '*', '(', ')', '_', '+', '\b', '\t',
+        'Q', 'W', 'E', 'R', 'T', 'Y', 'U', 'I', 'O', 'P', '{', '}', '\n',
+        0,  /* control key */
+        'A', 'S', 'D', 'F', 'G', 'H', 'J', 'K', 'L', ':', '\"', '~',
+        0,  /* left shift */
+        '|',
+        'Z', 'X', 'C', 'V', 'B', 'N', 'M', '<', '>', '?',
+        0,  /* right shift */
+        '*',  /* keypad '*' */
+        0,  /* Alt */
+        ' ',  /* Space bar */
+        0,  /* Caps lock */
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0,  /* F1 - F10 keys */
+        0,  /* 69 - Num lock*/
+        0,  /* Scroll Lock */
+        0,  /* Home key */
+        0,  /* Up Arrow */
+        0,  /* Page Up */
+        '-',  /* keypad '-' */
+        0,  /* Left Arrow */
+        0,
+        0,  /* Right Arrow */
+        '+',  /* keypad '+' */
+        0,  /* 79 - End key*/
+        0,  /* Down Arrow */
+        0,  /* Page Down */
+        0,  /* Insert Key */
+        0,  /* Delete Key */
+        0,   0,   0,
+        0,  /* F11 Key */
+        0,  /* F12 Key */
+        0,  /* All other keys are undefined */
+    },
+    {},  // US has no AltGr characters
+};
+
+// With num lock on the keypad produces digits, independent of the layout.
+constexpr char kNumpad[] = "789-456+1230.";
+
+constinit Keymap keymap = kUSKeymap;
+
+static bool ParseNumber(std::string_view s, int& value) {
+    int base = 10;
+    if (s.size() > 2 && s[0] == '0' && (s[1] == 'x' || s[1] == 'X')) {
+        base = 16;
+        s.remove_prefix(2);
+    }
+    if (s.empty()) return false;
+    value = 0;
+    for (char c : s) {
+        int digit;
+        if (c >= '0' && c <= '9') {
+            digit = c - '0';
+        } else if (base == 16 && c >= 'a' && c <= 'f') {
+            digit = c - 'a' + 10;
+        } else if (base == 16 && c >= 'A' && c <= 'F') {
+            digit = c - 'A' + 10;
+        } else {
+            return false;
+        }
+        if (digit >= base) return false;
+        value = value * base + digit;
+        if (value > 255) return false;
+    }
+    return true;
+}
+
+static bool ParseKeymap(std::string_view text, Keymap& map) {
+    while (!text.empty()) {
+        auto eol = text.find('\n');
+        auto line = text.substr(0, eol);
+        text.remove_prefix(eol == std::string_view::npos ? text.size() : eol + 1);
+
+        std::string_view tokens[4];
+        int n = 0;
+        while (true) {
+            while (!line.empty() && (line[0] == ' ' || line[0] == '\t' || line[0] == '\r')) line.remove_prefix(1);
+            if (line.empty()) break;
+            if (n == 0 && line[0] == '#') break;  // comment
+            if (n == 4) return false;
+            std::size_t len = 0;
+            while (len < line.size() && line[len] != ' ' && line[len] != '\t' && line[len] != '\r') len++;
+            tokens[n++] = line.substr(0, len);
+            line.remove_prefix(len);
+        }
+        if (n == 0) continue;
+        if (n == 1) return false;
+
+        int scancode;
+        if (!ParseNumber(tokens[0], scancode) || scancode >= kKeymapSize) return false;
+        uint8_t* tables[3] = {map.normal, map.shift, map.altgr};
+        for (int i = 1; i < n; i++) {
+            int c;
+            if (tokens[i].size() == 1) {
+                c = static_cast<uint8_t>(tokens[i][0]);
+            } else if (!ParseNumber(tokens[i], c)) {
+                return false;
+            }
+            tables[i - 1][scancode] = c;
+        }
+    }
+    return true;
+}
+
+bool LoadKeymap(std::string_view name) {
+    if (name == "us") {
+        keymap = kUSKeymap;
+        return true;
+    }
+
+    constexpr std::string_view kPrefix = "src/arch/x86/keymaps/";
+    constexpr std::string_view kSuffix = ".kmap";
+    char path[64];
+    if (name.empty() || name.size() > sizeof(path) - kPrefix.size() - kSuffix.size()) return false;
+    memcpy(path, kPrefix.data(), kPrefix.size());
+    memcpy(path + kPrefix.size(), name.data(), name.size());
+    memcpy(path + kPrefix.size() + name.size(), kSuffix.data(), kSuffix.size());
+
+    static char buffer[4096];
+    auto file = VfsLookup(std::string_view(path, kPrefix.size() + name.size() + kSuffix.size()));
+    if (!file.fs) return false;
+    int size = file.fs->Read(file.node, 0, buffer, sizeof(buffer));
+    if (size < 0 || size == sizeof(buffer)) return false;
+
+    Keymap tmp = kUSKeymap;
+    if (!ParseKeymap(std::string_view(buffer, size), tmp)) {
+        kprint("Malformed keymap {}\n", name);
+        return false;
+    }
+    keymap = tmp;
+    return true;
+}
+
+// Scancode set 2 is the only set every keyboard supports. Normally the 8042 translates it into set 1, which is what
+// the keymaps are indexed by, but not every controller can translate, in which case we do it ourselves.
+constexpr uint8_t kSet2ToSet1Pairs[][2] = {
+    {0x76, ESC}, {0x16, 0x02}, {0x1E, 0x03}, {0x26, 0x04}, {0x25, 0x05}, {0x2E, 0x06}, {0x36, 0x07}, {0x3D, 0x08},
+    {0x3E, 0x09}, {0x46, 0x0A}, {0x45, 0x0B}, {0x4E, 0x0C}, {0x55, 0x0D}, {0x66, 0x0E}, {0x0D, 0x0F},
+    {0x15, 0x10}, {0x1D, 0x11}, {0x24, 0x12}, {0x2D, 0x13}, {0x2C, 0x14}, {0x35, 0x15}, {0x3C, 0x16}, {0x43, 0x17},
+    {0x44, 0x18}, {0x4D, 0x19}, {0x54, 0x1A}, {0x5B, 0x1B}, {0x5A, 0x1C}, {0x14, CTRL},
+    {0x1C, 0x1E}, {0x1B, 0x1F}, {0x23, 0x20}, {0x2B, 0x21}, {0x34, 0x22}, {0x33, 0x23}, {0x3B, 0x24}, {0x42, 0x25},
+    {0x4B, 0x26}, {0x4C, 0x27}, {0x52, 0x28}, {0x0E, 0x29}, {0x12, LSHIFT}, {0x5D, 0x2B},
+    {0x1A, 0x2C}, {0x22, 0x2D}, {0x21, 0x2E}, {0x2A, 0x2F}, {0x32, 0x30}, {0x31, 0x31}, {0x3A, 0x32}, {0x41, 0x33},
+    {0x49, 0x34}, {0x4A, 0x35}, {0x59, RSHIFT}, {0x7C, 0x37}, {0x11, ALT}, {0x29, 0x39}, {0x58, CAPSLOCK},
+    {0x05, F1}, {0x06, F2}, {0x04, F3}, {0x0C, F4}, {0x03, F5}, {0x0B, F6}, {0x83, F7}, {0x0A, F8}, {0x01, F9},
+    {0x09, F10}, {0x78, F11}, {0x07, F12}, {0x77, NUMLOCK}, {0x7E, SCROLLLOCK},
+    {0x6C, HOME}, {0x75, UP}, {0x7D, PGUP}, {0x7B, KP_MINUS}, {0x6B, LEFT}, {0x73, KP_5}, {0x74, RIGHT},
+    {0x79, KP_PLUS}, {0x69, END}, {0x72, DOWN}, {0x7A, PGDN}, {0x70, INS}, {0x71, DEL}, {0x61, 0x56},
+    {0x1F, 0x5B}, {0x27, 0x5C}, {0x2F, 0x5D},  // only reachable with the 0xE0 prefix, the GUI and menu keys
+};
+
+struct Set2Table {
+    uint8_t set1[0x84];
+};
+
+constexpr Set2Table MakeSet2Table() {
+    Set2Table table{};
+    for (auto& pair : kSet2ToSet1Pairs) table.set1[pair[0]] = pair[1];
+    return table;
+}
+
+constexpr Set2Table kSet2Table = MakeSet2Table();
+
+constexpr uint16_t kDataPort = 0x60;
+constexpr uint16_t kStatusPort = 0x64;  // reads give the status, writes are controller commands
+
+constexpr uint8_t kOutputFull = 1;
+constexpr uint8_t kInputFull = 2;
+
+constexpr uint8_t kPort1Irq = 1;
+constexpr uint8_t kPort2Irq = 2;
+constexpr uint8_t kPort2ClockDisabled = 1 << 5;
+constexpr uint8_t kTranslate = 1 << 6;
+
+constexpr int kAck = 0xFA;
+constexpr int kResend = 0xFE;
+
+// Set when the keyboard sends set 2 and the controller doesn't translate.
+static bool decode_set2 = false;
+
+static bool WaitStatus(uint8_t bit, bool set) {
+    // Generous, the keyboard reset in particular can take a few hundred ms.
+    for (int i = 0; i < 1000000; i++) {
+        if (((X86_inb(kStatusPort) & bit) != 0) == set) return true;
+    }
+    return false;
+}
+
+static int ReadData() {
+    if (!WaitStatus(kOutputFull, true)) return -1;
+    return X86_inb(kDataPort);
+}
+
+static bool WriteData(uint8_t data) {
+    if (!WaitStatus(kInputFull, false)) return false;
+    X86_outb(kDataPort, data);
+    return true;
+}
+
+static bool ControllerCommand(uint8_t cmd) {
+    if (!WaitStatus(kInputFull, false)) return false;
+    X86_outb(kStatusPort, cmd);
+    return true;
+}
+
+static int ReadConfig() {
+    if (!ControllerCommand(0x20)) return -1;
+    return ReadData();
+}
+
+static void WriteConfig(uint8_t config) {
+    if (ControllerCommand(0x60)) WriteData(config);
+}
+
+// Sends a command byte to the keyboard and returns its response, retrying when the keyboard asks for a resend.
+static int DeviceCommand(uint8_t cmd) {
+    for (int retry = 0; retry < 3; retry++) {
+        if (!WriteData(cmd)) return -1;
+        int response = ReadData();
+        if (response != kResend) return response;
+    }
+    return -1;
+}
+
+// Brings the 8042 and the keyboard into a known state, instead of relying on whatever the BIOS left behind.
+void InitKeyboard() {
+    // Stop both devices from sending while we reconfigure, and drop anything already buffered.
+    ControllerCommand(0xAD);
+    ControllerCommand(0xA7);
+    for (int i = 0; i < 16 && (X86_inb(kStatusPort) & kOutputFull); i++) X86_inb(kDataPort);
+
+    int config = ReadConfig();
+    if (config < 0) {
+        kprint("PS/2 controller not responding, keeping BIOS setup\n");
+        ControllerCommand(0xAE);
+        return;
+    }
+    bool maybe_dual = config & kPort2ClockDisabled;
+    config &= ~(kPort1Irq | kPort2Irq | kTranslate);
+    WriteConfig(config);
+
+    if (!ControllerCommand(0xAA) || ReadData() != 0x55) {
+        kprint("PS/2 controller self test failed\n");
+        return;
+    }
+    // The self test can reset the controller on some chipsets.
+    WriteConfig(config);
+
+    // If enabling the second port enables its clock there is a second (mouse) port.
+    bool dual = false;
+    if (maybe_dual) {
+        ControllerCommand(0xA8);
+        dual = (ReadConfig() & kPort2ClockDisabled) == 0;
+        ControllerCommand(0xA7);
+    }
+
+    if (!ControllerCommand(0xAB) || ReadData() != 0) {
+        kprint("PS/2 keyboard port failed interface test\n");
+        return;
+    }
+    ControllerCommand(0xAE);
+
+    if (DeviceCommand(0xFF) != kAck || ReadData() != 0xAA) {
+        kprint("Keyboard reset failed\n");
+    }
+
+    // Ask for set 2 explicitly and read back what the keyboard actually uses, without translation the answer is
+    // the plain set number.
+    int set = 2;
+    if (DeviceCommand(0xF0) != kAck || DeviceCommand(0x02) != kAck) {
+        kprint("Keyboard refused scancode set 2\n");
+    }
+    if (DeviceCommand(0xF0) == kAck && DeviceCommand(0x00) == kAck) {
+        int current = ReadData();
+        if (current == 1 || current == 2) set = current;
+    }
+
+    bool translate = false;
+    if (set == 2) {
+        WriteConfig(config | kTranslate);
+        translate = (ReadConfig() & kTranslate) != 0;
+        if (translate) config |= kTranslate;
+        decode_set2 = !translate;
+    }
+    WriteConfig(config | kPort1Irq);
+    DeviceCommand(0xF4);  // Enable scanning
+
+    kprint("PS/2: {} port(s), keyboard scancode set {}{}\n", dual ? 2 : 1, set, translate ? " (translated)" : "");
+}
+
+// Keys with the 0xE0 prefix are identified by setting bit 7 of their set 1 code.
+constexpr int kExtended = 0x80;
+constexpr int RCTRL = kExtended | CTRL;
+constexpr int ALTGR = kExtended | ALT;
+constexpr int KP_ENTER = kExtended | 0x1C;
+constexpr int KP_SLASH = kExtended | 0x35;
+constexpr int GREY_UP = kExtended | UP;
+constexpr int GREY_DOWN = kExtended | DOWN;
+constexpr int GREY_LEFT = kExtended | LEFT;
+constexpr int GREY_RIGHT = kExtended | RIGHT;
+constexpr int GREY_INS = kExtended | INS;
+
+static void HandleKey(int key, bool released) {
+    static uint8_t key_state[32];
+    static bool capslock = false;
+    static bool numlock = false;
+    auto is_down = [](int k) { return (key_state[k >> 3] & (1 << (k & 7))) != 0; };
+
+    if (released) {
+        key_state[key >> 3] &= ~(1 << (key & 7));
+        if ((key == LSHIFT || key == RSHIFT) && !is_down(LSHIFT) && !is_down(RSHIFT)) EndSelection();
+        return;
+    }
+    key_state[key >> 3] |= 1 << (key & 7);
+    bool shift = is_down(LSHIFT) || is_down(RSHIFT);
+    bool alt = is_down(ALT);
+    bool ctrl = is_down(CTRL) || is_down(RCTRL);
+    bool altgr = is_down(ALTGR);
+    if (key == CAPSLOCK) {
+        capslock = !capslock;
+        return;
+    }
+    if (key == NUMLOCK) {
+        numlock = !numlock;
+        return;
+    }
+    if (alt && key >= F1 && key < F1 + kNumConsoles) {
+        // Alt+Fn switches to virtual console n
+        SwitchConsole(key - F1);
+        return;
+    }
+    if (shift) {
+        switch (key) {
+            case GREY_UP: return MoveSelection(0, -1);
+            case GREY_DOWN: return MoveSelection(0, 1);
+            case GREY_LEFT: return MoveSelection(-1, 0);
+            case GREY_RIGHT: return MoveSelection(1, 0);
+            case GREY_INS: return Paste();
+            default: break;
+        }
+    }
+    uint8_t c;
+    if (key & kExtended) {
+        // The grey navigation block never depends on num lock or the layout.
+        c = key == KP_ENTER ? '\n' : key == KP_SLASH ? '/' : 0;
+    } else if (key >= HOME && key <= DEL && key != KP_MINUS && key != KP_PLUS && numlock != shift) {
+        c = kNumpad[key - HOME];
+    } else if (altgr) {
+        c = keymap.altgr[key];
+    } else {
+        c = (shift != capslock) ? keymap.shift[key] : keymap.normal[key];
+    }
+    // Ctrl with a letter gives the control character, ctrl+U is the kill character of the line discipline.
+    if (ctrl && (c | 0x20) >= 'a' && (c | 0x20) <= 'z') c &= 0x1F;
+    if (c == 0) {
+        return;
+    } else {
+        TtyInput(active_console, c);
+    }
+}
+
+void KeyboardHandler() {
+    static bool extended = false;
+    static bool break_prefix = false;
+    static int skip = 0;
+
+    int code = X86_inb(kDataPort);
+    if (skip > 0) {
+        skip--;
+        return;
+    }
+    if (code == 0xE0) {
+        extended = true;
+        return;
+    }
+    if (code == 0xE1) {
+        // Pause is the only key with this prefix, it has no break code and we ignore the whole sequence.
+        skip = decode_set2 ? 7 : 5;
+        return;
+    }
+    if (code == kAck || code == kResend || code == 0x00 || code == 0xFF) {
+        // Command responses and buffer overrun errors.
+        return;
+    }
+
+    int key;
+    bool released;
+    if (decode_set2) {
+        if (code == 0xF0) {
+            break_prefix = true;
+            return;
+        }
+        key = code < static_cast<int>(sizeof(kSet2Table.set1)) ? kSet2Table.set1[code] : 0;
+        released = break_prefix;
+        break_prefix = false;
+    } else {
+        key = code & 0x7F;
+        released = code & 0x80;
+    }
+    bool is_extended = extended;
+    extended = false;
+
+    // 0xE0 0x2A and 0xE0 0x36 are fake shifts some keyboards wrap around the navigation keys.
+    if (key == 0 || (is_extended && (key == LSHIFT || key == RSHIFT))) return;
+    HandleKey(key | (is_extended ? kExtended : 0), released);
+}
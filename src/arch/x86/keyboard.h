@@ -0,0 +1,32 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#ifndef OS_KEYBOARD_H
+#define OS_KEYBOARD_H
+
+#include <cstdint>
+#include <string_view>
+
+constexpr int kKeymapSize = 128;
+
+// Translation of (set 1) scancodes into characters for a keyboard layout. A zero entry means the key doesn't
+// produce a character in that shift state. Layouts other than the built-in US one are loaded from text files in
+// the ramdisk (src/arch/x86/keymaps/<name>.kmap), each line of the form
+//     <scancode> <normal> [<shift> [<altgr>]]
+// where a single character stands for itself and anything longer is a decimal or 0x prefixed hex number. Only the
+// listed keys are changed relative to the US layout.
+struct Keymap {
+    uint8_t normal[kKeymapSize];
+    uint8_t shift[kKeymapSize];
+    uint8_t altgr[kKeymapSize];
+};
+
+void InitKeyboard();
+void KeyboardHandler();
+
+// Selects the active layout, "us" is built-in. Returns false if the layout can't be found or parsed, in which
+// case the active layout is unchanged.
+bool LoadKeymap(std::string_view name);
+
+#endif //OS_KEYBOARD_H
@@ -0,0 +1,114 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#include "klog.h"
+
+#include "console.h"
+#include "irq.h"
+#include "kassert.h"
+#include "paging.h"
+#include "sysctl.h"
+#include "thread.h"
+#include "x86_inst.h"
+
+static char ring[kKlogSize];
+static uint32_t klog_end;  // bytes ever logged
+static bool at_line_start = true;
+static bool line_to_console;
+static int message_level = kLogInfo;
+static int console_level = kLogDebug;
+static WaitQueue klog_readers;
+
+int SetMessageLevel(int level) {
+    auto old = message_level;
+    message_level = level;
+    return old;
+}
+
+static void Append(std::string_view str) {
+    for (char c : str) ring[klog_end++ % kKlogSize] = c;
+}
+
+// Right aligned in width characters, padded with pad.
+static std::size_t FormatNumber(char* out, uint32_t value, int width, char pad) {
+    char digits[10];
+    int n = 0;
+    do {
+        digits[n++] = '0' + value % 10;
+        value /= 10;
+    } while (value);
+    std::size_t size = 0;
+    for (int i = n; i < width; i++) out[size++] = pad;
+    while (n) out[size++] = digits[--n];
+    return size;
+}
+
+static void AppendPrefix() {
+    char prefix[32];
+    std::size_t size = 0;
+    auto ns = GetTimeNs();
+    prefix[size++] = '<';
+    prefix[size++] = '0' + message_level;
+    prefix[size++] = '>';
+    prefix[size++] = '[';
+    size += FormatNumber(prefix + size, ns / 1000000000, 5, ' ');
+    prefix[size++] = '.';
+    size += FormatNumber(prefix + size, ns / 1000 % 1000000, 6, '0');
+    prefix[size++] = ']';
+    prefix[size++] = ' ';
+    Append(std::string_view(prefix, size));
+}
+
+void InitKlog() {
+    RegisterTunable({"kernel/console_level", [] { return console_level; }, [](int value) {
+        if (value < 0 || value > kLogDebug) return false;
+        console_level = value;
+        return true;
+    }});
+}
+
+// Output from interrupt handlers can still end up in the middle of a line, but the buffer stays consistent.
+void KlogWrite(std::string_view str) {
+    while (!str.empty()) {
+        std::size_t n = 0;
+        while (n < str.size() && str[n] != '\n') n++;
+        if (n < str.size()) n++;
+        auto flags = X86_save_flags_cli();
+        if (at_line_start) {
+            AppendPrefix();
+            line_to_console = message_level <= console_level;
+        }
+        at_line_start = str[n - 1] == '\n';
+        Append(std::string_view(str.data(), n));
+        bool to_console = line_to_console;
+        X86_restore_flags(flags);
+        if (to_console) ActiveConsole().Write(std::string_view(str.data(), n));
+        str.remove_prefix(n);
+    }
+    WakeAll(&klog_readers);
+}
+
+void SysKlog(Regs* regs) {
+    uint32_t pos;
+    if (!CopyFromUser(&pos, regs->ebx, sizeof(pos)) || !IsUserRange(regs->edx, regs->ecx)) {
+        regs->eax = -kEFAULT;
+        return;
+    }
+    X86_cli();
+    uint32_t end = klog_end;
+    uint32_t start = end > kKlogSize ? end - kKlogSize : 0;
+    if (pos - start > end - start) pos = start;  // lost to the wrap around, or bogus
+    if (pos == end && regs->esi != 0) BlockOn(regs, &klog_readers, 0);
+    X86_sti();
+    uint32_t n = min<uint32_t>(regs->ecx, end - pos);
+    // The ring wraps around at most once in the part that is read.
+    uint32_t first = min<uint32_t>(n, kKlogSize - pos % kKlogSize);
+    uint32_t next = pos + n;
+    if (!CopyToUser(regs->edx, ring + pos % kKlogSize, first) || !CopyToUser(regs->edx + first, ring, n - first) ||
+        !CopyToUser(regs->ebx, &next, sizeof(next))) {
+        regs->eax = -kEFAULT;
+        return;
+    }
+    regs->eax = n;
+}
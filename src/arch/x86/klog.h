@@ -0,0 +1,28 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#ifndef OS_KLOG_H
+#define OS_KLOG_H
+
+#include <cstdint>
+#include <string_view>
+
+#include "entry.h"
+
+// Kernel log. All kernel output goes into a ring buffer, every line prefixed with its level and the time since
+// boot like "<6>[    1.234567] ". The visible console (and through it the serial port) is a sink that only gets the
+// lines at or below the level of the tunable "kernel/console_level", without the prefix. User space reads the
+// buffer with SysKlog, like dmesg.
+constexpr std::size_t kKlogSize = 16384;
+
+void InitKlog();
+void KlogWrite(std::string_view str);
+
+// edx points to a buffer of ecx bytes, ebx to the uint32_t position in the log to read from, which is advanced past
+// what was read. Positions count all bytes ever logged, the oldest ones are lost when the ring buffer wraps, then
+// reading continues at the oldest still there. Returns the number of bytes read. When there is nothing new it
+// returns 0, or blocks until there is if esi is nonzero.
+void SysKlog(Regs* regs);
+
+#endif //OS_KLOG_H
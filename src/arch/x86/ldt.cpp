@@ -0,0 +1,136 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#include "ldt.h"
+
+#include "descriptors.h"
+#include "paging.h"
+#include "thread.h"
+#include "x86_inst.h"
+#include "src/freestanding/utils.h"
+
+struct Ldt {
+    bool used;
+    int tid;
+    DescriptorEntry entries[kLdtEntries];
+};
+
+static Ldt ldts[kMaxLdts];
+static int loaded_ldt = -1;  // in ldts, -1 for none
+
+static int FindLdt(int tid) {
+    for (int i = 0; i < kMaxLdts; i++) {
+        if (ldts[i].used && ldts[i].tid == tid) return i;
+    }
+    return -1;
+}
+
+static int AllocLdt(int tid) {
+    for (int i = 0; i < kMaxLdts; i++) {
+        if (ldts[i].used) continue;
+        ldts[i] = Ldt{true, tid, {}};
+        return i;
+    }
+    return -1;
+}
+
+static void LoadLdt(int ldt) {
+    if (ldt == loaded_ldt) return;
+    if (ldt >= 0) SetLdtDescriptor(ldts[ldt].entries, sizeof(ldts[ldt].entries));
+    X86_lldt(ldt >= 0 ? kLdtSelector : 0);
+    loaded_ldt = ldt;
+}
+
+void SwitchLdt(int tid) {
+    LoadLdt(FindLdt(tid));
+}
+
+void ReleaseLdt(int tid) {
+    int ldt = FindLdt(tid);
+    if (ldt < 0) return;
+    // The thread may still run, it must not see the table once it's reused.
+    if (ldt == loaded_ldt) LoadLdt(-1);
+    ldts[ldt].used = false;
+}
+
+bool CanCopyLdt(int tid) {
+    if (FindLdt(tid) < 0) return true;
+    for (auto& ldt : ldts) {
+        if (!ldt.used) return true;
+    }
+    return false;
+}
+
+void CopyLdt(int parent_tid, int child_tid) {
+    int parent = FindLdt(parent_tid);
+    if (parent < 0) return;
+    int child = AllocLdt(child_tid);
+    if (child < 0) return;
+    memcpy(ldts[child].entries, ldts[parent].entries, sizeof(ldts[child].entries));
+}
+
+// Whether selector, which refers to the LDT, can be loaded into cs if code is set and into ss if stack is set.
+static bool IsLoadable(uint32_t selector, bool code, bool stack) {
+    int ldt = FindLdt(current_thread->tid);
+    unsigned index = selector >> 3;
+    if (ldt < 0 || index >= kLdtEntries) return false;
+    auto& e = ldts[ldt].entries[index];
+    if (!e.present) return false;
+    if (code) return e.ex;
+    if (stack) return !e.ex && e.rw;
+    return !e.ex || e.rw;
+}
+
+bool CheckLdtSelectors(Regs* regs) {
+    constexpr uint32_t kLdtBit = 4;
+    for (auto segment : {&regs->ds, &regs->es, &regs->fs, &regs->gs}) {
+        if ((*segment & kLdtBit) && !IsLoadable(*segment, false, false)) *segment = 0;
+    }
+    return (!(regs->cs & kLdtBit) || IsLoadable(regs->cs, true, false)) &&
+           (!(regs->ss & kLdtBit) || IsLoadable(regs->ss, false, true));
+}
+
+// edx is the index of the entry in the LDT of the calling process, ecx the base, ebx the limit (the offset of the last
+// byte or page) and esi the LdtFlags. Returns the selector of the entry, with user privilege, or -1.
+void SysModifyLdt(Regs* regs) {
+    uint32_t index = regs->edx;
+    uint32_t base = regs->ecx;
+    uint32_t limit = regs->ebx;
+    uint32_t flags = regs->esi;
+    regs->eax = -1;
+    if (index >= kLdtEntries || limit >= (1 << 20)) return;
+    uint64_t size = (uint64_t{limit} + 1) * (flags & kLdtPageGranular ? kPageSize : 1);
+    if ((flags & kLdtPresent) && base + size > kKernelBase) return;
+    int ldt = FindLdt(current_thread->tid);
+    if (ldt < 0) {
+        if (!(flags & kLdtPresent)) return;
+        ldt = AllocLdt(current_thread->tid);
+        if (ldt < 0) return;
+    }
+    auto& e = ldts[ldt].entries[index];
+    if (!(flags & kLdtPresent)) {
+        e = DescriptorEntry{};
+    } else {
+        bool big = flags & kLdt32Bit;
+        bool granular = flags & kLdtPageGranular;
+        e = DescriptorEntry{
+            limit & 0xFFFF,
+            base & 0xFFFFFF,
+            0,  // access
+            (flags & kLdtWritable) != 0,
+            0,  // expand down or conforming
+            (flags & kLdtCode) != 0,
+            1,  // code or data
+            3,  // dpl
+            1,  // present
+            limit >> 16,
+            0,  // reserved
+            big,
+            granular,
+            base >> 24,
+        };
+    }
+    LoadLdt(ldt);
+    regs->eax = index << 3 | 4 | 3;
+}
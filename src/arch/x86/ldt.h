@@ -0,0 +1,43 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#ifndef OS_LDT_H
+#define OS_LDT_H
+
+#include "entry.h"
+
+// Local descriptor tables, for runtimes that expect segments of their own like DOS extenders and old threading
+// libraries. A process defines its segments with modify_ldt and loads them through the returned selectors. Segments
+// always have user privilege and lie below kKernelBase, so a selector can't reach further than the flat user
+// segments do. The LDTs come from a small pool and are copied on fork, exec starts without one.
+//
+// System calls take linear addresses, a program using segments with a base must translate its pointers. Segments
+// that are removed or changed while loaded are caught on the way back to user mode, see CheckLdtSelectors.
+//
+// TODO: returning to a 16 bit stack segment leaves the high half of esp at its kernel value (the espfix problem),
+// which leaks where the kernel stack is to the program.
+constexpr int kMaxLdts = 8;
+constexpr int kLdtEntries = 16;
+
+// The flags of modify_ldt.
+enum LdtFlags : uint32_t {
+    kLdtPresent = 1,  // without it the entry is cleared
+    kLdtCode = 2,  // executable, otherwise data
+    kLdtWritable = 4,  // data can be written, code can be read
+    kLdt32Bit = 8,  // the default operand size of code, the stack pointer width of a stack
+    kLdtPageGranular = 16,  // the limit counts pages instead of bytes
+};
+
+void SwitchLdt(int tid);  // loads the LDT of the thread that is about to run
+void ReleaseLdt(int tid);
+bool CanCopyLdt(int tid);  // false if the thread has an LDT and the pool has no room for a copy
+void CopyLdt(int parent_tid, int child_tid);
+
+// The selectors saved in regs were valid when they were loaded, but the LDT of the current thread may have changed
+// since. Stale data segment selectors are replaced by the null selector, returns false if cs or ss is stale.
+bool CheckLdtSelectors(Regs* regs);
+
+void SysModifyLdt(Regs* regs);
+
+#endif //OS_LDT_H
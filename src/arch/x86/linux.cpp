@@ -0,0 +1,187 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#include "linux.h"
+
+#include "exec.h"
+#include "file.h"
+#include "paging.h"
+#include "thread.h"
+#include "vfs.h"
+
+constexpr int kENOENT = 2;
+constexpr int kEBADF = 9;
+constexpr int kENOMEM = 12;
+constexpr int kEACCES = 13;
+constexpr int kEINVAL = 22;
+constexpr int kENOSYS = 38;
+
+constexpr int kProtWrite = 2;
+constexpr int kMapShared = 1;
+constexpr int kMapAnonymous = 0x20;
+
+struct LinuxIovec {
+    uintptr_t base;
+    uint32_t len;
+};
+
+// The native handlers read their arguments from the native registers. Those that can't block are called on a copy
+// of regs with the arguments moved, the ones that can block must see the original registers to be restarted.
+static Regs NativeArgs(const Regs* regs, uint32_t edx, uint32_t ecx, uint32_t ebx) {
+    Regs native = *regs;
+    native.edx = edx;
+    native.ecx = ecx;
+    native.ebx = ebx;
+    return native;
+}
+
+static void LinuxExit(Regs* regs) {
+    regs->edx = regs->ebx;
+    SysExit(regs);
+}
+
+// The native calls fail with -1 for everything but a bad pointer, which is reported as error instead.
+static int LinuxResult(int n, int error) {
+    return n >= 0 || n == -kEFAULT ? n : -error;
+}
+
+static void LinuxRead(Regs* regs) {
+    regs->eax = LinuxResult(ReadToUser(regs, regs->ebx, regs->ecx, regs->edx), kEBADF);
+}
+
+static void LinuxWrite(Regs* regs) {
+    regs->eax = LinuxResult(WriteFromUser(regs, regs->ebx, regs->ecx, regs->edx), kEBADF);
+}
+
+// Only the first buffer may block, after that a full pipe ends the call with a partial write. Otherwise restarting
+// the call would write the first buffers again.
+static void LinuxWritev(Regs* regs) {
+    uint32_t count = regs->edx;
+    if (count > 1024 || !IsUserRange(regs->ecx, count * sizeof(LinuxIovec))) {
+        regs->eax = -kEINVAL;
+        return;
+    }
+    int total = 0;
+    for (uint32_t i = 0; i < count; i++) {
+        LinuxIovec iov;
+        if (!CopyFromUser(&iov, regs->ecx + i * sizeof(iov), sizeof(iov))) {
+            regs->eax = total > 0 ? total : -kEFAULT;
+            return;
+        }
+        int n = WriteFromUser(regs, regs->ebx, iov.base, iov.len, total == 0);
+        if (n < 0) {
+            regs->eax = total > 0 ? total : LinuxResult(n, kEBADF);
+            return;
+        }
+        total += n;
+        if (uint32_t(n) < iov.len) break;
+    }
+    regs->eax = total;
+}
+
+// Opening a FIFO blocks until the other end is opened, so the open works on regs itself. It's woken with the
+// descriptor in eax, without restarting.
+static void LinuxOpen(Regs* regs) {
+    OpenPath(regs, regs->ebx, regs->ecx);
+    if (int(regs->eax) < 0) regs->eax = -kENOENT;
+}
+
+static void LinuxClose(Regs* regs) {
+    auto native = NativeArgs(regs, regs->ebx, 0, 0);
+    SysClose(&native);
+    regs->eax = int(native.eax) < 0 ? -kEBADF : 0;
+}
+
+// Linux brk returns the current break when it fails.
+static void LinuxBrk(Regs* regs) {
+    auto native = NativeArgs(regs, regs->ebx, 0, 0);
+    SysBrk(&native);
+    regs->eax = int(native.eax) == -1 ? current_thread->brk : native.eax;
+}
+
+// Mappings are placed below the previous one starting under the stack. Like all user memory they are demand paged,
+// see Vma. ebx is the address hint, which is ignored, ecx the length, edx the protection, esi the flags, edi the
+// descriptor and ebp the offset in pages of a file mapping. Shared file mappings can't be written as nothing is
+// written back.
+static void LinuxMmap2(Regs* regs) {
+    uint32_t length = regs->ecx;
+    uint32_t prot = regs->edx;
+    uint32_t flags = regs->esi;
+    if (length == 0) {
+        regs->eax = -kEINVAL;
+        return;
+    }
+    VNode file{nullptr, -1};
+    if (!(flags & kMapAnonymous)) {
+        file = GetVNode(regs->edi);
+        if (!file.fs) {
+            regs->eax = -kEBADF;
+            return;
+        }
+        if ((flags & kMapShared) && (prot & kProtWrite)) {
+            regs->eax = -kEACCES;
+            return;
+        }
+    }
+    uint32_t size = (uint64_t(length) + kPageSize - 1) & -kPageSize;
+    auto base = current_thread->mmap_base;
+    if (size > base - current_thread->brk) {
+        regs->eax = -kENOMEM;
+        return;
+    }
+    bool writable = !file.fs || (prot & kProtWrite);
+    if (!AddVma(current_thread, base - size, base, file, uint64_t(regs->ebp) * kPageSize, writable)) {
+        regs->eax = -kENOMEM;
+        return;
+    }
+    current_thread->mmap_base = base - size;
+    regs->eax = base - size;
+}
+
+static void LinuxMunmap(Regs* regs) {
+    uintptr_t address = regs->ebx;
+    uint32_t length = regs->ecx;
+    if ((address & (kPageSize - 1)) != 0 || !IsUserRange(address, length)) {
+        regs->eax = -kEINVAL;
+        return;
+    }
+    FreeUserPages(address / kPageSize, (uint64_t(address) + length + kPageSize - 1) / kPageSize);
+    RemoveVmas(current_thread, address, (uint64_t(address) + length + kPageSize - 1) & -kPageSize);
+    regs->eax = 0;
+}
+
+static void LinuxNoSys(Regs* regs) {
+    regs->eax = -kENOSYS;
+}
+
+typedef void (*LinuxHandler)(Regs*);
+
+static LinuxHandler GetLinuxHandler(uint32_t num) {
+    switch (num) {
+        case 1: return LinuxExit;
+        case 3: return LinuxRead;
+        case 4: return LinuxWrite;
+        case 5: return LinuxOpen;
+        case 6: return LinuxClose;
+        case 45: return LinuxBrk;
+        case 91: return LinuxMunmap;
+        case 146: return LinuxWritev;
+        case 192: return LinuxMmap2;
+        case 252: return LinuxExit;  // exit_group, processes are single threaded
+        default: return LinuxNoSys;
+    }
+}
+
+void LinuxSystemCall(Regs* regs) {
+    GetLinuxHandler(regs->eax)(regs);
+}
+
+// edx points to the path of the Linux binary, see SysExec.
+void SysExecLinux(Regs* regs) {
+    bool linux_abi = current_thread->linux_abi;
+    current_thread->linux_abi = true;
+    SysExec(regs);
+    // On success regs is the fresh state of the new program, which has eax zero.
+    if (regs->eax != 0) current_thread->linux_abi = linux_abi;
+}
@@ -0,0 +1,18 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#ifndef OS_LINUX_H
+#define OS_LINUX_H
+
+#include "entry.h"
+
+// Linux compatibility, enough to run static "hello world" class Linux i386 binaries. A process with the Linux ABI
+// makes its system calls with int 0x80 using Linux numbers, arguments in ebx, ecx, edx, esi, edi and ebp, and
+// errors returned as -errno. The most common calls are mapped onto the native ones: exit, read, write, writev,
+// open, close, brk and anonymous mmap2/munmap. Everything else fails with ENOSYS.
+void LinuxSystemCall(Regs* regs);
+
+void SysExecLinux(Regs* regs);
+
+#endif //OS_LINUX_H
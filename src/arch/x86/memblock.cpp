@@ -0,0 +1,97 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#include "memblock.h"
+
+#include "kassert.h"
+#include "paging.h"
+
+// Page ranges [first, end). The E820 map has at most 32 entries, the reservations are the non usable entries plus a
+// few ranges of init and the allocations.
+struct PageRange {
+    int first, end;
+};
+
+constexpr int kMaxRanges = 64;
+
+static PageRange usable[kMaxRanges];
+static int num_usable;
+static PageRange reserved[kMaxRanges];
+static int num_reserved;
+static bool released;
+
+static void AddRange(PageRange* ranges, int* count, int first, int end) {
+    kassert(!released);
+    if (first >= end) return;
+    if (*count == kMaxRanges) panic("Memblock out of ranges");
+    ranges[(*count)++] = PageRange{first, end};
+}
+
+static int ClipPage(uint64_t page) {
+    return page < kNumPages ? page : kNumPages;
+}
+
+void MemblockAdd(uint64_t base, uint64_t end) {
+    AddRange(usable, &num_usable, ClipPage((base + kPageSize - 1) / kPageSize), ClipPage(end / kPageSize));
+}
+
+void MemblockReserve(uint64_t base, uint64_t end) {
+    AddRange(reserved, &num_reserved, ClipPage(base / kPageSize), ClipPage((end + kPageSize - 1) / kPageSize));
+}
+
+int MemblockEndPage() {
+    int end = 0;
+    for (int i = 0; i < num_usable; i++) end = max(end, usable[i].end);
+    return end;
+}
+
+// The range overlapping [first, end) that starts lowest, nullptr if there is none. The ranges are the reservations
+// and the first num_taken usable ranges, the usable ranges can overlap each other.
+static const PageRange* FirstOverlap(int first, int end, int num_taken) {
+    const PageRange* overlap = nullptr;
+    auto check = [&](const PageRange& r) {
+        if (r.first < end && first < r.end && (!overlap || r.first < overlap->first)) overlap = &r;
+    };
+    for (int i = 0; i < num_reserved; i++) check(reserved[i]);
+    for (int i = 0; i < num_taken; i++) check(usable[i]);
+    return overlap;
+}
+
+int MemblockAlloc(int npages) {
+    kassert(!released && npages > 0);
+    int best = -1;
+    for (int i = 0; i < num_usable; i++) {
+        // Move down below every reservation in the way.
+        int end = usable[i].end;
+        while (end - npages >= usable[i].first) {
+            auto overlap = FirstOverlap(end - npages, end, 0);
+            if (!overlap) {
+                best = max(best, end - npages);
+                break;
+            }
+            end = overlap->first;
+        }
+    }
+    if (best >= 0) AddRange(reserved, &num_reserved, best, best + npages);
+    return best;
+}
+
+void MemblockRelease(void (*free_pages)(int first, int end)) {
+    kassert(!released);
+    released = true;
+    for (int i = 0; i < num_usable; i++) {
+        int page = usable[i].first;
+        int end = usable[i].end;
+        while (page < end) {
+            // Pages covered by an earlier usable range were handed over with that one.
+            auto overlap = FirstOverlap(page, end, i);
+            if (!overlap) {
+                free_pages(page, end);
+                break;
+            }
+            if (overlap->first > page) free_pages(page, overlap->first);
+            page = overlap->end;
+        }
+    }
+}
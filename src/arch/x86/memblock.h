@@ -0,0 +1,28 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#ifndef OS_MEMBLOCK_H
+#define OS_MEMBLOCK_H
+
+#include <cstdint>
+
+// Early boot allocator of physical pages, for what init needs before the page allocator works, like the page map of
+// the page allocator itself. It's seeded with the usable regions of the E820 map and the ranges that are in use
+// (the kernel, the ramdisk, firmware data) are reserved, it hands out the free pages that remain top down so the low
+// zones stay free for DMA. When the page allocator takes over, what memblock didn't hand out becomes its free pages
+// and memblock is done. Only memory below 4gb is used.
+void MemblockAdd(uint64_t base, uint64_t end);  // the whole pages in [base, end)
+void MemblockReserve(uint64_t base, uint64_t end);  // every page touching [base, end)
+
+// One past the last usable page.
+int MemblockEndPage();
+
+// Returns the first of npages contiguous physical pages, or -1 if there is no such run.
+int MemblockAlloc(int npages);
+
+// Calls free_pages for every run [first, end) of pages not reserved nor handed out, after which memblock can't be
+// used anymore.
+void MemblockRelease(void (*free_pages)(int first, int end));
+
+#endif //OS_MEMBLOCK_H
@@ -0,0 +1,171 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#include "net.h"
+
+#include "irq.h"
+#include "paging.h"
+#include "sysctl.h"
+#include "thread.h"
+#include "x86_inst.h"
+#include "src/freestanding/utils.h"
+
+static NetInterface interfaces[kMaxInterfaces];
+static int num_interfaces;
+
+struct CaptureRecord {
+    CaptureHeader header;
+    uint8_t data[kCaptureSnapLen];
+};
+
+static CaptureRecord captures[kCaptureRecords];
+static int capture_head, capture_count;
+static WaitQueue capture_readers;
+static bool capture_enabled = true;
+
+static void Capture(const NetInterface* iface, CaptureDirection direction, const uint8_t* data, int size) {
+    if (!capture_enabled) return;
+    if (capture_count == kCaptureRecords) {
+        // Overwrite the oldest.
+        capture_head = (capture_head + 1) % kCaptureRecords;
+        capture_count--;
+    }
+    auto& record = captures[(capture_head + capture_count++) % kCaptureRecords];
+    record.header = CaptureHeader{GetTimeNs(), uint16_t(iface - interfaces), direction, 0, uint32_t(size)};
+    memcpy(record.data, data, min(size, kCaptureSnapLen));
+    WakeAll(&capture_readers);
+}
+
+int RegisterInterface(std::string_view name, int mtu, bool (*transmit)(NetInterface*, const uint8_t*, int)) {
+    if (num_interfaces == kMaxInterfaces || name.size() > kMaxInterfaceName) return -1;
+    auto& iface = interfaces[num_interfaces];
+    iface.name = name;
+    iface.mtu = mtu;
+    iface.transmit = transmit;
+    return num_interfaces++;
+}
+
+void NetReceive(NetInterface* iface, const uint8_t* data, int size) {
+    Capture(iface, kCaptureRx, data, size);
+    if (iface->count == kRxQueueSize) {
+        iface->rx_dropped++;
+        return;
+    }
+    auto& packet = iface->rx_queue[(iface->head + iface->count++) % kRxQueueSize];
+    packet.size = size;
+    memcpy(packet.data, data, size);
+    iface->rx_packets++;
+    WakeAll(&iface->receivers);
+}
+
+static bool LoopbackTransmit(NetInterface* iface, const uint8_t* data, int size) {
+    NetReceive(iface, data, size);
+    return true;
+}
+
+void InitNet() {
+    constexpr int kLoopbackMtu = 1500;
+    RegisterInterface("lo", kLoopbackMtu, LoopbackTransmit);
+    RegisterTunable({"net/capture", [] { return int(capture_enabled); }, [](int value) {
+        if (value != 0 && value != 1) return false;
+        capture_enabled = value;
+        return true;
+    }});
+}
+
+// edx points to the interface name of length ecx, returns the interface index or -1.
+void SysNetOpen(Regs* regs) {
+    char name[kMaxInterfaceName];
+    regs->eax = -1;
+    if (regs->ecx > sizeof(name)) return;
+    if (!CopyFromUser(name, regs->edx, regs->ecx)) {
+        regs->eax = -kEFAULT;
+        return;
+    }
+    for (int i = 0; i < num_interfaces; i++) {
+        if (interfaces[i].name == std::string_view(name, regs->ecx)) regs->eax = i;
+    }
+}
+
+// edx is the interface, ecx points to the packet of ebx bytes.
+void SysNetSend(Regs* regs) {
+    unsigned index = regs->edx;
+    int size = regs->ebx;
+    if (index >= unsigned(num_interfaces) || size <= 0 || size > interfaces[index].mtu) {
+        regs->eax = -1;
+        return;
+    }
+    uint8_t packet[kMaxPacketSize];
+    if (!CopyFromUser(packet, regs->ecx, size)) {
+        regs->eax = -kEFAULT;
+        return;
+    }
+    auto& iface = interfaces[index];
+    iface.tx_packets++;
+    Capture(&iface, kCaptureTx, packet, size);
+    regs->eax = iface.transmit(&iface, packet, size) ? 0 : -1;
+}
+
+// edx is the interface, ecx points to a buffer of ebx bytes, esi is the timeout in ms, negative to wait forever.
+// Returns the packet size or -1 on timeout or error. A packet larger than the buffer is truncated. With a bad pointer
+// the packet stays queued and -kEFAULT is returned.
+void SysNetReceive(Regs* regs) {
+    unsigned index = regs->edx;
+    int timeout_ms = regs->esi;
+    if (index >= unsigned(num_interfaces)) {
+        regs->eax = -1;
+        return;
+    }
+    auto& iface = interfaces[index];
+    int timeout_ticks = timeout_ms > 0 ? MsToTicks(timeout_ms) : 0;
+    // Packets are received in interrupt handlers.
+    X86_cli();
+    if (iface.count == 0 && timeout_ms != 0) BlockOn(regs, &iface.receivers, timeout_ticks);
+    X86_sti();
+    if (iface.count == 0) {
+        regs->eax = -1;
+        return;
+    }
+    // Interrupts only append to the queue, the head packet stays put during the copy.
+    auto& packet = iface.rx_queue[iface.head];
+    auto size = min<uint32_t>(packet.size, regs->ebx);
+    if (!CopyToUser(regs->ecx, packet.data, size)) {
+        regs->eax = -kEFAULT;
+        return;
+    }
+    X86_cli();
+    iface.head = (iface.head + 1) % kRxQueueSize;
+    iface.count--;
+    X86_sti();
+    regs->eax = size;
+}
+
+// edx points to a buffer of ecx bytes, ebx is the timeout in ms, negative to wait forever. Reads the oldest capture
+// record, a CaptureHeader followed by the captured data, truncated to the buffer. Returns the number of bytes
+// stored or -1 on timeout or if the buffer can't hold the header. With a bad pointer the record is lost and -kEFAULT
+// is returned.
+void SysNetCapture(Regs* regs) {
+    uint32_t size = regs->ecx;
+    int timeout_ms = regs->ebx;
+    if (size < sizeof(CaptureHeader)) {
+        regs->eax = -1;
+        return;
+    }
+    int timeout_ticks = timeout_ms > 0 ? MsToTicks(timeout_ms) : 0;
+    X86_cli();
+    if (capture_count == 0 && timeout_ms != 0) BlockOn(regs, &capture_readers, timeout_ticks);
+    X86_sti();
+    if (capture_count == 0) {
+        regs->eax = -1;
+        return;
+    }
+    // A full ring overwrites the oldest record from interrupts, so it's taken out before the copy.
+    X86_cli();
+    auto record = captures[capture_head];
+    capture_head = (capture_head + 1) % kCaptureRecords;
+    capture_count--;
+    X86_sti();
+    size = min<uint32_t>(size, sizeof(CaptureHeader) + min<uint32_t>(record.header.size, kCaptureSnapLen));
+    regs->eax = CopyToUser(regs->edx, &record, size) ? size : -kEFAULT;
+}
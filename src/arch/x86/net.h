@@ -0,0 +1,74 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#ifndef OS_NET_H
+#define OS_NET_H
+
+#include <cstdint>
+#include <string_view>
+
+#include "entry.h"
+#include "wait.h"
+
+constexpr int kMaxPacketSize = 1518;  // ethernet frame without the crc
+constexpr int kMaxInterfaces = 4;
+constexpr std::size_t kMaxInterfaceName = 16;
+constexpr int kRxQueueSize = 16;
+
+struct Packet {
+    int size;
+    uint8_t data[kMaxPacketSize];
+};
+
+// A network interface. The driver sends packets with transmit and hands received packets to NetReceive, which
+// queues them until they're read. The loopback interface "lo" transmits by receiving, so network code can be
+// tested without a NIC.
+//
+// TODO: a TFTP client to pull files from the host at runtime. It can store them in /tmp (see tmpfs.h), what's missing
+// is a NIC driver to reach the host, the only interface is loopback, and ARP, IP and UDP, which the client could
+// do itself on top of raw packets.
+//
+// TODO: an HTTP/0.9 demo server serving files from the VFS to several clients at once needs TCP sockets, or at
+// least a TCP state machine on top of raw packets in the app, and IP and ARP which don't exist either.
+struct NetInterface {
+    std::string_view name;
+    int mtu;
+    bool (*transmit)(NetInterface* iface, const uint8_t* data, int size);
+
+    int head, count;
+    Packet rx_queue[kRxQueueSize];
+    WaitQueue receivers;  // blocked on an empty receive queue
+
+    uint32_t rx_packets, tx_packets, rx_dropped;
+};
+
+// Packet capture, every packet sent or received on any interface is copied, up to kCaptureSnapLen bytes, into a
+// ring buffer that user space reads record by record. When the reader falls behind the oldest records are lost.
+constexpr int kCaptureSnapLen = 256;
+constexpr int kCaptureRecords = 32;
+
+enum CaptureDirection : uint8_t {
+    kCaptureRx = 0,
+    kCaptureTx = 1,
+};
+
+struct CaptureHeader {
+    uint64_t time_ns;  // since boot
+    uint16_t iface;
+    uint8_t direction;
+    uint8_t reserved;
+    uint32_t size;  // size of the packet, the captured data that follows the header can be shorter
+};
+
+void InitNet();
+int RegisterInterface(std::string_view name, int mtu, bool (*transmit)(NetInterface*, const uint8_t*, int));
+void NetReceive(NetInterface* iface, const uint8_t* data, int size);  // called by drivers, also from interrupts
+
+// Raw packet access from user space until there are sockets.
+void SysNetOpen(Regs* regs);
+void SysNetSend(Regs* regs);
+void SysNetReceive(Regs* regs);
+void SysNetCapture(Regs* regs);
+
+#endif //OS_NET_H
@@ -0,0 +1,87 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#include "overlayfs.h"
+
+#include "exec.h"
+#include "src/freestanding/utils.h"
+
+// Shared by all overlays, allocated front to back.
+static char pool[kOverlayPoolSize];
+static std::size_t pool_used;
+
+int OverlayFileSystem::Lookup(std::string_view path) {
+    int lower = lower_->Lookup(path);
+    if (lower < 0) return -1;
+    for (int i = 0; i < num_nodes_; i++) {
+        if (nodes_[i].lower == lower) return i;
+    }
+    if (num_nodes_ == kMaxOverlayNodes) return -1;
+    nodes_[num_nodes_] = Node{lower, nullptr, 0, 0};
+    return num_nodes_++;
+}
+
+// Moves the contents, from the lower filesystem or the previous copy, to a copy with room for at least capacity bytes.
+// A copy at least doubles when it grows, otherwise appending would use up the pool quadratically because the old
+// copies aren't reused.
+bool OverlayFileSystem::CopyUp(Node* node, std::size_t capacity) {
+    FileStat stat;
+    if (!node->copy && !lower_->Stat(node->lower, &stat)) return false;
+    std::size_t size = node->copy ? node->size : stat.size;
+    capacity = (max(capacity, size) + 511) & -512;
+    if (capacity > kOverlayPoolSize - pool_used) return false;
+    capacity = min(max(capacity, 2 * node->capacity), kOverlayPoolSize - pool_used);
+    auto copy = pool + pool_used;
+    if (node->copy) {
+        memcpy(copy, node->copy, size);
+    } else if (lower_->Read(node->lower, 0, copy, size) != int(size)) {
+        return false;
+    }
+    pool_used += capacity;
+    *node = Node{node->lower, copy, size, capacity};
+    return true;
+}
+
+int OverlayFileSystem::Read(int node, uint64_t offset, void* buf, std::size_t len) {
+    if (node < 0 || node >= num_nodes_) return -1;
+    auto& n = nodes_[node];
+    if (!n.copy) return lower_->Read(n.lower, offset, buf, len);
+    if (offset >= n.size) return 0;
+    len = min<uint64_t>(len, n.size - offset);
+    memcpy(buf, n.copy + offset, len);
+    return len;
+}
+
+int OverlayFileSystem::Write(int node, uint64_t offset, const void* buf, std::size_t len) {
+    if (node < 0 || node >= num_nodes_ || offset > kOverlayPoolSize || len > kOverlayPoolSize - offset) return -1;
+    auto& n = nodes_[node];
+    // A cached binary of the file would still run the old contents.
+    InvalidateBinary(Map(node).data());
+    if ((!n.copy || offset + len > n.capacity) && !CopyUp(&n, offset + len)) return -1;
+    // Writing past the end leaves a hole of zeroes.
+    if (offset > n.size) memset(n.copy + n.size, 0, offset - n.size);
+    memcpy(n.copy + offset, buf, len);
+    n.size = max<std::size_t>(n.size, offset + len);
+    return len;
+}
+
+int OverlayFileSystem::ReadDir(int node, std::size_t index, DirEntry* entry) {
+    if (node < 0 || node >= num_nodes_) return -1;
+    return lower_->ReadDir(nodes_[node].lower, index, entry);
+}
+
+bool OverlayFileSystem::Stat(int node, FileStat* stat) {
+    if (node < 0 || node >= num_nodes_) return false;
+    auto& n = nodes_[node];
+    if (!lower_->Stat(n.lower, stat)) return false;
+    if (n.copy) stat->size = n.size;
+    return true;
+}
+
+std::string_view OverlayFileSystem::Map(int node) {
+    if (node < 0 || node >= num_nodes_) return {};
+    auto& n = nodes_[node];
+    if (!n.copy) return lower_->Map(n.lower);
+    return std::string_view(n.copy, n.size);
+}
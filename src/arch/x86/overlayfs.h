@@ -0,0 +1,44 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#ifndef OS_OVERLAYFS_H
+#define OS_OVERLAYFS_H
+
+#include "vfs.h"
+
+// Makes a read only filesystem appear writable. Reads fall through to the lower filesystem until a file is written,
+// then the file is copied up into kernel memory and all further access goes to the copy. The lower filesystem, the
+// boot archive for the root, is never modified and the changes are lost at reboot.
+//
+// TODO: files can't be created or removed, only /tmp supports that (see tmpfs.h). Copies are allocated from a fixed
+// pool and their space isn't reused when they grow.
+constexpr int kMaxOverlayNodes = 64;
+constexpr std::size_t kOverlayPoolSize = 256 * 1024;
+
+class OverlayFileSystem : public FileSystem {
+public:
+    constexpr explicit OverlayFileSystem(FileSystem* lower) : lower_(lower) {}
+
+    int Lookup(std::string_view path) override;
+    int Read(int node, uint64_t offset, void* buf, std::size_t len) override;
+    int Write(int node, uint64_t offset, const void* buf, std::size_t len) override;
+    int ReadDir(int node, std::size_t index, DirEntry* entry) override;
+    bool Stat(int node, FileStat* stat) override;
+    std::string_view Map(int node) override;
+
+private:
+    struct Node {
+        int lower;
+        char* copy;  // nullptr until the file is written
+        std::size_t size, capacity;
+    };
+
+    bool CopyUp(Node* node, std::size_t capacity);
+
+    FileSystem* lower_;
+    Node nodes_[kMaxOverlayNodes] = {};
+    int num_nodes_ = 0;
+};
+
+#endif //OS_OVERLAYFS_H
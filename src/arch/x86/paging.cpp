@@ -4,26 +4,84 @@
 
 #include "paging.h"
 
+#include "descriptors.h"
+#include "file.h"
 #include "kassert.h"
 #include "x86_inst.h"
 #include "src/freestanding/utils.h"
 #include "irq.h"
+#include "memblock.h"
+#include "pci.h"
+#include "sysctl.h"
+#include "thread.h"
 
 uintptr_t kernel_free_pages_low;
 uintptr_t kernel_free_pages_high;
 uintptr_t kernel_temp_page;
 void* kernel_temp_page_ptr;
+static int persistent_page;
+static void* persistent_page_ptr;
 
-// page_tables[0] is the kernel page table
 // page_tables[2] maps the first 1mb
 // page_tables[3] is the kernel page directory
 // page_tables[4] is the zero page
 PageTable page_tables[5];
+// As many as the kernel image needs are used, see KernelPageTables.
+PageTable kernel_page_tables[kMaxKernelPageTables];
+static int num_kernel_page_tables;
+
+constexpr int kDmaZoneEnd = (16 << 20) / kPageSize;
+constexpr int kLowZoneEnd = (256 << 20) / kPageSize;
+
+// A map of physical page => shared count, for the max_pages pages up to the end of usable memory. It's allocated
+// with memblock (see memblock.h) and mapped after the kernel image.
+uint8_t* available;
+static int max_pages;
+
+// Pages handed out by the allocator, and those of them currently unused.
+static int managed_pages;
+static int free_page_count;
+static int kernel_pages;
+static int ramdisk_pages;
+static uint32_t reclaimed_pages;
+
+// The free pages per zone, allocations take from the highest zone they may use that has free pages.
+struct Zone {
+    std::string_view name;
+    int first, end;  // page range, empty if there is no memory there
+    int free_pages;
+};
+
+// Clipped to max_pages at boot.
+static constinit Zone zones[kNumZones] = {
+    {"dma", 0, kDmaZoneEnd, 0},
+    {"low", kDmaZoneEnd, kLowZoneEnd, 0},
+    {"normal", kLowZoneEnd, kNumPages, 0},
+};
+
+static Zone& ZoneOf(int page) {
+    return zones[page < kDmaZoneEnd ? kZoneDma : page < kLowZoneEnd ? kZoneLow : kZoneNormal];
+}
+
+// E820 memory types. Reclaimable ACPI memory holds the tables, it's free to use once they're parsed. NVS memory must
+// be preserved over sleep states.
+constexpr uint32_t kE820Usable = 1;
+constexpr uint32_t kE820Reserved = 2;
+constexpr uint32_t kE820AcpiReclaimable = 3;
+constexpr uint32_t kE820AcpiNvs = 4;
+constexpr uint32_t kE820Bad = 5;
 
-constexpr int kMaxPages = 32768;  // 128 MB
+// Copy of the E820 map, the boot data doesn't survive.
+static MMapEntry memory_map[array_size(BootData{}.mmap_entries)];
+static int memory_map_count;
 
-// A map of physical page => shared count.
-uint8_t available[kMaxPages];
+// Physical ranges mapped by user space drivers, owner 0 means unused.
+struct PhysReservation {
+    uint64_t base, end;
+    int owner;
+};
+
+static PhysReservation phys_reservations[8];
 
 PageEntry ZeroPageEntry(bool user, bool cow) {
     return PageEntry(PhysAddress(zero_page) / kPageSize, 0, user, cow);
@@ -33,28 +91,92 @@ inline void FlushTLB() {
     X86_set_cr3(CurrentCR3());
 }
 
+// Reserved pages (marked -1, like the zero page) aren't refcounted.
+constexpr uint8_t kReserved = 255;
+
 void IncSharedCount(int page) {
-    kassert(available[page] < 255);
-    available[page]++;
+    if (available[page] == kReserved) return;
+    kassert(available[page] < kReserved - 1);
+    if (available[page]++ == 0) {
+        free_page_count--;
+        ZoneOf(page).free_pages--;
+    }
 }
 
 void FreePhysPage(int page) {
+    if (available[page] == kReserved) return;
     kassert(available[page] > 0);
-    available[page]--;
+    if (--available[page] == 0) {
+        free_page_count++;
+        ZoneOf(page).free_pages++;
+    }
+}
+
+void* PersistentPage() {
+    return persistent_page_ptr;
 }
 
-int AllocPhysPage() {
-    for (int i = 0; i < kMaxPages; i++) {
-        if (available[i] == 0) {
-            IncSharedCount(i);
-            return i;
+int FreePageCount() {
+    return free_page_count;
+}
+
+MemInfo GetMemInfo() {
+    MemInfo info{kPageSize, uint32_t(managed_pages), uint32_t(free_page_count), 0, uint32_t(kernel_pages),
+                 uint32_t(ramdisk_pages), reclaimed_pages, 0, uint32_t(OpenFileCount()),
+                 uint32_t(FileDescriptorCount())};
+    for (int i = 0; i < max_pages; i++) {
+        if (available[i] > 1 && available[i] < 255) info.shared_pages++;
+    }
+    // Threads of a process share its heap, the process is the thread with its pid as tid.
+    for (auto& thread : threads) {
+        if (thread.state == THREAD_UNUSED || thread.state == THREAD_ZOMBIE || thread.tid != thread.pid) continue;
+        info.heap_pages += (thread.brk - thread.brk_base + kPageSize - 1) / kPageSize;
+    }
+    return info;
+}
+
+void ReclaimAcpiMemory() {
+    int reclaimed = 0;
+    for (int i = 0; i < memory_map_count; i++) {
+        auto& mmap = memory_map[i];
+        if (mmap.type != kE820AcpiReclaimable) continue;
+        // Only whole pages, and only those no other region claims.
+        auto start = min<uint64_t>((mmap.base + kPageSize - 1) / kPageSize, max_pages);
+        auto end = min<uint64_t>((mmap.base + mmap.length) / kPageSize, max_pages);
+        for (auto page = start; page < end; page++) {
+            bool claimed = false;
+            for (int j = 0; j < memory_map_count; j++) {
+                auto& other = memory_map[j];
+                if (other.type == kE820Usable || other.type == kE820AcpiReclaimable) continue;
+                if (other.base < (page + 1) * kPageSize && page * kPageSize < other.base + other.length) claimed = true;
+            }
+            if (claimed || available[page] != kReserved) continue;
+            available[page] = 0;
+            free_page_count++;
+            managed_pages++;
+            ZoneOf(page).free_pages++;
+            reclaimed++;
         }
     }
-    return -1;
+    if (reclaimed > 0) kprint("Reclaimed {} kb of ACPI tables\n", reclaimed * kPageSize / 1024);
 }
 
-void MarkUsed(unsigned low, unsigned high) {
-    for (; low < high; low++) available[low] = -1;
+// TODO: physically contiguous allocations for DMA buffers, none of the drivers needs them yet. When there is no free
+// run long enough, compaction should move user pages out of the way: copy them like a COW fault does and update
+// their page entries. That needs a reverse map from physical pages to the page tables mapping them, only the current
+// address space is mapped so the others can't be found now. Count the pages moved for the memory info.
+int AllocPhysPage(MemoryZone highest) {
+    for (int z = highest; z >= 0; z--) {
+        auto& zone = zones[z];
+        if (zone.free_pages == 0) continue;
+        for (int i = zone.first; i < zone.end; i++) {
+            if (available[i] == 0) {
+                IncSharedCount(i);
+                return i;
+            }
+        }
+    }
+    return -1;
 }
 
 // Add npages to the current address space
@@ -71,10 +193,110 @@ void* AllocPages(int npages) {
     return res;
 }
 
+// Whether [base, end) is known to be device memory: reserved in the E820 map, in a memory BAR of a PCI device or in
+// the ISA hole with the VGA memory and option ROMs. Anything else could be RAM, or memory of the firmware.
+static bool IsDeviceMemory(uint64_t base, uint64_t end) {
+    constexpr uint64_t kIsaHoleBase = 0xA0000;
+    constexpr uint64_t kIsaHoleEnd = 0x100000;
+    if (base >= kIsaHoleBase && end <= kIsaHoleEnd) return true;
+    for (int i = 0; i < memory_map_count; i++) {
+        auto& mmap = memory_map[i];
+        if (mmap.type == kE820Reserved && base >= mmap.base && end <= mmap.base + mmap.length) return true;
+    }
+    for (int i = 0; i < NumPciDevices(); i++) {
+        for (auto& bar : GetPciDevice(i)->bars) {
+            if (bar.address == 0 || bar.io) continue;
+            uint64_t bar_base = bar.address & -kPageSize;
+            uint64_t bar_end = (uint64_t(bar.address) + bar.size + kPageSize - 1) & -uint64_t(kPageSize);
+            if (base >= bar_base && end <= bar_end) return true;
+        }
+    }
+    return false;
+}
+
+void* MapPhys(uintptr_t phys, uintptr_t size, int owner) {
+    uint64_t base = phys & -kPageSize;
+    uint64_t end = (uint64_t(phys) + size + kPageSize - 1) & -uint64_t(kPageSize);
+    if (size == 0 || end > (uint64_t(1) << 32) || !IsDeviceMemory(base, end)) return nullptr;
+    PhysReservation* reservation = nullptr;
+    for (auto& r : phys_reservations) {
+        if (r.owner == 0) {
+            if (!reservation) reservation = &r;
+        } else if (base < r.end && r.base < end) {
+            return nullptr;
+        }
+    }
+    if (!reservation) return nullptr;
+
+    // Placed below the previous mapping like mmap does, and registered as a region so nothing else is put there. A
+    // free page table entry elsewhere can be memory of the program that wasn't touched yet.
+    auto thread = current_thread;
+    uintptr_t top = thread->mmap_base;
+    if (end - base > top - thread->brk) return nullptr;
+    uintptr_t start = top - (end - base);
+    int npages = (end - base) / kPageSize;
+    for (int j = 0; j < npages; j++) {
+        if (GetPageEntry(start / kPageSize + j)->IsPresent()) return nullptr;
+    }
+    if (!AddVma(thread, start, top)) return nullptr;
+    thread->mmap_base = start;
+    for (int j = 0; j < npages; j++) {
+        auto entry = PageEntry(base / kPageSize + j, 1, 1, 0);
+        entry.data |= PageEntry::kPhys | PageEntry::kCacheDisable | PageEntry::kWriteThrough;
+        *GetPageEntry(start / kPageSize + j) = entry;
+    }
+    FlushTLB();
+    *reservation = PhysReservation{base, end, owner};
+    return reinterpret_cast<void*>(start + (phys & (kPageSize - 1)));
+}
+
+void ReleasePhysReservations(int owner) {
+    for (auto& r : phys_reservations) {
+        if (r.owner == owner) r.owner = 0;
+    }
+}
+
+void* MapKernelPhys(uintptr_t phys) {
+    // Only the kernel page tables set up at boot are shared by all address spaces.
+    if (kernel_free_pages_low >= kKernelBase / kPageSize + num_kernel_page_tables * kNumPageEntries) return nullptr;
+    for (auto& r : phys_reservations) {
+        if (r.owner != 0) continue;
+        r = PhysReservation{phys & -kPageSize, (phys & -kPageSize) + kPageSize, -1};
+        auto page = kernel_free_pages_low++;
+        auto entry = PageEntry(phys / kPageSize, 1, 0, 0);
+        entry.data |= PageEntry::kPhys | PageEntry::kCacheDisable | PageEntry::kWriteThrough;
+        *GetPageEntry(page) = entry;
+        FlushTLB();
+        return reinterpret_cast<void*>(page * kPageSize + (phys & (kPageSize - 1)));
+    }
+    return nullptr;
+}
+
+void CopyFromPhys(void* dst, uintptr_t phys, std::size_t size) {
+    auto flags = X86_save_flags_cli();
+    auto saved = *GetPageEntry(kernel_temp_page);
+    auto out = static_cast<char*>(dst);
+    while (size > 0) {
+        auto offset = phys & (kPageSize - 1);
+        auto n = min<std::size_t>(size, kPageSize - offset);
+        *GetPageEntry(kernel_temp_page) = PageEntry(phys / kPageSize, 0, 0, 0);
+        FlushTLB();
+        memcpy(out, static_cast<const char*>(kernel_temp_page_ptr) + offset, n);
+        out += n;
+        phys += n;
+        size -= n;
+    }
+    *GetPageEntry(kernel_temp_page) = saved;
+    FlushTLB();
+    X86_restore_flags(flags);
+}
+
 void InitializePageDir(PageTable* page_dir) {
-    auto kt_page = PhysAddress(page_tables) / kPageSize;
     *page_dir = PageTable{};
-    page_dir->entries[kKernelBase / kPageSize / kNumPageEntries] = PageEntry(kt_page, 1, 0, 0);
+    for (int i = 0; i < num_kernel_page_tables; i++) {
+        auto kt_page = PhysAddress(kernel_page_tables + i) / kPageSize;
+        page_dir->entries[kKernelBase / kPageSize / kNumPageEntries + i] = PageEntry(kt_page, 1, 0, 0);
+    }
     page_dir->entries[kNumPageEntries - 2] = PageEntry(PhysAddress(page_tables + 2) / kPageSize, 1, 0, 0);
     page_dir->entries[kNumPageEntries - 1] = PageEntry(PhysAddress(page_dir) / kPageSize, 1, 0, 0);
 }
@@ -82,6 +304,8 @@ void InitializePageDir(PageTable* page_dir) {
 void RecurseMarkCOW(uintptr_t page, int depth) {
     if (depth >= 2) return;
     auto& e = *GetPageEntry(page);
+    // Device memory is shared by parent and child.
+    if (e.IsPhys()) return;
     if (e.IsPresent()) {
         for (int i = 0; i < kNumPageEntries; i++) {
             RecurseMarkCOW(kNumPages - (kNumPages - page) * kNumPageEntries + i, depth + 1);
@@ -100,6 +324,7 @@ void RecurseMarkCOW(uintptr_t page, int depth) {
 void RecurseFreePages(uintptr_t page, int depth) {
     if (depth >= 2) return;
     auto& e = *GetPageEntry(page);
+    if (e.IsPhys()) return;
     if (e.IsPresent()) {
         for (int i = 0; i < kNumPageEntries; i++) {
             RecurseFreePages(kNumPages - (kNumPages - page) * kNumPageEntries + i, depth + 1);
@@ -133,17 +358,57 @@ PageTable* ForkCurrent() {
     return static_cast<PageTable*>(page_dir);
 }
 
+// The child's page directory shares all user pages with the current one, dropping its references restores the shared
+// counts. The pages stay copy on write in the current address space, a write fault on them finds them unshared.
+void DestroyFork(const PageTable* page_dir) {
+    for (int i = 0; i < kKernelBase / kPageSize / kNumPageEntries; i++) {
+        RecurseFreePages(kNumPages - kNumPageEntries + i, 0);
+    }
+    DestroyPageDir(page_dir);
+}
+
+// The user space of the page directory must already be freed by ClearUserSpace, while it was the current one, as
+// only the current address space is mapped.
 void DestroyPageDir(const PageTable* p) {
-    auto cr3 = CurrentCR3();
     auto this_page = PhysAddress(p);
-    kassert(cr3 != this_page);
+    kassert(CurrentCR3() != this_page);
+    FreePhysPage(this_page / kPageSize);
+    *GetPageEntry(GetPageIndex(p)) = PageEntry();
+    FlushTLB();
+}
+
+// Unmaps and frees the user pages [first_page, end_page) of the current address space.
+void FreeUserPages(uintptr_t first_page, uintptr_t end_page) {
+    for (auto page = first_page; page < end_page; page++) {
+        // Don't touch the page table if there is none, that would demand page it.
+        if (!GetPageEntry(kNumPages - kNumPageEntries + page / kNumPageEntries)->IsPresent()) continue;
+        RecurseFreePages(page, 1);
+        *GetPageEntry(page) = PageEntry();
+    }
+    FlushTLB();
+}
 
+// Makes a user page read only for good, a page that isn't present yet maps the zero page.
+void WriteProtectPage(uintptr_t page) {
+    auto& e = *GetPageEntry(page);
+    if (!e.IsPresent()) e = ZeroPageEntry(true, false);
+    e.data &= ~(PageEntry::kReadWrite | PageEntry::kCow);
+    FlushTLB();
+}
+
+// Unmaps and frees all of user space of the current address space.
+void ClearUserSpace() {
     for (unsigned i = 0; i < kKernelBase / kPageSize / kNumPageEntries; i++) {
         RecurseFreePages(kNumPages - kNumPageEntries + i, 0);
+        GetCurrentDir()[i] = PageEntry();
     }
-    FreePhysPage(GetPageEntry(kNumPages - 1)->Page());
+    FlushTLB();
 }
 
+// TODO: every switch flushes the whole TLB. PCID (tagging the TLB entries with an address space id given in the low
+// bits of cr3, a write with bit 63 set keeps them) can't be used, cr4.PCIDE can only be set in long mode and cr3 is 32
+// bits here. What 32 bit paging offers is global pages (cr4.PGE, cpuid 1 edx bit 13) for the kernel half, which
+// survive the cr3 write. That needs FlushTLB for kernel mappings, like the temporary page, to become invlpg first.
 void SwitchPageDir(PageTable* new_dir) {
     // We must keep the kernel addresses mapped identically
     auto kernel_entries = kKernelBase / kPageSize / kNumPageEntries;
@@ -151,13 +416,90 @@ void SwitchPageDir(PageTable* new_dir) {
     X86_set_cr3(PhysAddress(new_dir));
 }
 
-void segv(Regs* regs) {
-    (void)regs;  // silence unused warning
-    // TODO send sigsegv
-    panic("Seg fault, user outside allocation\n");
+static bool reclaim_enabled = true;
+static uintptr_t reclaim_cursor;
+
+// The page is checked through the temporary page, reading it through its user mapping would set its accessed bit.
+static bool IsZeroPhysPage(int page) {
+    *GetPageEntry(kernel_temp_page) = PageEntry(page, 0, 0, 0);
+    FlushTLB();
+    auto words = static_cast<const uint32_t*>(kernel_temp_page_ptr);
+    for (unsigned i = 0; i < kPageSize / sizeof(uint32_t); i++) {
+        if (words[i] != 0) return false;
+    }
+    return true;
+}
+
+void ReclaimStep() {
+    constexpr int kReclaimBatch = 64;
+    constexpr uintptr_t kFirstPage = 0x10000 / kPageSize;
+    constexpr uintptr_t kEndPage = kKernelBase / kPageSize;
+    if (!reclaim_enabled) return;
+    // Interrupts are off so nothing writes the page between the check and unmapping it.
+    auto flags = X86_save_flags_cli();
+    auto saved = *GetPageEntry(kernel_temp_page);
+    for (int i = 0; i < kReclaimBatch; i++) {
+        if (reclaim_cursor < kFirstPage || reclaim_cursor >= kEndPage) reclaim_cursor = kFirstPage;
+        auto page = reclaim_cursor++;
+        // Skip the rest of a page table that isn't there, looking at it would demand page it.
+        if (!GetCurrentDir()[page / kNumPageEntries].IsPresent()) {
+            reclaim_cursor = (page / kNumPageEntries + 1) * kNumPageEntries;
+            continue;
+        }
+        auto& e = *GetPageEntry(page);
+        // Read only and copy-on-write pages are shared or protected, device memory isn't ours to free.
+        if (!e.IsPresent() || !e.IsReadWrite() || !e.IsUserSuper() || e.IsPhys() || available[e.Page()] != 1) continue;
+        if (e.IsAccessed()) {
+            e.data &= ~PageEntry::kAccessed;
+            continue;
+        }
+        if (!IsZeroPhysPage(e.Page())) continue;
+        FreePhysPage(e.Page());
+        e = ZeroPageEntry(true, true);
+        reclaimed_pages++;
+    }
+    *GetPageEntry(kernel_temp_page) = saved;
+    FlushTLB();
+    X86_restore_flags(flags);
+}
+
+bool CopyFromUser(void* dst, uintptr_t user, std::size_t size) {
+    return IsUserRange(user, size) && copy_user(dst, reinterpret_cast<const void*>(user), size) == 0;
+}
+
+bool CopyToUser(uintptr_t user, const void* src, std::size_t size) {
+    return IsUserRange(user, size) && copy_user(reinterpret_cast<void*>(user), src, size) == 0;
 }
 
-extern uint8_t kernel_stack[4096];
+// The instructions of the kernel that may fault on user memory, and where they continue when the fault can't be
+// resolved.
+struct FaultFixup {
+    const char* eip;
+    const char* fixup;
+};
+
+static const FaultFixup fault_fixups[] = {
+    {copy_user_copy, copy_user_fault},
+};
+
+// A fault of the kernel on user memory the process doesn't have comes from a bad pointer passed to a system call.
+// The copy that touched it fails, any other access of the kernel to it is a bug. Returns false if there's no fixup.
+static bool FixupFault(Regs* regs) {
+    for (auto& f : fault_fixups) {
+        if (regs->eip != AsLinear(f.eip)) continue;
+        regs->eip = AsLinear(f.fixup);
+        return true;
+    }
+    return false;
+}
+
+// TODO: deliver SIGSEGV once there are signals, until then the process is killed.
+void segv(Regs* regs) {
+    if (!current_thread || current_thread->tid == 0) panic("Seg fault, user outside allocation\n");
+    kprint("Thread {} segmentation fault @{}:{}\n", current_thread->tid, Hex(regs->cs), Hex(regs->eip));
+    regs->edx = 128 + 11;  // exit code of a shell for death by SIGSEGV
+    SysExit(regs);
+}
 
 void page_fault(Regs* regs) {
     constexpr uintptr_t kPresent = 1; (void)kPresent;
@@ -218,11 +560,35 @@ void page_fault(Regs* regs) {
                 //kprint("COW page {} {} done\n", page_index, page_entry);
             }
         } else {
+            if (!(error & kUser) && FixupFault(regs)) return;
             kassert(error & kUser);  // Kernel should never try to write to read only page.
             segv(regs);
         }
     } else {
         //kprint("Page not present\n");
+        if (fault_address < kKernelBase && current_thread && !IsValidUserAddress(current_thread, fault_address)) {
+            if (!(error & kUser)) {
+                if (FixupFault(regs)) return;
+                // Only the user copy helpers may touch unmapped user memory, anything else is a kernel bug.
+                panic("Kernel page fault @{} on unmapped user address {}\n", Hex(regs->eip), Hex(fault_address));
+            }
+            return segv(regs);
+        }
+        auto vma = fault_address < kKernelBase && current_thread ? FindVma(current_thread, fault_address) : nullptr;
+        if (vma && vma->file.fs) {
+            // A private copy of the file contents, the part past the end of the file is zero.
+            int phys_page = AllocPhysPage();
+            if (phys_page == -1) panic("OOM");
+            page_entry = PageEntry(phys_page, 1, is_user, 0);
+            FlushTLB();
+            auto page = reinterpret_cast<char*>(fault_address & -kPageSize);
+            auto offset = vma->offset + (reinterpret_cast<uintptr_t>(page) - vma->start);
+            int n = vma->file.fs->Read(vma->file.node, offset, page, kPageSize);
+            memset(page + max(n, 0), 0, kPageSize - max(n, 0));
+            if (!vma->writable) page_entry.data &= ~PageEntry::kReadWrite;
+            FlushTLB();
+            return;
+        }
         if (false /*&& !IsZero(page_entry)*/) {
             panic("Swapping not implemented yet\n");
         } else {
@@ -233,75 +599,139 @@ void page_fault(Regs* regs) {
     }
 }
 
-void InitPaging(int kernel_low, int kernel_high, int ramdisk_low, int ramdisk_high, const BootData* boot_data) {
-    for (unsigned i = 0; i < array_size(available); i++) {
-        kassert(available[i] == 0);
+static void FreeEarlyPages(int first, int end) {
+    for (int i = first; i < end; i++) {
+        available[i] = 0;
+        free_page_count++;
+        ZoneOf(i).free_pages++;
     }
-    memset(available, -1, sizeof(available));
-    int free_pages = 0;
+}
+
+void InitPaging(int kernel_low, int kernel_high) {
+    // The kernel page tables map the image followed by the spare pages.
+    kernel_pages = kernel_high - kernel_low;
+    num_kernel_page_tables = KernelPageTables(kernel_pages);
+    kernel_free_pages_low = kKernelBase / kPageSize + kernel_pages;
+    kernel_free_pages_high = kLowMemBase / kPageSize;
+
+    // We are done with the identity mapping, make zero page zero
+    *zero_page = PageTable{};
+
+    kernel_temp_page = kernel_free_pages_low++;
+    kernel_temp_page_ptr = reinterpret_cast<void*>(kernel_temp_page * kPageSize);
+
+    // Make page dir as it should be
+    InitializePageDir(page_tables + 3);
+
+    // The guard page below the kernel stack (see descriptors.h), its memory is part of the kernel image and stays
+    // unused.
+    static_assert(kStackGuardSize == kPageSize);
+    *GetPageEntry(GetPageIndex(kernel_stack)) = PageEntry();
+
+    RegisterTunable({"vm/zero_reclaim", [] { return int(reclaim_enabled); }, [](int value) {
+        if (value != 0 && value != 1) return false;
+        reclaim_enabled = value;
+        return true;
+    }});
+
+    FlushTLB();
+}
+
+void InitPhysMemory(int kernel_low, int kernel_high, int ramdisk_low, int ramdisk_high, const BootData* boot_data) {
+    memory_map_count = boot_data->mmap_count;
+    memcpy(memory_map, boot_data->mmap_entries, sizeof(memory_map));
     for (int i = 0; i < boot_data->mmap_count; i++) {
         auto& mmap = boot_data->mmap_entries[i];
-        if (mmap.type != 1) continue;
+        if (mmap.type != kE820Usable) continue;
         auto start = (mmap.base + kPageSize - 1) / kPageSize;
         auto end = (mmap.base + mmap.length) / kPageSize;
         kprint("Available memory {} - {} ({} pages)\n", Hex(mmap.base), Hex(mmap.base + mmap.length), end - start);
-        start = min<int>(start, kMaxPages);
-        end = min<int>(end, kMaxPages);
-        if (start < end) memset(available + start, 0, (end - start) * sizeof(available[0]));
-        free_pages += end - start;
+        MemblockAdd(mmap.base, mmap.base + mmap.length);
     }
+    max_pages = MemblockEndPage();
+    // Regions overlapping usable memory win, as do those not included in the map at all. Partial pages are reserved.
+    uint64_t type_size[kE820Bad + 1] = {};
+    for (int i = 0; i < boot_data->mmap_count; i++) {
+        auto& mmap = boot_data->mmap_entries[i];
+        if (mmap.type <= kE820Bad) type_size[mmap.type] += mmap.length;
+        if (mmap.type == kE820Usable) continue;
+        MemblockReserve(mmap.base, mmap.base + mmap.length);
+    }
+    kprint("Memory map: {} kb usable, {} kb reserved, {} kb ACPI reclaimable, {} kb ACPI NVS, {} kb bad\n",
+           type_size[kE820Usable] >> 10, type_size[kE820Reserved] >> 10, type_size[kE820AcpiReclaimable] >> 10,
+           type_size[kE820AcpiNvs] >> 10, type_size[kE820Bad] >> 10);
+    // The extended BIOS data area below 640kb isn't always reserved by the map. Its segment is in the BIOS data area.
+    uintptr_t ebda = uintptr_t(*reinterpret_cast<const uint16_t*>(kLowMemBase + 0x40E)) << 4;
+    if (ebda >= 0x80000 && ebda < 0xA0000) MemblockReserve(ebda, 0xA0000);
 
     if (!CheckA20()) {
         // So far we only used < 1MB memory, so nothing is fucked yet as we haven't encountered aliased mem.
         // We can simply continue by marking all pages at an odd 1MB segment unavailable, this halves the available
         // memory.
         kprint("A20 disabled! Compensating but losing half the memory");
-        constexpr auto kPagesPerMB = (1 << 20) / kPageSize;
-        for (int i = kPagesPerMB; i < kMaxPages; i += 2 * kPagesPerMB) {
-            MarkUsed(i, min(kMaxPages, int(i + kPagesPerMB)));
-        }
+        constexpr uint64_t kMB = 1 << 20;
+        for (uint64_t i = kMB; i < uint64_t(max_pages) * kPageSize; i += 2 * kMB) MemblockReserve(i, i + kMB);
     }
 
-    MarkUsed(0, 1);  // zero page is used by bios
+    MemblockReserve(0, kPageSize);  // zero page is used by bios
 
     // Mark pages where kernel is loaded as used
     kprint("Kernel pages {} {}\n", kernel_low, kernel_high);
-    MarkUsed(kernel_low, kernel_high);
-    free_pages -= kernel_high - kernel_low;
+    MemblockReserve(uint64_t(kernel_low) * kPageSize, uint64_t(kernel_high) * kPageSize);
 
     kprint("ramdisk pages {} {}\n", ramdisk_low, ramdisk_high);
-    MarkUsed(ramdisk_low, ramdisk_high);
-    free_pages -= ramdisk_high - ramdisk_low;
+    MemblockReserve(uint64_t(ramdisk_low) * kPageSize, uint64_t(ramdisk_high) * kPageSize);
+    ramdisk_pages = ramdisk_high - ramdisk_low;
+
+    // The last page of memory holds the persistent log (see pstore.h), it's the page least likely to be touched by
+    // the bios and bootloader on a warm reboot.
+    persistent_page = max_pages - 1;
+    MemblockReserve(uint64_t(persistent_page) * kPageSize, uint64_t(max_pages) * kPageSize);
 
     if (kernel_high >= ramdisk_low) {
         kprint("Ramdisk overlaps kernel\n");
         terminate(-1);
     }
+}
 
-    kprint("Free mem {}\n", free_pages * kPageSize);
-
-    // We are done with the identity mapping, make zero page zero
-    *zero_page = PageTable{};
-    kernel_free_pages_low = kKernelBase / kPageSize + kernel_high - kernel_low;
-    kernel_free_pages_high = kLowMemBase / kPageSize;
-
-    kernel_temp_page = kernel_free_pages_low++;
-    kernel_temp_page_ptr = reinterpret_cast<void*>(kernel_temp_page * kPageSize);
+void InitPageAllocator() {
+    int map_pages = (max_pages + kPageSize - 1) / kPageSize;
+    int map = MemblockAlloc(map_pages);
+    if (map < 0) panic("No memory for the page map of {} pages", max_pages);
+    // The page map and the persistent page are mapped in the spare pages of the kernel page tables set up at boot.
+    if (kernel_free_pages_low + map_pages + 1 > kKernelBase / kPageSize + num_kernel_page_tables * kNumPageEntries) {
+        panic("No kernel address space for the page map of {} pages", map_pages);
+    }
+    available = reinterpret_cast<uint8_t*>(kernel_free_pages_low * kPageSize);
+    for (int i = 0; i < map_pages; i++) *GetPageEntry(kernel_free_pages_low++) = PageEntry(map + i, 1, 0, 0);
+    FlushTLB();
+    memset(available, kReserved, max_pages);
+    kernel_pages += map_pages;
 
-    // Make page dir as it should be
-    InitializePageDir(page_tables + 3);
+    for (auto& zone : zones) {
+        zone.first = min(zone.first, max_pages);
+        zone.end = min(zone.end, max_pages);
+    }
+    MemblockRelease(FreeEarlyPages);
+    kprint("Free mem {}\n", free_page_count * kPageSize);
+    for (auto& zone : zones) kprint("Zone {}: {} free pages\n", zone.name, zone.free_pages);
+    managed_pages = free_page_count;
 
+    persistent_page_ptr = reinterpret_cast<void*>(kernel_free_pages_low * kPageSize);
+    *GetPageEntry(kernel_free_pages_low++) = PageEntry(persistent_page, 1, 0, 0);
     FlushTLB();
 }
 
 // Note: this function cannot access any global variables as it is called before the kernel is paged at the correct
 // address
-void EnablePaging(PageTable* ptables, uintptr_t phys_address) {
+void EnablePaging(PageTable* ptables, PageTable* kernel_tables, int num_kernel_tables, uintptr_t phys_address) {
     // Identity map the lowest 4mb, we use the zero-page to store the mapping as we don't use it yet
-    // Map 4mb starting at the start of the kernel into kernel mem
-    for (unsigned i = 0; i < 1024; i++) {
-        ptables[4].entries[i] = PageEntry(i, 1, 0, 0);
-        ptables[0].entries[i] = PageEntry(i + phys_address / kPageSize, 1, 0, 0);
+    for (unsigned i = 0; i < 1024; i++) ptables[4].entries[i] = PageEntry(i, 1, 0, 0);
+    // Map the memory starting at the start of the kernel into kernel mem, 4mb per kernel page table
+    for (int t = 0; t < num_kernel_tables; t++) {
+        for (unsigned i = 0; i < kNumPageEntries; i++) {
+            kernel_tables[t].entries[i] = PageEntry(t * kNumPageEntries + i + phys_address / kPageSize, 1, 0, 0);
+        }
     }
 
     // Paging is not enabled so physical address == linear address
@@ -309,8 +739,10 @@ void EnablePaging(PageTable* ptables, uintptr_t phys_address) {
 
     // Identity map the first 4mb
     ptables[3].entries[0] = PageEntry(AsPhysical(ptables + 4) / kPageSize, 1, 0, 0);
-    // Map 4mb from the start of the loaded kernel into the kernel mem.
-    ptables[3].entries[kKernelBase / kPageSize / kNumPageEntries] = PageEntry(AsPhysical(ptables + 0) / kPageSize, 1, 0, 0);
+    for (int t = 0; t < num_kernel_tables; t++) {
+        auto entry = PageEntry(AsPhysical(kernel_tables + t) / kPageSize, 1, 0, 0);
+        ptables[3].entries[kKernelBase / kPageSize / kNumPageEntries + t] = entry;
+    }
     // Put a page at the end of kernel space before the page table space
     ptables[3].entries[kNumPageEntries - 2] = PageEntry(AsPhysical(ptables + 2) / kPageSize, 1, 0, 0);
     // Use recursive page table trick to map the page tables into themselves
@@ -44,8 +44,8 @@
 // to set the proper access rights.
 //
 // So what to do on a page fault. Well it's either the user accessing space outside of it's allocation (sigsegv) or
-// it's either the user or kernel accessing space that is not currently not mapped (swapped/COW). For now we will
-// not do write protection or allocation and just allow user address everything in userspace.
+// it's either the user or kernel accessing space that is not currently not mapped (swapped/COW). User space that the
+// process mapped (see IsValidUserAddress) is demand paged, anything else is a segmentation fault.
 //
 /*
 void page_fault(uintptr_t fault_addr, int error) {
@@ -64,6 +64,11 @@ void page_fault(uintptr_t fault_addr, int error) {
 // Simple 32bit paging gives pd -> pt -> page (3 * 10 + 2 = 32 bits)
 // [0xFFC00000, 0x100000000) is covered by pd as pt and is array of all pt pages.
 // [0xFFFFF000, 0x100000000) is covered by pd as page and is array of all pd pages.
+//
+// TODO: memory above 4gb needs PAE, pdpt -> pd -> pt -> page (2 + 9 + 9 + 12 = 32 bits) with 64 bit entries holding
+// physical pages of up to 52 bits. PageEntry, the allocator's page numbers and MapPhys would need 64 bit physical
+// addresses, and the recursive mapping becomes four page directories mapped by the last pd. Memory above 4gb is
+// ignored now, the normal zone would take the high memory for user pages.
 
 // Higher level paging
 // pml4 -> pdpt -> pd -> pt -> page  (5 * 9 + 3 = 48 bits)
@@ -101,6 +106,7 @@ struct PageEntry {
     bool IsAccessed() const { return data & kAccessed; }
     bool IsDirty() const { return data & kDirty; }
     bool IsCow() const { return data & kCow; }
+    bool IsPhys() const { return data & kPhys; }
     uintptr_t Page() const { return data / kPage; }
     uintptr_t AsUInt() const { return data; }
 
@@ -110,7 +116,10 @@ struct PageEntry {
         kUserSuper = 1 << 2,
         kAccessed = 1 << 5,
         kDirty = 1 << 6,
+        kWriteThrough = 1 << 3,
+        kCacheDisable = 1 << 4,
         kCow = 1 << 9,
+        kPhys = 1 << 10,  // maps device memory, not refcounted and shared as is on fork
         kPage = 1 << 12,
     };
 
@@ -149,10 +158,35 @@ constexpr PageTable* kernel_page_dir = page_tables + 3;
 
 static_assert(sizeof(PageTable) == kPageSize);
 
+// The kernel page tables map the kernel image followed by kKernelSparePages pages, for the page map of the page
+// allocator (a byte per physical page, up to kPageMapPages), MapKernelPhys and the temporary mappings. They are set
+// up at boot and shared by all page directories, so their number is fixed from the size of the image.
+constexpr int kMaxKernelPageTables = 4;  // 16mb of kernel space
+constexpr int kPageMapPages = kNumPages / kPageSize;
+constexpr int kKernelSparePages = kPageMapPages + 256;
+
+constexpr int KernelPageTables(int kernel_pages) {
+    return (kernel_pages + kKernelSparePages + int(kNumPageEntries) - 1) / int(kNumPageEntries);
+}
+
 inline uintptr_t AsLinear(const void* p) {
     return reinterpret_cast<uintptr_t>(p);
 }
 
+// Whether [address, address + size) lies in user space, above the null pages that are never mapped. System calls
+// check user pointers with this, the kernel must not be tricked into accessing its own memory.
+inline bool IsUserRange(uintptr_t address, std::size_t size) {
+    constexpr uintptr_t kNullLimit = 0x10000;
+    return address >= kNullLimit && address <= kKernelBase && size <= kKernelBase - address;
+}
+
+// Copy between kernel memory and size bytes of user memory at user, checked with IsUserRange. Memory the process
+// doesn't have fails the copy instead of killing it, so system calls return -kEFAULT for bad pointers. False on
+// failure, part of the bytes may have been copied.
+constexpr int kEFAULT = 14;  // the Linux number, so the Linux personality passes it on unchanged
+bool CopyFromUser(void* dst, uintptr_t user, std::size_t size);
+bool CopyToUser(uintptr_t user, const void* src, std::size_t size);
+
 inline PageEntry* GetPageEntry(uintptr_t page) {
     return reinterpret_cast<PageEntry*>(kCurPageTab) + page;
 }
@@ -174,15 +208,79 @@ inline uintptr_t GetPageIndex(const void* p) {
     return AsLinear(p) / kPageSize;
 }
 
-void InitPaging(int kernel_low, int kernel_high, int ramdisk_low, int ramdisk_high, const BootData* boot_data);
-void EnablePaging(PageTable* ptables, uintptr_t phys_address);
+// Init of memory management in three steps: the kernel mappings replace the identity mapping of boot, memblock is
+// seeded with the physical memory map (see memblock.h), and the page allocator takes over from memblock.
+void InitPaging(int kernel_low, int kernel_high);
+void InitPhysMemory(int kernel_low, int kernel_high, int ramdisk_low, int ramdisk_high, const BootData* boot_data);
+void InitPageAllocator();
+void EnablePaging(PageTable* ptables, PageTable* kernel_tables, int num_kernel_tables, uintptr_t phys_address);
 
 void* AllocPages(int npages);
 
+// Physical memory is split in zones by which devices and page tables can address it. Allocations ask for the highest
+// zone they can use and fall back to the lower ones, so ordinary allocations leave the scarce low memory to those
+// that need it.
+enum MemoryZone {
+    kZoneDma,  // below 16mb, reachable by ISA DMA
+    kZoneLow,  // below 256mb
+    kZoneNormal,  // the rest, up to the memory the allocator manages
+    kNumZones,
+};
+
+// Frees the memory the firmware reserved for the ACPI tables, they must have been parsed (see InitAcpi).
+void ReclaimAcpiMemory();
+
+int AllocPhysPage(MemoryZone highest = kZoneNormal);  // returns the physical page number or -1
+void FreePhysPage(int page);
+
+// Memory statistics, the memory counts are in pages. The kernel has no heap or caches of its own, the ramdisk is
+// the only memory it holds on to besides its image. The heaps are those of the processes, up to their program break,
+// and the open files are in use of the system wide table (see file.h).
+struct MemInfo {
+    uint32_t page_size;
+    uint32_t total_pages;  // pages managed by the allocator
+    uint32_t free_pages;
+    uint32_t shared_pages;  // pages shared copy-on-write between address spaces
+    uint32_t kernel_pages;
+    uint32_t ramdisk_pages;
+    uint32_t reclaimed_pages;  // zero pages given back by ReclaimStep since boot
+    uint32_t heap_pages;
+    uint32_t open_files;
+    uint32_t file_descriptors;  // open descriptors of all threads, referring to the open files
+};
+
+int FreePageCount();
+MemInfo GetMemInfo();
+
+// Zero page reclaim. Touching a page that maps the zero page copies it even if only zeroes are written, so when idle
+// the kernel scans the current address space a few pages at a time for private writable pages that are all zero and
+// maps the zero page copy-on-write in their place again, freeing them. Pages accessed since the previous scan are
+// skipped, they're likely to be written again. It can be turned off with the vm/zero_reclaim tunable.
+void ReclaimStep();  // called when idle
+
+void* PersistentPage();  // a page of physical memory that keeps its contents over a warm reboot
+
+// Maps the physical range [phys, phys + size) uncached into the user part of the current address space, for user
+// space drivers accessing MMIO. It goes below the mappings of the process, like mmap. The range must be device
+// memory, reserved in the E820 map, a PCI memory BAR or the ISA hole, and is reserved for owner until
+// ReleasePhysReservations(owner). Returns nullptr on failure.
+void* MapPhys(uintptr_t phys, uintptr_t size, int owner);
+void ReleasePhysReservations(int owner);
+
+// Maps a page of device memory uncached into kernel space for good, it's reserved so user space drivers can't map
+// it too. For boot time setup of kernel drivers, returns nullptr when kernel space is full.
+void* MapKernelPhys(uintptr_t phys);
+// Copies from anywhere in physical memory through the temporary page, for reading firmware tables.
+void CopyFromPhys(void* dst, uintptr_t phys, std::size_t size);
+
 //PageTable* CreatePageDir();
-void DestroyPageDir(const PageTable* p);
+void DestroyPageDir(const PageTable* p);  // user space must be cleared
 
-PageTable* ForkCurrent();
+PageTable* ForkCurrent();  // returns nullptr when out of memory
+void DestroyFork(const PageTable* page_dir);  // undoes ForkCurrent when the child can't be created
+void ClearUserSpace();
+void WriteProtectPage(uintptr_t page);
+void FreeUserPages(uintptr_t first_page, uintptr_t end_page);
 
 void SwitchPageDir(PageTable* new_dir);
 
@@ -0,0 +1,178 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#include "pci.h"
+
+#include "kassert.h"
+#include "x86_inst.h"
+
+constexpr uint16_t kConfigAddress = 0xCF8;
+constexpr uint16_t kConfigData = 0xCFC;
+
+// Configuration space registers, as offsets of the dwords holding them.
+constexpr uint8_t kVendorDevice = 0x00;
+constexpr uint8_t kCommandStatus = 0x04;
+constexpr uint8_t kClass = 0x08;
+constexpr uint8_t kHeaderType = 0x0C;
+constexpr uint8_t kBar0 = 0x10;
+constexpr uint8_t kInterrupt = 0x3C;
+
+constexpr uint32_t kCommandIo = 1;
+constexpr uint32_t kCommandMemory = 2;
+constexpr uint32_t kCommandBusMaster = 4;
+
+static PciDevice devices[kMaxPciDevices];
+static int num_devices;
+static const PciDriver* drivers[kMaxPciDrivers];
+static int num_drivers;
+
+static uint32_t ConfigAddress(int bus, int device, int function, uint8_t offset) {
+    return 0x80000000 | (bus << 16) | (device << 11) | (function << 8) | (offset & 0xFC);
+}
+
+static uint32_t ConfigRead(int bus, int device, int function, uint8_t offset) {
+    auto flags = X86_save_flags_cli();
+    X86_outl(kConfigAddress, ConfigAddress(bus, device, function, offset));
+    auto value = X86_inl(kConfigData);
+    X86_restore_flags(flags);
+    return value;
+}
+
+static void ConfigWrite(int bus, int device, int function, uint8_t offset, uint32_t value) {
+    auto flags = X86_save_flags_cli();
+    X86_outl(kConfigAddress, ConfigAddress(bus, device, function, offset));
+    X86_outl(kConfigData, value);
+    X86_restore_flags(flags);
+}
+
+uint32_t PciConfigRead(const PciDevice& device, uint8_t offset) {
+    return ConfigRead(device.bus, device.device, device.function, offset);
+}
+
+void PciConfigWrite(const PciDevice& device, uint8_t offset, uint32_t value) {
+    ConfigWrite(device.bus, device.device, device.function, offset, value);
+}
+
+void PciEnableBusMaster(const PciDevice& device) {
+    // The upper half is the status register, whose bits are cleared by writing ones.
+    auto command = PciConfigRead(device, kCommandStatus) & 0xFFFF;
+    PciConfigWrite(device, kCommandStatus, command | kCommandIo | kCommandMemory | kCommandBusMaster);
+}
+
+int NumPciDevices() {
+    return num_devices;
+}
+
+PciDevice* GetPciDevice(int index) {
+    return &devices[index];
+}
+
+std::string_view PciClassName(uint8_t class_code, uint8_t subclass) {
+    switch (class_code) {
+        case 0x01:
+            switch (subclass) {
+                case 0x01: return "IDE controller";
+                case 0x06: return "SATA controller";
+                case 0x08: return "NVMe controller";
+                default: return "storage controller";
+            }
+        case 0x02: return subclass == 0x00 ? "ethernet controller" : "network controller";
+        case 0x03: return "display controller";
+        case 0x04: return "multimedia controller";
+        case 0x05: return "memory controller";
+        case 0x06:
+            switch (subclass) {
+                case 0x00: return "host bridge";
+                case 0x01: return "ISA bridge";
+                case 0x04: return "PCI bridge";
+                default: return "bridge";
+            }
+        case 0x07: return "communication controller";
+        case 0x08: return "system peripheral";
+        case 0x0C: return subclass == 0x03 ? "USB controller" : "serial bus controller";
+        default: return "device";
+    }
+}
+
+// Sizes a BAR by writing all ones, the bits that stay zero are the size. Decoding is turned off meanwhile, so the
+// device doesn't respond at the bogus address. Returns the number of BARs used, 64 bit memory BARs take two.
+static int ParseBar(PciDevice* device, int index) {
+    uint8_t offset = kBar0 + 4 * index;
+    auto command = PciConfigRead(*device, kCommandStatus) & 0xFFFF;
+    PciConfigWrite(*device, kCommandStatus, command & ~(kCommandIo | kCommandMemory));
+    auto value = PciConfigRead(*device, offset);
+    PciConfigWrite(*device, offset, 0xFFFFFFFF);
+    auto mask = PciConfigRead(*device, offset);
+    PciConfigWrite(*device, offset, value);
+    uint32_t high = 0;
+    bool is_64bit = !(value & 1) && ((value >> 1) & 3) == 2;
+    if (is_64bit && index < 5) high = PciConfigRead(*device, offset + 4);
+    PciConfigWrite(*device, kCommandStatus, command);
+
+    auto& bar = device->bars[index];
+    bar.io = value & 1;
+    bar.prefetchable = !bar.io && (value & 8);
+    uint32_t address_mask = bar.io ? ~3u : ~15u;
+    if ((mask & address_mask) == 0) return 1;  // not implemented
+    bar.size = ~(mask & address_mask) + 1;
+    if (bar.io) bar.size &= 0xFFFF;
+    bar.address = high == 0 ? value & address_mask : 0;
+    return is_64bit ? 2 : 1;
+}
+
+static bool TryAttach(const PciDriver* driver, PciDevice* device) {
+    if (device->driver || !driver->match(*device) || !driver->attach(device)) return false;
+    device->driver = driver;
+    kprint("PCI {}:{}.{} claimed by {}\n", device->bus, device->device, device->function, driver->name);
+    return true;
+}
+
+bool RegisterPciDriver(const PciDriver* driver) {
+    if (num_drivers == kMaxPciDrivers) return false;
+    drivers[num_drivers++] = driver;
+    for (int i = 0; i < num_devices; i++) TryAttach(driver, &devices[i]);
+    return true;
+}
+
+static void AddDevice(int bus, int device, int function, uint32_t id) {
+    if (num_devices == kMaxPciDevices) return;
+    auto& dev = devices[num_devices++];
+    dev = PciDevice{};
+    dev.bus = bus;
+    dev.device = device;
+    dev.function = function;
+    dev.vendor_id = id & 0xFFFF;
+    dev.device_id = id >> 16;
+    auto class_reg = ConfigRead(bus, device, function, kClass);
+    dev.class_code = class_reg >> 24;
+    dev.subclass = class_reg >> 16;
+    dev.prog_if = class_reg >> 8;
+    dev.irq = ConfigRead(bus, device, function, kInterrupt) & 0xFF;
+    // Only ordinary devices (header type 0) have six BARs, bridges have two.
+    int num_bars = (ConfigRead(bus, device, function, kHeaderType) >> 16 & 0x7F) == 0 ? 6 : 2;
+    for (int i = 0; i < num_bars; ) i += ParseBar(&dev, i);
+    kprint("PCI {}:{}.{} {}:{} {}\n", bus, device, function, Hex(dev.vendor_id), Hex(dev.device_id),
+           PciClassName(dev.class_code, dev.subclass));
+    for (int i = 0; i < num_drivers; i++) {
+        if (TryAttach(drivers[i], &dev)) break;
+    }
+}
+
+void InitPci() {
+    // The configuration mechanism reads back the address written when it exists.
+    X86_outl(kConfigAddress, 0x80000000);
+    if (X86_inl(kConfigAddress) != 0x80000000) return;
+    for (int bus = 0; bus < 256; bus++) {
+        for (int device = 0; device < 32; device++) {
+            auto id = ConfigRead(bus, device, 0, kVendorDevice);
+            if ((id & 0xFFFF) == 0xFFFF) continue;
+            AddDevice(bus, device, 0, id);
+            bool multi_function = ConfigRead(bus, device, 0, kHeaderType) & (0x80 << 16);
+            for (int function = 1; multi_function && function < 8; function++) {
+                id = ConfigRead(bus, device, function, kVendorDevice);
+                if ((id & 0xFFFF) != 0xFFFF) AddDevice(bus, device, function, id);
+            }
+        }
+    }
+}
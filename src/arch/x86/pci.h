@@ -0,0 +1,49 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#ifndef OS_PCI_H
+#define OS_PCI_H
+
+#include <cstdint>
+#include <string_view>
+
+// PCI devices, found at boot by scanning the configuration space of every bus, device and function through the
+// configuration mechanism #1 ports. Drivers register with a match function and claim the devices they attach to,
+// whether they register before or after the scan.
+constexpr int kMaxPciDevices = 32;
+constexpr int kMaxPciDrivers = 8;
+
+struct PciBar {
+    uint32_t address;  // 0 if the BAR isn't implemented, or is 64 bit and above 4gb
+    uint32_t size;
+    bool io;  // io ports rather than memory
+    bool prefetchable;
+};
+
+struct PciDevice {
+    uint8_t bus, device, function;
+    uint16_t vendor_id, device_id;
+    uint8_t class_code, subclass, prog_if;
+    uint8_t irq;  // the legacy IRQ line as set up by the firmware, 0xFF for none
+    PciBar bars[6];
+    const struct PciDriver* driver;  // that claimed it, nullptr if none
+};
+
+struct PciDriver {
+    std::string_view name;
+    bool (*match)(const PciDevice& device);
+    bool (*attach)(PciDevice* device);  // returns whether the driver claims the device
+};
+
+void InitPci();
+bool RegisterPciDriver(const PciDriver* driver);  // the driver must stay valid
+int NumPciDevices();
+PciDevice* GetPciDevice(int index);
+std::string_view PciClassName(uint8_t class_code, uint8_t subclass);
+
+uint32_t PciConfigRead(const PciDevice& device, uint8_t offset);
+void PciConfigWrite(const PciDevice& device, uint8_t offset, uint32_t value);
+void PciEnableBusMaster(const PciDevice& device);  // and memory and io decoding, for DMA
+
+#endif //OS_PCI_H
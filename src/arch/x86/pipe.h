@@ -11,7 +11,7 @@
 
 class Pipe {
 public:
-    Pipe(std::size_t size) : size(size) {}
+    constexpr Pipe(std::size_t size) : size(size) {}
 
     int Write(std::string_view s) {
         int i = 0;
@@ -36,6 +36,10 @@ public:
         return Buffer()[read_pos++ & (size - 1)];
     }
 
+    void Clear() {
+        read_pos = write_pos;
+    }
+
     bool Empty() {
         return read_pos == write_pos;
     }
@@ -64,12 +68,12 @@ private:
 template <int N>
 class PipeN : public Pipe {
 public:
-    PipeN() : Pipe(N) {
+    constexpr PipeN() : Pipe(N) {
         static_assert((N & (N - 1)) == 0, "N must be a power of 2");
     }
 
 private:
-    char buffer[N];
+    char buffer[N] = {};
 };
 
 
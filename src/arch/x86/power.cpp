@@ -0,0 +1,46 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#include "power.h"
+
+#include "kassert.h"
+#include "thread.h"
+#include "vfs.h"
+#include "x86_inst.h"
+
+[[noreturn]] static void PowerOff() {
+    // There is no ACPI support, these are the shortcuts of the emulators: QEMU's ACPI PM port and the older
+    // QEMU/Bochs one.
+    X86_outw(0x604, 0x2000);
+    X86_outw(0xB004, 0x2000);
+    kprint("It's now safe to turn off your computer\n");
+    while (true) X86_hlt();
+}
+
+[[noreturn]] static void Reboot() {
+    // Pulse the reset line through the keyboard controller, once its input buffer is empty.
+    constexpr uint16_t kKbdStatusPort = 0x64;
+    for (int i = 0; i < 100000 && (X86_inb(kKbdStatusPort) & 2); i++) {}
+    X86_outb(kKbdStatusPort, 0xFE);
+    // If that didn't work, an interrupt without an IDT triple faults, which resets the cpu.
+    X86_lidt(nullptr, 1);
+    asm volatile("int3");
+    while (true) X86_hlt();
+}
+
+// edx is the ShutdownMode. Only init may shut down, drivers are privileged but can't. Doesn't return on success.
+//
+// TODO: processes should get a chance to clean up (SIGTERM, then SIGKILL after a timeout), but there are no
+// signals yet. They are just never scheduled again.
+void SysShutdown(Regs* regs) {
+    if (current_thread->pid != 0 || (regs->edx != kPowerOff && regs->edx != kReboot)) {
+        regs->eax = -1;
+        return;
+    }
+    X86_cli();
+    kprint("Shutting down\n");
+    UnmountAll();
+    if (regs->edx == kReboot) Reboot();
+    PowerOff();
+}
@@ -0,0 +1,17 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#ifndef OS_POWER_H
+#define OS_POWER_H
+
+#include "entry.h"
+
+enum ShutdownMode {
+    kPowerOff = 0,
+    kReboot = 1,
+};
+
+void SysShutdown(Regs* regs);
+
+#endif //OS_POWER_H
@@ -0,0 +1,293 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#include "procfs.h"
+
+#include "exec.h"
+#include "irq.h"
+#include "paging.h"
+#include "pstore.h"
+#include "sysctl.h"
+#include "thread.h"
+#include "src/freestanding/utils.h"
+
+// Nodes below kMaxTunables are the tunable files, the nodes from kProcessNodes on are kProcessFiles per process.
+constexpr int kRootNode = kMaxTunables;
+constexpr int kSysNode = kMaxTunables + 1;
+constexpr int kMemInfoNode = kMaxTunables + 2;
+constexpr int kUptimeNode = kMaxTunables + 3;
+constexpr int kLastLogNode = kMaxTunables + 4;
+constexpr int kProcessNodes = kMaxTunables + 5;
+
+enum ProcessFile {
+    kProcessDir,
+    kStatusFile,
+    kMapsFile,
+    kProcessFiles,
+};
+
+constexpr std::string_view kRootFiles[] = {"sys", "meminfo", "uptime", "lastlog"};
+constexpr std::string_view kProcessFileNames[] = {"status", "maps"};
+
+constinit ProcFileSystem procfs;
+
+void InitProcFs() {
+    Mount("/proc", &procfs);
+}
+
+static bool IsTunable(int node) {
+    return node >= 0 && node < NumTunables();
+}
+
+// Processes are known by the tid of their first thread, which is their pid.
+static bool IsProcess(int pid) {
+    return pid >= 0 && pid < kMaxThreads && threads[pid].state != THREAD_UNUSED && threads[pid].pid == pid;
+}
+
+static int ProcessNode(int pid, ProcessFile file) {
+    return kProcessNodes + pid * kProcessFiles + file;
+}
+
+// Returns the pid of a node of a process that still exists, or -1.
+static int NodeProcess(int node, ProcessFile* file) {
+    if (node < kProcessNodes) return -1;
+    int pid = (node - kProcessNodes) / kProcessFiles;
+    *file = ProcessFile((node - kProcessNodes) % kProcessFiles);
+    return IsProcess(pid) ? pid : -1;
+}
+
+// Generated files are formatted in full on every read.
+class TextBuffer : public OutputStream {
+public:
+    void Push(std::string_view str) override {
+        auto n = min(str.size(), sizeof(text_) - size_);
+        memcpy(text_ + size_, str.data(), n);
+        size_ += n;
+    }
+
+    std::string_view Text() const { return {text_, size_}; }
+
+private:
+    char text_[1024];
+    std::size_t size_ = 0;
+};
+
+static char StateChar(ThreadState state) {
+    switch (state) {
+        case THREAD_RUNNING:
+        case THREAD_READY: return 'R';
+        case THREAD_BLOCKED: return 'S';
+        case THREAD_ZOMBIE: return 'Z';
+        default: return '?';
+    }
+}
+
+static void FormatStatus(const Thread& process, OutputStream& out) {
+    int num_threads = 0;
+    uint64_t user_ns = 0, system_ns = 0;
+    for (auto& thread : threads) {
+        if (thread.state == THREAD_UNUSED || thread.pid != process.pid) continue;
+        num_threads++;
+        user_ns += thread.user_ns;
+        system_ns += thread.system_ns;
+    }
+    print(out, "Pid: {}\nPPid: {}\nState: {}\n", process.pid, threads[process.parent_tid].pid,
+          StateChar(process.state));
+    print(out, "Priority: {}\nThreads: {}\nTicks: {}\n", process.priority, num_threads, process.time);
+    print(out, "UserTime: {} ms\nSystemTime: {} ms\n", user_ns / 1000000, system_ns / 1000000);
+    print(out, "Heap: {} kb\nPrivileged: {}\n", (process.brk - process.brk_base) / 1024, int(process.privileged));
+}
+
+// A line per mapping: the address range, the access and where the contents come from. The heap and stack aren't
+// mappings but are shown like them.
+static void FormatMaps(const Thread& process, OutputStream& out) {
+    for (int i = 0; i < process.num_vmas; i++) {
+        auto& vma = process.vmas[i];
+        print(out, "{}-{} {} {}\n", Hex(vma.start), Hex(vma.end), vma.writable ? "rw" : "r-",
+              vma.file.fs ? "file" : "anon");
+    }
+    if (process.brk > process.brk_base) {
+        print(out, "{}-{} rw [heap]\n", Hex(process.brk_base), Hex(process.brk));
+    }
+    print(out, "{}-{} rw [stack]\n", Hex(kStackLimit), Hex(kKernelBase));
+}
+
+static void FormatMemInfo(OutputStream& out) {
+    auto info = GetMemInfo();
+    auto kb = [&info](uint32_t pages) { return pages * (info.page_size / 1024); };
+    print(out, "MemTotal: {} kb\nMemFree: {} kb\nShared: {} kb\n", kb(info.total_pages), kb(info.free_pages),
+          kb(info.shared_pages));
+    print(out, "Kernel: {} kb\nRamdisk: {} kb\nReclaimed: {} kb\n", kb(info.kernel_pages), kb(info.ramdisk_pages),
+          kb(info.reclaimed_pages));
+    print(out, "Heap: {} kb\nOpenFiles: {}\nFileDescriptors: {}\n", kb(info.heap_pages), info.open_files,
+          info.file_descriptors);
+}
+
+// Seconds since boot with two decimals.
+static void FormatUptime(OutputStream& out) {
+    auto centiseconds = GetTimeNs() / 10000000;
+    int fraction = centiseconds % 100;
+    print(out, "{}.{}{}\n", uint32_t(centiseconds / 100), fraction / 10, fraction % 10);
+}
+
+// Returns false if node isn't a generated file.
+static bool FormatFile(int node, OutputStream& out) {
+    ProcessFile file;
+    int pid = NodeProcess(node, &file);
+    if (node == kMemInfoNode) {
+        FormatMemInfo(out);
+    } else if (node == kUptimeNode) {
+        FormatUptime(out);
+    } else if (pid >= 0 && file == kStatusFile) {
+        FormatStatus(threads[pid], out);
+    } else if (pid >= 0 && file == kMapsFile) {
+        FormatMaps(threads[pid], out);
+    } else {
+        return false;
+    }
+    return true;
+}
+
+// Accepts only the decimal pid of an existing process.
+static int ParsePid(std::string_view s) {
+    if (s.empty() || s.size() > 4) return -1;
+    int pid = 0;
+    for (char c : s) {
+        if (c < '0' || c > '9') return -1;
+        pid = pid * 10 + (c - '0');
+    }
+    return IsProcess(pid) ? pid : -1;
+}
+
+// Returns the number of characters written to buf, which must hold 12.
+static int FormatInt(int value, char* buf) {
+    char digits[10];
+    int n = 0;
+    uint32_t v = value < 0 ? -uint32_t(value) : value;
+    do {
+        digits[n++] = '0' + v % 10;
+        v /= 10;
+    } while (v);
+    int len = 0;
+    if (value < 0) buf[len++] = '-';
+    while (n > 0) buf[len++] = digits[--n];
+    buf[len++] = '\n';
+    return len;
+}
+
+// Accepts an optional sign and digits, followed by optional whitespace.
+static bool ParseInt(std::string_view s, int* value) {
+    while (!s.empty() && (s.back() == '\n' || s.back() == ' ')) s.remove_suffix(1);
+    bool negative = !s.empty() && s.front() == '-';
+    if (negative) s.remove_prefix(1);
+    if (s.empty() || s.size() > 9) return false;
+    int v = 0;
+    for (char c : s) {
+        if (c < '0' || c > '9') return false;
+        v = v * 10 + (c - '0');
+    }
+    *value = negative ? -v : v;
+    return true;
+}
+
+int ProcFileSystem::Lookup(std::string_view path) {
+    if (path.empty()) return kRootNode;
+    if (path == "sys") return kSysNode;
+    if (path == "meminfo") return kMemInfoNode;
+    if (path == "uptime") return kUptimeNode;
+    if (path == "lastlog") return kLastLogNode;
+    if (path.starts_with("sys/")) {
+        path.remove_prefix(4);
+        return FindTunable(path);
+    }
+    std::size_t n = 0;
+    while (n < path.size() && path[n] != '/') n++;
+    int pid = ParsePid(path.substr(0, n));
+    if (pid < 0) return -1;
+    path.remove_prefix(n);
+    while (!path.empty() && path.front() == '/') path.remove_prefix(1);
+    if (path.empty()) return ProcessNode(pid, kProcessDir);
+    for (int i = 0; i < int(array_size(kProcessFileNames)); i++) {
+        if (path == kProcessFileNames[i]) return ProcessNode(pid, ProcessFile(kStatusFile + i));
+    }
+    return -1;
+}
+
+int ProcFileSystem::Read(int node, uint64_t offset, void* buf, std::size_t len) {
+    TextBuffer text;
+    if (IsTunable(node)) {
+        char value[12];
+        text.Push(std::string_view(value, FormatInt(GetTunable(node).get(), value)));
+    } else if (node != kLastLogNode && !FormatFile(node, text)) {
+        return -1;
+    }
+    // The log of the previous boot doesn't fit the text buffer, but it doesn't change either.
+    auto contents = node == kLastLogNode ? LastLog() : text.Text();
+    if (offset >= contents.size()) return 0;
+    len = min<std::size_t>(len, contents.size() - offset);
+    memcpy(buf, contents.data() + offset, len);
+    return len;
+}
+
+// The whole value must be written at once, by a privileged process.
+int ProcFileSystem::Write(int node, uint64_t offset, const void* buf, std::size_t len) {
+    int value;
+    if (!IsTunable(node) || offset != 0 || !current_thread->privileged) return -1;
+    if (!ParseInt(std::string_view(static_cast<const char*>(buf), len), &value)) return -1;
+    if (!GetTunable(node).set(value)) return -1;
+    return len;
+}
+
+int ProcFileSystem::ReadDir(int node, std::size_t index, DirEntry* entry) {
+    std::string_view name;
+    FileType type;
+    char pid_name[12];
+    ProcessFile file;
+    if (node == kRootNode) {
+        if (index < array_size(kRootFiles)) {
+            name = kRootFiles[index];
+            type = index == 0 ? kDirectory : kRegularFile;
+        } else {
+            // Followed by a directory per process.
+            index -= array_size(kRootFiles);
+            int pid = 0;
+            while (pid < kMaxThreads && !(IsProcess(pid) && index-- == 0)) pid++;
+            if (pid == kMaxThreads) return 0;
+            name = std::string_view(pid_name, FormatInt(pid, pid_name) - 1);  // without the newline
+            type = kDirectory;
+        }
+    } else if (node == kSysNode) {
+        if (index >= std::size_t(NumTunables())) return 0;
+        name = GetTunable(index).name;
+        type = kRegularFile;
+    } else if (NodeProcess(node, &file) >= 0 && file == kProcessDir) {
+        if (index >= array_size(kProcessFileNames)) return 0;
+        name = kProcessFileNames[index];
+        type = kRegularFile;
+    } else {
+        return -1;
+    }
+    auto size = min(name.size(), kMaxNameLength - 1);
+    memcpy(entry->name, name.data(), size);
+    entry->name[size] = 0;
+    entry->type = type;
+    return 1;
+}
+
+bool ProcFileSystem::Stat(int node, FileStat* stat) {
+    ProcessFile file;
+    int pid = NodeProcess(node, &file);
+    if (node == kRootNode || node == kSysNode || (pid >= 0 && file == kProcessDir)) {
+        *stat = FileStat{0, kDirectory, 0555, 0};
+        return true;
+    }
+    if (node == kLastLogNode) {
+        *stat = FileStat{LastLog().size(), kRegularFile, 0444, 0};
+        return true;
+    }
+    if (!IsTunable(node) && node != kMemInfoNode && node != kUptimeNode && pid < 0) return false;
+    // The size isn't known without generating the contents. Only the tunables can be written.
+    *stat = FileStat{0, kRegularFile, IsTunable(node) ? 0644u : 0444u, 0};
+    return true;
+}
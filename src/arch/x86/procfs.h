@@ -0,0 +1,28 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#ifndef OS_PROCFS_H
+#define OS_PROCFS_H
+
+#include "vfs.h"
+
+// The /proc filesystem, generated from kernel state when read. /proc/sys has a file per tunable (see sysctl.h)
+// holding its value in decimal, writing a number to it changes the tunable. meminfo has the memory statistics of
+// GetMemInfo, the memory in kb, uptime the seconds since boot and lastlog the log of the previous boot if it crashed.
+// Every process has a directory named by its pid with its status and maps, the address ranges of its mappings, heap
+// and stack.
+class ProcFileSystem : public FileSystem {
+public:
+    constexpr ProcFileSystem() = default;
+
+    int Lookup(std::string_view path) override;
+    int Read(int node, uint64_t offset, void* buf, std::size_t len) override;
+    int Write(int node, uint64_t offset, const void* buf, std::size_t len) override;
+    int ReadDir(int node, std::size_t index, DirEntry* entry) override;
+    bool Stat(int node, FileStat* stat) override;
+};
+
+void InitProcFs();
+
+#endif //OS_PROCFS_H
@@ -0,0 +1,62 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#include "profile.h"
+
+#include "descriptors.h"
+#include "paging.h"
+#include "src/freestanding/utils.h"
+
+static bool profiling;
+static int num_samples;
+static ProfileSample samples[kMaxProfileSamples];
+
+// Whether the word at address can be read without faulting.
+static bool IsMapped(uintptr_t address) {
+    return GetCurrentDir()[address / kPageSize / kNumPageEntries].IsPresent() &&
+           GetPageEntry(address / kPageSize)->IsPresent();
+}
+
+void ProfileTick(const Regs* regs) {
+    if (!profiling || num_samples == kMaxProfileSamples) return;
+    auto& sample = samples[num_samples++];
+    sample.pcs[0] = regs->eip;
+    sample.depth = 1;
+
+    bool is_user = (regs->cs & 3) == 3;
+    uintptr_t low = is_user ? 0x10000 : AsLinear(kernel_stack + kStackGuardSize);
+    uintptr_t high = is_user ? kKernelBase : AsLinear(kernel_stack + sizeof(kernel_stack));
+    uintptr_t frame = regs->ebp;
+    while (sample.depth < kMaxProfileDepth) {
+        // A frame is the saved ebp followed by the return address.
+        if (frame < low || frame > high - 8 || (frame & 3) || !IsMapped(frame) || !IsMapped(frame + 4)) break;
+        auto fp = reinterpret_cast<const uint32_t*>(frame);
+        sample.pcs[sample.depth++] = fp[1];
+        if (fp[0] <= frame) break;
+        frame = fp[0];
+    }
+}
+
+// edx is the command. Fetch copies at most ebx ProfileSamples to ecx and returns the number copied, the other commands
+// return the number of samples recorded so far.
+void SysProfile(Regs* regs) {
+    switch (regs->edx) {
+        case kProfileStart:
+            num_samples = 0;
+            profiling = true;
+            break;
+        case kProfileStop:
+            profiling = false;
+            break;
+        case kProfileFetch: {
+            int n = min<uint32_t>(num_samples, regs->ebx);
+            regs->eax = CopyToUser(regs->ecx, samples, n * sizeof(samples[0])) ? n : -kEFAULT;
+            return;
+        }
+        default:
+            regs->eax = -1;
+            return;
+    }
+    regs->eax = num_samples;
+}
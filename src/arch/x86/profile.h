@@ -0,0 +1,34 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#ifndef OS_PROFILE_H
+#define OS_PROFILE_H
+
+#include <cstdint>
+
+#include "entry.h"
+
+// Sampling profiler. While profiling, every timer tick records the interrupted eip, kernel or user, together with
+// a short backtrace in a buffer that is fetched by user space. Once the buffer is full further samples are dropped.
+// The backtrace follows the ebp chain, which only works because everything is compiled with frame pointers. Frames
+// are validated, the chain has to stay within the stack it started on and move up the stack, so a function
+// using ebp for something else only ends the backtrace early.
+constexpr int kMaxProfileSamples = 2048;
+constexpr int kMaxProfileDepth = 8;
+
+struct ProfileSample {
+    uint32_t depth;
+    uint32_t pcs[kMaxProfileDepth];  // pcs[0] is the interrupted eip, followed by the return addresses
+};
+
+enum ProfileCommand {
+    kProfileStart = 0,  // clears the buffer
+    kProfileStop = 1,
+    kProfileFetch = 2,
+};
+
+void ProfileTick(const Regs* regs);
+void SysProfile(Regs* regs);
+
+#endif //OS_PROFILE_H
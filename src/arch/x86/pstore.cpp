@@ -0,0 +1,76 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#include "pstore.h"
+
+#include <cstdint>
+
+#include "kassert.h"
+#include "paging.h"
+#include "src/freestanding/utils.h"
+
+constexpr uint32_t kPstoreMagic = 0x6B6C6F67;  // "klog"
+
+struct PersistentLog {
+    uint32_t magic;
+    uint32_t crashed;
+    uint32_t size;  // total bytes ever written, the log is a ring buffer
+    uint32_t checksum;  // of the header fields, so random memory isn't taken for a log
+    char buf[kPageSize - 16];
+};
+
+static_assert(sizeof(PersistentLog) == kPageSize);
+
+static PersistentLog* plog;
+static char last_log[sizeof(PersistentLog::buf)];
+static std::size_t last_log_size;
+
+static uint32_t HeaderChecksum(const PersistentLog& log) {
+    return ~(log.magic ^ log.crashed ^ log.size);
+}
+
+static void UpdateChecksum() {
+    plog->checksum = HeaderChecksum(*plog);
+}
+
+void InitPstore() {
+    auto log = static_cast<PersistentLog*>(PersistentPage());
+    if (log->magic == kPstoreMagic && log->checksum == HeaderChecksum(*log) && log->crashed) {
+        // Unroll the ring buffer.
+        constexpr auto kBufSize = sizeof(log->buf);
+        last_log_size = min<std::size_t>(log->size, kBufSize);
+        for (std::size_t i = 0; i < last_log_size; i++) {
+            last_log[i] = log->buf[(log->size - last_log_size + i) % kBufSize];
+        }
+    }
+    log->magic = kPstoreMagic;
+    log->crashed = 0;
+    log->size = 0;
+    plog = log;
+    UpdateChecksum();
+    if (last_log_size) kprint("The previous boot crashed, its last {} bytes of log are saved\n", last_log_size);
+}
+
+void PersistLog(std::string_view str) {
+    if (!plog) return;
+    for (char c : str) plog->buf[plog->size++ % sizeof(plog->buf)] = c;
+    UpdateChecksum();
+}
+
+void MarkCrashed() {
+    if (!plog) return;
+    plog->crashed = 1;
+    UpdateChecksum();
+}
+
+std::string_view LastLog() {
+    return {last_log, last_log_size};
+}
+
+// edx points to a buffer of ecx bytes which receives the log of the previous boot if it crashed. Returns the
+// size of the log.
+void SysLastLog(Regs* regs) {
+    auto size = min<std::size_t>(last_log_size, regs->ecx);
+    regs->eax = CopyToUser(regs->edx, last_log, size) ? size : -kEFAULT;
+}
@@ -0,0 +1,24 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#ifndef OS_PSTORE_H
+#define OS_PSTORE_H
+
+#include <string_view>
+
+#include "entry.h"
+
+// Persistent kernel log. The tail of the kernel output is kept in a page of memory that survives a warm reboot,
+// and marked as crashed when the kernel dies. If the previous boot crashed, its log is kept around and can be read
+// by user space with SysLastLog or from /proc/lastlog.
+void InitPstore();
+void PersistLog(std::string_view str);
+void MarkCrashed();
+
+// The log of the previous boot, empty if it didn't crash.
+std::string_view LastLog();
+
+void SysLastLog(Regs* regs);
+
+#endif //OS_PSTORE_H
@@ -0,0 +1,56 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#include "random.h"
+
+#include "x86_inst.h"
+#include "src/freestanding/utils.h"
+
+// Must not be all zero, the generator would only produce zeros.
+static uint32_t pool[4] = {0x9E3779B9, 0x243F6A88, 0xB7E15162, 0x6A09E667};
+static unsigned mix_index;
+static bool has_tsc;
+
+void InitRandom() {
+    has_tsc = HasTsc();
+    AddEntropy(0);
+}
+
+static uint32_t Rotl(uint32_t x, int k) {
+    return (x << k) | (x >> (32 - k));
+}
+
+static uint32_t Next() {
+    uint32_t result = Rotl(pool[1] * 5, 7) * 9;
+    uint32_t t = pool[1] << 9;
+    pool[2] ^= pool[0];
+    pool[3] ^= pool[1];
+    pool[1] ^= pool[2];
+    pool[0] ^= pool[3];
+    pool[2] ^= t;
+    pool[3] = Rotl(pool[3], 11);
+    return result;
+}
+
+void AddEntropy(uint32_t value) {
+    auto flags = X86_save_flags_cli();
+    if (has_tsc) value ^= uint32_t(X86_rdtsc());
+    pool[mix_index++ % 4] ^= value;
+    if ((pool[0] | pool[1] | pool[2] | pool[3]) == 0) pool[0] = 1;
+    Next();
+    X86_restore_flags(flags);
+}
+
+void GetRandomBytes(void* buf, std::size_t len) {
+    auto out = static_cast<char*>(buf);
+    while (len > 0) {
+        auto flags = X86_save_flags_cli();
+        uint32_t r = Next();
+        X86_restore_flags(flags);
+        auto n = min<std::size_t>(len, sizeof(r));
+        memcpy(out, &r, n);
+        out += n;
+        len -= n;
+    }
+}
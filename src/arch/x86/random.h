@@ -0,0 +1,22 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#ifndef OS_RANDOM_H
+#define OS_RANDOM_H
+
+#include <cstddef>
+#include <cstdint>
+
+// Random numbers for /dev/random. Interrupts feed the time stamp counter at which they arrive into an entropy pool,
+// the jitter of keystrokes and packets makes its low bits unpredictable. Random bytes come from a xoshiro128**
+// generator whose state is the pool, so reading never blocks.
+//
+// TODO: the output isn't cryptographically strong and nothing estimates how much entropy was collected, so the
+// first bytes after boot are guessable. That needs a hash (ChaCha20) over the pool and RDRAND where the cpu has it.
+// Before the pentium there is no time stamp counter and only the order of the interrupts is mixed in.
+void InitRandom();
+void AddEntropy(uint32_t value);  // may be called from interrupt handlers
+void GetRandomBytes(void* buf, std::size_t len);
+
+#endif //OS_RANDOM_H
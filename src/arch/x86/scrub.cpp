@@ -0,0 +1,81 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#include "scrub.h"
+
+#include <cstdint>
+
+#include "kassert.h"
+#include "paging.h"
+#include "sysctl.h"
+#include "src/freestanding/utils.h"
+
+extern "C" uint8_t _start[];
+extern "C" uint8_t _erodata[];
+
+struct ScrubRegion {
+    std::string_view name;
+    const uint8_t* base;
+    int npages;
+};
+
+constexpr int kMaxScrubPages = 1024;
+
+static ScrubRegion regions[2];
+static uint32_t checksums[kMaxScrubPages];
+static int num_pages;
+static int next_page;
+
+static uint32_t Checksum(const uint8_t* page) {
+    // FNV-1a over words, any single bit flip changes it.
+    auto words = reinterpret_cast<const uint32_t*>(page);
+    uint32_t hash = 2166136261;
+    for (unsigned i = 0; i < kPageSize / sizeof(uint32_t); i++) hash = (hash ^ words[i]) * 16777619;
+    return hash;
+}
+
+// Maps a page number over all regions to the region and page within.
+static const uint8_t* ScrubPage(int page, const ScrubRegion** region) {
+    for (auto& r : regions) {
+        if (page < r.npages) {
+            *region = &r;
+            return r.base + page * kPageSize;
+        }
+        page -= r.npages;
+    }
+    return nullptr;
+}
+
+static bool scrub_enabled = true;
+
+void InitScrub(const void* ramdisk, std::size_t ramdisk_size) {
+    if (!kScrubMemory) return;
+    RegisterTunable({"scrub/enabled", [] { return int(scrub_enabled); }, [](int value) {
+        if (value != 0 && value != 1) return false;
+        scrub_enabled = value;
+        return true;
+    }});
+    // Only whole pages, the partial ones are shared with data that does change.
+    regions[0] = ScrubRegion{"kernel", _start, int((_erodata - _start) / kPageSize)};
+    regions[1] = ScrubRegion{"ramdisk", static_cast<const uint8_t*>(ramdisk), int(ramdisk_size / kPageSize)};
+    for (auto& r : regions) {
+        r.npages = min(r.npages, kMaxScrubPages - num_pages);
+        num_pages += r.npages;
+    }
+    const ScrubRegion* region = nullptr;
+    for (int i = 0; i < num_pages; i++) checksums[i] = Checksum(ScrubPage(i, &region));
+}
+
+void ScrubStep() {
+    if (!kScrubMemory || !scrub_enabled || num_pages == 0) return;
+    const ScrubRegion* region = nullptr;
+    auto page = ScrubPage(next_page, &region);
+    auto checksum = Checksum(page);
+    if (checksum != checksums[next_page]) {
+        kprint("Memory corruption in {} page {} at {}\n", region->name, (page - region->base) / kPageSize, page);
+        // Report once.
+        checksums[next_page] = checksum;
+    }
+    next_page = (next_page + 1) % num_pages;
+}
@@ -0,0 +1,18 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#ifndef OS_SCRUB_H
+#define OS_SCRUB_H
+
+#include <cstddef>
+
+// Memory scrubbing. Without ECC a flipped bit goes unnoticed until it crashes something, so when idle the kernel
+// re-checksums memory that should never change, its code and read only data and the ramdisk, one page at a time
+// and reports pages that don't match their checksum at boot. Scrubbing can be paused with the scrub/enabled tunable.
+constexpr bool kScrubMemory = true;
+
+void InitScrub(const void* ramdisk, std::size_t ramdisk_size);
+void ScrubStep();  // called when idle
+
+#endif //OS_SCRUB_H
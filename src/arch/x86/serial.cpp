@@ -0,0 +1,120 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#include "serial.h"
+
+#include <cstdint>
+
+#include "console.h"
+#include "irq.h"
+#include "sysctl.h"
+#include "tty.h"
+#include "x86_inst.h"
+#include "src/freestanding/utils.h"
+
+constexpr uint16_t kCom1Port = 0x3F8;
+constexpr int kCom1Irq = 4;
+
+// UART registers, as offsets from the port. The divisor latch replaces the data and interrupt enable registers
+// while the DLAB bit of the line control register is set.
+constexpr uint16_t kData = 0;
+constexpr uint16_t kInterruptEnable = 1;
+constexpr uint16_t kDivisorLow = 0;
+constexpr uint16_t kDivisorHigh = 1;
+constexpr uint16_t kFifoControl = 2;
+constexpr uint16_t kLineControl = 3;
+constexpr uint16_t kModemControl = 4;
+constexpr uint16_t kLineStatus = 5;
+
+constexpr uint8_t kLineDlab = 0x80;
+constexpr uint8_t kLine8N1 = 0x03;
+constexpr uint8_t kStatusDataReady = 0x01;
+constexpr uint8_t kStatusTransmitEmpty = 0x20;
+constexpr uint8_t kModemLoopback = 0x10;
+// DTR and RTS, OUT2 gates the interrupt line of the UART.
+constexpr uint8_t kModemNormal = 0x0B;
+
+static bool present;
+static bool mirror;
+static bool raw;
+
+// What arrived while raw, a ring buffer. Bytes arriving while it's full are dropped, XMODEM retransmits them.
+constexpr std::size_t kRawBufferSize = 4096;
+static char raw_buffer[kRawBufferSize];
+static std::size_t raw_head, raw_tail;  // total bytes received and read
+
+static void SendByte(char c) {
+    while (!(X86_inb(kCom1Port + kLineStatus) & kStatusTransmitEmpty)) {}
+    X86_outb(kCom1Port + kData, c);
+}
+
+void SerialWrite(std::string_view str) {
+    if (!present || !mirror || raw) return;
+    for (char c : str) {
+        if (c == '\n') SendByte('\r');
+        SendByte(c);
+    }
+}
+
+int SerialReadRaw(void* buf, std::size_t len) {
+    if (!present) return -1;
+    auto flags = X86_save_flags_cli();
+    len = min(len, raw_head - raw_tail);
+    for (std::size_t i = 0; i < len; i++) static_cast<char*>(buf)[i] = raw_buffer[raw_tail++ % kRawBufferSize];
+    X86_restore_flags(flags);
+    return len;
+}
+
+int SerialWriteRaw(const void* buf, std::size_t len) {
+    if (!present) return -1;
+    for (std::size_t i = 0; i < len; i++) SendByte(static_cast<const char*>(buf)[i]);
+    return len;
+}
+
+// Terminals send carriage return for enter and DEL for backspace.
+static void SerialHandler() {
+    while (X86_inb(kCom1Port + kLineStatus) & kStatusDataReady) {
+        char c = X86_inb(kCom1Port + kData);
+        if (raw) {
+            if (raw_head - raw_tail < kRawBufferSize) raw_buffer[raw_head++ % kRawBufferSize] = c;
+            continue;
+        }
+        if (c == '\r') c = '\n';
+        if (c == 0x7F) c = '\b';
+        TtyInput(active_console, c);
+    }
+}
+
+bool InitSerial() {
+    X86_outb(kCom1Port + kInterruptEnable, 0);
+    X86_outb(kCom1Port + kLineControl, kLineDlab);
+    X86_outb(kCom1Port + kDivisorLow, 1);  // 115200 baud
+    X86_outb(kCom1Port + kDivisorHigh, 0);
+    X86_outb(kCom1Port + kLineControl, kLine8N1);
+    X86_outb(kCom1Port + kFifoControl, 0xC7);  // enable and clear the fifos, interrupt at 14 bytes
+    // A byte sent in loopback mode must come back, otherwise there is no UART.
+    X86_outb(kCom1Port + kModemControl, kModemLoopback | kModemNormal);
+    X86_outb(kCom1Port + kData, 0xAE);
+    if (X86_inb(kCom1Port + kData) != 0xAE) return false;
+    X86_outb(kCom1Port + kModemControl, kModemNormal);
+
+    present = mirror = true;
+    RegisterTunable({"serial/mirror", [] { return int(mirror); }, [](int value) {
+        if (value != 0 && value != 1) return false;
+        mirror = value;
+        return true;
+    }});
+    // Switching drops what is left from a previous transfer.
+    RegisterTunable({"serial/raw", [] { return int(raw); }, [](int value) {
+        if (value != 0 && value != 1) return false;
+        auto flags = X86_save_flags_cli();
+        raw = value;
+        raw_tail = raw_head;
+        X86_restore_flags(flags);
+        return true;
+    }});
+    RegisterIrqHandler(kCom1Irq, SerialHandler);
+    X86_outb(kCom1Port + kInterruptEnable, 1);  // data available
+    return true;
+}
@@ -0,0 +1,26 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#ifndef OS_SERIAL_H
+#define OS_SERIAL_H
+
+#include <cstddef>
+#include <string_view>
+
+// The first serial port (COM1, a 16550 UART) as a console for headless machines. The output of the visible console,
+// kernel messages included, is mirrored to it unless the tunable "serial/mirror" is 0. What arrives on it is typed
+// into the visible console, like input from the keyboard. Sending is polled, receiving uses IRQ 4.
+//
+// Setting the tunable "serial/raw" to 1 detaches the port from the console for transfers like XMODEM: nothing is
+// mirrored to it and what arrives is buffered for /dev/ttyS0 instead of typed into the console. Bytes pass unchanged
+// in both directions then.
+bool InitSerial();  // returns false if there is no UART
+void SerialWrite(std::string_view str);
+
+// The raw port, for /dev/ttyS0. Reads don't wait, they return what arrived so far, which can be nothing. Both
+// return -1 if there is no UART.
+int SerialReadRaw(void* buf, std::size_t len);
+int SerialWriteRaw(const void* buf, std::size_t len);
+
+#endif //OS_SERIAL_H
@@ -6,70 +6,55 @@
 
 #include "boot/boot.h"
 #include "src/freestanding/utils.h"
+#include "acpi.h"
+#include "console.h"
 #include "descriptors.h"
+#include "devfs.h"
+#include "exec.h"
 #include "irq.h"
 #include "kassert.h"
+#include "klog.h"
+#include "net.h"
+#include "overlayfs.h"
 #include "paging.h"
+#include "pci.h"
+#include "procfs.h"
+#include "pstore.h"
+#include "random.h"
+#include "scrub.h"
+#include "serial.h"
+#include "tarfs.h"
 #include "thread.h"
+#include "tmpfs.h"
 #include "x86_inst.h"
 
-struct Screen {
-    int cursor_x = 0, cursor_y = 0;
-
-    void ClearScreen() {
-        uint16_t *video = reinterpret_cast<uint16_t *>(kLowMemBase + 0xB8000);
-        memset(video, 0, 80 * 25 * 2);
-        cursor_x = cursor_y = 0;
-    }
-
-    void Put(char c) {
-        uint16_t* video = reinterpret_cast<uint16_t *>(kLowMemBase + 0xB8000);
-        if (c == '\n') {
-            cursor_x = 0;
-            cursor_y++;
-        } else {
-            video[cursor_y * 80 + cursor_x] = 0x700 | c;
-            cursor_x++;
-        }
-        if (cursor_x == 80) {
-            cursor_x = 0;
-            cursor_y++;
-        }
-        if (cursor_y == 25) {
-            memmove(video, video + 80, 80 * 24 * 2);
-            memset(video + 80 * 24, 0, 80 * 2);
-            cursor_y = 24;
-        }
-    }
-};
-
 struct KernelOutput : public OutputStream {
     void Push(std::string_view str) override;
-
-    Screen screen_;
 };
 
+// Kernel messages go to the kernel log, which shows them on whatever console is currently visible.
 void KernelOutput::Push(std::string_view str) {
-    Screen tmp = screen_;
-    for (char c : str) {
-        tmp.Put(c);
-    }
-    screen_ = tmp;
+    KlogWrite(str);
+    PersistLog(str);
 }
 
 constinit KernelOutput kout;
 
 NOINLINE [[noreturn]] void terminate(int) {
+    MarkCrashed();
     while (true) X86_hlt();
 }
 
 extern PageTable page_tables[];
+extern PageTable kernel_page_tables[];
 
 extern "C" uint8_t _start[];
 extern "C" uint8_t _edata[];
 extern "C" uint8_t _end[];
 
-extern uint8_t kernel_stack[4096 * 16];
+// Boot runs on its own stack, interrupts taken from user mode start at the top of kernel_stack. Once the first thread
+// is entered the boot stack is abandoned.
+alignas(4096) static uint8_t boot_stack[4096 * 4];
 
 // This is a subtle function. The bootloader loads the kernel at some arbitrary physical address with unpaged
 // memory, the kernel is compiled/linked expecting to be loaded at kKernelBase. When enabling paging the page tables
@@ -77,7 +62,7 @@ extern uint8_t kernel_stack[4096 * 16];
 // execute at the right address. However this code is called before paging is enabled, so we have to be careful because
 // access of globals will be at the wrong physical address. We compensate by passing in `delta` to offset the address
 // of globals to the correct physical address. After paging is enabled we should switch to the right stack and right
-// ip, this must be done in asm and will be handled in entry.asm. This function returns the address of the stack.
+// ip, this must be done in asm and will be handled in entry.asm. This function returns the address of the boot stack.
 extern "C" void* PrepareKernel(const BootData* boot_data) {
     auto phys_address = reinterpret_cast<uintptr_t>(boot_data->kernel);
     if ((phys_address & (kPageSize - 1)) != 0 || reinterpret_cast<uintptr_t>(_start) != kKernelBase) {
@@ -91,83 +76,148 @@ extern "C" void* PrepareKernel(const BootData* boot_data) {
     // Need to do this as not to override pages 
     memset(adjust(_edata), 0, _end - _edata);  // Zero bss
 
+    // The kernel page tables are part of the image, so they don't need to map themselves beyond it.
+    int num_kernel_tables = KernelPageTables((_end - _start + kPageSize - 1) / kPageSize);
+    if (num_kernel_tables > kMaxKernelPageTables) terminate(-1);
+
     auto ptables = adjust(page_tables);
-    EnablePaging(ptables, phys_address);
+    EnablePaging(ptables, adjust(kernel_page_tables), num_kernel_tables, phys_address);
 
-    return kernel_stack + sizeof(kernel_stack);
+    return boot_stack + sizeof(boot_stack);
 }
 
 void* ramdisk;
 std::size_t ramdisk_size;
 
-class RamUSTARReader : public USTARReader {
-public:
-    constexpr RamUSTARReader(const char* data, std::size_t size) : data_(data), size_(size) {}
-
-    bool ReadBlocks(std::size_t block, int n, void *buf) override {
-        //kprint("ReadBlocks from {} at block {} n {} to {}\n", (void*)data_, block_, n, buf);
-        if ((block + n) * 512 > size_) return false;
-        memcpy(buf, data_ + block * 512, n * 512);
-        return true;
-    }
-
-private:
-    const char* data_;
-    std::size_t size_;
-};
-
-// alignas(alignof(RamUSTARReader)) uint8_t fs[sizeof(RamUSTARReader)];
-constinit RamUSTARReader fs(nullptr, 0);
+constinit TarFileSystem tarfs;
+constinit OverlayFileSystem rootfs(&tarfs);  // the root is writable, changes are kept in memory
 
+// The ramdisk is reached through the mapping of the first 1mb at kLowMemBase, which limits the archive to what the
+// bootloader can fit below the video memory.
+//
+// TODO: read the archive from the disk instead of the ramdisk. That needs a disk driver (ATA PIO to start with)
+// reading sectors from the start of the filesystem, which the bootloader passes in the boot data, and TarFileSystem
+// reading headers and file contents through it instead of indexing memory. Map, used by exec and mmap of files, then
+// has to copy into pages as FatFileSystem does. The scrubber and /dev/ram0 rely on the ramdisk and would go with it.
 void InitFS(uintptr_t phys, std::size_t size) {
     ramdisk = reinterpret_cast<void*>(kLowMemBase + phys);
     ramdisk_size = size;
+    tarfs.Init(static_cast<const char*>(ramdisk), ramdisk_size);
+    Mount("/", &rootfs);
 }
 
-std::size_t Open(std::string_view path) {
-    fs = RamUSTARReader(static_cast<const char*>(ramdisk), ramdisk_size);
-    return fs.FindFile(path);
+// Boot time instrumentation, the time stamp counter is recorded at the end of every init stage. It's converted to
+// time by comparing with the timer, which is only running after interrupts are set up.
+struct BootStage {
+    std::string_view name;
+    uint64_t tsc;
+    uint64_t ns;  // 0 if the timer wasn't running yet
+};
+
+static bool has_tsc;
+static BootStage boot_stages[16];
+static std::size_t num_boot_stages;
+
+static void BootStageDone(std::string_view name, bool timer_running) {
+    if (!has_tsc || num_boot_stages == array_size(boot_stages)) return;
+    boot_stages[num_boot_stages++] = BootStage{name, X86_rdtsc(), timer_running ? GetTimeNs() : 0};
 }
 
-void ReadFile(void* dst, std::size_t size) {
-    fs.ReadFile(dst, size);
+static void PrintBootTimes() {
+    if (num_boot_stages < 2) return;
+    // Calibrate the tsc against the timer between the first and last stage during which it was running.
+    auto first = num_boot_stages - 1;
+    while (first > 0 && boot_stages[first - 1].ns != 0) first--;
+    auto& last = boot_stages[num_boot_stages - 1];
+    uint64_t ns = last.ns - boot_stages[first].ns;
+    uint64_t cycles = last.tsc - boot_stages[first].tsc;
+    kprint("Boot times:\n");
+    for (std::size_t i = 1; i < num_boot_stages; i++) {
+        uint64_t delta = boot_stages[i].tsc - boot_stages[i - 1].tsc;
+        if (ns > 0 && cycles > 0) {
+            kprint("  {}: {} cycles, {} us\n", boot_stages[i].name, delta, delta * (ns / 1000) / cycles);
+        } else {
+            kprint("  {}: {} cycles\n", boot_stages[i].name, delta);
+        }
+    }
 }
 
 extern "C" [[noreturn]] void KernelInit(const BootData* boot_data) {
-    kout.screen_.cursor_x = boot_data->cursor_pos & 0xFF;
-    kout.screen_.cursor_y = (boot_data->cursor_pos >> 8) & 0xFF;
+    has_tsc = HasTsc();
+    InitRandom();
+    BootStageDone("start", false);
+
+    // The boot data is on the stack of the bootloader below 1mb, it stays reachable after the identity mapping is gone.
+    boot_data = reinterpret_cast<const BootData*>(kLowMemBase + AsLinear(boot_data));
+    ActiveConsole().screen.cursor_x = boot_data->cursor_pos & 0xFF;
+    ActiveConsole().screen.cursor_y = (boot_data->cursor_pos >> 8) & 0xFF;
 
     uintptr_t ramdisk = PhysAddress(boot_data->ramdisk);
     std::size_t ramdisk_size = boot_data->ramdisk_size;
 
     int kernel_low = PhysAddress(_start) / kPageSize;
     int kernel_high = (PhysAddress(_end) + kPageSize - 1) / kPageSize;
-    InitPaging(kernel_low, kernel_high, ramdisk / kPageSize, (ramdisk + ramdisk_size + kPageSize - 1) / kPageSize, boot_data);
+    InitPaging(kernel_low, kernel_high);
+    BootStageDone("paging", false);
+
+    int ramdisk_low = ramdisk / kPageSize;
+    int ramdisk_high = (ramdisk + ramdisk_size + kPageSize - 1) / kPageSize;
+    InitPhysMemory(kernel_low, kernel_high, ramdisk_low, ramdisk_high, boot_data);
+    BootStageDone("phys_mm", false);
+
+    // The kernel has no heap of its own, its memory comes from the page allocator.
+    InitPageAllocator();
+    InitPstore();
+    InitKlog();
+    BootStageDone("heap", false);
 
     SetupDescriptorTables();
+    BootStageDone("descriptors", false);
 
+    InitAcpi();
+    ReclaimAcpiMemory();
+    InitPci();
     RemapInterrupts();
+    InitSerial();
+    InitNet();
     X86_sti();
+    BootStageDone("irq", true);
+
+    InitScheduler();
+    BootStageDone("threading", true);
 
     InitFS(ramdisk, ramdisk_size);
+    InitProcFs();
+    InitTmpFs();
+    InitDevFs(::ramdisk, ::ramdisk_size);
+    InitScrub(::ramdisk, ramdisk_size);
+    BootStageDone("fs", true);
 
     std::string_view filename = "src/arch/x86/init.bin";
-    auto size = Open(filename);
-    if (size == SIZE_MAX) {
+    auto file = VfsLookup(filename);
+    FileStat stat;
+    if (!file.fs || !file.fs->Stat(file.node, &stat)) {
         kprint("Failed to load {}\n", filename);
         terminate(-1);
     }
+    std::size_t size = stat.size;
     auto dst = reinterpret_cast<void*>(0x10000);
-    ReadFile(dst, size);
+    file.fs->Read(file.node, 0, dst, size);
     char md5_out[16];
     md5(std::string_view(static_cast<const char*>(dst), size), md5_out);
-    auto init_stack = reinterpret_cast<uintptr_t>(kKernelBase);
+    // The stack is zero, so this is argc 0 with empty argv, envp and aux vector (see SysExec).
+    auto init_stack = reinterpret_cast<uintptr_t>(kKernelBase - 16);
+
+    BootStageDone("init exec", true);
+    PrintBootTimes();
 
     kprint("Boot succeeded!\nLoaded {} of size {} with md5 {} at {}\nMoving to userspace\n", filename, size, Hex(std::string_view(md5_out, 16)), dst);
 
     auto thread = CreateThread(nullptr, kernel_page_dir, true);
     thread->cpu_state.eip = reinterpret_cast<uintptr_t>(dst);
     thread->cpu_state.esp = init_stack;
+    thread->brk_base = thread->brk = (reinterpret_cast<uintptr_t>(dst) + size + kFlatBssSize + kPageSize - 1) & -kPageSize;
+    AddVma(thread, reinterpret_cast<uintptr_t>(dst), thread->brk_base);
 
     ExitToThread(thread);
 }
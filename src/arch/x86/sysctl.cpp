@@ -0,0 +1,49 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#include "sysctl.h"
+
+#include "kassert.h"
+#include "thread.h"
+#include "vfs.h"
+
+static Tunable tunables[kMaxTunables];
+static int num_tunables;
+
+bool RegisterTunable(const Tunable& tunable) {
+    kassert(FindTunable(tunable.name) < 0);
+    if (num_tunables == kMaxTunables) return false;
+    tunables[num_tunables++] = tunable;
+    return true;
+}
+
+int NumTunables() {
+    return num_tunables;
+}
+
+const Tunable& GetTunable(int index) {
+    return tunables[index];
+}
+
+int FindTunable(std::string_view name) {
+    for (int i = 0; i < num_tunables; i++) {
+        if (tunables[i].name == name) return i;
+    }
+    return -1;
+}
+
+// edx points to the zero terminated name of the tunable, if ebx is nonzero it's set to ecx. Returns the old value, or
+// -1 if the tunable doesn't exist, the value is out of range or the caller isn't privileged to set it.
+void SysSysctl(Regs* regs) {
+    char name[kMaxPathLength];
+    int length = CopyPathFromUser(regs->edx, name);
+    regs->eax = -1;
+    if (length < 0) return;
+    int index = FindTunable(std::string_view(name, length));
+    if (index < 0) return;
+    auto& tunable = tunables[index];
+    int old = tunable.get();
+    if (regs->ebx != 0 && (!current_thread->privileged || !tunable.set(regs->ecx))) return;
+    regs->eax = old;
+}
@@ -0,0 +1,30 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#ifndef OS_SYSCTL_H
+#define OS_SYSCTL_H
+
+#include <string_view>
+
+#include "entry.h"
+
+// Kernel tunables. Subsystems register their configuration knobs by name, like "sched/time_slice", with hooks to
+// read and change them. They can be inspected and changed at runtime by the sysctl system call or through the files
+// in /proc/sys. Anyone can read them, only privileged processes can change them.
+constexpr int kMaxTunables = 32;
+
+struct Tunable {
+    std::string_view name;
+    int (*get)();
+    bool (*set)(int value);  // returns false if the value is out of range
+};
+
+bool RegisterTunable(const Tunable& tunable);
+int NumTunables();
+const Tunable& GetTunable(int index);
+int FindTunable(std::string_view name);  // -1 if it doesn't exist
+
+void SysSysctl(Regs* regs);
+
+#endif //OS_SYSCTL_H
@@ -0,0 +1,181 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#include "tarfs.h"
+
+#include "fatfs.h"
+#include "thread.h"
+#include "src/freestanding/utils.h"
+
+class RamUSTARReader : public USTARReader {
+public:
+    constexpr RamUSTARReader(const char* data, std::size_t size) : data_(data), size_(size) {}
+
+    bool ReadBlocks(std::size_t block, int n, void *buf) override {
+        if ((block + n) * 512 > size_) return false;
+        memcpy(buf, data_ + block * 512, n * 512);
+        return true;
+    }
+
+private:
+    const char* data_;
+    std::size_t size_;
+};
+
+void TarFileSystem::Init(const char* data, std::size_t size) {
+    data_ = data;
+    size_ = size;
+    num_nodes_ = 0;
+}
+
+static bool IsRegularFile(char typeflag) {
+    return typeflag == '0' || typeflag == '\0' || typeflag == '7';
+}
+
+// Returns the name of the child of dir, empty for the root, that the entry is or lies below, and whether the child is
+// a directory. Empty if the entry isn't below dir or isn't a file or directory.
+static std::string_view ChildName(const USTAREntry& entry, std::string_view dir, bool* directory) {
+    auto name = entry.filename;
+    if (name.substr(0, dir.size()) != dir) return {};
+    name.remove_prefix(dir.size());
+    if (!dir.empty()) {
+        if (name.empty() || name.front() != '/') return {};
+        name.remove_prefix(1);
+    }
+    std::size_t n = 0;
+    while (n < name.size() && name[n] != '/') n++;
+    *directory = n < name.size() || entry.typeflag == '5';
+    if (!*directory && !IsRegularFile(entry.typeflag)) return {};
+    return name.substr(0, n);
+}
+
+int TarFileSystem::AddNode(const Node& node) {
+    for (int i = 0; i < num_nodes_; i++) {
+        auto& n = nodes_[i];
+        if (n.type == node.type && n.offset == node.offset && n.size == node.size) return i;
+    }
+    if (num_nodes_ == kMaxNodes) return -1;
+    nodes_[num_nodes_] = node;
+    return num_nodes_++;
+}
+
+int TarFileSystem::Lookup(std::string_view path) {
+    while (!path.empty() && path.back() == '/') path.remove_suffix(1);
+    if (path.empty()) return AddNode(Node{kDirectory, 0, 0, 0755, 0});
+    auto slash = path.rfind('/');
+    auto dir = path.substr(0, slash == std::string_view::npos ? 0 : slash);
+    auto name = path;
+    name.remove_prefix(slash == std::string_view::npos ? 0 : slash + 1);
+    RamUSTARReader reader(data_, size_);
+    USTAREntry entry;
+    while (true) {
+        auto header = reader.Offset();
+        if (!reader.NextEntry(&entry)) return -1;
+        bool directory;
+        if (ChildName(entry, dir, &directory) != name) continue;
+        uint32_t mode = entry.mode & 07777;
+        if (directory) {
+            // The entry may lie below the directory instead of being the directory itself.
+            auto own_name = entry.filename;
+            while (!own_name.empty() && own_name.back() == '/') own_name.remove_suffix(1);
+            bool own = entry.typeflag == '5' && own_name == path;
+            return AddNode(Node{kDirectory, header, path.size(), own ? mode : 0755, own ? entry.mtime : 0});
+        }
+        if (entry.offset > size_ || entry.size > size_ - entry.offset) return -1;  // truncated archive
+        return AddNode(Node{kRegularFile, entry.offset, entry.size, mode, entry.mtime});
+    }
+}
+
+int TarFileSystem::Read(int node, uint64_t offset, void* buf, std::size_t len) {
+    if (node < 0 || node >= num_nodes_ || nodes_[node].type != kRegularFile) return -1;
+    auto& n = nodes_[node];
+    if (offset >= n.size) return 0;
+    len = min<uint64_t>(len, n.size - offset);
+    memcpy(buf, data_ + n.offset + offset, len);
+    return len;
+}
+
+// Whether one of the first count entries of the archive already gave the child name of dir.
+bool TarFileSystem::ListedBefore(std::size_t count, std::string_view dir, std::string_view name) {
+    RamUSTARReader reader(data_, size_);
+    USTAREntry entry;
+    for (std::size_t i = 0; i < count && reader.NextEntry(&entry); i++) {
+        bool directory;
+        if (ChildName(entry, dir, &directory) == name) return true;
+    }
+    return false;
+}
+
+int TarFileSystem::ReadDir(int node, std::size_t index, DirEntry* entry) {
+    if (node < 0 || node >= num_nodes_ || nodes_[node].type != kDirectory) return -1;
+    auto& n = nodes_[node];
+    USTAREntry dir_entry;
+    std::string_view dir;
+    if (n.size > 0) {
+        if (!RamUSTARReader(data_ + n.offset, size_ - n.offset).NextEntry(&dir_entry)) return -1;
+        dir = dir_entry.filename.substr(0, n.size);
+    }
+    RamUSTARReader reader(data_, size_);
+    USTAREntry e;
+    for (std::size_t i = 0; reader.NextEntry(&e); i++) {
+        bool directory;
+        auto name = ChildName(e, dir, &directory);
+        // A directory shows up once for every file below it.
+        if (name.empty() || name.size() >= kMaxNameLength || ListedBefore(i, dir, name) || index-- > 0) continue;
+        memcpy(entry->name, name.data(), name.size());
+        entry->name[name.size()] = 0;
+        entry->type = directory ? kDirectory : kRegularFile;
+        return 1;
+    }
+    return 0;
+}
+
+bool TarFileSystem::Stat(int node, FileStat* stat) {
+    if (node < 0 || node >= num_nodes_) return false;
+    auto& n = nodes_[node];
+    *stat = FileStat{n.type == kDirectory ? 0 : n.size, n.type, n.mode, n.mtime};
+    return true;
+}
+
+std::string_view TarFileSystem::Map(int node) {
+    if (node < 0 || node >= num_nodes_ || nodes_[node].type != kRegularFile) return {};
+    return std::string_view(data_ + nodes_[node].offset, nodes_[node].size);
+}
+
+struct LoopMount {
+    bool used;
+    char path[kMaxPathLength];  // the mount point, which must outlive the mount
+    TarFileSystem tar;
+    FatFileSystem fat;
+};
+
+static constinit LoopMount loop_mounts[kMaxLoopMounts];
+
+// edx points to the path of the image, a FAT filesystem or else a USTAR archive, ecx to the path to mount it at.
+// Only for privileged processes, returns 0 or -1.
+void SysMountImage(Regs* regs) {
+    regs->eax = -1;
+    if (!current_thread->privileged) return;
+    char image_path[kMaxPathLength];
+    int image_length = CopyPathFromUser(regs->edx, image_path);
+    if (image_length < 0) return;
+    int i = 0;
+    while (i < kMaxLoopMounts && loop_mounts[i].used) i++;
+    if (i == kMaxLoopMounts) return;
+    auto& loop = loop_mounts[i];
+    int length = CopyPathFromUser(regs->ecx, loop.path);
+    if (length < 0) return;
+    auto image = VfsLookup(std::string_view(image_path, image_length));
+    if (!image.fs) return;
+    FileSystem* fs = &loop.fat;
+    if (!loop.fat.Init(image)) {
+        auto contents = image.fs->Map(image.node);
+        if (contents.data() == nullptr) return;
+        loop.tar.Init(contents.data(), contents.size());
+        fs = &loop.tar;
+    }
+    if (!Mount(std::string_view(loop.path, length), fs)) return;
+    loop.used = true;
+    regs->eax = 0;
+}
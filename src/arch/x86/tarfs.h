@@ -0,0 +1,60 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#ifndef OS_TARFS_H
+#define OS_TARFS_H
+
+#include "entry.h"
+#include "vfs.h"
+
+// Read only filesystem of a USTAR archive in memory, the ramdisk. Nodes are the files and directories that were
+// looked up. Directories are the '5' entries as well as the paths leading to files, tar doesn't have to store them.
+// There is no index, listing a directory scans all the headers, which is fine for the small boot archive. Links and
+// other special entries are left out. Directories without an entry of their own have mode 0755 and an unknown mtime.
+//
+// TODO: the bootloader reads the ramdisk through the BIOS and the kernel has no disk driver. Once an ATA driver with a
+// request queue exists, it should merge adjacent requests, order them by LBA (elevator) and keep queue depth
+// statistics per device. Besides PIO it should do writes and UDMA through the busmaster IDE controller (found with
+// the PCI scan as class 1 subclass 1, its BAR 4 holds the busmaster ports) with a PRD table and IRQ 14 completion.
+class TarFileSystem : public FileSystem {
+public:
+    constexpr TarFileSystem() = default;
+
+    void Init(const char* data, std::size_t size);
+
+    int Lookup(std::string_view path) override;
+    int Read(int node, uint64_t offset, void* buf, std::size_t len) override;
+    int ReadDir(int node, std::size_t index, DirEntry* entry) override;
+    bool Stat(int node, FileStat* stat) override;
+    std::string_view Map(int node) override;
+
+private:
+    // A directory is the first size characters of the name of the entry starting at offset, empty for the root, so
+    // it needs no room for its path.
+    struct Node {
+        FileType type;
+        std::size_t offset;  // of the contents in the archive
+        std::size_t size;
+        uint32_t mode;
+        uint64_t mtime;
+    };
+
+    static constexpr int kMaxNodes = 64;
+
+    int AddNode(const Node& node);
+    bool ListedBefore(std::size_t count, std::string_view dir, std::string_view name);
+
+    const char* data_ = nullptr;
+    std::size_t size_ = 0;
+    Node nodes_[kMaxNodes] = {};
+    int num_nodes_ = 0;
+};
+
+// Loop mounts: an archive inside a file is mounted as a filesystem of its own, which is read in place, so the image
+// must be on a memory backed filesystem. FAT images (see fatfs.h) are read and written through the file instead.
+constexpr int kMaxLoopMounts = 4;
+
+void SysMountImage(Regs* regs);
+
+#endif //OS_TARFS_H
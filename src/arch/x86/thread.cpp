@@ -4,13 +4,111 @@
 
 #include "thread.h"
 
+#include "descriptors.h"
+#include "exec.h"
 #include "kassert.h"
+#include "ipc.h"
 #include "irq.h"
+#include "ldt.h"
 #include "paging.h"
+#include "scrub.h"
+#include "sysctl.h"
+#include "x86_inst.h"
 
 Thread* current_thread = nullptr;
 Thread threads[kMaxThreads];
 
+CpuGroup cpu_groups[kMaxCpuGroups] = {{kDefaultCpuWeight, 0, 0, 0, -1}};
+
+// Virtual runtime of the group that was picked last. A group that was idle would otherwise get to run until it
+// caught up with the groups that kept running, so groups becoming active again start from here.
+static uint64_t min_vruntime;
+
+// Set by the timer interrupt when the running thread used up its time slice, the switch happens when it returns to
+// user mode (see Preempt).
+static bool need_resched;
+
+// Threads blocked with a deadline, sleeping or waiting with a timeout, ordered by wake_tick so the timer interrupt
+// only looks at the ones that are due. A thread is on the list iff its wake_tick is nonzero.
+static Thread* timers;
+
+static void ArmTimer(Thread* thread, int wake_tick) {
+    thread->wake_tick = wake_tick;
+    auto p = &timers;
+    while (*p && (*p)->wake_tick <= wake_tick) p = &(*p)->timer_next;
+    thread->timer_next = *p;
+    *p = thread;
+}
+
+static void DisarmTimer(Thread* thread) {
+    if (thread->wake_tick == 0) return;
+    auto p = &timers;
+    while (*p != thread) p = &(*p)->timer_next;
+    *p = thread->timer_next;
+    thread->wake_tick = 0;
+}
+
+// After a change of the tick frequency the ticks of sleeping threads are of the old length, their deadlines in ns
+// give the tick at the new rate. Threads waiting with a timeout keep their tick.
+static void RearmSleepers() {
+    for (auto& thread : threads) {
+        if (thread.wake_tick == 0 || thread.wait_queue != nullptr) continue;
+        DisarmTimer(&thread);
+        ArmTimer(&thread, NsToTick(thread.wake_ns));
+    }
+}
+
+static void Enqueue(WaitQueue* queue, Thread* thread) {
+    thread->wait_queue = queue;
+    thread->wait_next = nullptr;
+    if (queue->tail) {
+        queue->tail->wait_next = thread;
+    } else {
+        queue->head = thread;
+    }
+    queue->tail = thread;
+}
+
+// Takes the thread out of the queue it's blocked on, if any.
+static void Dequeue(Thread* thread) {
+    auto queue = thread->wait_queue;
+    if (!queue) return;
+    Thread* prev = nullptr;
+    for (auto t = queue->head; t != thread; t = t->wait_next) prev = t;
+    (prev ? prev->wait_next : queue->head) = thread->wait_next;
+    if (queue->tail == thread) queue->tail = prev;
+    thread->wait_queue = nullptr;
+}
+
+// Interrupt handlers wake threads, so the timer list and wait queues are only touched with interrupts disabled.
+static void MakeReady(Thread* thread) {
+    auto flags = X86_save_flags_cli();
+    DisarmTimer(thread);
+    Dequeue(thread);
+    thread->state = THREAD_READY;
+    thread->ready_since = GetTime();
+    auto& group = cpu_groups[thread->cpu_group];
+    if (group.vruntime < min_vruntime) group.vruntime = min_vruntime;
+    X86_restore_flags(flags);
+}
+
+static void LeaveCpuGroup(Thread* thread) {
+    auto& group = cpu_groups[thread->cpu_group];
+    // The root group lives forever, the others are freed with their last member.
+    if (--group.members == 0 && thread->cpu_group != 0) group.weight = 0;
+}
+
+// Groups the exiting process created but nobody joined are freed, the others can no longer be joined by a new process
+// that gets the same pid.
+static void ReleaseCpuGroups(int pid) {
+    for (int i = 1; i < kMaxCpuGroups; i++) {
+        auto& group = cpu_groups[i];
+        if (group.weight == 0 || group.creator != pid) continue;
+        if (group.members == 0) group.weight = 0;
+        group.creator = -1;
+    }
+}
+
 Thread* CreateThread(Thread* parent, PageTable* page_dir, bool is_process) {
     for (int i = 0; i < kMaxThreads; i++) {
         if (threads[i].state == THREAD_UNUSED) {
@@ -18,8 +116,30 @@ Thread* CreateThread(Thread* parent, PageTable* page_dir, bool is_process) {
             threads[i].pid = is_process ? i : parent->pid;
             threads[i].priority = parent ? parent->priority : 0;
             threads[i].parent_tid = parent ? parent->tid : -1;
-            threads[i].state = THREAD_READY;
             threads[i].time = GetTime();
+            threads[i].yield_until = 0;
+            threads[i].user_ns = 0;
+            threads[i].system_ns = 0;
+            threads[i].wake_tick = 0;
+            threads[i].low_mem_threshold = 0;
+            threads[i].console = parent ? parent->console : 0;
+            threads[i].cpu_group = parent ? parent->cpu_group : 0;
+            threads[i].privileged = parent == nullptr;  // init
+            threads[i].num_io_ranges = 0;
+            threads[i].irqs_pending = 0;
+            threads[i].irq_wait_mask = 0;
+            threads[i].wait_queue = nullptr;
+            threads[i].children = WaitQueue{};
+            threads[i].brk_base = parent ? parent->brk_base : 0;
+            threads[i].brk = parent ? parent->brk : 0;
+            threads[i].mmap_base = parent ? parent->mmap_base : kStackLimit;
+            threads[i].linux_abi = parent ? parent->linux_abi : false;
+            threads[i].num_vmas = parent ? parent->num_vmas : 0;
+            if (parent) memcpy(threads[i].vmas, parent->vmas, sizeof(parent->vmas));
+            threads[i].binary = parent ? parent->binary : -1;
+            AcquireBinary(threads[i].binary);
+            InheritFiles(&threads[i], parent);
+            cpu_groups[threads[i].cpu_group].members++;
             threads[i].page_dir = page_dir;
             constexpr uint32_t kIFMask = 1 << 9;
             threads[i].cpu_state = Regs {
@@ -28,6 +148,7 @@ Thread* CreateThread(Thread* parent, PageTable* page_dir, bool is_process) {
                 0, 0,                    // int_no, err_code;
                 0, 0x1B, kIFMask, 0, 0x23    // eip, cs, eflags, esp, ss
             };
+            MakeReady(&threads[i]);
 
             return &threads[i];
         }
@@ -39,64 +160,559 @@ void SaveState(Thread* thread, Regs* regs) {
     thread->cpu_state = *regs;
 }
 
-constexpr uint64_t a = 0xdeadbeed;  // a - 1 is divisible by all prime factors of 2^64 and a - 1 is divisible by 4
-constexpr uint64_t c = 0x12345679;  // c and 2^64 are relatively prime
-uint64_t seed = 0xcafebabedeadbeef;
+static void SetIoPorts(const Thread* thread, bool allowed) {
+    for (int i = 0; i < thread->num_io_ranges; i++) {
+        SetIoPermission(thread->io_ranges[i].base, thread->io_ranges[i].count, allowed);
+    }
+}
+
+// Charges the time since the thread last entered or left the kernel as user or system time.
+static void ChargeTime(Thread* thread, bool user) {
+    auto now = GetTimeNs();
+    (user ? thread->user_ns : thread->system_ns) += now - thread->mode_ns;
+    thread->mode_ns = now;
+}
 
 [[noreturn]] void ExitToThread(Thread* thread) {
-    auto old_page_dir = (current_thread && current_thread->state == THREAD_UNUSED) 
-            ? current_thread->page_dir : nullptr;
     thread->state = THREAD_RUNNING;
+    if (current_thread != thread) {
+        thread->slice_ticks = 0;
+        // The io permission bitmap in the TSS belongs to the running thread.
+        if (current_thread) SetIoPorts(current_thread, false);
+        SetIoPorts(thread, true);
+    }
     current_thread = thread;
     SwitchPageDir(thread->page_dir);
-    if (old_page_dir) DestroyPageDir(old_page_dir);
-    exit_kernel(&thread->cpu_state);
+    SwitchLdt(thread->tid);
+    // exit_kernel pops the registers from the thread state, an interrupt would push on top of it.
+    X86_cli();
+    need_resched = false;
+    interrupt_depth = 0;
+    // The time it wasn't running isn't charged, Schedule charged the kernel time up to the switch.
+    thread->mode_ns = GetTimeNs();
+    ReturnToUser(&thread->cpu_state);
 }
 
-void Schedule(int tid, bool must_switch) {
+// A thread that yields is put behind all other ready threads for a few ticks, otherwise a busy waiting thread
+// that yields in a loop is picked again as often as the thread it's waiting on.
+constexpr int kYieldPenaltyTicks = 2;
+
+// Aging, a ready thread gains a priority level for every kAgingTicks it waits, so eventually even the lowest
+// priority thread wins from a steady stream of higher priority ones.
+constexpr int kAgingTicks = 5;
+
+// Waiting longer than this means aging is not doing its job, or the time slice is longer than this.
+constexpr int kStarvationTicks = 500;
+
+// Number of times the starvation detector fired, exposed as sched/starvations so it can be checked from user space.
+static int starvations = 0;
+
+static int time_slice_ticks = 1;
+static SchedPolicy sched_policy = kPolicyPriority;
+
+void InitScheduler() {
+    RegisterTunable({"sched/tick_frequency", TickFrequency, [](int hz) {
+        // No tick may wake a sleeper in between.
+        auto flags = X86_save_flags_cli();
+        bool ok = SetTickFrequency(hz);
+        if (ok) RearmSleepers();
+        X86_restore_flags(flags);
+        return ok;
+    }});
+    RegisterTunable({"sched/time_slice", [] { return time_slice_ticks; }, [](int value) {
+        if (value < 1 || value > 1000) return false;
+        time_slice_ticks = value;
+        return true;
+    }});
+    RegisterTunable({"sched/policy", [] { return int(sched_policy); }, [](int value) {
+        if (value != kPolicyPriority && value != kPolicyRoundRobin) return false;
+        sched_policy = static_cast<SchedPolicy>(value);
+        return true;
+    }});
+    // Only writing 0 is allowed, to reset the count.
+    RegisterTunable({"sched/starvations", [] { return starvations; }, [](int value) {
+        if (value != 0) return false;
+        starvations = 0;
+        return true;
+    }});
+}
+
+static int EffectivePriority(const Thread& thread, int now) {
+    return thread.priority + (now - thread.ready_since) / kAgingTicks - (now < thread.yield_until ? 1 : 0);
+}
+
+// The group with ready threads that is most behind on its share of the CPU, -1 if nothing is ready.
+static int PickCpuGroup(int skip_tid) {
+    int best = -1;
+    for (int i = 1; i < kMaxThreads; i++) {
+        if (i == skip_tid || threads[i].state != THREAD_READY) continue;
+        int group = threads[i].cpu_group;
+        if (best < 0 || cpu_groups[group].vruntime < cpu_groups[best].vruntime) best = group;
+    }
+    return best;
+}
+
+// Picks the ready thread of the highest effective priority within the group most entitled to the CPU. Among equals
+// the one that waited longest goes first, so threads of the same priority take turns. Thread 0 is never picked.
+static Thread* PickNext(int skip_tid) {
     Thread* next_thread = nullptr;
-    seed = a * seed + c;
-    int count = 0;
+    int group = PickCpuGroup(skip_tid);
+    if (group < 0) return nullptr;
+    if (cpu_groups[group].vruntime > min_vruntime) min_vruntime = cpu_groups[group].vruntime;
+    int now = GetTime();
+    int best = 0;
     // Skip 0 task
     for (int i = 1; i < kMaxThreads; i++) {
-        if (i == tid) continue;
-        if (threads[i].state == THREAD_READY) {
-            count++;
-            // resevoir sampling
-            if (seed % count == 0) {
-                next_thread = &threads[i];
-            }
+        auto& thread = threads[i];
+        if (i == skip_tid || thread.cpu_group != group || thread.state != THREAD_READY) continue;
+        // Round robin is all threads at the same priority.
+        int priority = sched_policy == kPolicyRoundRobin ? 0 : EffectivePriority(thread, now);
+        if (next_thread == nullptr || priority > best ||
+            (priority == best && thread.ready_since < next_thread->ready_since)) {
+            best = priority;
+            next_thread = &thread;
         }
     }
+    return next_thread;
+}
+
+void Schedule(int tid, bool must_switch) {
+    if (current_thread) ChargeTime(current_thread, false);
+    Thread* next_thread = PickNext(tid);
     if (next_thread == nullptr) {
-        if (!must_switch || current_thread->tid == 0) {
+        if (!must_switch) {
             return;
         }
-        next_thread = &threads[0];
+        // Thread 0 runs when nothing else can. If it's blocked as well, there is nothing to do until an interrupt
+        // wakes up some thread.
+        while ((next_thread = PickNext(-1)) == nullptr) {
+            if (threads[0].state == THREAD_READY) {
+                next_thread = &threads[0];
+                break;
+            }
+            ScrubStep();
+            ReclaimStep();
+            X86_hlt();
+        }
     }
     ExitToThread(next_thread);
 }
 
+// edx are the fork flags. Returns the tid of the child, 0 in the child, or -1.
 void SysFork(Regs* regs) {
+    if (!CanCopyLdt(current_thread->tid)) {
+        regs->eax = -1;
+        return;
+    }
     auto page_dir = ForkCurrent();
+    if (page_dir == nullptr) {
+        regs->eax = -1;
+        return;
+    }
     auto child_thread = CreateThread(current_thread, page_dir, true);
+    if (child_thread == nullptr) {
+        DestroyFork(page_dir);
+        regs->eax = -1;
+        return;
+    }
+    child_thread->privileged = current_thread->privileged && (regs->edx & kForkPrivileged);
     SaveState(child_thread, regs);
+    CopyLdt(current_thread->tid, child_thread->tid);
     regs->eax = child_thread->tid;
     child_thread->cpu_state.eax = 0;
 }
 
 void Yield(Regs* regs) {
     SaveState(current_thread, regs);
-    current_thread->state = THREAD_READY;
+    MakeReady(current_thread);
+    current_thread->yield_until = GetTime() + kYieldPenaltyTicks;
     Schedule(current_thread->tid, false);
+    current_thread->state = THREAD_RUNNING;
+}
+
+// Called on every return to user mode, so a thread that never yields can't starve the others. That includes returning
+// from system calls, a thread spending most of its time in the kernel is preempted as well.
+static void Preempt(Regs* regs) {
+    if (!need_resched) return;
+    kassert(interrupt_depth == 1);
+    need_resched = false;
+    current_thread->slice_ticks = 0;
+    SaveState(current_thread, regs);
+    MakeReady(current_thread);
+    Schedule(current_thread->tid, false);
+    current_thread->state = THREAD_RUNNING;
+}
+
+void EnterFromUser() {
+    ChargeTime(current_thread, true);
 }
 
-// edx is exit code
+// A selector of the LDT or the flat user segment, with user privilege.
+static bool IsUserSelector(uint32_t selector, uint32_t flat) {
+    return (selector & 3) == 3 && ((selector & 4) || selector == (flat | 3));
+}
+
+void ReturnToUser(Regs* regs) {
+    Preempt(regs);  // a switch comes back here for the other thread, through ExitToThread
+    // TODO: deliver pending signals here once there are signals, by pushing the registers and a frame for the handler
+    // onto the user stack and pointing eip at the handler. Tracing system call exits would hook in here too.
+    if (!CheckLdtSelectors(regs)) {
+        kprint("Thread {} returns to a removed segment @{}:{}\n", current_thread->tid, Hex(regs->cs), Hex(regs->eip));
+        regs->edx = 128 + 11;  // exit code of a shell for death by SIGSEGV
+        SysExit(regs);
+    }
+    X86_cli();
+    ChargeTime(current_thread, false);
+    // Returning through a frame with kernel selectors would run user code, or whatever eip is, at ring 0.
+    if (!IsUserSelector(regs->cs, kUserCS) || !IsUserSelector(regs->ss, kUserDS)) {
+        panic("Return to user mode with cs {} ss {}\n", Hex(regs->cs), Hex(regs->ss));
+    }
+    interrupt_depth = 0;
+    exit_kernel(regs);
+}
+
+// Saves the state of the current thread, which must be woken up by someone else, and runs another thread.
+[[noreturn]] static void Block(Regs* regs) {
+    SaveState(current_thread, regs);
+    current_thread->state = THREAD_BLOCKED;
+    Schedule(current_thread->tid, true);
+    __builtin_unreachable();
+}
+
+void BlockOn(Regs* regs, WaitQueue* queue, int timeout_ticks) {
+    // Switching threads enables interrupts again.
+    X86_cli();
+    Enqueue(queue, current_thread);
+    if (timeout_ticks > 0) ArmTimer(current_thread, GetTime() + timeout_ticks);
+    Block(regs);
+}
+
+void WakeToRestart(Thread* thread) {
+    kassert(thread->state == THREAD_BLOCKED);
+    // Back up over the int 0x80 instruction, eax still holds the system call number.
+    thread->cpu_state.eip -= 2;
+    MakeReady(thread);
+}
+
+void WakeAll(WaitQueue* queue) {
+    auto flags = X86_save_flags_cli();
+    while (queue->head) WakeToRestart(queue->head);
+    X86_restore_flags(flags);
+}
+
+void CompleteAll(WaitQueue* queue) {
+    auto flags = X86_save_flags_cli();
+    while (queue->head) MakeReady(queue->head);
+    X86_restore_flags(flags);
+}
+
+// edx (low) and ecx (high) is the duration in ns. The thread sleeps until the last tick before the deadline and
+// returns the remaining ns in eax, which is less than a tick. Durations shorter than a tick are busy waited, so
+// sleeping again for the remainder gives sub-tick accuracy.
+void SysNanosleep(Regs* regs) {
+    uint64_t ns = regs->edx | (uint64_t(regs->ecx) << 32);
+    auto deadline = GetTimeNs() + ns;
+    if (ns < TickNs()) {
+        while (GetTimeNs() < deadline) {}
+        regs->eax = 0;
+        return;
+    }
+    current_thread->wake_ns = deadline;
+    X86_cli();
+    ArmTimer(current_thread, NsToTick(deadline));
+    Block(regs);
+}
+
+// edx points to a uint64_t that receives the time since boot in ns. Returns 0 or -kEFAULT for a bad pointer.
+void SysGetTime(Regs* regs) {
+    uint64_t now = GetTimeNs();
+    regs->eax = CopyToUser(regs->edx, &now, sizeof(now)) ? 0 : -kEFAULT;
+}
+
+void SchedulerTick(int tick) {
+    if (current_thread && current_thread->state == THREAD_RUNNING) {
+        if (++current_thread->slice_ticks >= time_slice_ticks) need_resched = true;
+        auto& group = cpu_groups[current_thread->cpu_group];
+        group.ticks++;
+        group.vruntime += (kMaxCpuWeight * kDefaultCpuWeight) / group.weight;
+    }
+    while (timers && tick >= timers->wake_tick) {
+        auto& thread = *timers;
+        if (thread.wait_queue != nullptr) {
+            thread.cpu_state.eax = -1;  // timed out
+        } else {
+            auto start = TickToNs(tick);
+            thread.cpu_state.eax = thread.wake_ns > start ? thread.wake_ns - start : 0;
+        }
+        MakeReady(&thread);  // takes it off the timer list and its wait queue
+    }
+    for (int i = 0; i < kMaxThreads; i++) {
+        auto& thread = threads[i];
+        // Starvation detector, reports once at the tick the thread crosses the limit. Thread 0 only runs when
+        // nothing else is ready, so it's expected to wait.
+        if (i != 0 && thread.state == THREAD_READY && tick - thread.ready_since == kStarvationTicks) {
+            starvations++;
+            kprint("Thread {} starved, ready for {} ticks without running\n", thread.tid, kStarvationTicks);
+        }
+        if (thread.state != THREAD_BLOCKED) continue;
+        if (thread.low_mem_threshold != 0 && FreePageCount() < thread.low_mem_threshold) {
+            thread.cpu_state.eax = FreePageCount();
+            thread.low_mem_threshold = 0;
+            MakeReady(&thread);
+        }
+    }
+}
+
+// edx is the threshold in pages. Blocks until fewer pages are free, returns the number of free pages. The check is
+// done every tick, so processes caching memory get a chance to release it before allocations start failing.
+void SysWaitLowMemory(Regs* regs) {
+    int threshold = regs->edx;
+    if (threshold <= 0 || FreePageCount() < threshold) {
+        regs->eax = FreePageCount();
+        return;
+    }
+    current_thread->low_mem_threshold = threshold;
+    Block(regs);
+}
+
+bool AddVma(Thread* thread, uintptr_t start, uintptr_t end, VNode file, uint64_t offset, bool writable) {
+    if (thread->num_vmas == kMaxVmas) return false;
+    thread->vmas[thread->num_vmas++] = Vma{start & -kPageSize, (end + kPageSize - 1) & -kPageSize, file, offset, writable};
+    return true;
+}
+
+// Unmaps [start, end) from the regions, a region partly inside is trimmed or split in two.
+void RemoveVmas(Thread* thread, uintptr_t start, uintptr_t end) {
+    for (int i = 0; i < thread->num_vmas; i++) {
+        auto& vma = thread->vmas[i];
+        if (end <= vma.start || vma.end <= start) continue;
+        if (start > vma.start && end < vma.end) {
+            // Split, if there is no room for the second half it stays mapped.
+            if (thread->num_vmas < kMaxVmas) {
+                thread->vmas[thread->num_vmas++] = Vma{end, vma.end, vma.file, vma.offset + (end - vma.start), vma.writable};
+                vma.end = start;
+            }
+        } else if (start > vma.start) {
+            vma.end = start;
+        } else if (end < vma.end) {
+            vma.offset += end - vma.start;
+            vma.start = end;
+        } else {
+            vma = thread->vmas[--thread->num_vmas];
+            i--;
+        }
+    }
+}
+
+const Vma* FindVma(const Thread* thread, uintptr_t address) {
+    for (int i = 0; i < thread->num_vmas; i++) {
+        if (address >= thread->vmas[i].start && address < thread->vmas[i].end) return &thread->vmas[i];
+    }
+    return nullptr;
+}
+
+bool IsValidUserAddress(const Thread* thread, uintptr_t address) {
+    if (address >= kStackLimit && address < kKernelBase) return true;
+    if (address >= thread->brk_base && address < thread->brk) return true;
+    return FindVma(thread, address) != nullptr;
+}
+
+// edx is exit code. The thread releases everything but its TCB and page directory and becomes a zombie until its
+// parent collects the exit code with SysWait. Children of the exiting thread are handed to init.
 void SysExit(Regs* regs) {
     kassert(current_thread->tid != 0);
     kprint("Thread {} exited with code {} at @{}:{}\n", current_thread->tid, regs->edx, Hex(regs->cs), Hex(regs->eip));
-    current_thread->state = THREAD_UNUSED;
+    current_thread->state = THREAD_ZOMBIE;
+    current_thread->exit_code = regs->edx;
+    LeaveCpuGroup(current_thread);
+    if (current_thread->tid == current_thread->pid) ReleaseCpuGroups(current_thread->pid);
+    ReleaseIrqs(current_thread->tid);
+    ReleasePhysReservations(current_thread->tid);
+    ReleasePorts(current_thread->tid);
+    ReleaseEvents(current_thread->tid);
+    ReleaseLdt(current_thread->tid);
+    ReleaseBinary(current_thread->binary);
+    CloseFiles(current_thread);
+    ClearUserSpace();
+    for (auto& thread : threads) {
+        if (thread.state != THREAD_UNUSED && thread.parent_tid == current_thread->tid) {
+            thread.parent_tid = 0;
+            if (thread.state == THREAD_ZOMBIE) WakeAll(&threads[0].children);
+        }
+    }
+    WakeAll(&threads[current_thread->parent_tid].children);
     Schedule(current_thread->tid, true);
-    // TODO send exit code to parent
-    // Free file descriptors
+}
+
+// edx is the tid of the child to wait for, -1 for any child, ecx points to where to store the exit code (may be
+// null). Blocks until the child exits, frees it and returns its tid, or -1 if there is no such child.
+void SysWait(Regs* regs) {
+    int tid = regs->edx;
+    bool has_child = false;
+    for (auto& thread : threads) {
+        if (thread.state == THREAD_UNUSED || thread.parent_tid != current_thread->tid) continue;
+        if (tid != -1 && thread.tid != tid) continue;
+        has_child = true;
+        if (thread.state != THREAD_ZOMBIE) continue;
+        if (regs->ecx && !CopyToUser(regs->ecx, &thread.exit_code, sizeof(thread.exit_code))) {
+            regs->eax = -kEFAULT;  // the child stays a zombie
+            return;
+        }
+        DestroyPageDir(thread.page_dir);
+        thread.state = THREAD_UNUSED;
+        regs->eax = thread.tid;
+        return;
+    }
+    if (!has_child) {
+        regs->eax = -1;
+        return;
+    }
+    BlockOn(regs, &current_thread->children, 0);
+}
+
+// Returns the process id of the caller, the tid of the thread that created the process.
+void SysGetPid(Regs* regs) {
+    regs->eax = current_thread->pid;
+}
+
+// Returns the process id of the parent of the caller, orphans have init (0) as parent.
+void SysGetPpid(Regs* regs) {
+    regs->eax = threads[current_thread->parent_tid].pid;
+}
+
+void SysGetTid(Regs* regs) {
+    regs->eax = current_thread->tid;
+}
+
+// edx is the tid, ecx the new priority between kMinPriority and kMaxPriority. A thread may change its own priority and
+// that of its children, only privileged threads may raise a priority. Returns the old priority or -1.
+void SysSetPriority(Regs* regs) {
+    unsigned tid = regs->edx;
+    int priority = regs->ecx;
+    regs->eax = -1;
+    if (tid >= kMaxThreads || priority < kMinPriority || priority > kMaxPriority) return;
+    auto& thread = threads[tid];
+    if (thread.state == THREAD_UNUSED || thread.state == THREAD_ZOMBIE) return;
+    if (&thread != current_thread && thread.parent_tid != current_thread->tid) return;
+    if (priority > thread.priority && !current_thread->privileged) return;
+    regs->eax = thread.priority;
+    thread.priority = priority;
+}
+
+// edx is the tid, returns its priority or -1 if there is no such thread.
+void SysGetPriority(Regs* regs) {
+    unsigned tid = regs->edx;
+    if (tid >= kMaxThreads || threads[tid].state == THREAD_UNUSED || threads[tid].state == THREAD_ZOMBIE) {
+        regs->eax = -1;
+        return;
+    }
+    regs->eax = threads[tid].priority;
+}
+
+// edx is the weight of the new group, returns the group id or -1.
+void SysCreateCpuGroup(Regs* regs) {
+    int weight = regs->edx;
+    regs->eax = -1;
+    if (weight < 1 || weight > kMaxCpuWeight) return;
+    for (int i = 1; i < kMaxCpuGroups; i++) {
+        if (cpu_groups[i].weight == 0) {
+            // Start level with the running groups, not with a CPU credit of everything consumed since boot.
+            cpu_groups[i] = CpuGroup{weight, 0, 0, min_vruntime, current_thread->pid};
+            regs->eax = i;
+            return;
+        }
+    }
+}
+
+// edx is the group the calling thread moves to, its children inherit the group. Unprivileged threads can only move
+// into groups their process created, so they can't escape a group a privileged process put them in.
+void SysSetCpuGroup(Regs* regs) {
+    auto group = regs->edx;
+    if (group >= kMaxCpuGroups || cpu_groups[group].weight == 0 ||
+        (!current_thread->privileged && cpu_groups[group].creator != current_thread->pid)) {
+        regs->eax = -1;
+        return;
+    }
+    cpu_groups[group].members++;
+    LeaveCpuGroup(current_thread);
+    current_thread->cpu_group = group;
+    regs->eax = 0;
+}
+
+// edx is the first port and ecx the number of ports the calling thread is allowed to access from user mode.
+void SysGrantIoPorts(Regs* regs) {
+    int base = regs->edx;
+    int count = regs->ecx;
+    regs->eax = -1;
+    if (!current_thread->privileged || current_thread->num_io_ranges == kMaxIoRanges) return;
+    if (base < 0 || count <= 0 || count > kNumIoPorts - base) return;
+    current_thread->io_ranges[current_thread->num_io_ranges++] = IoRange{base, count};
+    SetIoPermission(base, count, true);
+    regs->eax = 0;
+}
+
+// edx is the first port and ecx the number of ports to take away from the calling thread again. Granted ranges that lie
+// within them are revoked, returns the number of ranges revoked.
+void SysRevokeIoPorts(Regs* regs) {
+    uint32_t base = regs->edx;
+    uint32_t count = regs->ecx;
+    auto thread = current_thread;
+    int revoked = 0;
+    SetIoPorts(thread, false);
+    for (int i = 0; i < thread->num_io_ranges; i++) {
+        auto& range = thread->io_ranges[i];
+        if (uint32_t(range.base) < base || uint32_t(range.base + range.count) - base > count) {
+            thread->io_ranges[i - revoked] = range;
+        } else {
+            revoked++;
+        }
+    }
+    thread->num_io_ranges -= revoked;
+    SetIoPorts(thread, true);
+    regs->eax = revoked;
+}
+
+// edx is the IRQ to forward to the calling thread, which collects it with SysWaitIrq.
+void SysClaimIrq(Regs* regs) {
+    regs->eax = current_thread->privileged && ClaimIrq(regs->edx, current_thread->tid) ? 0 : -1;
+}
+
+// edx is a bit mask of claimed IRQs. Acknowledges them, as the driver has serviced the device if it waits for the
+// next interrupt, and blocks until one of them fires. Returns the mask of IRQs that fired.
+void SysWaitIrq(Regs* regs) {
+    int mask = regs->edx & 0xFFFF;
+    for (int irq = 0; irq < 16; irq++) {
+        if (mask & (1 << irq)) AcknowledgeIrq(irq, current_thread->tid);
+    }
+    if (mask == 0 || (current_thread->irqs_pending & mask)) {
+        regs->eax = current_thread->irqs_pending & mask;
+        current_thread->irqs_pending &= ~mask;
+        return;
+    }
+    current_thread->irq_wait_mask = mask;
+    Block(regs);
+}
+
+void DeliverIrq(int tid, int irq) {
+    auto& thread = threads[tid];
+    thread.irqs_pending |= 1 << irq;
+    if (thread.state == THREAD_BLOCKED && (thread.irq_wait_mask & thread.irqs_pending)) {
+        thread.cpu_state.eax = thread.irq_wait_mask & thread.irqs_pending;
+        thread.irqs_pending &= ~thread.irq_wait_mask;
+        thread.irq_wait_mask = 0;
+        MakeReady(&thread);
+    }
+}
+
+// edx is the physical address and ecx the size of an MMIO range to map uncached into the caller's address space.
+// Returns the address of the mapping or 0. The range stays reserved until the process exits.
+void SysMapPhys(Regs* regs) {
+    void* p = current_thread->privileged ? MapPhys(regs->edx, regs->ecx, current_thread->pid) : nullptr;
+    regs->eax = reinterpret_cast<uintptr_t>(p);
+}
+
+// Permanently gives up the right to access hardware.
+void SysDropPrivileges(Regs* regs) {
+    current_thread->privileged = false;
+    regs->eax = 0;
 }
@@ -9,7 +9,10 @@
 #include <cstddef>
 
 #include "entry.h"
+#include "file.h"
 #include "paging.h"
+#include "vfs.h"
+#include "wait.h"
 
 struct CPUState {
     uint32_t eax, ebx, ecx, edx, esi, edi, ebp, esp;
@@ -26,6 +29,26 @@ enum ThreadState {
     THREAD_ZOMBIE,
 };
 
+struct IoRange {
+    int base, count;
+};
+
+constexpr int kMaxIoRanges = 4;
+
+// A region of user space the process has mapped, [start, end) page aligned. Anonymous regions are demand zero, file
+// mappings are filled from the file on fault. The pages of a file mapping are private copies, writes never go back
+// to the file.
+//
+// TODO: pages of the same file mapped by unrelated processes are separate copies, sharing them needs a page cache.
+struct Vma {
+    uintptr_t start, end;
+    VNode file;  // fs is nullptr for anonymous memory
+    uint64_t offset;  // in the file of start
+    bool writable;
+};
+
+constexpr int kMaxVmas = 16;
+
 struct Thread {
     int tid;  // 0 is the init/idle thread
     int pid;
@@ -33,10 +56,63 @@ struct Thread {
     int parent_tid;
     ThreadState state;
     int time;
+    int ready_since;  // tick at which the thread last became ready, used for aging
+    int yield_until;  // tick until which the thread is deprioritized after yielding
+    int slice_ticks;  // ticks the thread has been running since it was last switched to
+    uint64_t user_ns;  // CPU time spent in user mode
+    uint64_t system_ns;  // CPU time spent in the kernel on behalf of the thread
+    uint64_t mode_ns;  // when the thread last entered or left the kernel, the start of the time not yet charged
+    int wake_tick;  // tick at which a sleeping thread is woken, 0 if not sleeping
+    Thread* timer_next;  // in the list of threads with a wake_tick
+    uint64_t wake_ns;  // exact deadline of a sleeping thread
+    int low_mem_threshold;  // a thread waiting for memory pressure is woken below this many free pages, 0 if not waiting
+    int console;  // controlling console, inherited from the parent
+    int cpu_group;  // inherited from the parent
+    bool privileged;  // may drive hardware directly, only init and the drivers it starts (see kForkPrivileged)
+    int num_io_ranges;
+    IoRange io_ranges[kMaxIoRanges];  // io ports the thread may access, not inherited
+    int irqs_pending;  // bit mask of claimed IRQs that fired and weren't collected yet
+    int irq_wait_mask;  // IRQs the thread is blocked on, 0 if not waiting
+    int exit_code;  // valid once the thread is a zombie, which it stays until its parent collects the code
+    uintptr_t brk_base;  // the heap lies between the end of the program and the program break
+    uintptr_t brk;
+    uintptr_t mmap_base;  // anonymous mappings of Linux programs are allocated downwards from here
+    int num_vmas;
+    Vma vmas[kMaxVmas];  // the program and its mappings, the heap and stack are implied by brk and kStackLimit
+    bool linux_abi;  // system calls follow the Linux i386 ABI (see linux.h), inherited and kept across exec
+    int binary;  // entry in the binary cache of the program being run, -1 for init which is loaded at boot
+    WaitQueue* wait_queue;  // the thread is blocked on (see BlockOn), nullptr if none
+    Thread* wait_next;  // in the wait queue
+    WaitQueue children;  // a thread waiting for its children to exit
     PageTable* page_dir;
     Regs cpu_state;
-    int num_file_descriptors;
-    int file_descriptors[16];
+    int file_descriptors[kMaxFileDescriptors];  // index in the open file table, -1 if closed
+};
+
+// CPU groups divide the CPU proportionally to their weight between the groups that have ready threads, whatever the
+// number of threads in each group. Group 0 is the root group.
+struct CpuGroup {
+    int weight;  // 0 means the group is unused
+    int members;  // number of threads in the group
+    int ticks;  // ticks consumed
+    uint64_t vruntime;  // ticks consumed, scaled inversely to the weight
+    int creator;  // pid of the process that created the group, -1 once it exited
+};
+
+constexpr int kMaxCpuGroups = 16;
+constexpr int kDefaultCpuWeight = 100;
+constexpr int kMaxCpuWeight = 10000;
+extern CpuGroup cpu_groups[kMaxCpuGroups];
+
+// Higher priorities run first. Priorities are inherited from the parent, init has priority 0.
+constexpr int kMinPriority = -20;
+constexpr int kMaxPriority = 20;
+
+// Scheduling policies, set with the sched/policy tunable. The priority policy runs the ready threads of the highest
+// priority after aging round robin, the round robin policy ignores priorities.
+enum SchedPolicy {
+    kPolicyPriority = 0,
+    kPolicyRoundRobin = 1,
 };
 
 extern Thread* current_thread;
@@ -44,10 +120,53 @@ extern Thread* current_thread;
 constexpr int kMaxThreads = 1024;
 extern Thread threads[kMaxThreads];
 
+void InitScheduler();  // registers the scheduler tunables: tick_frequency in Hz, time_slice in ticks and policy
 [[noreturn]] void ExitToThread(Thread* thread);
 Thread* CreateThread(Thread* parent, PageTable* page_dir, bool is_process);  // parent == nullptr means init thread
 void Yield(Regs* regs);
+// Kernel entries from user mode and the way back, which is shared by all of them and by switching to a thread. The
+// time in between is charged to the thread as system time, the rest of its time running as user time.
+void EnterFromUser();
+[[noreturn]] void ReturnToUser(Regs* regs);
 void SysExit(Regs* regs);
+void SysWait(Regs* regs);
+void SysGetPid(Regs* regs);
+void SysGetPpid(Regs* regs);
+void SysGetTid(Regs* regs);
+void SysSetPriority(Regs* regs);
+void SysGetPriority(Regs* regs);
+// Flags of fork in edx. A privileged process starts a driver by forking with kForkPrivileged, other children are
+// never privileged.
+constexpr uint32_t kForkPrivileged = 1;
 void SysFork(Regs* regs);
+void SysNanosleep(Regs* regs);
+void SysGetTime(Regs* regs);
+void SysCreateCpuGroup(Regs* regs);
+void SysSetCpuGroup(Regs* regs);
+void SysWaitLowMemory(Regs* regs);
+void SysGrantIoPorts(Regs* regs);
+void SysRevokeIoPorts(Regs* regs);
+void SysClaimIrq(Regs* regs);
+void SysWaitIrq(Regs* regs);
+void SysDropPrivileges(Regs* regs);
+void SysMapPhys(Regs* regs);
+// Valid user addresses are demand paged, access to anything else is a segmentation fault.
+bool AddVma(Thread* thread, uintptr_t start, uintptr_t end, VNode file = {nullptr, -1}, uint64_t offset = 0,
+            bool writable = true);
+const Vma* FindVma(const Thread* thread, uintptr_t address);
+void RemoveVmas(Thread* thread, uintptr_t start, uintptr_t end);
+bool IsValidUserAddress(const Thread* thread, uintptr_t address);
+
+void SchedulerTick(int tick);  // wakes sleepers and does CPU accounting, called from the timer interrupt
+void DeliverIrq(int tid, int irq);  // called from the interrupt handler for IRQs claimed by a user space driver
+
+// Blocking until a kernel object changes state. The thread's system call is restarted when it is woken by
+// WakeToRestart, or fails with -1 when the timeout (in ticks, 0 is none) passes first. Callers waiting for something
+// an interrupt handler provides must check for it with interrupts disabled, otherwise the wake up can come between
+// the check and blocking.
+[[noreturn]] void BlockOn(Regs* regs, WaitQueue* queue, int timeout_ticks);
+void WakeToRestart(Thread* thread);
+void WakeAll(WaitQueue* queue);  // restarts all threads in the queue
+void CompleteAll(WaitQueue* queue);  // wakes all threads in the queue without restarting, eax holds their result
 
 #endif //OS_THREAD_H
@@ -0,0 +1,194 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#include "tmpfs.h"
+
+#include "src/freestanding/utils.h"
+
+static char blocks[kTmpBlocks][kTmpBlockSize];
+static int next_block[kTmpBlocks];  // in the chain of a file, -1 at the end
+static bool block_used[kTmpBlocks];
+
+constinit TmpFileSystem tmpfs;
+
+void InitTmpFs() {
+    Mount("/tmp", &tmpfs);
+}
+
+// Blocks are zeroed when allocated, so the part of a file past its size always reads as zero.
+static int AllocBlock() {
+    for (int i = 0; i < kTmpBlocks; i++) {
+        if (block_used[i]) continue;
+        block_used[i] = true;
+        next_block[i] = -1;
+        memset(blocks[i], 0, kTmpBlockSize);
+        return i;
+    }
+    return -1;
+}
+
+static void FreeBlocks(int block) {
+    while (block >= 0) {
+        block_used[block] = false;
+        block = next_block[block];
+    }
+}
+
+// Splits the first component off path.
+static std::string_view NextComponent(std::string_view* path) {
+    std::size_t n = 0;
+    while (n < path->size() && (*path)[n] != '/') n++;
+    auto component = path->substr(0, n);
+    path->remove_prefix(n);
+    while (!path->empty() && path->front() == '/') path->remove_prefix(1);
+    return component;
+}
+
+int TmpFileSystem::FindChild(int dir, std::string_view name) const {
+    for (int i = 0; i < kMaxTmpNodes; i++) {
+        auto& n = nodes_[i];
+        if (n.used && n.parent == dir && std::string_view(n.name, n.name_length) == name) return i;
+    }
+    return -1;
+}
+
+int TmpFileSystem::Lookup(std::string_view path) {
+    int node = kRoot;
+    while (!path.empty()) {
+        if (nodes_[node].type != kDirectory) return -1;
+        node = FindChild(node, NextComponent(&path));
+        if (node < 0) return -1;
+    }
+    return node;
+}
+
+// Returns the directory that should hold the last component of path, which is stored in name, or -1 if it doesn't
+// exist or the name can't be used.
+int TmpFileSystem::LookupParent(std::string_view path, std::string_view* name) const {
+    int dir = kRoot;
+    while (true) {
+        *name = NextComponent(&path);
+        if (path.empty()) break;
+        dir = FindChild(dir, *name);
+        if (dir < 0 || nodes_[dir].type != kDirectory) return -1;
+    }
+    if (name->empty() || name->size() >= kMaxNameLength || *name == "." || *name == "..") return -1;
+    return dir;
+}
+
+// Returns block n of the file, allocating the blocks up to it if grow is set. -1 if it doesn't exist or the pool is
+// exhausted.
+int TmpFileSystem::SeekBlock(int node, uint64_t n, bool grow) {
+    int* link = &nodes_[node].first_block;
+    while (true) {
+        if (*link < 0) {
+            if (!grow) return -1;
+            *link = AllocBlock();
+            if (*link < 0) return -1;
+        }
+        if (n-- == 0) return *link;
+        link = &next_block[*link];
+    }
+}
+
+int TmpFileSystem::Read(int node, uint64_t offset, void* buf, std::size_t len) {
+    if (!IsNode(node) || nodes_[node].type != kRegularFile) return -1;
+    auto size = nodes_[node].size;
+    if (offset >= size) return 0;
+    len = min<uint64_t>(len, size - offset);
+    std::size_t n = 0;
+    while (n < len) {
+        std::size_t in_block = (offset + n) % kTmpBlockSize;
+        auto chunk = min(len - n, kTmpBlockSize - in_block);
+        int block = SeekBlock(node, (offset + n) / kTmpBlockSize, false);
+        if (block < 0) return -1;
+        memcpy(static_cast<char*>(buf) + n, blocks[block] + in_block, chunk);
+        n += chunk;
+    }
+    return n;
+}
+
+// Writing past the end grows the file, a gap reads as zero. When the pool runs out the bytes that fit are written.
+int TmpFileSystem::Write(int node, uint64_t offset, const void* buf, std::size_t len) {
+    if (!IsNode(node) || nodes_[node].type != kRegularFile) return -1;
+    std::size_t n = 0;
+    while (n < len) {
+        std::size_t in_block = (offset + n) % kTmpBlockSize;
+        auto chunk = min(len - n, kTmpBlockSize - in_block);
+        int block = SeekBlock(node, (offset + n) / kTmpBlockSize, true);
+        if (block < 0) break;
+        memcpy(blocks[block] + in_block, static_cast<const char*>(buf) + n, chunk);
+        n += chunk;
+    }
+    if (n == 0 && len > 0) return -1;
+    nodes_[node].size = max(nodes_[node].size, offset + n);
+    return n;
+}
+
+int TmpFileSystem::ReadDir(int node, std::size_t index, DirEntry* entry) {
+    if (!IsNode(node) || nodes_[node].type != kDirectory) return -1;
+    for (auto& n : nodes_) {
+        if (!n.used || n.parent != node || index-- > 0) continue;
+        memcpy(entry->name, n.name, n.name_length);
+        entry->name[n.name_length] = 0;
+        entry->type = n.type;
+        return 1;
+    }
+    return 0;
+}
+
+int TmpFileSystem::Create(std::string_view path, FileType type) {
+    std::string_view name;
+    int dir = LookupParent(path, &name);
+    if (dir < 0 || FindChild(dir, name) >= 0) return -1;
+    for (int i = 0; i < kMaxTmpNodes; i++) {
+        auto& n = nodes_[i];
+        if (n.used) continue;
+        n = Node{true, type, dir, {}, name.size(), 0, -1};
+        memcpy(n.name, name.data(), name.size());
+        return i;
+    }
+    return -1;
+}
+
+bool TmpFileSystem::Unlink(std::string_view path) {
+    int node = Lookup(path);
+    if (node <= kRoot) return false;
+    // A directory must be empty.
+    for (auto& n : nodes_) {
+        if (n.used && n.parent == node) return false;
+    }
+    FreeBlocks(nodes_[node].first_block);
+    nodes_[node].used = false;
+    return true;
+}
+
+bool TmpFileSystem::Rename(std::string_view from, std::string_view to) {
+    int node = Lookup(from);
+    std::string_view name;
+    int dir = LookupParent(to, &name);
+    if (node <= kRoot || dir < 0) return false;
+    // A directory can't be moved into itself.
+    for (int d = dir; d >= 0; d = nodes_[d].parent) {
+        if (d == node) return false;
+    }
+    int old = FindChild(dir, name);
+    if (old == node) return true;
+    if (old >= 0) {
+        if (nodes_[old].type != kRegularFile || nodes_[node].type != kRegularFile) return false;
+        FreeBlocks(nodes_[old].first_block);
+        nodes_[old].used = false;
+    }
+    auto& n = nodes_[node];
+    n.parent = dir;
+    memcpy(n.name, name.data(), name.size());
+    n.name_length = name.size();
+    return true;
+}
+
+bool TmpFileSystem::Stat(int node, FileStat* stat) {
+    if (!IsNode(node)) return false;
+    *stat = FileStat{nodes_[node].size, nodes_[node].type, nodes_[node].type == kDirectory ? 0755u : 0644u, 0};
+    return true;
+}
@@ -0,0 +1,53 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#ifndef OS_TMPFS_H
+#define OS_TMPFS_H
+
+#include "vfs.h"
+
+// Writable filesystem in kernel memory, mounted at /tmp. Files and directories can be created, removed and renamed,
+// everything is lost at reboot. File contents are chains of fixed size blocks, like the clusters of FAT, taken from a
+// static pool as the files grow and returned when they are removed. Node numbers are reused after removal.
+constexpr int kMaxTmpNodes = 64;
+constexpr std::size_t kTmpBlockSize = 1024;
+constexpr int kTmpBlocks = 256;
+
+class TmpFileSystem : public FileSystem {
+public:
+    constexpr TmpFileSystem() { nodes_[kRoot] = Node{true, kDirectory, -1, {}, 0, 0, -1}; }
+
+    int Lookup(std::string_view path) override;
+    int Read(int node, uint64_t offset, void* buf, std::size_t len) override;
+    int Write(int node, uint64_t offset, const void* buf, std::size_t len) override;
+    int ReadDir(int node, std::size_t index, DirEntry* entry) override;
+    int Create(std::string_view path, FileType type) override;
+    bool Unlink(std::string_view path) override;
+    bool Rename(std::string_view from, std::string_view to) override;
+    bool Stat(int node, FileStat* stat) override;
+
+private:
+    struct Node {
+        bool used;
+        FileType type;
+        int parent;  // -1 for the root
+        char name[kMaxNameLength];
+        std::size_t name_length;
+        uint64_t size;
+        int first_block;  // -1 for an empty file
+    };
+
+    static constexpr int kRoot = 0;
+
+    bool IsNode(int node) const { return node >= 0 && node < kMaxTmpNodes && nodes_[node].used; }
+    int FindChild(int dir, std::string_view name) const;
+    int LookupParent(std::string_view path, std::string_view* name) const;
+    int SeekBlock(int node, uint64_t n, bool grow);
+
+    Node nodes_[kMaxTmpNodes] = {};
+};
+
+void InitTmpFs();
+
+#endif //OS_TMPFS_H
@@ -4,10 +4,25 @@
 
 #include <cstdint>
 
+#include "checkpoint.h"
+#include "console.h"
 #include "entry.h"
+#include "exec.h"
+#include "file.h"
+#include "ipc.h"
 #include "irq.h"
 #include "kassert.h"
+#include "klog.h"
+#include "ldt.h"
+#include "linux.h"
+#include "keyboard.h"
+#include "net.h"
 #include "paging.h"
+#include "profile.h"
+#include "power.h"
+#include "pstore.h"
+#include "sysctl.h"
+#include "tarfs.h"
 #include "thread.h"
 #include "x86_inst.h"
 #include "src/freestanding/utils.h"
@@ -35,30 +50,35 @@ void ShowRegs(Regs* regs) {
     kprint("ShowRegs: @{}:{} stack {}:{}\nkernel stack @{} ecx: {} edx: {}\n", Hex(regs->cs), Hex(regs->eip), Hex(regs->ss), Hex(regs->esp), Hex(regs->temp_esp), Hex(regs->ecx), Hex(regs->edx));
 }
 
-void ReadSyscall(Regs* regs) {
-    auto fd = regs->ecx;
-    auto buf = reinterpret_cast<char*>(regs->edx);
-    auto len = regs->esi;
-    if (fd != 0) {
-        kprint("Non-stdin not supported\n");
+// edx is the console to attach to
+void SetConsoleSyscall(Regs* regs) {
+    auto console = regs->edx;
+    if (console >= kNumConsoles) {
+        regs->eax = -1;
         return;
     }
-    auto ret = key_pipe.Read(buf, len);
-    regs->eax = ret;
+    current_thread->console = console;
+    regs->eax = 0;
 }
 
-void WriteSyscall(Regs* regs) {
-    auto fd = regs->ecx;
-    auto buf = reinterpret_cast<char*>(regs->edx);
-    auto len = regs->esi;
-    if (regs->edx != 1) {
-        kprint("Non-stdout not supported\n");
+// edx points to the layout name of length ecx
+void SetKeymapSyscall(Regs* regs) {
+    char name[kMaxNameLength];
+    if (regs->ecx > sizeof(name)) {
+        regs->eax = -1;
         return;
-    } else {
-        kprint("{}", std::string_view(reinterpret_cast<char*>(regs->ecx), regs->ebx));
     }
-    auto ret = len;
-    regs->eax = ret;
+    if (!CopyFromUser(name, regs->edx, regs->ecx)) {
+        regs->eax = -kEFAULT;
+        return;
+    }
+    regs->eax = LoadKeymap(std::string_view(name, regs->ecx)) ? 0 : -1;
+}
+
+// edx points to a MemInfo to fill. Returns 0 or -kEFAULT.
+void MemInfoSyscall(Regs* regs) {
+    auto info = GetMemInfo();
+    regs->eax = CopyToUser(regs->edx, &info, sizeof(info)) ? 0 : -kEFAULT;
 }
 
 static const EntryHandler syscall_table[] = {
@@ -67,11 +87,77 @@ static const EntryHandler syscall_table[] = {
         nullptr,
         nullptr,
         SysFork,  // 4
-        nullptr,
-        nullptr,
-        nullptr,
-        ReadSyscall,  // 8
-        WriteSyscall,  // 9
+        SysExec,  // 5
+        SysOpen,  // 6
+        SysClose,  // 7
+        SysRead,  // 8
+        SysWrite,  // 9
+        SysSeek,  // 10
+        SetConsoleSyscall,  // 11
+        SetKeymapSyscall,  // 12
+        SysNanosleep,  // 13
+        SysCreateCpuGroup,  // 14
+        SysSetCpuGroup,  // 15
+        MemInfoSyscall,  // 16
+        SysWaitLowMemory,  // 17
+        SysGrantIoPorts,  // 18
+        SysClaimIrq,  // 19
+        SysWaitIrq,  // 20
+        SysDropPrivileges,  // 21
+        SysMapPhys,  // 22
+        SysCreatePort,  // 23
+        SysDestroyPort,  // 24
+        SysSend,  // 25
+        SysReceive,  // 26
+        SysCreateEvent,  // 27
+        SysDestroyEvent,  // 28
+        SysEventRead,  // 29
+        SysEventWrite,  // 30
+        SysNetOpen,  // 31
+        SysNetSend,  // 32
+        SysNetReceive,  // 33
+        SysNetCapture,  // 34
+        SysProfile,  // 35
+        SysLastLog,  // 36
+        SysDup,  // 37
+        SysDup2,  // 38
+        SysWait,  // 39
+        SysPipe,  // 40
+        SysBrk,  // 41
+        SysExecLinux,  // 42
+        SysSysctl,  // 43
+        SysShutdown,  // 44
+        SysIoctl,  // 45
+        SysSync,  // 46
+        SysFsync,  // 47
+        SysGetPid,  // 48
+        SysGetPpid,  // 49
+        SysGetTid,  // 50
+        SysMountImage,  // 51
+        SysSetPriority,  // 52
+        SysGetPriority,  // 53
+        SysMkfifo,  // 54
+        SysOpenPty,  // 55
+        SysGetTime,  // 56
+        SysKlog,  // 57
+        SysWatch,  // 58
+        SysCheckpoint,  // 59
+        SysRestore,  // 60
+        SysMkdir,  // 61
+        SysUnlink,  // 62
+        SysRename,  // 63
+        SysReadDir,  // 64
+        SysStat,  // 65
+        SysFstat,  // 66
+        SysRevokeIoPorts,  // 67
+        SysModifyLdt,  // 68
+        SysPread,  // 69
+        SysPwrite,  // 70
+        SysSocket,  // 71
+        SysBind,  // 72
+        SysListen,  // 73
+        SysAccept,  // 74
+        SysConnect,  // 75
 };
 
 enum Signals : int {
@@ -103,9 +189,19 @@ GenericException exceptions[32] = {
         {SIGSEGV, "alignment check"},  // 17
 };
 
+// Faults of a user program on its own segments, like breaking the limit of a segment in its LDT (see ldt.h), kill it.
+//
+// TODO: deliver the signals once there are signals.
+static void SegmentFault(Regs* regs, std::string_view name) {
+    kprint("Thread {} {} @{}:{}\n", current_thread->tid, name, Hex(regs->cs), Hex(regs->eip));
+    regs->edx = 128 + 11;  // exit code of a shell for death by SIGSEGV
+    SysExit(regs);
+}
+
 static void generic_exception_handler(Regs* regs) {
     auto int_no = regs->int_no;
     auto signal = exceptions[int_no].signal;
+    if ((regs->cs & 3) == 3 && signal == SIGBUS) return SegmentFault(regs, exceptions[int_no].name);
 
     panic("An unsupported exception, signal = {} name = {} @{}:{}\n", signal, exceptions[regs->int_no].name, Hex(regs->cs), Hex(regs->eip));
 }
@@ -135,13 +231,18 @@ static void double_fault(Regs*) {
 }
 
 static void general_protection(Regs* regs) {
+    if ((regs->cs & 3) == 3) return SegmentFault(regs, "general protection");
     panic("GP {} {}:{}", regs->err_code, Hex(regs->cs), Hex(regs->eip));
 }
 
 void page_fault(Regs* regs);
 
+// System calls are int 0x80 with the number in eax and the arguments in edx, ecx, ebx, esi and edi, all 32 bit.
+// TODO: a 64 bit ABI (arguments in 64 bit registers, pointers above 4GB) only makes sense once the kernel runs in
+// long mode, it is 32 bit protected mode only and can't run 64 bit user programs.
 static void SystemCall(Regs* regs) {
     //kprint("SystemCall: {}\n", regs->eax);
+    if (current_thread->linux_abi) return LinuxSystemCall(regs);
     if (regs->eax >= array_size(syscall_table) || !syscall_table[regs->eax]) {
         regs->eax = ENOSYS;
         return;
@@ -190,10 +291,20 @@ constexpr IsrTable MakeTable() {
 
 const IsrTable isr_table = MakeTable();
 
+int interrupt_depth;
+
+// Handlers run with interrupts enabled, interrupts can nest on the kernel stack up to kMaxInterruptDepth. State shared
+// with interrupt handlers is only touched with interrupts disabled. Threads are only switched on the way back to user
+// mode, where nothing of the kernel is left on the stack (see ReturnToUser).
 extern "C" [[noreturn]] void isr_handler(Regs* regs) {
+    if (++interrupt_depth > kMaxInterruptDepth) panic("Interrupts nested {} deep\n", interrupt_depth);
+    bool from_user = (regs->cs & 3) == 3;
+    if (from_user) EnterFromUser();
     X86_sti();
     regs->int_no = (regs->int_no - reinterpret_cast<uintptr_t>(int_vector)) / 8;
     isr_table.entries[regs->int_no](regs);
+    if (from_user) ReturnToUser(regs);
     X86_cli();
+    interrupt_depth--;
     exit_kernel(regs);
 }
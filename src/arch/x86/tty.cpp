@@ -0,0 +1,163 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#include "tty.h"
+
+#include "thread.h"
+#include "x86_inst.h"
+
+constinit Tty ttys[kMaxTtys];
+
+struct Pty {
+    bool used;
+    bool master_open, slave_open;
+    PipeN<kPtyBufferSize> output;  // written by the slave, read by the master
+    WaitQueue master_readers;  // blocked on empty output
+    WaitQueue slave_writers;  // blocked on full output
+};
+
+static Pty ptys[kMaxPtys];
+
+constexpr char kErase = '\b';
+constexpr char kKill = 'U' & 0x1F;
+
+static bool IsPty(int n) {
+    return n >= kNumConsoles;
+}
+
+// Output of the terminal, which can't block as echo comes from interrupt handlers. Returns the number of bytes
+// written, output of a pty is lost when the master doesn't keep up.
+static int Output(int n, std::string_view str) {
+    if (!IsPty(n)) {
+        consoles[n].Write(str);
+        return str.size();
+    }
+    auto& pty = ptys[n - kNumConsoles];
+    int written = pty.output.Write(str);
+    WakeAll(&pty.master_readers);
+    return written;
+}
+
+static void Echo(int n, std::string_view str) {
+    if (ttys[n].mode & kTtyEcho) Output(n, str);
+}
+
+// Moves the edited line to the input queue, readers are woken up.
+static void Commit(int n) {
+    auto& tty = ttys[n];
+    tty.input.Write(std::string_view(tty.line, tty.line_size));
+    tty.line_size = 0;
+    WakeAll(&tty.readers);
+}
+
+void TtyInput(int n, char c) {
+    auto& tty = ttys[n];
+    if (!(tty.mode & kTtyCanonical)) {
+        if (tty.input.Write(std::string_view(&c, 1)) == 0) return;
+        Echo(n, std::string_view(&c, 1));
+        WakeAll(&tty.readers);
+        return;
+    }
+    if (c == kErase || c == kKill) {
+        do {
+            if (tty.line_size == 0) return;
+            tty.line_size--;
+            Echo(n, "\b \b");
+        } while (c == kKill);
+        return;
+    }
+    // Keep room for the newline.
+    if (c != '\n' && tty.line_size == sizeof(tty.line) - 1) return;
+    tty.line[tty.line_size++] = c;
+    Echo(n, std::string_view(&c, 1));
+    if (c == '\n') Commit(n);
+}
+
+int TtyIoctl(int n, uint32_t request, uint32_t arg) {
+    auto& tty = ttys[n];
+    switch (request) {
+        case kTtyGetMode:
+            return tty.mode;
+        case kTtySetMode: {
+            if (arg & ~(kTtyCanonical | kTtyEcho)) return -1;
+            auto old = tty.mode;
+            tty.mode = arg;
+            // Leaving canonical mode hands the partial line to the reader.
+            if (!(arg & kTtyCanonical) && tty.line_size > 0) Commit(n);
+            return old;
+        }
+        default:
+            return -1;
+    }
+}
+
+// Blocks while there is no input, the slave of a pty whose master is closed reads the end of file.
+int TtyRead(Regs* regs, int n, char* buf, std::size_t len) {
+    auto& tty = ttys[n];
+    // The keyboard interrupt fills the input queue of the consoles.
+    X86_cli();
+    if (tty.input.Empty() && len > 0 && !(IsPty(n) && !ptys[n - kNumConsoles].master_open)) {
+        BlockOn(regs, &tty.readers, 0);
+    }
+    X86_sti();
+    return tty.input.Read(buf, len);
+}
+
+// Writing a pty slave writes what fits, blocking only while nothing fits. Fails when the master is closed.
+int TtyWrite(Regs* regs, int n, const char* buf, std::size_t len, bool may_block) {
+    if (!IsPty(n)) return Output(n, std::string_view(buf, len));
+    auto& pty = ptys[n - kNumConsoles];
+    if (!pty.master_open) return -1;
+    int written = Output(n, std::string_view(buf, len));
+    if (written == 0 && len > 0 && may_block) BlockOn(regs, &pty.slave_writers, 0);
+    return written;
+}
+
+int OpenPty() {
+    for (int i = 0; i < kMaxPtys; i++) {
+        auto& pty = ptys[i];
+        if (pty.used) continue;
+        pty.used = pty.master_open = pty.slave_open = true;
+        pty.output.Clear();
+        auto& tty = ttys[PtyTty(i)];
+        tty.mode = kTtyCanonical | kTtyEcho;
+        tty.line_size = 0;
+        tty.input.Clear();
+        return i;
+    }
+    return -1;
+}
+
+// The other end sees the end of file, or fails to write. The pty is freed when both ends are closed.
+void ClosePtyEnd(int pty, bool master) {
+    auto& p = ptys[pty];
+    if (master) {
+        p.master_open = false;
+        WakeAll(&ttys[PtyTty(pty)].readers);
+        WakeAll(&p.slave_writers);
+    } else {
+        p.slave_open = false;
+        WakeAll(&p.master_readers);
+    }
+    if (!p.master_open && !p.slave_open) p.used = false;
+}
+
+// Blocks while there is no output, reads the end of file once the slave is closed.
+int PtyMasterRead(Regs* regs, int pty, char* buf, std::size_t len) {
+    auto& p = ptys[pty];
+    // Echo comes from interrupt handlers.
+    X86_cli();
+    if (p.output.Empty() && len > 0 && p.slave_open) BlockOn(regs, &p.master_readers, 0);
+    X86_sti();
+    int n = p.output.Read(buf, len);
+    WakeAll(&p.slave_writers);
+    return n;
+}
+
+// The bytes are typed on the slave terminal. Fails when the slave is closed.
+int PtyMasterWrite(int pty, const char* buf, std::size_t len) {
+    if (!ptys[pty].slave_open) return -1;
+    for (std::size_t i = 0; i < len; i++) TtyInput(PtyTty(pty), buf[i]);
+    return len;
+}
@@ -0,0 +1,65 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#ifndef OS_TTY_H
+#define OS_TTY_H
+
+#include <cstdint>
+
+#include "console.h"
+#include "entry.h"
+#include "pipe.h"
+#include "wait.h"
+
+// Terminals. Every virtual console is a terminal: the keyboard feeds its input queue through the line discipline and
+// output goes to its screen. In canonical mode input is line buffered, a line can be edited with backspace (erase a
+// character) and ctrl+U (kill the line) and goes to the input queue when enter is pressed. In raw mode every character
+// goes to the input queue as it is typed. Typed characters are echoed unless echo is turned off.
+//
+// Pseudo-terminals are terminals without hardware. A pty is a pair of descriptors, what is written to the master is
+// the input of the slave, going through the line discipline, and what is written to the slave (including echo) is
+// read from the master. A terminal emulator or remote shell server holds the master and runs a shell on the slave.
+//
+// TODO: a telnet daemon started from the inittab, relaying between a TCP connection and the master of a pty with a
+// shell on the slave. Blocked on a TCP stack with sockets (net only has raw packets) and on a shell program.
+constexpr int kMaxPtys = 4;
+constexpr int kMaxTtys = kNumConsoles + kMaxPtys;  // the consoles followed by the pty slaves
+constexpr int kPtyBufferSize = 4096;
+
+constexpr uint32_t kTtyCanonical = 1;
+constexpr uint32_t kTtyEcho = 2;
+
+// ioctl requests on a terminal descriptor
+enum TtyRequest {
+    kTtyGetMode = 1,  // returns the mode flags
+    kTtySetMode = 2,  // sets the mode flags to the argument, returns the old flags
+};
+
+struct Tty {
+    uint32_t mode = kTtyCanonical | kTtyEcho;
+    char line[kScreenWidth * 2];  // the line being edited in canonical mode
+    int line_size = 0;
+    PipeN<1024> input;
+    WaitQueue readers;  // blocked on an empty input queue
+};
+
+extern Tty ttys[kMaxTtys];
+
+inline int PtyTty(int pty) {
+    return kNumConsoles + pty;
+}
+
+void TtyInput(int n, char c);  // called for every typed character
+int TtyIoctl(int n, uint32_t request, uint32_t arg);
+
+// Reading and writing terminals on behalf of a system call, see ReadFile and WriteFile.
+int TtyRead(Regs* regs, int n, char* buf, std::size_t len);
+int TtyWrite(Regs* regs, int n, const char* buf, std::size_t len, bool may_block);
+
+int OpenPty();  // returns the pty, both ends open, or -1
+void ClosePtyEnd(int pty, bool master);
+int PtyMasterRead(Regs* regs, int pty, char* buf, std::size_t len);
+int PtyMasterWrite(int pty, const char* buf, std::size_t len);
+
+#endif //OS_TTY_H
@@ -0,0 +1,125 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#include "vfs.h"
+
+#include "paging.h"
+
+struct MountPoint {
+    std::string_view path;  // without leading or trailing '/', empty for the root
+    FileSystem* fs;
+};
+
+static MountPoint mounts[kMaxMounts];
+static int num_mounts;
+
+static std::string_view StripSlashes(std::string_view path) {
+    while (!path.empty() && path.front() == '/') path.remove_prefix(1);
+    while (!path.empty() && path.back() == '/') path.remove_suffix(1);
+    return path;
+}
+
+bool Mount(std::string_view path, FileSystem* fs) {
+    if (num_mounts == kMaxMounts) return false;
+    mounts[num_mounts++] = MountPoint{StripSlashes(path), fs};
+    return true;
+}
+
+void SyncAll() {
+    for (int i = 0; i < num_mounts; i++) mounts[i].fs->Sync();
+}
+
+void UnmountAll() {
+    SyncAll();
+    num_mounts = 0;
+}
+
+// The filesystem is the one with the longest mount path that is a prefix of path, as a whole path component. The
+// path is replaced by the rest relative to the mount point.
+static const MountPoint* FindMount(std::string_view* path) {
+    *path = StripSlashes(*path);
+    const MountPoint* best = nullptr;
+    for (int i = 0; i < num_mounts; i++) {
+        auto& m = mounts[i];
+        if (!path->starts_with(m.path)) continue;
+        if (!m.path.empty() && path->size() > m.path.size() && (*path)[m.path.size()] != '/') continue;
+        if (!best || m.path.size() >= best->path.size()) best = &m;
+    }
+    if (best) {
+        path->remove_prefix(best->path.size());
+        *path = StripSlashes(*path);
+    }
+    return best;
+}
+
+VNode VfsLookup(std::string_view path) {
+    auto mount = FindMount(&path);
+    if (!mount) return {nullptr, -1};
+    int node = mount->fs->Lookup(path);
+    if (node < 0) return {nullptr, -1};
+    return {mount->fs, node};
+}
+
+// Mount points themselves can't be created, removed or renamed.
+VNode VfsCreate(std::string_view path, FileType type) {
+    auto mount = FindMount(&path);
+    if (!mount || path.empty()) return {nullptr, -1};
+    int node = mount->fs->Create(path, type);
+    if (node < 0) return {nullptr, -1};
+    return {mount->fs, node};
+}
+
+bool VfsUnlink(std::string_view path) {
+    auto mount = FindMount(&path);
+    return mount && !path.empty() && mount->fs->Unlink(path);
+}
+
+bool VfsRename(std::string_view from, std::string_view to) {
+    auto mount = FindMount(&from);
+    if (!mount || from.empty() || FindMount(&to) != mount || to.empty()) return false;
+    return mount->fs->Rename(from, to);
+}
+
+static bool IsValidUtf8(std::string_view s) {
+    for (std::size_t i = 0; i < s.size(); ) {
+        uint8_t c = s[i++];
+        int continuation;
+        uint32_t code_point;
+        if (c < 0x80) continue;
+        if ((c & 0xE0) == 0xC0) {
+            continuation = 1;
+            code_point = c & 0x1F;
+        } else if ((c & 0xF0) == 0xE0) {
+            continuation = 2;
+            code_point = c & 0x0F;
+        } else if ((c & 0xF8) == 0xF0) {
+            continuation = 3;
+            code_point = c & 0x07;
+        } else {
+            return false;
+        }
+        if (s.size() - i < std::size_t(continuation)) return false;
+        for (int j = 0; j < continuation; j++) {
+            uint8_t next = s[i++];
+            if ((next & 0xC0) != 0x80) return false;
+            code_point = (code_point << 6) | (next & 0x3F);
+        }
+        // Reject overlong encodings, surrogates and code points beyond unicode.
+        constexpr uint32_t kMinCodePoint[] = {0, 0x80, 0x800, 0x10000};
+        if (code_point < kMinCodePoint[continuation]) return false;
+        if ((code_point >= 0xD800 && code_point < 0xE000) || code_point > 0x10FFFF) return false;
+    }
+    return true;
+}
+
+int CopyPathFromUser(uintptr_t user_path, char* path) {
+    std::size_t length = 0;
+    while (true) {
+        if (length == kMaxPathLength || !CopyFromUser(path + length, user_path + length, 1)) return -1;
+        if (path[length] == 0) break;
+        length++;
+    }
+    if (length == 0 || !IsValidUtf8(std::string_view(path, length))) return -1;
+    return length;
+}
@@ -0,0 +1,91 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#ifndef OS_VFS_H
+#define OS_VFS_H
+
+#include <cstdint>
+#include <cstddef>
+#include <string_view>
+
+// Virtual filesystem. Filesystems are mounted at a path and everything in the kernel accesses files by path
+// through here, not knowing which filesystem serves them. Paths are relative to the root, a leading '/' is
+// optional. A filesystem identifies its files by node numbers of its own choosing.
+//
+// TODO: a native writable filesystem (superblock, inode table with extents, directory entries) that survives
+// crashes through a journal replayed on mount. It needs a block device with a disk driver to store it.
+constexpr int kMaxMounts = 8;
+constexpr std::size_t kMaxNameLength = 100;
+constexpr std::size_t kMaxPathLength = 256;  // the USTAR prefix, a '/' and the name field
+
+enum FileType : uint32_t {
+    kRegularFile = 1,
+    kDirectory = 2,
+    kCharDevice = 3,  // a stream without offsets, in /dev
+};
+
+// mode and mtime come from the metadata of the filesystem where it has them. The permission bits aren't enforced, there
+// are no users, and there is no wall clock to stamp modifications with.
+struct FileStat {
+    uint64_t size;
+    FileType type;
+    uint32_t mode;  // permission bits, like 0644
+    uint64_t mtime;  // of the last modification in seconds since 1970, 0 if unknown
+};
+
+struct DirEntry {
+    char name[kMaxNameLength];
+    FileType type;
+};
+
+class FileSystem {
+public:
+    // path is relative to the mount point, returns the node or -1 if it doesn't exist.
+    virtual int Lookup(std::string_view path) = 0;
+    // Return the number of bytes transferred, or -1 on error.
+    virtual int Read(int node, uint64_t offset, void* buf, std::size_t len) = 0;
+    // TODO: account the bytes written per user and enforce quotas here, with a syscall for the usage and a du tool.
+    // There are no users (uids) yet.
+    virtual int Write(int, uint64_t, const void*, std::size_t) { return -1; }
+    // Fills entry number index of a directory, returns 1 if there is one, 0 past the end and -1 on error.
+    virtual int ReadDir(int, std::size_t, DirEntry*) { return -1; }
+    // Creates a file or directory at path, in an existing directory. Returns the node, or -1 if the name is taken or
+    // the filesystem can't create files.
+    virtual int Create(std::string_view, FileType) { return -1; }
+    // Removes a file or empty directory. The VFS users make sure the file isn't open, nodes aren't refcounted.
+    virtual bool Unlink(std::string_view) { return false; }
+    // Moves a file or directory, replacing a file at the destination.
+    virtual bool Rename(std::string_view, std::string_view) { return false; }
+    virtual bool Stat(int node, FileStat* stat) = 0;
+    // Memory backed filesystems give access to the contents in place, others return a view with nullptr data.
+    virtual std::string_view Map(int) { return {}; }
+    // Writes back whatever the filesystem buffers.
+    //
+    // TODO: no filesystem buffers writes yet, the ramdisk is read only and read in place. With a disk driver,
+    // filesystems should read sectors through an LRU block cache of pages, so scanning the TAR headers on lookups
+    // and loading binaries don't go to the disk again. Writes dirty the cached blocks, Sync writes them back. It
+    // needs a flusher that periodically writes blocks that have been dirty for more than a few seconds, and the number
+    // of cached and dirty pages in the memory info.
+    virtual void Sync() {}
+};
+
+struct VNode {
+    FileSystem* fs;  // nullptr if the file doesn't exist
+    int node;
+};
+
+bool Mount(std::string_view path, FileSystem* fs);  // path must stay valid
+VNode VfsLookup(std::string_view path);
+VNode VfsCreate(std::string_view path, FileType type);
+bool VfsUnlink(std::string_view path);
+bool VfsRename(std::string_view from, std::string_view to);  // both must be on the same filesystem
+void SyncAll();
+void UnmountAll();  // syncs the filesystems first
+
+// Copies the zero terminated path at user_path into path, which has room for kMaxPathLength bytes. Returns the
+// length, or -1 if the path isn't in the memory of the process, is empty, too long or isn't valid UTF-8. All system
+// calls taking a path go through this.
+int CopyPathFromUser(uintptr_t user_path, char* path);
+
+#endif //OS_VFS_H
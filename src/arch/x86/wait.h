@@ -0,0 +1,17 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#ifndef OS_WAIT_H
+#define OS_WAIT_H
+
+struct Thread;
+
+// The threads blocked on a kernel object, see BlockOn. They are woken in the order in which they blocked. A queue
+// may be woken from interrupt handlers.
+struct WaitQueue {
+    Thread* head = nullptr;
+    Thread* tail = nullptr;
+};
+
+#endif //OS_WAIT_H
@@ -26,6 +26,10 @@ inline void X86_ltr(int selector) {
     asm volatile ("ltr %w0\n\t"::"r" (selector));
 }
 
+inline void X86_lldt(int selector) {
+    asm volatile ("lldt %w0\n\t"::"r" (selector));
+}
+
 // This is the physical address, pointers in the kernel refer
 // to linear address.
 inline void X86_set_cr3(uintptr_t page) {
@@ -36,12 +40,26 @@ inline void X86_outb(uint16_t port, uint8_t data) {
     asm volatile("outb %0, %1" : : "a"(data), "d"(port));
 }
 
+inline void X86_outw(uint16_t port, uint16_t data) {
+    asm volatile("outw %0, %1" : : "a"(data), "d"(port));
+}
+
+inline void X86_outl(uint16_t port, uint32_t data) {
+    asm volatile("outl %0, %1" : : "a"(data), "d"(port));
+}
+
 inline uint8_t X86_inb(uint16_t port) {
     uint8_t data;
     asm volatile("inb %1, %0" : "=a"(data) : "d"(port));
     return data;
 }
 
+inline uint32_t X86_inl(uint16_t port) {
+    uint32_t data;
+    asm volatile("inl %1, %0" : "=a"(data) : "d"(port));
+    return data;
+}
+
 inline void X86_sti() {
     asm volatile ("sti\n\t");
 }
@@ -50,6 +68,17 @@ inline void X86_cli() {
     asm volatile ("cli\n\t");
 }
 
+// Disables interrupts, returns the flags to restore the previous state with X86_restore_flags.
+inline uintptr_t X86_save_flags_cli() {
+    uintptr_t flags;
+    asm volatile("pushf\n\tpop %0\n\tcli" : "=r"(flags) : : "memory");
+    return flags;
+}
+
+inline void X86_restore_flags(uintptr_t flags) {
+    asm volatile("push %0\n\tpopf" : : "r"(flags) : "memory", "cc");
+}
+
 inline void X86_hlt() {
     asm volatile("hlt\n\t");
 }
@@ -60,6 +89,48 @@ inline uintptr_t X86_load_cr2() {
     return address;
 }
 
+inline uint64_t X86_rdtsc() {
+    uint64_t tsc;
+    asm volatile ("rdtsc" : "=A"(tsc));
+    return tsc;
+}
+
+inline void X86_cpuid(uint32_t leaf, uint32_t regs[4]) {
+    asm volatile ("cpuid" : "=a"(regs[0]), "=b"(regs[1]), "=c"(regs[2]), "=d"(regs[3]) : "a"(leaf));
+}
+
+inline uint64_t X86_rdmsr(uint32_t msr) {
+    uint64_t value;
+    asm volatile ("rdmsr" : "=A"(value) : "c"(msr));
+    return value;
+}
+
+// The cpuid instruction exists if the ID flag (bit 21) in eflags can be toggled.
+inline bool HasCpuid() {
+    uint32_t before, after;
+    asm volatile (
+            "pushfl\n\t"
+            "pushfl\n\t"
+            "popl %0\n\t"
+            "movl %0, %1\n\t"
+            "xorl $0x200000, %1\n\t"
+            "pushl %1\n\t"
+            "popfl\n\t"
+            "pushfl\n\t"
+            "popl %1\n\t"
+            "popfl\n\t"
+            : "=&r"(before), "=&r"(after));
+    return (before ^ after) & 0x200000;
+}
+
+// The time stamp counter exists from the pentium on.
+inline bool HasTsc() {
+    if (!HasCpuid()) return false;
+    uint32_t regs[4];
+    X86_cpuid(1, regs);
+    return regs[3] & (1 << 4);
+}
+
 inline bool CheckA20() {
     volatile uint32_t tmp = 0xDEADBEEF;
     uint32_t volatile* a20_aliased = reinterpret_cast<uint32_t *>(reinterpret_cast<uintptr_t>(&tmp) ^ 0x100000);
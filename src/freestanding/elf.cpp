@@ -0,0 +1,106 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#include "elf.h"
+
+#include "utils.h"
+
+struct ElfHeader {
+    uint8_t ident[16];
+    uint16_t type;
+    uint16_t machine;
+    uint32_t version;
+    uint32_t entry;
+    uint32_t phoff;
+    uint32_t shoff;
+    uint32_t flags;
+    uint16_t ehsize;
+    uint16_t phentsize;
+    uint16_t phnum;
+    uint16_t shentsize;
+    uint16_t shnum;
+    uint16_t shstrndx;
+};
+
+struct ElfProgramHeader {
+    uint32_t type;
+    uint32_t offset;
+    uint32_t vaddr;
+    uint32_t paddr;
+    uint32_t filesz;
+    uint32_t memsz;
+    uint32_t flags;
+    uint32_t align;
+};
+
+static_assert(sizeof(ElfHeader) == 52);
+static_assert(sizeof(ElfProgramHeader) == 32);
+
+constexpr std::string_view kElfMagic = "\x7F" "ELF";
+constexpr uint8_t kElfClass32 = 1;
+constexpr uint8_t kElfDataLsb = 1;
+constexpr uint8_t kElfVersion = 1;
+constexpr uint16_t kElfTypeExec = 2;
+constexpr uint16_t kElfMachine386 = 3;
+constexpr uint32_t kPtLoad = 1;
+
+// Whether [offset, offset + size) lies within the file, computed without overflow.
+static bool InFile(std::string_view file, uint32_t offset, uint32_t size) {
+    return offset <= file.size() && size <= file.size() - offset;
+}
+
+bool IsElf(std::string_view file) {
+    return file.substr(0, kElfMagic.size()) == kElfMagic;
+}
+
+bool ParseElf(std::string_view file, ElfImage* image) {
+    ElfHeader header;
+    if (!IsElf(file) || file.size() < sizeof(header)) return false;
+    memcpy(&header, file.data(), sizeof(header));
+    if (header.ident[4] != kElfClass32 || header.ident[5] != kElfDataLsb || header.ident[6] != kElfVersion) return false;
+    if (header.type != kElfTypeExec || header.machine != kElfMachine386 || header.version != kElfVersion) return false;
+    if (header.phentsize != sizeof(ElfProgramHeader)) return false;
+    if (!InFile(file, header.phoff, uint32_t(header.phnum) * sizeof(ElfProgramHeader))) return false;
+
+    image->entry = header.entry;
+    image->osabi = header.ident[7];
+    image->num_segments = 0;
+    bool entry_found = false;
+    for (int i = 0; i < header.phnum; i++) {
+        ElfProgramHeader ph;
+        memcpy(&ph, file.data() + header.phoff + i * sizeof(ph), sizeof(ph));
+        if (ph.type != kPtLoad) continue;
+        if (image->num_segments == kMaxElfSegments) return false;
+        if (!InFile(file, ph.offset, ph.filesz) || ph.filesz > ph.memsz) return false;
+        // The segment must not wrap around the address space.
+        if (ph.memsz == 0 || ph.vaddr > UINT32_MAX - ph.memsz) return false;
+        // Segments must not overlap, the loader would silently let the later one win.
+        for (int j = 0; j < image->num_segments; j++) {
+            auto& other = image->segments[j];
+            if (ph.vaddr < other.vaddr + other.memsz && other.vaddr < ph.vaddr + ph.memsz) return false;
+        }
+        image->segments[image->num_segments++] = ElfSegment{ph.vaddr, ph.memsz, ph.offset, ph.filesz, ph.flags};
+        if ((ph.flags & ElfSegment::kExecute) && header.entry - ph.vaddr < ph.memsz) entry_found = true;
+    }
+    return entry_found;
+}
+
+static bool IsWritablePage(const ElfImage& image, uint32_t page, uint32_t page_size) {
+    for (int i = 0; i < image.num_segments; i++) {
+        auto& segment = image.segments[i];
+        if (segment.memsz == 0 || !(segment.flags & ElfSegment::kWrite)) continue;
+        if (segment.vaddr / page_size <= page && page <= (segment.vaddr + segment.memsz - 1) / page_size) return true;
+    }
+    return false;
+}
+
+void ForEachReadOnlyPage(const ElfImage& image, uint32_t page_size, void (*protect)(uintptr_t page)) {
+    for (int i = 0; i < image.num_segments; i++) {
+        auto& segment = image.segments[i];
+        if (segment.memsz == 0) continue;
+        for (auto page = segment.vaddr / page_size; page <= (segment.vaddr + segment.memsz - 1) / page_size; page++) {
+            if (!IsWritablePage(image, page, page_size)) protect(page);
+        }
+    }
+}
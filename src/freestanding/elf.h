@@ -0,0 +1,48 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#ifndef OS_ELF_H
+#define OS_ELF_H
+
+#include <cstdint>
+#include <string_view>
+
+// Parsing of 32 bit x86 ELF executables. The file comes from user space, so nothing in it is trusted: every offset
+// and size is checked against the file and for overflow, and headers are copied out instead of referenced in place
+// as the file can be arbitrarily aligned.
+constexpr int kMaxElfSegments = 8;
+constexpr uint8_t kElfOsAbiLinux = 3;
+
+struct ElfSegment {
+    uint32_t vaddr;
+    uint32_t memsz;
+    uint32_t offset;  // in the file
+    uint32_t filesz;  // at most memsz, the rest is zero filled
+    uint32_t flags;
+
+    enum {
+        kExecute = 1,
+        kWrite = 2,
+        kRead = 4,
+    };
+};
+
+struct ElfImage {
+    uint32_t entry;
+    uint8_t osabi;
+    int num_segments;
+    ElfSegment segments[kMaxElfSegments];  // the loadable segments
+};
+
+bool IsElf(std::string_view file);
+
+// Returns false if the file is not a valid executable.
+bool ParseElf(std::string_view file, ElfImage* image);
+
+// Calls protect with the page number of every page of the image that no writable segment touches. Segments need not
+// be page aligned so they can share pages, a page gets the union of the permissions of the segments on it. A page
+// shared by several segments can be reported more than once.
+void ForEachReadOnlyPage(const ElfImage& image, uint32_t page_size, void (*protect)(uintptr_t page));
+
+#endif //OS_ELF_H
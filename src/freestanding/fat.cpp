@@ -0,0 +1,90 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#include "fat.h"
+
+#include "utils.h"
+
+constexpr uint32_t kSectorSize = 512;
+constexpr uint32_t kNumFats = 2;
+constexpr uint8_t kMediaFixedDisk = 0xF8;
+
+// Sectors of one FAT with an entry for every cluster, the first two entries are reserved.
+static uint32_t FatSectors(uint64_t clusters) {
+    uint64_t entries = clusters + 2;
+    uint64_t bytes = clusters <= kFat12MaxClusters ? (entries * 3 + 1) / 2 : entries * 2;
+    return (bytes + kSectorSize - 1) / kSectorSize;
+}
+
+static void CopyLabel(char* out, std::string_view label) {
+    for (std::size_t i = 0; i < 11; i++) {
+        char c = i < label.size() ? label[i] : ' ';
+        out[i] = c >= 'a' && c <= 'z' ? c - 'a' + 'A' : c;
+    }
+}
+
+bool FormatFat(uint64_t size, std::string_view label, uint32_t volume_id,
+               bool (*write)(uint64_t offset, const void* data, std::size_t len)) {
+    if (size < kFatMinImageSize || label.size() > 11) return false;
+    uint64_t total = size / kSectorSize;
+    // Floppy sized images get the root directory of a floppy.
+    uint32_t root_entries = total < 4096 ? 224 : 512;
+    uint32_t reserved = 1;
+    uint32_t root_sectors = root_entries * sizeof(FatDirEntry) / kSectorSize;
+    for (uint32_t per_cluster = 1; per_cluster <= 64; per_cluster *= 2) {
+        // The FATs take space from the clusters they describe, grow them until they cover what remains.
+        uint32_t fat_sectors = 1;
+        uint64_t clusters;
+        while (true) {
+            uint64_t meta = reserved + kNumFats * fat_sectors + root_sectors;
+            if (meta >= total) return false;
+            clusters = (total - meta) / per_cluster;
+            auto needed = FatSectors(clusters);
+            if (needed <= fat_sectors) break;
+            fat_sectors = needed;
+        }
+        if (clusters > kFat16MaxClusters) continue;
+        if (clusters == 0) return false;
+        bool fat12 = clusters <= kFat12MaxClusters;
+
+        FatBootSector boot = {};
+        boot.jump[0] = 0xEB;  // jmp over the boot sector fields, nop
+        boot.jump[1] = 0x3C;
+        boot.jump[2] = 0x90;
+        memcpy(boot.oem, "RETROOS ", sizeof(boot.oem));
+        boot.bytes_per_sector = kSectorSize;
+        boot.sectors_per_cluster = per_cluster;
+        boot.reserved_sectors = reserved;
+        boot.num_fats = kNumFats;
+        boot.root_entries = root_entries;
+        boot.total_sectors16 = total < 0x10000 ? total : 0;
+        boot.total_sectors32 = total < 0x10000 ? 0 : total;
+        boot.media = kMediaFixedDisk;
+        boot.fat_size16 = fat_sectors;
+        boot.sectors_per_track = 32;
+        boot.heads = 64;
+        boot.signature = 0xAA55;
+        FatExtendedBootSector extended = {0x80, 0, 0x29, volume_id, {}, {}};
+        CopyLabel(extended.label, label.empty() ? "NO NAME" : label);
+        memcpy(extended.type, fat12 ? "FAT12   " : "FAT16   ", sizeof(extended.type));
+        uint8_t sector[kSectorSize];
+        memcpy(sector, &boot, sizeof(boot));
+        memcpy(sector + kFatExtendedBootOffset, &extended, sizeof(extended));
+        if (!write(0, sector, sizeof(sector))) return false;
+
+        // The first entry holds the media byte, the second is an end of chain marker.
+        static const uint8_t kFatStart[] = {kMediaFixedDisk, 0xFF, 0xFF, 0xFF};
+        for (uint32_t i = 0; i < kNumFats; i++) {
+            uint64_t offset = uint64_t(reserved + i * fat_sectors) * kSectorSize;
+            if (!write(offset, kFatStart, fat12 ? 3 : 4)) return false;
+        }
+
+        if (label.empty()) return true;
+        FatDirEntry volume = {};
+        CopyLabel(volume.name, label);
+        volume.attributes = kAttrVolume;
+        return write(uint64_t(reserved + kNumFats * fat_sectors) * kSectorSize, &volume, sizeof(volume));
+    }
+    return false;
+}
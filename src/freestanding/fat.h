@@ -0,0 +1,98 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#ifndef OS_FAT_H
+#define OS_FAT_H
+
+#include <cstddef>
+#include <cstdint>
+#include <string_view>
+
+// The on-disk structures of the FAT filesystem, read by the kernel (see fatfs.h) and written by mkfs.fat. The type
+// of a FAT is decided by its number of clusters only: FAT12 below 4085, FAT16 below 65525 and FAT32 above.
+constexpr uint32_t kFat12MaxClusters = 4084;
+constexpr uint32_t kFat16MaxClusters = 65524;
+
+struct [[gnu::packed]] FatBootSector {
+    uint8_t jump[3];
+    char oem[8];
+    uint16_t bytes_per_sector;
+    uint8_t sectors_per_cluster;
+    uint16_t reserved_sectors;
+    uint8_t num_fats;
+    uint16_t root_entries;  // 0 for FAT32, its root directory is a cluster chain
+    uint16_t total_sectors16;
+    uint8_t media;
+    uint16_t fat_size16;  // 0 for FAT32
+    uint16_t sectors_per_track;
+    uint16_t heads;
+    uint32_t hidden_sectors;
+    uint32_t total_sectors32;
+    // FAT32 only from here.
+    uint32_t fat_size32;
+    uint16_t flags;
+    uint16_t version;
+    uint32_t root_cluster;
+    uint8_t unused[462];
+    uint16_t signature;
+};
+
+// Follows the common part of the boot sector on FAT12 and FAT16, where FAT32 has its own fields.
+struct [[gnu::packed]] FatExtendedBootSector {
+    uint8_t drive;
+    uint8_t reserved;
+    uint8_t signature;  // 0x29
+    uint32_t volume_id;
+    char label[11];
+    char type[8];
+};
+
+struct [[gnu::packed]] FatDirEntry {
+    char name[11];  // 8.3 padded with spaces, without the dot
+    uint8_t attributes;
+    uint8_t case_flags;  // the base and extension of the 8.3 name are shown in lower case
+    uint8_t create_time_tenths;
+    uint16_t create_time, create_date, access_date;
+    uint16_t cluster_high;
+    uint16_t write_time, write_date;
+    uint16_t cluster_low;
+    uint32_t size;
+};
+
+// A long name is stored in entries preceding the 8.3 entry, in reverse order, 13 UTF-16 characters each.
+struct [[gnu::packed]] FatLfnEntry {
+    uint8_t order;  // 1 for the first part, kLfnLast is set on the entry of the last part which comes first
+    uint16_t name1[5];
+    uint8_t attributes;  // kAttrLfn
+    uint8_t type;
+    uint8_t checksum;  // of the 8.3 name
+    uint16_t name2[6];
+    uint16_t cluster;
+    uint16_t name3[2];
+};
+
+static_assert(sizeof(FatBootSector) == 512 && sizeof(FatExtendedBootSector) == 26);
+static_assert(sizeof(FatDirEntry) == 32 && sizeof(FatLfnEntry) == 32);
+
+constexpr std::size_t kFatExtendedBootOffset = 36;
+
+constexpr uint8_t kAttrReadOnly = 0x01;
+constexpr uint8_t kAttrVolume = 0x08;
+constexpr uint8_t kAttrDirectory = 0x10;
+constexpr uint8_t kAttrLfn = 0x0F;
+constexpr uint8_t kLfnLast = 0x40;
+constexpr uint8_t kCaseLowerBase = 0x08;
+constexpr uint8_t kCaseLowerExtension = 0x10;
+constexpr uint8_t kDeleted = 0xE5;
+
+// Writes an empty FAT12 or FAT16 filesystem into an image of size bytes, whichever the number of clusters gives. The
+// clusters are the smallest that keep it below the FAT32 limit. Only the boot sector, the first entries of the FATs
+// and the volume label in the root directory are written through write, the rest of the image must be zero already.
+// The label is upper cased, an empty label writes none. Returns false if the size is too small or too large (above
+// 2gb), the label too long or a write fails.
+constexpr uint64_t kFatMinImageSize = 64 * 512;
+bool FormatFat(uint64_t size, std::string_view label, uint32_t volume_id,
+               bool (*write)(uint64_t offset, const void* data, std::size_t len));
+
+#endif //OS_FAT_H
@@ -0,0 +1,43 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#include "mbr.h"
+
+#include "utils.h"
+
+struct MbrEntry {
+    uint8_t status;
+    uint8_t chs_first[3];
+    uint8_t type;
+    uint8_t chs_last[3];
+    uint32_t first_lba;
+    uint32_t num_sectors;
+};
+
+static_assert(sizeof(MbrEntry) == 16);
+
+constexpr std::size_t kSectorSize = 512;
+constexpr std::size_t kTableOffset = 0x1BE;
+constexpr uint8_t kStatusBootable = 0x80;
+
+bool ParseMbr(std::string_view sector, Partition partitions[kMbrPartitions]) {
+    if (sector.size() < kSectorSize || uint8_t(sector[510]) != 0x55 || uint8_t(sector[511]) != 0xAA) return false;
+    for (int i = 0; i < kMbrPartitions; i++) {
+        // The table is at an offset that isn't 4 byte aligned.
+        MbrEntry entry;
+        memcpy(&entry, sector.data() + kTableOffset + i * sizeof(entry), sizeof(entry));
+        bool valid = entry.type != 0 && entry.first_lba != 0 && entry.num_sectors != 0 &&
+                     entry.num_sectors <= UINT32_MAX - entry.first_lba;
+        partitions[i] = valid ? Partition{entry.type, (entry.status & kStatusBootable) != 0, entry.first_lba,
+                                          entry.num_sectors} : Partition{};
+    }
+    return true;
+}
+
+const Partition* FindPartition(const Partition partitions[kMbrPartitions], uint8_t type) {
+    for (int i = 0; i < kMbrPartitions; i++) {
+        if (partitions[i].type == type) return &partitions[i];
+    }
+    return nullptr;
+}
@@ -0,0 +1,31 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#ifndef OS_MBR_H
+#define OS_MBR_H
+
+#include <cstdint>
+#include <string_view>
+
+// The partition table of a master boot record: four primary partitions at offset 0x1BE of the first sector, followed
+// by the 0xAA55 signature. Only LBA addressing is used, the CHS fields are ignored, and extended partitions aren't
+// followed. The boot archive is the partition of type kPartitionTar.
+constexpr int kMbrPartitions = 4;
+constexpr uint8_t kPartitionTar = 0xDA;  // "non-filesystem data"
+
+struct Partition {
+    uint8_t type;  // 0 for an unused entry
+    bool bootable;
+    uint32_t first_lba;
+    uint32_t num_sectors;
+};
+
+// Fills partitions from the first sector of a disk. Returns false if it has no MBR signature. Entries that are empty
+// or lie partly outside the 32 bit LBA range are returned as unused.
+bool ParseMbr(std::string_view sector, Partition partitions[kMbrPartitions]);
+
+// The first partition of the type, nullptr if there is none.
+const Partition* FindPartition(const Partition partitions[kMbrPartitions], uint8_t type);
+
+#endif //OS_MBR_H
@@ -330,12 +330,19 @@ USTARHeader Convert(const USTARRawHeader& h) {
     if (h.magic[0] == 'u' && h.magic[1] == 's' && h.magic[2] == 't' && h.magic[3] == 'a' && h.magic[4] == 'r'  && h.magic[5] == '\0') {
         extended = true;
     }
-    auto p = extended ? strncpy(result.filename_, h.prefix, sizeof(h.prefix)) : result.filename_;
-    strncpy(p, h.filename, sizeof(h.filename));
+    // A path that doesn't fit the name field is split at a '/' over the prefix and the name.
+    std::size_t length = 0;
+    if (extended && h.prefix[0] != '\0') {
+        length = strnlen(h.prefix, sizeof(h.prefix));
+        memcpy(result.filename_, h.prefix, length);
+        result.filename_[length++] = '/';
+    }
+    auto name_length = strnlen(h.filename, sizeof(h.filename));
+    memcpy(result.filename_ + length, h.filename, name_length);
 
     strncpy(result.link_target_, h.link_target, sizeof(h.link_target));
 
-    result.filename = std::string_view(result.filename_, strnlen(result.filename_, sizeof(result.filename_)));
+    result.filename = std::string_view(result.filename_, length + name_length);
     result.link_target = std::string_view(result.link_target_, strnlen(result.link_target_, sizeof(result.link_target_)));
 
     result.filemode = ReadOctal(std::string_view(h.filemode, sizeof(h.filemode)));
@@ -351,15 +358,54 @@ USTARHeader Convert(const USTARRawHeader& h) {
 
 constexpr int kUSTARBlockSize = 512;
 
-std::size_t USTARReader::FindFile(std::string_view filename) {
-    USTARRawHeader raw_header;
-    while (ReadBlocks(1, &raw_header)) {
-        USTARHeader header = Convert(raw_header);
-        if (header.filename == filename) {
-            return header.filesize;
+// Reads the header of the next entry, leaving the contents to be read. GNU tar puts a record of type 'L' holding the
+// name before an entry whose name doesn't fit the header, it replaces the truncated name. A name too long for the
+// entry is left empty. Records of type 'K', the same for link targets, are skipped.
+bool USTARReader::ReadEntry(USTAREntry* entry) {
+    std::size_t long_name = SIZE_MAX;  // length of the name from an 'L' record in entry->filename_
+    while (true) {
+        USTARRawHeader raw_header;
+        if (!ReadBlocks(1, &raw_header) || raw_header.filename[0] == '\0') {
+            return false;
         }
+        USTARHeader header = Convert(raw_header);
         int nblocks = (header.filesize + kUSTARBlockSize - 1) / kUSTARBlockSize;
-        SkipBlocks(nblocks);
+        if (header.typeflag == 'K') {
+            SkipBlocks(nblocks);
+            continue;
+        }
+        if (header.typeflag == 'L') {
+            if (header.filesize > sizeof(entry->filename_)) {
+                long_name = 0;
+                SkipBlocks(nblocks);
+            } else if (!ReadFile(entry->filename_, header.filesize)) {
+                return false;
+            } else {
+                long_name = strnlen(entry->filename_, header.filesize);
+            }
+            continue;
+        }
+        if (long_name == SIZE_MAX) {
+            long_name = header.filename.size();
+            memcpy(entry->filename_, header.filename_, long_name);
+        }
+        entry->filename = std::string_view(entry->filename_, long_name);
+        entry->size = header.filesize;
+        entry->offset = Offset();
+        entry->mode = header.filemode;
+        entry->mtime = header.mtime;
+        entry->typeflag = header.typeflag;
+        return true;
+    }
+}
+
+std::size_t USTARReader::FindFile(std::string_view filename) {
+    USTAREntry entry;
+    while (ReadEntry(&entry)) {
+        if (entry.filename == filename) {
+            return entry.size;
+        }
+        SkipBlocks((entry.size + kUSTARBlockSize - 1) / kUSTARBlockSize);
     }
     return SIZE_MAX;
 }
@@ -376,6 +422,18 @@ std::size_t USTARReader::ReadHeader(void* buf) {
     return header.filesize;
 }
 
+bool USTARReader::NextEntry(USTAREntry* entry) {
+    if (!ReadEntry(entry)) {
+        return false;
+    }
+    SkipBlocks((entry->size + kUSTARBlockSize - 1) / kUSTARBlockSize);
+    return true;
+}
+
+std::size_t USTARReader::Offset() const {
+    return block_ * kUSTARBlockSize;
+}
+
 bool USTARReader::ReadFile(void* buf, std::size_t bufsize) {
     char tmp_buf[kUSTARBlockSize];
 
@@ -228,17 +228,32 @@ inline void AssertImpl(bool cond, OutputStream& out, std::string_view cond_str,
     if (kDebug && !cond) panic_assert(out, cond_str, file, line);
 }
 
+struct USTAREntry {
+    std::string_view filename;  // the full path, from the prefix and name fields or a GNU long name
+    std::size_t size;
+    std::size_t offset;  // of the contents in the archive
+    uint32_t mode;
+    uint64_t mtime;  // seconds since 1970
+    char typeflag;  // '0' or '\0' for a regular file, '5' for a directory
+
+    char filename_[256];
+};
+
 class USTARReader {
 public:
     USTARReader() = default;
 
     std::size_t FindFile(std::string_view filename);
     std::size_t ReadHeader(void* buf);
+    bool NextEntry(USTAREntry* entry);  // reads the header and skips the contents, false at the end of the archive
     bool ReadFile(void* buf, std::size_t size);
+    std::size_t Offset() const;  // byte offset in the archive of the next read
 
 private:
     std::size_t block_ = 0;
 
+    bool ReadEntry(USTAREntry* entry);
+
     virtual bool ReadBlocks(std::size_t block, int n, void *buf) = 0;
     bool ReadBlocks(int n, void* buf) {
         if (!ReadBlocks(block_, n, buf)) return false;
@@ -276,4 +291,9 @@ T min(const T& a, const T& b) {
     return a < b ? a : b;
 }
 
+template <typename T>
+T max(const T& a, const T& b) {
+    return a < b ? b : a;
+}
+
 #endif //OS_UTILS_H
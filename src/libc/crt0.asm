@@ -12,18 +12,23 @@ section .text.startup
 [bits 32]
 global _start
 _start:
+    ; System V layout, esp points to argc followed by the argv pointers, a null and the envp pointers
+    mov eax, [esp]
+    lea esi, [esp + 4]
+    lea edi, [esi + eax * 4 + 4]
     mov [_argenv], edi
+    push edi  ; envp
     push esi  ; argv
     push eax  ; argc
     extern main
     call main
-    mov ebx, eax
-    mov eax, 1  ; sys_exit
+    mov edx, eax
+    xor eax, eax  ; sys_exit
     int 0x80
 global terminate
 terminate:
-    mov eax, 1  ; sys_exit
-    mov ebx, [esp + 4]
+    xor eax, eax  ; sys_exit
+    mov edx, [esp + 4]
     int 0x80
 section .data
     _argenv dd 0
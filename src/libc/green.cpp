@@ -0,0 +1,103 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#include "green.h"
+
+#include "libc.h"
+
+struct GreenThread {
+    uintptr_t esp;  // saved by GreenSwitch
+    void (*fn)(void*);
+    void* arg;
+    void* stack;
+    bool done;
+    GreenThread* next;  // in the circular run list
+};
+
+static GreenThread* current;  // null while the scheduler runs
+static GreenThread* last;  // of the run list, new threads go behind it
+static uintptr_t scheduler_esp;
+
+// Pushes the callee saved registers on the current stack, stores the stack pointer in *from and continues on the
+// stack to, popping the registers saved there.
+extern "C" void GreenSwitch(uintptr_t* from, uintptr_t to);
+
+asm(R"(
+    .text
+    .globl GreenSwitch
+GreenSwitch:
+    movl 4(%esp), %eax
+    movl 8(%esp), %edx
+    pushl %ebp
+    pushl %ebx
+    pushl %esi
+    pushl %edi
+    movl %esp, (%eax)
+    movl %edx, %esp
+    popl %edi
+    popl %esi
+    popl %ebx
+    popl %ebp
+    ret
+)");
+
+// The first GreenSwitch to a thread returns here.
+[[noreturn]] static void GreenStart() {
+    current->fn(current->arg);
+    current->done = true;
+    GreenSwitch(&current->esp, scheduler_esp);
+    __builtin_unreachable();
+}
+
+bool GreenSpawn(void (*fn)(void*), void* arg, std::size_t stack_size) {
+    auto stack_base = Alloc(stack_size);
+    auto thread = stack_base ? new GreenThread{0, fn, arg, stack_base, false, nullptr} : nullptr;
+    if (!thread) {
+        Free(stack_base);
+        return false;
+    }
+    // GreenSwitch pops ebp, ebx, esi, edi, then returns to GreenStart as if it were called with an aligned stack.
+    auto top = (reinterpret_cast<uintptr_t>(stack_base) + stack_size) & -16;
+    auto stack = reinterpret_cast<uintptr_t*>(top);
+    *--stack = 0;  // return address of GreenStart
+    *--stack = reinterpret_cast<uintptr_t>(GreenStart);
+    for (int i = 0; i < 4; i++) *--stack = 0;
+    thread->esp = reinterpret_cast<uintptr_t>(stack);
+    if (last) {
+        thread->next = last->next;
+        last->next = thread;
+    } else {
+        thread->next = thread;
+    }
+    last = thread;
+    return true;
+}
+
+void GreenYield() {
+    if (current) GreenSwitch(&current->esp, scheduler_esp);
+}
+
+void GreenRun() {
+    while (last) {
+        auto prev = last;
+        current = last->next;
+        GreenSwitch(&scheduler_esp, current->esp);
+        auto thread = current;
+        current = nullptr;
+        if (!thread->done) {
+            last = thread;
+            continue;
+        }
+        // Unlink it, the list may have grown behind it while it ran.
+        while (prev->next != thread) prev = prev->next;
+        if (prev == thread) {
+            last = nullptr;
+        } else {
+            prev->next = thread->next;
+            if (last == thread) last = prev;
+        }
+        Free(thread->stack);
+        delete thread;
+    }
+}
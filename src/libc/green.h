@@ -0,0 +1,20 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#ifndef OS_GREEN_H
+#define OS_GREEN_H
+
+#include <cstddef>
+
+// Cooperative green threads, multiplexed on the calling thread. A green thread runs until it yields or returns,
+// GreenRun runs them round robin until all have returned. They can spawn more green threads.
+//
+// TODO: a green thread that reads or waits blocks all of them, the kernel has neither nonblocking descriptors nor
+// poll. With those a green thread waiting for a descriptor would be parked until poll reports it ready, and
+// GreenRun would sleep in poll when all green threads wait.
+bool GreenSpawn(void (*fn)(void*), void* arg, std::size_t stack_size = 16384);  // false when out of memory
+void GreenYield();
+void GreenRun();
+
+#endif //OS_GREEN_H
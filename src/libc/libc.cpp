@@ -12,3 +12,49 @@ uintptr_t SysCall(uintptr_t num, uintptr_t arg0, uintptr_t arg1, uintptr_t arg2,
     return num;
 }
 
+
+// Blocks are preceded by a header, free blocks are kept in a first fit list. Blocks are split but never merged, which
+// is fine for the small programs we run.
+struct alignas(8) BlockHeader {
+    std::size_t size;  // excluding the header
+    BlockHeader* next;  // in the free list
+};
+
+static BlockHeader* free_list;
+
+void* Alloc(std::size_t size) {
+    size = (size + 7) & -8;
+    for (auto p = &free_list; *p; p = &(*p)->next) {
+        auto block = *p;
+        if (block->size < size) continue;
+        if (block->size >= size + sizeof(BlockHeader) + 8) {
+            // Split off the tail as a new free block.
+            auto rest = reinterpret_cast<BlockHeader*>(reinterpret_cast<char*>(block + 1) + size);
+            rest->size = block->size - size - sizeof(BlockHeader);
+            rest->next = block->next;
+            block->size = size;
+            *p = rest;
+        } else {
+            *p = block->next;
+        }
+        return block + 1;
+    }
+    auto block = static_cast<BlockHeader*>(Sbrk(sizeof(BlockHeader) + size));
+    if (block == (void*) -1) return nullptr;
+    block->size = size;
+    return block + 1;
+}
+
+void Free(void* ptr) {
+    if (!ptr) return;
+    auto block = static_cast<BlockHeader*>(ptr) - 1;
+    block->next = free_list;
+    free_list = block;
+}
+
+void* operator new(std::size_t size) { return Alloc(size); }
+void* operator new[](std::size_t size) { return Alloc(size); }
+void operator delete(void* ptr) noexcept { Free(ptr); }
+void operator delete[](void* ptr) noexcept { Free(ptr); }
+void operator delete(void* ptr, std::size_t) noexcept { Free(ptr); }
+void operator delete[](void* ptr, std::size_t) noexcept { Free(ptr); }
@@ -12,37 +12,106 @@
 
 uintptr_t SysCall(uintptr_t num, uintptr_t arg0, uintptr_t arg1, uintptr_t arg2, uintptr_t arg3, uintptr_t arg4);
 
+// System calls fail with -1, or with -kEFAULT when a pointer argument isn't memory of the process.
+constexpr int kEFAULT = 14;
+
 [[noreturn]] inline void Exit(int code) {
     SysCall(0, code, 0, 0, 0, 0);
     __builtin_unreachable();
 }
 
+// Let other threads run, the caller is put behind all other ready threads for a short while.
 inline void Yield() {
     SysCall(1, 0, 0, 0, 0, 0);
 }
 
-inline void* Alloc(std::size_t size) {
-    return (void*) SysCall(2, size, 0, 0, 0, 0);
+// Heap allocation on top of the program break, also used by new and delete. Returns nullptr when out of memory.
+void* Alloc(std::size_t size);
+void Free(void* ptr);
+
+// Set the program break, the end of the heap, nullptr just returns it. Returns the break or -1 on failure.
+inline void* Brk(void* end) {
+    return (void*) SysCall(41, (uintptr_t) end, 0, 0, 0, 0);
+}
+
+// Grow (or shrink) the heap by increment bytes, returns the old break or -1 on failure.
+inline void* Sbrk(intptr_t increment) {
+    auto old = (uintptr_t) Brk(nullptr);
+    if (increment != 0 && Brk((void*) (old + increment)) == (void*) -1) return (void*) -1;
+    return (void*) old;
+}
+
+// The child of a privileged process forked with kForkPrivileged is privileged too, for starting drivers. Other
+// children never are.
+constexpr int kForkPrivileged = 1;
+
+inline int Fork(int flags = 0) {
+    return SysCall(4, flags, 0, 0, 0, 0);
+}
+
+// Wait for child tid (-1 for any child) to exit, storing its exit code in status if not null. Returns the tid of the
+// child or -1 if there is no such child.
+inline int Wait(int tid, int* status) {
+    return SysCall(39, tid, (uintptr_t) status, 0, 0, 0);
+}
+
+inline int GetPid() {
+    return SysCall(48, 0, 0, 0, 0, 0);
+}
+
+inline int GetPpid() {
+    return SysCall(49, 0, 0, 0, 0, 0);
+}
+
+inline int GetTid() {
+    return SysCall(50, 0, 0, 0, 0, 0);
 }
 
-inline void Free(void* ptr) {
-    SysCall(3, (uintptr_t) ptr, 0, 0, 0, 0);
+// Set the priority of thread tid (the caller or one of its children), higher runs first. Only privileged processes
+// may raise a priority. Returns the old priority or -1.
+inline int SetPriority(int tid, int priority) {
+    return SysCall(52, tid, priority, 0, 0, 0);
 }
 
-inline int Fork() {
-    return SysCall(4, 0, 0, 0, 0, 0);
+inline int GetPriority(int tid) {
+    return SysCall(53, tid, 0, 0, 0, 0);
+}
+
+// Lower the priority of the caller by increment, like the nice value in POSIX. Returns the new priority or -1.
+inline int Nice(int increment) {
+    int priority = GetPriority(GetTid()) - increment;
+    if (SetPriority(GetTid(), priority) == -1) return -1;
+    return priority;
 }
 
 inline void Exec(const char* path, char* const argv[], char* const envp[]) {
     SysCall(5, (uintptr_t) path, (uintptr_t) argv, (uintptr_t) envp, 0, 0);
 }
 
+// Write an image of the calling process to the file of fd. Returns 0, 1 when the process is restored from the image
+// and -1 on failure.
+inline int Checkpoint(int fd) {
+    return SysCall(59, fd, 0, 0, 0, 0);
+}
+
+// Replace the calling process by the image in the file of fd, it keeps the descriptors of the caller. Only returns
+// on failure.
+inline void Restore(int fd) {
+    SysCall(60, fd, 0, 0, 0, 0);
+}
+
+// The open flags, matches file.h.
+constexpr int kOpenReadOnly = 0;
+constexpr int kOpenWriteOnly = 1;
+constexpr int kOpenReadWrite = 2;
+constexpr int kOpenCreate = 0x40;
+
 inline int Open(const char* path, int flags, int mode) {
     return SysCall(6, (uintptr_t) path, flags, mode, 0, 0);
 }
 
-inline void Close(int fd) {
-    SysCall(7, fd, 0, 0, 0, 0);
+inline int Close(int fd) {
+    return SysCall(7, fd, 0, 0, 0, 0);
 }
 
 inline std::size_t Read(int fd, void* buf, std::size_t count) {
@@ -53,10 +122,394 @@ inline int Write(int fd, const void* buf, std::size_t count) {
     return SysCall(9, fd, (uintptr_t) buf, count, 0, 0);
 }
 
+// Where the offset of Seek counts from.
+constexpr int kSeekSet = 0;
+constexpr int kSeekCur = 1;
+constexpr int kSeekEnd = 2;
+
 inline int Seek(int fd, int offset, int whence) {
     return SysCall(10, fd, offset, whence, 0, 0);
 }
 
+// Read or write at offset in a file, without using or moving the offset of fd. Return the number of bytes
+// transferred or -1.
+inline int Pread(int fd, void* buf, std::size_t count, uint64_t offset) {
+    return SysCall(69, fd, (uintptr_t) buf, count, offset, offset >> 32);
+}
+
+inline int Pwrite(int fd, const void* buf, std::size_t count, uint64_t offset) {
+    return SysCall(70, fd, (uintptr_t) buf, count, offset, offset >> 32);
+}
+
+// Create a named pipe at path. Opening it for reading or writing blocks until the other end is opened too.
+inline int Mkfifo(const char* path) {
+    return SysCall(54, (uintptr_t) path, 0, 0, 0, 0);
+}
+
+// Unix domain stream sockets. Socket returns an unconnected socket, which Bind names by a path that must not exist
+// yet. After Listen, Accept blocks until a connection is made to it and returns the connected socket. Connect
+// connects to the listening socket at path, it fails if its queue of connections not yet accepted is full. The name
+// goes away when the bound socket is closed. Return -1 on failure.
+inline int Socket() {
+    return SysCall(71, 0, 0, 0, 0, 0);
+}
+
+inline int Bind(int fd, const char* path) {
+    return SysCall(72, fd, (uintptr_t) path, 0, 0, 0);
+}
+
+inline int Listen(int fd) {
+    return SysCall(73, fd, 0, 0, 0, 0);
+}
+
+inline int Accept(int fd) {
+    return SysCall(74, fd, 0, 0, 0, 0);
+}
+
+inline int Connect(int fd, const char* path) {
+    return SysCall(75, fd, (uintptr_t) path, 0, 0, 0);
+}
+
+// Create a directory at path, in an existing directory. Only /tmp supports it. Returns 0 or -1.
+inline int Mkdir(const char* path) {
+    return SysCall(61, (uintptr_t) path, 0, 0, 0, 0);
+}
+
+// Remove a file, named pipe or empty directory that isn't open. Returns 0 or -1.
+inline int Unlink(const char* path) {
+    return SysCall(62, (uintptr_t) path, 0, 0, 0, 0);
+}
+
+// Move a file or directory within a filesystem, replacing a file at to. Returns 0 or -1.
+inline int Rename(const char* from, const char* to) {
+    return SysCall(63, (uintptr_t) from, (uintptr_t) to, 0, 0, 0);
+}
+
+// Directory entries and file metadata, matches vfs.h.
+enum FileType : uint32_t {
+    kRegularFile = 1,
+    kDirectory = 2,
+    kCharDevice = 3,
+};
+
+struct DirEntry {
+    char name[100];  // zero terminated
+    FileType type;
+};
+
+struct FileStat {
+    uint64_t size;
+    FileType type;  // pipes and terminals are character devices
+    uint32_t mode;  // permission bits, like 0644
+    uint64_t mtime;  // seconds since 1970, 0 if unknown
+};
+
+// Read the next entry of the directory opened as fd into entry. Returns 1, 0 after the last entry or -1.
+inline int ReadDir(int fd, DirEntry* entry) {
+    return SysCall(64, fd, (uintptr_t) entry, 0, 0, 0);
+}
+
+// Fill stat with the metadata of the file at path. Returns 0 or -1.
+inline int Stat(const char* path, FileStat* stat) {
+    return SysCall(65, (uintptr_t) path, (uintptr_t) stat, 0, 0, 0);
+}
+
+// Fill stat with the metadata of the file open as fd. Returns 0 or -1.
+inline int Fstat(int fd, FileStat* stat) {
+    return SysCall(66, fd, (uintptr_t) stat, 0, 0, 0);
+}
+
+// Create a pseudo-terminal, fds receives the descriptors of the master and the slave. Returns 0 or -1.
+inline int OpenPty(int fds[2]) {
+    return SysCall(55, (uintptr_t) fds, 0, 0, 0, 0);
+}
+
+// File change notification, reading the descriptor returned by Watch gives whole WatchEvents, matches file.h.
+enum WatchEventType : uint32_t {
+    kWatchCreate = 1,  // of a file in the watched directory
+    kWatchModify = 2,  // of the watched file
+    kWatchDelete = 4,  // of a file in the watched directory or the watched file
+    kWatchOverflow = 8,  // events were lost, always reported
+};
+
+struct WatchEvent {
+    uint32_t type;
+    char name[100];  // relative to the watched directory, empty for the watched file
+};
+
+// Watch path for the events in mask. Returns the descriptor to read the events from or -1.
+inline int Watch(const char* path, uint32_t mask) {
+    return SysCall(58, (uintptr_t) path, mask, 0, 0, 0);
+}
+
+// Returns the lowest free descriptor referring to the same open file as fd, they share the offset.
+inline int Dup(int fd) {
+    return SysCall(37, fd, 0, 0, 0, 0);
+}
+
+// Make new_fd refer to the open file of fd, closing what new_fd referred to before.
+inline int Dup2(int fd, int new_fd) {
+    return SysCall(38, fd, new_fd, 0, 0, 0);
+}
+
+// Create a pipe, fds[0] is the read end and fds[1] the write end. Reads block while the pipe is empty and return 0
+// once all write ends are closed, writes block while it's full and fail once all read ends are closed.
+inline int Pipe(int fds[2]) {
+    return SysCall(40, (uintptr_t) fds, 0, 0, 0, 0);
+}
+
+// Exec an unmodified static Linux binary, the process uses the Linux i386 system call ABI from then on. ELF binaries
+// marked as Linux can be run with Exec as well. Only returns on failure.
+inline int ExecLinux(const char* path) {
+    return SysCall(42, (uintptr_t) path, 0, 0, 0, 0);
+}
+
+// Read the kernel tunable with the given name, like "sched/time_slice", the same as reading /proc/sys/<name>.
+// Returns the value or -1.
+inline int GetSysctl(const char* name) {
+    return SysCall(43, (uintptr_t) name, 0, 0, 0, 0);
+}
+
+// Change a kernel tunable, returns the old value or -1.
+inline int SetSysctl(const char* name, int value) {
+    return SysCall(43, (uintptr_t) name, value, 1, 0, 0);
+}
+
+// Sync and unmount the filesystems, then power off (mode 0) or reboot (mode 1). Only for init, it only returns
+// on failure.
+inline int Shutdown(int mode) {
+    return SysCall(44, mode, 0, 0, 0, 0);
+}
+
+// Device specific request on a descriptor, for consoles see TtyRequest in tty.h. Returns -1 on failure.
+inline int Ioctl(int fd, int request, int arg) {
+    return SysCall(45, fd, request, arg, 0, 0);
+}
+
+// Write back the buffered data of all filesystems.
+inline int Sync() {
+    return SysCall(46, 0, 0, 0, 0, 0);
+}
+
+// Write back the buffered data of the file of fd. Returns -1 on failure.
+inline int Fsync(int fd) {
+    return SysCall(47, fd, 0, 0, 0, 0);
+}
+
+// Mount the FAT filesystem or USTAR archive in file image at path. Only for privileged processes, returns -1 on failure.
+inline int MountImage(const char* image, const char* path) {
+    return SysCall(51, (uintptr_t) image, (uintptr_t) path, 0, 0, 0);
+}
+
+// Attach the calling process to virtual console n, children inherit it.
+inline int SetConsole(int n) {
+    return SysCall(11, n, 0, 0, 0, 0);
+}
+
+// Sleep for ns nanoseconds. The kernel sleeps whole timer ticks and returns what remains of the last tick, which
+// the second call busy waits in the kernel.
+inline void NanoSleep(uint64_t ns) {
+    auto remaining = SysCall(13, ns & 0xFFFFFFFF, ns >> 32, 0, 0, 0);
+    if (remaining) SysCall(13, remaining, 0, 0, 0, 0);
+}
+
+// Monotonic time since boot in ns.
+inline uint64_t GetTimeNs() {
+    uint64_t ns = 0;
+    SysCall(56, (uintptr_t) &ns, 0, 0, 0, 0);
+    return ns;
+}
+
+// Create a CPU group, the CPU is divided between groups in proportion to their weight (1 - 10000, the root group
+// has 100). Returns the group id or -1.
+inline int CreateCpuGroup(int weight) {
+    return SysCall(14, weight, 0, 0, 0, 0);
+}
+
+// Move the calling process into a CPU group, children inherit the group. Only the process that created the group
+// and privileged processes can move into it, to move children into a group the creator moves in before forking.
+inline int SetCpuGroup(int group) {
+    return SysCall(15, group, 0, 0, 0, 0);
+}
+
+// Memory statistics, the memory counts are in pages, matches the kernel's MemInfo.
+struct MemInfo {
+    uint32_t page_size;
+    uint32_t total_pages;
+    uint32_t free_pages;
+    uint32_t shared_pages;
+    uint32_t kernel_pages;
+    uint32_t ramdisk_pages;
+    uint32_t reclaimed_pages;  // zero pages given back to the free pool since boot
+    uint32_t heap_pages;  // the heaps of all processes, up to their program break
+    uint32_t open_files;  // in use of the system wide open file table
+    uint32_t file_descriptors;  // open descriptors of all threads
+};
+
+inline MemInfo GetMemInfo() {
+    MemInfo info;
+    SysCall(16, (uintptr_t) &info, 0, 0, 0, 0);
+    return info;
+}
+
+// Block until fewer than threshold pages are free, returns the number of free pages. Lets a process shed cached
+// memory before the system runs out.
+inline int WaitLowMemory(int threshold) {
+    return SysCall(17, threshold, 0, 0, 0, 0);
+}
+
+// User space drivers, only allowed for privileged processes. Grants the calling thread access to the io ports
+// [base, base + count) with in/out instructions.
+inline int GrantIoPorts(int base, int count) {
+    return SysCall(18, base, count, 0, 0, 0);
+}
+
+// Give up the granted ranges of io ports that lie within [base, base + count), once a driver is done with its device.
+// Returns the number of ranges given up.
+inline int RevokeIoPorts(int base, int count) {
+    return SysCall(67, base, count, 0, 0, 0);
+}
+
+// Segments of the calling process in its LDT, matches ldt.h.
+enum LdtFlags : uint32_t {
+    kLdtPresent = 1,  // without it the entry is cleared
+    kLdtCode = 2,  // executable, otherwise data
+    kLdtWritable = 4,  // data can be written, code can be read
+    kLdt32Bit = 8,  // the default operand size of code, the stack pointer width of a stack
+    kLdtPageGranular = 16,  // the limit counts pages instead of bytes
+};
+
+// Define entry index of the LDT as a segment from base up to and including limit, which must lie below the kernel.
+// Returns the selector to load, or -1.
+inline int ModifyLdt(int index, uintptr_t base, uint32_t limit, uint32_t flags) {
+    return SysCall(68, index, base, limit, flags, 0);
+}
+
+// Forward an IRQ without kernel driver to the calling thread.
+inline int ClaimIrq(int irq) {
+    return SysCall(19, irq, 0, 0, 0, 0);
+}
+
+// Acknowledge the claimed IRQs in mask and wait until one of them fires, returns the mask of the IRQs that fired.
+inline int WaitIrq(int mask) {
+    return SysCall(20, mask, 0, 0, 0, 0);
+}
+
+// Map the physical MMIO range [phys, phys + size) uncached into the address space, returns nullptr on failure.
+inline void* MapPhys(uintptr_t phys, std::size_t size) {
+    return (void*) SysCall(22, phys, size, 0, 0, 0);
+}
+
+// Give up the right to drive hardware for good.
+inline void DropPrivileges() {
+    SysCall(21, 0, 0, 0, 0, 0);
+}
+
+// Message passing. A port is created by the process that receives from it, any process can send to it.
+inline int CreatePort() {
+    return SysCall(23, 0, 0, 0, 0, 0);
+}
+
+inline int DestroyPort(int port) {
+    return SysCall(24, port, 0, 0, 0, 0);
+}
+
+// Sends a message of at most 256 bytes, blocks while the port's queue is full.
+inline int Send(int port, uint32_t type, const void* data, std::size_t size) {
+    return SysCall(25, port, type, (uintptr_t) data, size, 0);
+}
+
+// Receives a message into buf, stores its type in *type and returns its size. Waits at most timeout_ms ms, forever
+// if negative. Returns -1 on timeout.
+inline int Receive(int port, void* buf, std::size_t size, uint32_t* type, int timeout_ms) {
+    return SysCall(26, port, (uintptr_t) buf, size, timeout_ms, (uintptr_t) type);
+}
+
+// Event objects, a counter that EventWrite adds to and EventRead takes, blocking until it's nonzero.
+inline int CreateEvent() {
+    return SysCall(27, 0, 0, 0, 0, 0);
+}
+
+inline int DestroyEvent(int event) {
+    return SysCall(28, event, 0, 0, 0, 0);
+}
+
+inline uint64_t EventRead(int event) {
+    uint64_t counter = 0;
+    SysCall(29, event, (uintptr_t) &counter, 0, 0, 0);
+    return counter;
+}
+
+inline int EventWrite(int event, uint64_t n) {
+    return SysCall(30, event, n & 0xFFFFFFFF, n >> 32, 0, 0);
+}
+
+// Raw packet access to a network interface, e.g. "lo".
+inline int NetOpen(std::string_view name) {
+    return SysCall(31, (uintptr_t) name.data(), name.size(), 0, 0, 0);
+}
+
+inline int NetSend(int iface, const void* packet, std::size_t size) {
+    return SysCall(32, iface, (uintptr_t) packet, size, 0, 0);
+}
+
+// Returns the size of the received packet or -1 if none arrived within timeout_ms ms (forever if negative).
+inline int NetReceive(int iface, void* buf, std::size_t size, int timeout_ms) {
+    return SysCall(33, iface, (uintptr_t) buf, size, timeout_ms, 0);
+}
+
+// Header of a packet capture record, matches the kernel's CaptureHeader. The captured data follows.
+struct CaptureHeader {
+    uint64_t time_ns;
+    uint16_t iface;
+    uint8_t direction;  // 0 received, 1 sent
+    uint8_t reserved;
+    uint32_t size;
+};
+
+// Reads the oldest packet capture record into buf, returns its size or -1 if none arrived within timeout_ms ms
+// (forever if negative).
+inline int NetCapture(void* buf, std::size_t size, int timeout_ms) {
+    return SysCall(34, (uintptr_t) buf, size, timeout_ms, 0, 0);
+}
+
+// Sampling profiler, records the eip at every timer tick between ProfileStart and ProfileStop.
+inline void ProfileStart() {
+    SysCall(35, 0, 0, 0, 0, 0);
+}
+
+inline int ProfileStop() {
+    return SysCall(35, 1, 0, 0, 0, 0);
+}
+
+// A sample is the eip followed by a backtrace of return addresses, matches the kernel's ProfileSample.
+struct ProfileSample {
+    uint32_t depth;
+    uint32_t pcs[8];
+};
+
+// Copies at most max samples, returns the number copied.
+inline int ProfileFetch(ProfileSample* samples, int max) {
+    return SysCall(35, 2, (uintptr_t) samples, max, 0, 0);
+}
+
+// Copies the kernel log of the previous boot, if it crashed, into buf. Returns its size, 0 if there is none.
+inline std::size_t LastLog(char* buf, std::size_t size) {
+    return SysCall(36, (uintptr_t) buf, size, 0, 0, 0);
+}
+
+// Reads the kernel log from position *pos on into buf and advances *pos, start at 0 for the oldest. Every line
+// starts with "<level>[seconds.micros] ". Returns the number of bytes read, 0 when there is nothing new unless
+// follow is set, then it waits for more.
+inline int Klog(char* buf, std::size_t size, uint32_t* pos, bool follow) {
+    return SysCall(57, (uintptr_t) buf, size, (uintptr_t) pos, follow, 0);
+}
+
+// Select the keyboard layout, e.g. "us", "de" or "dvorak".
+inline int SetKeymap(std::string_view name) {
+    return SysCall(12, (uintptr_t) name.data(), name.size(), 0, 0, 0);
+}
+
 class Reader : public InputStream {
 public:
     Reader(int fd) : fd_(fd) {}
@@ -0,0 +1,283 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#include <cstdio>
+#include <cstdlib>
+#include <cstring>
+#include <set>
+#include <vector>
+
+#include "src/freestanding/elf.h"
+
+// Host test of the ELF parser and of the page protection derived from the segments. Every input is copied into a
+// heap buffer of exactly its size, so with the address sanitizer any read past the end of a malformed or truncated
+// file is caught, not just a wrong answer.
+
+#define CHECK(cond) do { \
+    if (!(cond)) { \
+        std::fprintf(stderr, "%s:%d: CHECK failed: %s\n", __FILE__, __LINE__, #cond); \
+        std::exit(1); \
+    } \
+} while (0)
+
+constexpr uint32_t kBase = 0x10000;
+constexpr uint32_t kHeaderSize = 52;
+constexpr uint32_t kPhdrSize = 32;
+
+struct Phdr {
+    uint32_t type, offset, vaddr, paddr, filesz, memsz, flags, align;
+};
+
+struct Header {
+    uint8_t ident[16];
+    uint16_t type, machine;
+    uint32_t version, entry, phoff, shoff, flags;
+    uint16_t ehsize, phentsize, phnum, shentsize, shnum, shstrndx;
+};
+
+static_assert(sizeof(Header) == kHeaderSize && sizeof(Phdr) == kPhdrSize);
+
+// A well formed executable: text at kBase covering the headers, as linkers lay it out, and data in the next page.
+static std::vector<uint8_t> MakeElf(std::vector<Phdr> phdrs = {}) {
+    if (phdrs.empty()) {
+        phdrs = {
+            {1, 0, kBase, kBase, 0x200, 0x200, ElfSegment::kRead | ElfSegment::kExecute, 0x1000},
+            {1, 0x200, kBase + 0x1200, kBase + 0x1200, 0x100, 0x800, ElfSegment::kRead | ElfSegment::kWrite, 0x1000},
+        };
+    }
+    Header header{};
+    std::memcpy(header.ident, "\x7F" "ELF\x01\x01\x01", 7);
+    header.type = 2;
+    header.machine = 3;
+    header.version = 1;
+    header.entry = kBase + 0x100;
+    header.phoff = kHeaderSize;
+    header.ehsize = kHeaderSize;
+    header.phentsize = kPhdrSize;
+    header.phnum = phdrs.size();
+    std::vector<uint8_t> file(0x300);
+    std::memcpy(file.data(), &header, sizeof(header));
+    std::memcpy(file.data() + kHeaderSize, phdrs.data(), phdrs.size() * kPhdrSize);
+    return file;
+}
+
+static Header& HeaderOf(std::vector<uint8_t>& file) {
+    return *reinterpret_cast<Header*>(file.data());
+}
+
+static Phdr& PhdrOf(std::vector<uint8_t>& file, int i) {
+    return *reinterpret_cast<Phdr*>(file.data() + kHeaderSize + i * kPhdrSize);
+}
+
+static bool Parse(const std::vector<uint8_t>& file, std::size_t size, ElfImage* image) {
+    auto copy = new char[size > 0 ? size : 1];
+    std::memcpy(copy, file.data(), size);
+    bool ok = ParseElf({copy, size}, image);
+    delete[] copy;
+    return ok;
+}
+
+static bool Parse(const std::vector<uint8_t>& file) {
+    ElfImage image;
+    return Parse(file, file.size(), &image);
+}
+
+// What the loader relies on for anything ParseElf accepts.
+static void CheckInvariants(const ElfImage& image, std::size_t file_size) {
+    CHECK(image.num_segments >= 1 && image.num_segments <= kMaxElfSegments);
+    bool entry_found = false;
+    for (int i = 0; i < image.num_segments; i++) {
+        auto& segment = image.segments[i];
+        CHECK(segment.offset <= file_size && segment.filesz <= file_size - segment.offset);
+        CHECK(segment.filesz <= segment.memsz && segment.memsz > 0);
+        CHECK(segment.vaddr <= UINT32_MAX - segment.memsz);
+        for (int j = 0; j < i; j++) {
+            auto& other = image.segments[j];
+            CHECK(segment.vaddr + segment.memsz <= other.vaddr || other.vaddr + other.memsz <= segment.vaddr);
+        }
+        if ((segment.flags & ElfSegment::kExecute) && image.entry - segment.vaddr < segment.memsz) entry_found = true;
+    }
+    CHECK(entry_found);
+}
+
+static void TestValid() {
+    auto file = MakeElf();
+    ElfImage image;
+    CHECK(Parse(file, file.size(), &image));
+    CHECK(image.entry == kBase + 0x100 && image.num_segments == 2);
+    CHECK(image.segments[1].vaddr == kBase + 0x1200 && image.segments[1].filesz == 0x100);
+    CheckInvariants(image, file.size());
+}
+
+// The text segment spans the whole file, so every proper prefix misses part of it or of the headers.
+static void TestTruncated() {
+    auto file = MakeElf();
+    PhdrOf(file, 0).filesz = file.size();
+    PhdrOf(file, 0).memsz = file.size();
+    PhdrOf(file, 1).offset = 0;
+    PhdrOf(file, 1).filesz = 0;
+    CHECK(Parse(file));
+    for (std::size_t size = 0; size < file.size(); size++) {
+        ElfImage image;
+        CHECK(!Parse(file, size, &image));
+    }
+}
+
+static void TestPhoffOutOfRange() {
+    for (uint32_t phoff : {0x300u, 0x301u, 0x300u - kPhdrSize, 0x300u - 1, 0xFFFFFFFFu, 0xFFFFFFE0u, 0x80000000u}) {
+        auto file = MakeElf();
+        HeaderOf(file).phoff = phoff;
+        CHECK(!Parse(file));
+    }
+    // The table starts in the file but runs past its end.
+    auto file = MakeElf();
+    HeaderOf(file).phnum = (file.size() - kHeaderSize) / kPhdrSize + 1;
+    CHECK(!Parse(file));
+    HeaderOf(file).phnum = 0xFFFF;
+    CHECK(!Parse(file));
+    file = MakeElf();
+    HeaderOf(file).phentsize = kPhdrSize - 1;
+    CHECK(!Parse(file));
+}
+
+static void TestPhdrOutOfRange() {
+    struct {
+        uint32_t offset, filesz;
+    } cases[] = {{0x300, 1}, {0x2FF, 2}, {0, 0x301}, {0xFFFFFFFF, 2}, {2, 0xFFFFFFFF}, {0x80000000, 0x80000000}};
+    for (auto c : cases) {
+        auto file = MakeElf();
+        PhdrOf(file, 1).offset = c.offset;
+        PhdrOf(file, 1).filesz = c.filesz;
+        PhdrOf(file, 1).memsz = c.filesz;
+        CHECK(!Parse(file));
+    }
+    // More file than memory.
+    auto file = MakeElf();
+    PhdrOf(file, 1).memsz = PhdrOf(file, 1).filesz - 1;
+    CHECK(!Parse(file));
+    // Wrapping around the address space.
+    file = MakeElf();
+    PhdrOf(file, 1).vaddr = 0xFFFFF000;
+    PhdrOf(file, 1).memsz = 0x1001;
+    CHECK(!Parse(file));
+    // Empty segment.
+    file = MakeElf();
+    PhdrOf(file, 1).filesz = 0;
+    PhdrOf(file, 1).memsz = 0;
+    CHECK(!Parse(file));
+}
+
+static void TestOverlap() {
+    constexpr uint32_t kRw = ElfSegment::kRead | ElfSegment::kWrite;
+    constexpr uint32_t kRx = ElfSegment::kRead | ElfSegment::kExecute;
+    // Sharing a page is fine, sharing a byte is not.
+    CHECK(Parse(MakeElf({{1, 0, kBase, 0, 0x200, 0x200, kRx, 0}, {1, 0x200, kBase + 0x200, 0, 0x10, 0x10, kRw, 0}})));
+    CHECK(!Parse(MakeElf({{1, 0, kBase, 0, 0x200, 0x200, kRx, 0}, {1, 0x200, kBase + 0x1FF, 0, 0x10, 0x10, kRw, 0}})));
+    // Contained in, and containing an earlier segment.
+    CHECK(!Parse(MakeElf({{1, 0, kBase, 0, 0x200, 0x200, kRx, 0}, {1, 0, kBase + 0x10, 0, 0x10, 0x10, kRw, 0}})));
+    CHECK(!Parse(MakeElf({{1, 0x200, kBase + 0x10, 0, 0x10, 0x10, kRw, 0}, {1, 0, kBase, 0, 0x200, 0x200, kRx, 0}})));
+    // Overlap with a segment that isn't the previous one.
+    CHECK(!Parse(MakeElf({{1, 0, kBase, 0, 0x200, 0x200, kRx, 0}, {1, 0x200, kBase + 0x2000, 0, 0x10, 0x10, kRw, 0},
+                          {1, 0x200, kBase + 0x100, 0, 0x10, 0x10, kRw, 0}})));
+    // Non loadable headers don't take part.
+    CHECK(Parse(MakeElf({{1, 0, kBase, 0, 0x200, 0x200, kRx, 0}, {4, 0, kBase, 0, 0x200, 0x200, kRw, 0}})));
+}
+
+static void TestEntryAndLimits() {
+    auto file = MakeElf();
+    HeaderOf(file).entry = kBase + 0x1300;  // in the data segment
+    CHECK(!Parse(file));
+    HeaderOf(file).entry = kBase + 0x200;  // one past the text segment
+    CHECK(!Parse(file));
+    std::vector<Phdr> phdrs;
+    for (uint32_t i = 0; i <= kMaxElfSegments; i++) {
+        phdrs.push_back({1, 0, kBase + i * 0x1000, 0, 0x200, 0x200, ElfSegment::kRead | ElfSegment::kExecute, 0});
+    }
+    CHECK(!Parse(MakeElf(phdrs)));
+    phdrs.pop_back();
+    CHECK(Parse(MakeElf(phdrs)));
+}
+
+// Random corruption of the headers. Anything accepted must still be safe to load.
+static void TestMutations() {
+    uint32_t state = 12345;
+    auto next = [&state] {
+        state = state * 1103515245 + 12345;
+        return state >> 8;
+    };
+    auto original = MakeElf();
+    int accepted = 0;
+    for (int round = 0; round < 200000; round++) {
+        auto file = original;
+        int mutations = 1 + next() % 4;
+        for (int i = 0; i < mutations; i++) {
+            auto pos = next() % (kHeaderSize + 2 * kPhdrSize);
+            switch (next() % 3) {
+                case 0: file[pos] = next(); break;
+                case 1: file[pos] ^= 1 << (next() % 8); break;
+                default: std::memset(&file[pos & ~3u], 0xFF, 4); break;
+            }
+        }
+        auto size = next() % 4 == 0 ? next() % (file.size() + 1) : file.size();
+        ElfImage image;
+        if (Parse(file, size, &image)) {
+            CheckInvariants(image, size);
+            accepted++;
+        }
+    }
+    CHECK(accepted > 0);
+}
+
+static std::set<uintptr_t> protected_pages;
+
+static std::set<uintptr_t> ReadOnlyPages(std::vector<ElfSegment> segments) {
+    ElfImage image{kBase, 0, int(segments.size()), {}};
+    for (std::size_t i = 0; i < segments.size(); i++) image.segments[i] = segments[i];
+    protected_pages.clear();
+    ForEachReadOnlyPage(image, 0x1000, [](uintptr_t page) { protected_pages.insert(page); });
+    return protected_pages;
+}
+
+static void TestProtection() {
+    constexpr uint32_t kR = ElfSegment::kRead;
+    constexpr uint32_t kRw = ElfSegment::kRead | ElfSegment::kWrite;
+    constexpr uint32_t kRx = ElfSegment::kRead | ElfSegment::kExecute;
+    constexpr uint32_t kRwx = ElfSegment::kRead | ElfSegment::kWrite | ElfSegment::kExecute;
+    constexpr uintptr_t kPage = kBase / 0x1000;
+    // Text and rodata are protected, data is not.
+    CHECK((ReadOnlyPages({{kBase, 0x1800, 0, 0x1800, kRx}, {kBase + 0x2000, 0x800, 0, 0, kR},
+                          {kBase + 0x3000, 0x1000, 0, 0, kRw}}) == std::set<uintptr_t>{kPage, kPage + 1, kPage + 2}));
+    // Text the linker marked writable stays writable.
+    CHECK(ReadOnlyPages({{kBase, 0x2000, 0, 0x2000, kRx | kRw}}).empty());
+    CHECK(ReadOnlyPages({{kBase, 0x2000, 0, 0x2000, kRwx}, {kBase + 0x2000, 0x1000, 0, 0, kR}}) ==
+          std::set<uintptr_t>{kPage + 2});
+    // Segments sharing a page: the page gets the union of their permissions, so text ending in the page where data
+    // starts leaves that page writable, while text pages that are its own stay protected.
+    CHECK((ReadOnlyPages({{kBase, 0x1200, 0, 0x1200, kRx}, {kBase + 0x1200, 0x100, 0, 0, kRw}}) ==
+           std::set<uintptr_t>{kPage}));
+    CHECK((ReadOnlyPages({{kBase + 0x1200, 0x100, 0, 0, kRw}, {kBase, 0x1200, 0, 0x1200, kRx}}) ==
+           std::set<uintptr_t>{kPage}));
+    // Data in the middle of a page shared by two read only segments.
+    CHECK((ReadOnlyPages({{kBase, 0x100, 0, 0x100, kRx}, {kBase + 0x100, 0x100, 0, 0, kRw},
+                          {kBase + 0x200, 0x1000, 0, 0, kR}}) == std::set<uintptr_t>{kPage + 1}));
+    // Read only segments sharing a page don't unprotect it.
+    CHECK((ReadOnlyPages({{kBase, 0x1200, 0, 0x1200, kRx}, {kBase + 0x1200, 0x100, 0, 0, kR}}) ==
+           std::set<uintptr_t>{kPage, kPage + 1}));
+    // A segment ending exactly at a page boundary doesn't touch the next page.
+    CHECK((ReadOnlyPages({{kBase, 0x1000, 0, 0x1000, kRw}, {kBase + 0x1000, 0x1000, 0, 0, kRx}}) ==
+           std::set<uintptr_t>{kPage + 1}));
+}
+
+int main() {
+    TestValid();
+    TestTruncated();
+    TestPhoffOutOfRange();
+    TestPhdrOutOfRange();
+    TestOverlap();
+    TestEntryAndLimits();
+    TestMutations();
+    TestProtection();
+    std::printf("elf_test passed\n");
+    return 0;
+}
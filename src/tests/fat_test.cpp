@@ -0,0 +1,174 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#include <cstdio>
+#include <cstdlib>
+#include <cstring>
+#include <vector>
+
+#include "src/arch/x86/fatfs.h"
+#include "src/freestanding/fat.h"
+
+// Host test of mkfs.fat's formatter, checked by mounting the result with the kernel's FAT filesystem the way
+// MountImage does, listing it and writing files through it.
+
+#define CHECK(cond) do { \
+    if (!(cond)) { \
+        std::fprintf(stderr, "%s:%d: CHECK failed: %s\n", __FILE__, __LINE__, #cond); \
+        std::exit(1); \
+    } \
+} while (0)
+
+static std::vector<char> disk;
+
+static bool WriteDisk(uint64_t offset, const void* data, std::size_t len) {
+    if (offset + len > disk.size()) return false;
+    std::memcpy(disk.data() + offset, data, len);
+    return true;
+}
+
+// The image file the filesystem is mounted from, node 0.
+class ImageFile : public FileSystem {
+public:
+    int Lookup(std::string_view) override { return 0; }
+
+    int Read(int, uint64_t offset, void* buf, std::size_t len) override {
+        if (offset >= disk.size()) return 0;
+        len = std::min<uint64_t>(len, disk.size() - offset);
+        std::memcpy(buf, disk.data() + offset, len);
+        return len;
+    }
+
+    int Write(int, uint64_t offset, const void* buf, std::size_t len) override {
+        if (offset >= disk.size()) return -1;
+        len = std::min<uint64_t>(len, disk.size() - offset);
+        std::memcpy(disk.data() + offset, buf, len);
+        return len;
+    }
+
+    bool Stat(int, FileStat* stat) override {
+        *stat = FileStat{disk.size(), kRegularFile, 0644, 0};
+        return true;
+    }
+};
+
+static ImageFile image;
+
+static FatBootSector BootSector() {
+    FatBootSector boot;
+    std::memcpy(&boot, disk.data(), sizeof(boot));
+    return boot;
+}
+
+static uint64_t RootOffset() {
+    auto boot = BootSector();
+    return (boot.reserved_sectors + uint64_t{boot.num_fats} * boot.fat_size16) * boot.bytes_per_sector;
+}
+
+static void Format(uint64_t kb, std::string_view label) {
+    disk.assign(kb * 1024, 0);
+    CHECK(FormatFat(disk.size(), label, 0x1234, WriteDisk));
+    auto boot = BootSector();
+    CHECK(boot.signature == 0xAA55 && boot.bytes_per_sector == 512 && boot.num_fats == 2);
+}
+
+static std::string_view FatType() {
+    return {disk.data() + kFatExtendedBootOffset + offsetof(FatExtendedBootSector, type), 5};
+}
+
+// The FAT entry of a cluster, decoded independently of the filesystem.
+static uint32_t FatEntry(uint32_t cluster) {
+    auto boot = BootSector();
+    auto fat = reinterpret_cast<const uint8_t*>(disk.data()) + boot.reserved_sectors * boot.bytes_per_sector;
+    if (FatType() == "FAT12") {
+        auto pair = fat[cluster * 3 / 2] | fat[cluster * 3 / 2 + 1] << 8;
+        return cluster & 1 ? pair >> 4 : pair & 0xFFF;
+    }
+    return fat[cluster * 2] | fat[cluster * 2 + 1] << 8;
+}
+
+static void TestLimits() {
+    disk.assign(kFatMinImageSize, 0);
+    CHECK(!FormatFat(kFatMinImageSize - 512, "", 0, WriteDisk));
+    CHECK(!FormatFat(kFatMinImageSize, "TWELVE CHARS", 0, WriteDisk));
+    CHECK(!FormatFat(uint64_t{3} << 30, "", 0, WriteDisk));  // too large for FAT16
+    CHECK(FormatFat(kFatMinImageSize, "", 0, WriteDisk));
+}
+
+// An empty filesystem mounts and lists as empty, the volume label isn't a file.
+static void TestEmpty(uint64_t kb, std::string_view type) {
+    Format(kb, "retro");
+    CHECK(FatType() == type);
+    FatDirEntry label;
+    std::memcpy(&label, disk.data() + RootOffset(), sizeof(label));
+    CHECK(std::memcmp(label.name, "RETRO      ", 11) == 0 && label.attributes == kAttrVolume);
+
+    FatFileSystem fs;
+    CHECK(fs.Init(VNode{&image, 0}));
+    FileStat stat;
+    CHECK(fs.Stat(0, &stat) && stat.type == kDirectory);
+    DirEntry entry;
+    CHECK(fs.ReadDir(0, 0, &entry) == 0);
+    CHECK(fs.Lookup("retro") == -1);
+}
+
+// Files can't be created through the filesystem, so the entry is added to the root directory by hand. Writing it
+// allocates clusters, which must read back after mounting again and be the same in both FATs.
+static void TestWrite(uint64_t kb) {
+    Format(kb, "");
+    FatDirEntry raw = {};
+    std::memcpy(raw.name, "DATA    BIN", 11);
+    std::memcpy(disk.data() + RootOffset(), &raw, sizeof(raw));
+
+    auto boot = BootSector();
+    std::size_t cluster_size = boot.sectors_per_cluster * boot.bytes_per_sector;
+    std::vector<char> data(21 * cluster_size + 100);
+    for (std::size_t i = 0; i < data.size(); i++) data[i] = char(i * 7 + i / 251);
+    {
+        FatFileSystem fs;
+        CHECK(fs.Init(VNode{&image, 0}));
+        int node = fs.Lookup("data.bin");
+        CHECK(node > 0);
+        for (std::size_t done = 0; done < data.size(); ) {
+            auto len = std::min<std::size_t>(700, data.size() - done);
+            CHECK(fs.Write(node, done, data.data() + done, len) == int(len));
+            done += len;
+        }
+    }
+    std::memcpy(&raw, disk.data() + RootOffset(), sizeof(raw));
+    CHECK(raw.size == data.size());
+    int chain = 1;
+    uint32_t end = FatType() == "FAT12" ? 0xFF8 : 0xFFF8;
+    for (uint32_t cluster = raw.cluster_low; FatEntry(cluster) < end; cluster = FatEntry(cluster)) chain++;
+    CHECK(chain == 22);
+    uint64_t fat_offset = uint64_t{boot.reserved_sectors} * boot.bytes_per_sector;
+    uint64_t fat_size = uint64_t{boot.fat_size16} * boot.bytes_per_sector;
+    CHECK(std::memcmp(disk.data() + fat_offset, disk.data() + fat_offset + fat_size, fat_size) == 0);
+
+    FatFileSystem fs;
+    CHECK(fs.Init(VNode{&image, 0}));
+    DirEntry entry;
+    CHECK(fs.ReadDir(0, 0, &entry) == 1 && std::strcmp(entry.name, "DATA.BIN") == 0);
+    CHECK(fs.ReadDir(0, 1, &entry) == 0);
+    int node = fs.Lookup("data.bin");
+    FileStat stat;
+    CHECK(node > 0 && fs.Stat(node, &stat) && stat.size == data.size());
+    std::vector<char> back(data.size());
+    CHECK(fs.Read(node, 0, back.data(), back.size()) == int(back.size()));
+    CHECK(back == data);
+}
+
+int main() {
+    TestLimits();
+    TestEmpty(32, "FAT12");
+    TestEmpty(160, "FAT12");
+    TestEmpty(1440, "FAT12");
+    TestEmpty(4096, "FAT16");
+    TestEmpty(65536, "FAT16");
+    TestWrite(160);
+    TestWrite(1440);
+    TestWrite(8192);
+    std::printf("fat_test passed\n");
+    return 0;
+}
@@ -0,0 +1,55 @@
+//
+// Created by gerben stavenga on 10/16/26.
+//
+
+#include <cstdio>
+#include <cstdlib>
+#include <cstring>
+
+#include "src/freestanding/mbr.h"
+
+// Host test of the MBR partition table parser.
+
+#define CHECK(cond) do { \
+    if (!(cond)) { \
+        std::fprintf(stderr, "%s:%d: CHECK failed: %s\n", __FILE__, __LINE__, #cond); \
+        std::exit(1); \
+    } \
+} while (0)
+
+static void SetEntry(char* sector, int i, uint8_t status, uint8_t type, uint32_t first, uint32_t count) {
+    auto entry = sector + 0x1BE + i * 16;
+    entry[0] = status;
+    entry[4] = type;
+    std::memcpy(entry + 8, &first, 4);
+    std::memcpy(entry + 12, &count, 4);
+}
+
+int main() {
+    char sector[512] = {};
+    Partition partitions[kMbrPartitions];
+    CHECK(!ParseMbr({sector, sizeof(sector)}, partitions));
+    sector[510] = 0x55;
+    sector[511] = char(0xAA);
+    CHECK(!ParseMbr({sector, 511}, partitions));
+    CHECK(ParseMbr({sector, sizeof(sector)}, partitions));
+    CHECK(FindPartition(partitions, kPartitionTar) == nullptr);
+
+    // As written by mkpart.sh, the CHS fields saturated.
+    std::memset(sector + 0x1BE, 0xFF, 16);
+    SetEntry(sector, 0, 0, kPartitionTar, 37, 32731);
+    SetEntry(sector, 1, 0x80, 0x0C, 40000, 1000);
+    SetEntry(sector, 2, 0, 0x83, 0, 100);  // starts at the MBR
+    SetEntry(sector, 3, 0, 0x83, 0xFFFFFF00, 0x100);  // runs past 2tb
+    CHECK(ParseMbr({sector, sizeof(sector)}, partitions));
+    auto archive = FindPartition(partitions, kPartitionTar);
+    CHECK(archive == &partitions[0] && archive->first_lba == 37 && archive->num_sectors == 32731);
+    CHECK(!archive->bootable);
+    CHECK(partitions[1].type == 0x0C && partitions[1].bootable && partitions[1].first_lba == 40000);
+    CHECK(partitions[2].type == 0 && partitions[3].type == 0);
+    CHECK(FindPartition(partitions, 0x83) == nullptr);
+    SetEntry(sector, 3, 0, 0x83, 0xFFFFFF00, 0xFF);
+    CHECK(ParseMbr({sector, sizeof(sector)}, partitions) && FindPartition(partitions, 0x83) == &partitions[3]);
+    std::printf("mbr_test passed\n");
+    return 0;
+}